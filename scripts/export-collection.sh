@@ -25,6 +25,7 @@ show_usage() {
     echo "  -v, --include-vectors   Include vectors in export (increases file size)"
     echo "  -p, --pretty            Pretty-print JSON output"
     echo "  -b, --batch-size SIZE   Batch size for fetching (default: 100)"
+    echo "  -s, --since TIME        Only points ingested at or after TIME (RFC 3339)"
     echo "  -h, --help              Show this help message"
     echo ""
     echo "Environment Variables:"
@@ -78,6 +79,10 @@ while [[ $# -gt 0 ]]; do
             ARGS+=("--batch-size" "$2")
             shift 2
             ;;
+        -s|--since)
+            ARGS+=("--since" "$2")
+            shift 2
+            ;;
         -h|--help)
             show_usage
             ;;
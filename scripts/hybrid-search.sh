@@ -30,6 +30,8 @@ show_usage() {
     echo "  -v, --vector-weight W   Vector search weight 0-1 (default: 0.7)"
     echo "  -k, --keyword-weight W  Keyword search weight 0-1 (default: 0.3)"
     echo "  -f, --filter KEY=VALUE  Filter by metadata (can be repeated)"
+    echo "  -x, --exclude KEY=VALUE Leave out matching points (can be repeated)"
+    echo "  -s, --sparse            Score keywords with Qdrant's sparse index (ingest-hierarchical collections)"
     echo "  -j, --json              Output as JSON"
     echo "  -h, --help              Show this help message"
     echo ""
@@ -49,6 +51,7 @@ show_usage() {
     echo "  --filter is_code=true           # Only code blocks"
     echo "  --filter source=myfile.pdf      # From specific file"
     echo "  --filter chunk_type=Code        # Specific chunk type"
+    echo "  --exclude is_code=true          # Everything except code blocks"
     exit 1
 }
 
@@ -87,6 +90,14 @@ while [[ $# -gt 0 ]]; do
             FILTERS+=("$2")
             shift 2
             ;;
+        -x|--exclude)
+            ARGS+=("--exclude" "$2")
+            shift 2
+            ;;
+        -s|--sparse)
+            ARGS+=("--sparse")
+            shift
+            ;;
         -j|--json)
             ARGS+=("--json")
             shift
@@ -22,6 +22,9 @@ show_usage() {
     echo "Options:"
     echo "  -c, --collection NAME   Target collection name (default: use name from export)"
     echo "  -f, --force             Force import, merge with existing collection"
+    echo "  -m, --merge-strategy S  With --force: overwrite (default), skip-existing or payload-merge"
+    echo "  --verify                Check the point count and sampled payloads after importing"
+    echo "  --verify-sample N       Payloads --verify compares (default: 100)"
     echo "  -s, --skip-create       Skip collection creation (assume exists)"
     echo "  -b, --batch-size SIZE   Batch size for uploading (default: 100)"
     echo "  -h, --help              Show this help message"
@@ -68,6 +71,18 @@ while [[ $# -gt 0 ]]; do
             ARGS+=("--force")
             shift
             ;;
+        -m|--merge-strategy)
+            ARGS+=("--merge-strategy" "$2")
+            shift 2
+            ;;
+        --verify)
+            ARGS+=("--verify")
+            shift
+            ;;
+        --verify-sample)
+            ARGS+=("--verify-sample" "$2")
+            shift 2
+            ;;
         -s|--skip-create)
             ARGS+=("--skip-create")
             shift
@@ -62,7 +62,8 @@ for dir in "$INGEST_DIR"/*; do
     
     # Get directory name for collection
     DIRNAME=$(basename "$dir")
-    COLLECTION="${DIRNAME}-books"
+    # Same normalization as ingest-by-directory: lowercase, runs of other characters become '-'
+    COLLECTION=$(printf '%s' "${DIRNAME}-books" | LC_ALL=C tr '[:upper:]' '[:lower:]' | LC_ALL=C sed -E 's/[^a-z0-9]+/-/g; s/^-+//; s/-+$//')
     
     # Count PDFs in this directory
     PDF_COUNT=$(find "$dir" -maxdepth 1 -name "*.pdf" 2>/dev/null | wc -l | tr -d ' ')
@@ -0,0 +1,674 @@
+// Copyright (c) 2025 Michael A. Wright
+// Licensed under the MIT License
+
+//! Answer a question with retrieval-augmented generation.
+//!
+//! Runs the same vector search as `search-qdrant`, packs the best chunks into
+//! a prompt within a character budget (and a token budget with
+//! `--context-tokens`), and streams the LLM's answer from
+//! Ollama followed by the sources it was given. With `--json` the answer is
+//! collected and printed once, as a single JSON object.
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use rag_demo::config::parse_args;
+use rag_demo::rag::{
+    ApiKey, Consistency, EmbedApi, Embedder, EmbeddingCache, QdrantClient, RetryPolicy,
+    SearchResult, TokenCounter, Tokenizer, http_client_builder, namespaced_collection, preflight,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+const SYSTEM_PROMPT: &str = "You answer questions using only the numbered context passages provided. \
+Cite passages by their number, e.g. [1]. If the context does not contain the answer, say what information is missing.";
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Answer a question from retrieved chunks with an LLM", long_about = None)]
+struct Args {
+    #[arg(help = "Question to answer")]
+    query: String,
+
+    #[arg(
+        short,
+        long,
+        default_value = "5",
+        help = "Number of chunks to retrieve as context"
+    )]
+    limit: usize,
+
+    #[arg(
+        long,
+        default_value = "documents",
+        env = "RAG_COLLECTION",
+        help = "Qdrant collection name"
+    )]
+    collection: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_PREFIX",
+        help = "Prepended to the collection name, e.g. acme_ to keep one tenant's collections apart"
+    )]
+    collection_prefix: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_SUFFIX",
+        help = "Appended to the collection name"
+    )]
+    collection_suffix: String,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:6333",
+        env = "QDRANT_URL",
+        help = "Qdrant URL"
+    )]
+    qdrant_url: String,
+
+    #[arg(
+        long,
+        env = "QDRANT_API_KEY",
+        hide_env_values = true,
+        help = "Qdrant API key, sent as the api-key header (Qdrant Cloud)"
+    )]
+    qdrant_api_key: Option<ApiKey>,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:11434",
+        env = "OLLAMA_URL",
+        help = "Ollama URL"
+    )]
+    ollama_url: String,
+
+    #[arg(
+        short,
+        long,
+        default_value = "nomic-embed-text",
+        env = "RAG_MODEL",
+        help = "Embedding model"
+    )]
+    model: String,
+
+    #[arg(
+        long,
+        default_value = "mistral:7b",
+        help = "LLM used to generate the answer"
+    )]
+    llm_model: String,
+
+    #[arg(
+        long,
+        default_value = "8000",
+        help = "Maximum characters of retrieved context placed in the prompt"
+    )]
+    max_context_chars: usize,
+
+    #[arg(
+        long,
+        help = "Maximum tokens of retrieved context placed in the prompt, measured with --tokenizer; keep it under the LLM's context window"
+    )]
+    context_tokens: Option<usize>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "cl100k",
+        help = "Tokenizer used to measure --context-tokens (chars estimates 4 characters per token)"
+    )]
+    tokenizer: Tokenizer,
+
+    #[arg(
+        long,
+        help = "Leave out a chunk whose text is already in the prompt, such as a child after its parent from ingest-hierarchical"
+    )]
+    dedupe_overlap: bool,
+
+    #[arg(
+        long,
+        default_value = "text",
+        help = "Payload field that stores the chunk text"
+    )]
+    text_field: String,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "ollama",
+        help = "Embedding API: ollama (/api/embeddings), ollama-embed (/api/embed), openai (/v1/embeddings)"
+    )]
+    embed_api: EmbedApi,
+
+    #[arg(
+        long,
+        help = "Skip checking that Qdrant, Ollama and the embedding model are available"
+    )]
+    skip_checks: bool,
+
+    #[arg(
+        long,
+        help = "Fail instead of warning when the collection was embedded with a model other than --model"
+    )]
+    strict: bool,
+
+    #[arg(
+        long,
+        default_value = "4",
+        help = "Times to retry a failed embedding request (5xx, 429, connection errors)"
+    )]
+    embed_retries: u32,
+
+    #[arg(
+        long,
+        default_value = "500",
+        help = "Delay before the first embedding retry in ms, doubled on each retry"
+    )]
+    embed_retry_delay_ms: u64,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "eventual",
+        help = "strong waits for upserts to be applied and searches exactly, so just-ingested points are always found"
+    )]
+    consistency: Consistency,
+
+    #[arg(
+        long,
+        help = "Directory for cached query embeddings (default: ~/.cache/rag-demo)"
+    )]
+    cache_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Always ask the embedding server instead of using the cache"
+    )]
+    no_cache: bool,
+
+    #[arg(
+        short,
+        long,
+        help = "Print the whole answer and its sources as one JSON object when generation finishes"
+    )]
+    json: bool,
+}
+
+/// One line of Ollama's streaming `/api/generate` output
+#[derive(Debug, Deserialize)]
+struct GenerateChunk {
+    #[serde(default)]
+    response: String,
+    #[serde(default)]
+    done: bool,
+    error: Option<String>,
+}
+
+/// Limits on the retrieved context placed in the prompt
+#[derive(Debug)]
+struct ContextBudget {
+    max_chars: usize,
+    /// `--context-tokens` and the counter that measures them
+    max_tokens: Option<(usize, TokenCounter)>,
+    /// `--dedupe-overlap`
+    dedupe: bool,
+}
+
+/// Retrieved context that fits the prompt budget, plus the sources it came from
+#[derive(Debug, PartialEq)]
+struct PromptContext {
+    text: String,
+    sources: Vec<String>,
+    /// Retrieved chunks placed in the prompt, the last possibly cut short
+    chunks: usize,
+    /// Chunks left out because their text was already in the prompt
+    duplicates: usize,
+}
+
+/// Whether `result` repeats context already packed: its text is contained in
+/// a packed chunk, or it is the child of a packed parent
+fn overlaps_packed(result: &SearchResult, chunk: &str, packed: &[(&str, &str)]) -> bool {
+    let parent_id = result.payload.get("parent_id").and_then(|v| v.as_str());
+    packed
+        .iter()
+        .any(|&(id, text)| parent_id == Some(id) || text.contains(chunk))
+}
+
+fn result_source(result: &SearchResult) -> String {
+    ["source", "source_path"]
+        .iter()
+        .find_map(|key| result.payload.get(*key).and_then(|v| v.as_str()))
+        .unwrap_or("unknown")
+        .to_string()
+}
+
+/// "p. 4" or "pp. 4-5" for chunks ingested with page numbers
+fn result_pages(result: &SearchResult) -> Option<String> {
+    let page = result.payload.get("page")?.as_u64()?;
+    match result.payload.get("page_end").and_then(|v| v.as_u64()) {
+        Some(end) if end > page => Some(format!("pp. {page}-{end}")),
+        _ => Some(format!("p. {page}")),
+    }
+}
+
+/// Cut `s` to at most `max_chars` characters without splitting a character
+fn truncate_chars(s: &str, max_chars: usize) -> &str {
+    match s.char_indices().nth(max_chars) {
+        Some((idx, _)) => &s[..idx],
+        None => s,
+    }
+}
+
+/// Number the chunks in score order and keep adding them until the budget is used up
+fn build_context(
+    results: &[SearchResult],
+    text_field: &str,
+    budget: &ContextBudget,
+) -> PromptContext {
+    let mut text = String::new();
+    let mut sources: Vec<String> = Vec::new();
+    let mut packed: Vec<(&str, &str)> = Vec::new();
+    let mut duplicates = 0;
+    let mut used = 0;
+    let mut used_tokens = 0;
+
+    for result in results {
+        let chunk = result
+            .payload
+            .get(text_field)
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .trim();
+        if chunk.is_empty() {
+            continue;
+        }
+        if budget.dedupe && overlaps_packed(result, chunk, &packed) {
+            duplicates += 1;
+            continue;
+        }
+
+        let source = result_source(result);
+        let number = match sources.iter().position(|s| *s == source) {
+            Some(i) => i + 1,
+            None => sources.len() + 1,
+        };
+        // The blank line before every chunk but the first counts against the budget too
+        let separator = if text.is_empty() { "" } else { "\n\n" };
+        let header = match result_pages(result) {
+            Some(pages) => format!("{separator}[{number}] ({source}, {pages})\n"),
+            None => format!("{separator}[{number}] ({source})\n"),
+        };
+
+        let remaining = budget.max_chars.saturating_sub(used);
+        let header_len = header.chars().count();
+        if remaining <= header_len {
+            break;
+        }
+        let mut body = truncate_chars(chunk, remaining - header_len);
+        if let Some((max_tokens, counter)) = budget.max_tokens {
+            let remaining = max_tokens.saturating_sub(used_tokens);
+            let header_tokens = counter.count(&header);
+            if remaining <= header_tokens {
+                break;
+            }
+            body = counter.truncate(body, remaining - header_tokens);
+            if body.is_empty() {
+                break;
+            }
+            used_tokens += header_tokens + counter.count(body);
+        }
+
+        if number > sources.len() {
+            sources.push(source);
+        }
+        text.push_str(&header);
+        text.push_str(body);
+        used += header_len + body.chars().count();
+        packed.push((&result.id, chunk));
+
+        if body.len() < chunk.len() {
+            break;
+        }
+    }
+
+    PromptContext {
+        text: text.trim_end().to_string(),
+        sources,
+        chunks: packed.len(),
+        duplicates,
+    }
+}
+
+fn build_prompt(query: &str, context: &PromptContext) -> String {
+    if context.text.is_empty() {
+        return format!("Please answer this question: {query}");
+    }
+    format!("Context:\n{}\n\nQuestion: {}", context.text, query)
+}
+
+/// Copy streamed answer text to `out` as it arrives, returning the whole answer
+fn stream_answer(reader: impl BufRead, out: &mut impl Write) -> Result<String> {
+    let mut answer = String::new();
+    let mut done = false;
+    for line in reader.lines() {
+        let line = line.context("Failed to read answer stream")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let chunk: GenerateChunk =
+            serde_json::from_str(&line).context("Failed to parse answer stream")?;
+        if let Some(error) = chunk.error {
+            anyhow::bail!("Ollama returned error: {error}");
+        }
+        write!(out, "{}", chunk.response)?;
+        out.flush()?;
+        answer.push_str(&chunk.response);
+        if chunk.done {
+            done = true;
+            break;
+        }
+    }
+    writeln!(out)?;
+    if !done {
+        anyhow::bail!("Answer stream ended before Ollama finished; the answer is incomplete");
+    }
+    Ok(answer)
+}
+
+fn main() -> Result<()> {
+    let mut args: Args = parse_args()?;
+    args.collection = namespaced_collection(
+        &args.collection_prefix,
+        &args.collection,
+        &args.collection_suffix,
+    );
+    if !args.skip_checks {
+        preflight(
+            &args.qdrant_url,
+            &args.ollama_url,
+            &args.model,
+            args.embed_api,
+        )?;
+    }
+    let embedder = Embedder::new(
+        &args.ollama_url,
+        args.embed_api,
+        &args.model,
+        RetryPolicy {
+            retries: args.embed_retries,
+            base_delay_ms: args.embed_retry_delay_ms,
+        },
+    )
+    .cache(EmbeddingCache::from_flags(
+        args.cache_dir.as_deref(),
+        args.no_cache,
+    ));
+    let qdrant = QdrantClient::new(&args.qdrant_url)
+        .api_key(args.qdrant_api_key.as_ref())
+        .consistency(args.consistency);
+    qdrant.require_collection(&args.collection)?;
+    qdrant.check_embed_model(&args.collection, &args.model, args.strict)?;
+    let vector_name = qdrant
+        .collection_info(&args.collection)?
+        .default_vector_name();
+    let qdrant = qdrant.vector_name(vector_name);
+
+    // Retrieve context
+    let query_embedding = embedder
+        .embed(&args.query)
+        .context("Failed to get query embedding")?;
+    let results = qdrant.search(&args.collection, &query_embedding, args.limit, None)?;
+    let budget = ContextBudget {
+        max_chars: args.max_context_chars,
+        max_tokens: args
+            .context_tokens
+            .map(|tokens| (tokens, TokenCounter::load(args.tokenizer))),
+        dedupe: args.dedupe_overlap,
+    };
+    let context = build_context(&results, &args.text_field, &budget);
+
+    if context.sources.is_empty() {
+        eprintln!("⚠️  No relevant context found in knowledge base");
+    } else {
+        eprintln!(
+            "📚 Packed {} of {} retrieved chunks into the prompt, from {} sources",
+            context.chunks,
+            results.len(),
+            context.sources.len()
+        );
+    }
+    if context.duplicates > 0 {
+        eprintln!(
+            "✂️  Left out {} chunks already covered by the prompt",
+            context.duplicates
+        );
+    }
+    eprintln!("💭 Generating answer with {}...\n", args.llm_model);
+
+    // Generation can take far longer than the default request timeout
+    let client = http_client_builder()
+        .timeout(None)
+        .build()
+        .context("Failed to build HTTP client")?;
+    let response = client
+        .post(format!("{}/api/generate", args.ollama_url))
+        .json(&json!({
+            "model": args.llm_model,
+            "system": SYSTEM_PROMPT,
+            "prompt": build_prompt(&args.query, &context),
+            "stream": true,
+        }))
+        .send()
+        .context("Failed to reach Ollama for generation")?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response
+            .text()
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        anyhow::bail!("Ollama returned error ({}): {}", status, error_text);
+    }
+
+    let mut stdout = std::io::stdout().lock();
+    if args.json {
+        let answer = stream_answer(BufReader::new(response), &mut std::io::sink())?;
+        let output = json!({
+            "query": args.query,
+            "model": args.llm_model,
+            "answer": answer,
+            "sources": context.sources,
+            "chunks_retrieved": results.len(),
+            "chunks_in_prompt": context.chunks,
+        });
+        writeln!(stdout, "{}", serde_json::to_string_pretty(&output)?)?;
+        return Ok(());
+    }
+    stream_answer(BufReader::new(response), &mut stdout)?;
+
+    if !context.sources.is_empty() {
+        writeln!(stdout, "\nSources:")?;
+        for (i, source) in context.sources.iter().enumerate() {
+            writeln!(stdout, "  [{}] {}", i + 1, source)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(max_chars: usize) -> ContextBudget {
+        ContextBudget {
+            max_chars,
+            max_tokens: None,
+            dedupe: false,
+        }
+    }
+
+    fn result(text: &str, source: &str) -> SearchResult {
+        SearchResult {
+            id: "id".to_string(),
+            score: 0.9,
+            payload: json!({"text": text, "source": source}),
+        }
+    }
+
+    #[test]
+    fn test_context_numbers_sources_once() {
+        let results = vec![
+            result("Ownership moves values.", "rust.pdf"),
+            result("Promises are async.", "js.pdf"),
+            result("Borrowing lends values.", "rust.pdf"),
+        ];
+
+        let context = build_context(&results, "text", &chars(10_000));
+
+        assert_eq!(context.sources, vec!["rust.pdf", "js.pdf"]);
+        assert!(context.text.starts_with("[1] (rust.pdf)\nOwnership"));
+        assert!(context.text.contains("[2] (js.pdf)\nPromises"));
+        assert!(context.text.contains("[1] (rust.pdf)\nBorrowing"));
+    }
+
+    #[test]
+    fn test_context_cites_pages() {
+        let mut first = result("Ownership moves values.", "rust.pdf");
+        first.payload["page"] = json!(4);
+        first.payload["page_end"] = json!(4);
+        let mut second = result("Borrowing lends values.", "rust.pdf");
+        second.payload["page"] = json!(7);
+        second.payload["page_end"] = json!(8);
+
+        let context = build_context(&[first, second], "text", &chars(10_000));
+
+        assert_eq!(context.sources, vec!["rust.pdf"]);
+        assert!(context.text.starts_with("[1] (rust.pdf, p. 4)\nOwnership"));
+        assert!(context.text.contains("[1] (rust.pdf, pp. 7-8)\nBorrowing"));
+    }
+
+    #[test]
+    fn test_context_respects_char_budget() {
+        let results = vec![
+            result(&"a".repeat(50), "one.md"),
+            result(&"b".repeat(50), "two.md"),
+        ];
+
+        let context = build_context(&results, "text", &chars(60));
+
+        assert!(context.text.chars().count() <= 60);
+        assert_eq!(context.sources, vec!["one.md"]);
+        assert_eq!(context.chunks, 1);
+    }
+
+    #[test]
+    fn test_context_budget_counts_separators() {
+        let results = vec![
+            result(&"a".repeat(20), "one.md"),
+            result(&"b".repeat(20), "two.md"),
+        ];
+
+        let context = build_context(&results, "text", &chars(66));
+
+        assert_eq!(context.text.chars().count(), 66);
+        assert!(context.text.contains("\n\n[2] (two.md)\n"));
+        assert!(context.text.ends_with(&"b".repeat(18)));
+    }
+
+    #[test]
+    fn test_context_respects_token_budget() {
+        let results: Vec<SearchResult> = (0..5)
+            .map(|i| result(&"word ".repeat(40), &format!("{i}.md")))
+            .collect();
+        let counter = TokenCounter::estimate();
+        let budget = ContextBudget {
+            max_chars: 10_000,
+            max_tokens: Some((120, counter)),
+            dedupe: false,
+        };
+
+        let context = build_context(&results, "text", &budget);
+
+        assert!(counter.count(&context.text) <= 120);
+        assert_eq!(
+            context.chunks, 3,
+            "two whole chunks and the start of a third"
+        );
+        assert_eq!(context.sources, vec!["0.md", "1.md", "2.md"]);
+    }
+
+    #[test]
+    fn test_dedupe_leaves_out_children_of_packed_parents() {
+        let mut parent = result("Ownership moves values. Borrowing lends them.", "rust.md");
+        parent.id = "parent".to_string();
+        let mut child = result("A child chunk with other words", "rust.md");
+        child.payload["parent_id"] = json!("parent");
+        let contained = result("Borrowing lends them.", "rust.md");
+        let other = result("Lifetimes bound references.", "rust.md");
+        let results = vec![parent, child, contained, other];
+        let budget = ContextBudget {
+            dedupe: true,
+            ..chars(10_000)
+        };
+
+        let deduped = build_context(&results, "text", &budget);
+        let all = build_context(&results, "text", &chars(10_000));
+
+        assert_eq!(deduped.chunks, 2);
+        assert_eq!(deduped.duplicates, 2);
+        assert!(deduped.text.contains("Lifetimes"));
+        assert!(!deduped.text.contains("child chunk"));
+        assert_eq!((all.chunks, all.duplicates), (4, 0));
+    }
+
+    #[test]
+    fn test_prompt_without_context_asks_plainly() {
+        let context = build_context(&[], "text", &chars(100));
+
+        assert_eq!(
+            build_prompt("What is Rust?", &context),
+            "Please answer this question: What is Rust?"
+        );
+    }
+
+    #[test]
+    fn test_stream_answer_concatenates_until_done() {
+        let stream = concat!(
+            "{\"response\":\"Hello\",\"done\":false}\n",
+            "{\"response\":\", world\",\"done\":false}\n",
+            "{\"response\":\"\",\"done\":true}\n",
+        );
+        let mut out = Vec::new();
+
+        let answer = stream_answer(stream.as_bytes(), &mut out).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "Hello, world\n");
+        assert_eq!(answer, "Hello, world");
+    }
+
+    #[test]
+    fn test_stream_answer_without_done_is_incomplete() {
+        let stream = "{\"response\":\"Hel\",\"done\":false}\n";
+        let mut out = Vec::new();
+
+        let err = stream_answer(stream.as_bytes(), &mut out).unwrap_err();
+
+        assert!(err.to_string().contains("incomplete"), "{err}");
+        assert_eq!(String::from_utf8(out).unwrap(), "Hel\n");
+    }
+
+    #[test]
+    fn test_stream_answer_surfaces_errors() {
+        let stream = "{\"error\":\"model 'nope' not found\"}\n";
+
+        let err = stream_answer(stream.as_bytes(), &mut Vec::new()).unwrap_err();
+
+        assert!(err.to_string().contains("model 'nope' not found"));
+    }
+}
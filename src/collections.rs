@@ -0,0 +1,561 @@
+// Copyright (c) 2025 Michael A. Wright
+// Licensed under the MIT License
+
+//! List, inspect, optimize and drop Qdrant collections, see which documents
+//! one holds, or backfill payload fields, without curling the REST API.
+
+use anyhow::Result;
+use clap::{Parser, Subcommand, ValueEnum};
+use rag_demo::config::parse_args;
+use rag_demo::rag::{
+    ApiKey, CollectionResult, QdrantClient, TokenCounter, Tokenizer, build_filter, detect_lang,
+    fence_lang,
+};
+use serde_json::{Map, Value, json};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "List, inspect and drop Qdrant collections and the documents in them", long_about = None)]
+struct Args {
+    #[arg(
+        long,
+        global = true,
+        default_value = "http://localhost:6333",
+        env = "QDRANT_URL",
+        help = "Qdrant URL"
+    )]
+    qdrant_url: String,
+
+    #[arg(
+        long,
+        global = true,
+        env = "QDRANT_API_KEY",
+        hide_env_values = true,
+        help = "Qdrant API key, sent as the api-key header (Qdrant Cloud)"
+    )]
+    qdrant_api_key: Option<ApiKey>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the name of every collection
+    List,
+    /// Show point counts, vector parameters and status of a collection
+    Info {
+        #[arg(help = "Collection name")]
+        name: String,
+    },
+    /// Delete a collection and all of its points
+    Drop {
+        #[arg(help = "Collection name")]
+        name: String,
+
+        #[arg(long, help = "Confirm the deletion; without it nothing is deleted")]
+        yes: bool,
+    },
+    /// List the distinct documents in a collection with their point counts
+    Sources {
+        #[arg(help = "Collection name")]
+        name: String,
+
+        #[arg(
+            long,
+            default_value = "source",
+            help = "Payload field naming the document"
+        )]
+        field: String,
+
+        #[arg(long, help = "Output as JSON: [{source, count}]")]
+        json: bool,
+    },
+    /// Count the points in a collection, or those matching --filter and --exclude
+    Count {
+        #[arg(help = "Collection name")]
+        name: String,
+
+        #[arg(
+            long,
+            help = "Filter by metadata field: key=value, key=a|b|c (any of), or key>=N, key<=N, key>N, key<N (repeatable)"
+        )]
+        filter: Vec<String>,
+
+        #[arg(
+            long,
+            help = "Leave out points matching this condition, same syntax as --filter (repeatable)"
+        )]
+        exclude: Vec<String>,
+
+        #[arg(
+            long,
+            help = "Count exactly rather than letting Qdrant estimate (always exact with --filter or --exclude)"
+        )]
+        exact: bool,
+
+        #[arg(
+            long,
+            help = "Output as JSON: {collection, count, exact, filter, exclude}"
+        )]
+        json: bool,
+    },
+    /// Start Qdrant's optimizers now, optionally waiting until indexing is done
+    Optimize {
+        #[arg(help = "Collection name")]
+        name: String,
+
+        #[arg(long, help = "Wait until the collection status is green")]
+        wait: bool,
+
+        #[arg(
+            long,
+            default_value = "600",
+            help = "Seconds --wait waits before failing"
+        )]
+        timeout: u64,
+    },
+    /// Recompute payload fields from each point's stored text, keeping vectors as they are
+    UpdatePayload {
+        #[arg(help = "Collection name")]
+        name: String,
+
+        #[arg(
+            long,
+            value_enum,
+            value_delimiter = ',',
+            default_value = "lang,code-lang,char-count,token-count",
+            help = "Fields to recompute, comma-separated"
+        )]
+        fields: Vec<PayloadField>,
+
+        #[arg(
+            long,
+            default_value = "text",
+            help = "Payload field that stores the chunk text"
+        )]
+        text_field: String,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value = "cl100k",
+            help = "Tokenizer for token-count"
+        )]
+        tokenizer: Tokenizer,
+
+        #[arg(long, help = "Only fill in fields a point does not have yet")]
+        missing_only: bool,
+
+        #[arg(
+            long,
+            default_value = "256",
+            help = "Points read and updated per request"
+        )]
+        batch_size: usize,
+
+        #[arg(
+            long,
+            help = "Count the points that would change without updating them"
+        )]
+        dry_run: bool,
+    },
+}
+
+/// Payload fields `update-payload` can derive from a chunk's text
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum PayloadField {
+    /// Detected language, as ingestion stores it
+    Lang,
+    /// Fence language, for chunks that are a fenced code block
+    CodeLang,
+    CharCount,
+    TokenCount,
+}
+
+impl PayloadField {
+    fn key(self) -> &'static str {
+        match self {
+            PayloadField::Lang => "lang",
+            PayloadField::CodeLang => "code_lang",
+            PayloadField::CharCount => "char_count",
+            PayloadField::TokenCount => "token_count",
+        }
+    }
+
+    fn compute(self, text: &str, counter: TokenCounter) -> Option<Value> {
+        match self {
+            PayloadField::Lang => Some(json!(detect_lang(text))),
+            PayloadField::CodeLang => {
+                let fence = text.trim_start().lines().next()?;
+                fence.starts_with("```").then(|| json!(fence_lang(fence)))
+            }
+            PayloadField::CharCount => Some(json!(text.chars().count())),
+            PayloadField::TokenCount => Some(json!(counter.count(text))),
+        }
+    }
+}
+
+/// Points fetched per scroll request by `sources`
+const SCROLL_BATCH_SIZE: usize = 1000;
+/// How often `optimize --wait` polls the collection status
+const OPTIMIZE_POLL: Duration = Duration::from_secs(2);
+
+/// Fields of `payload` whose recomputed value differs from the stored one,
+/// or `None` if nothing changes or the point has no text
+fn recompute_payload(
+    payload: &Value,
+    text_field: &str,
+    fields: &[PayloadField],
+    counter: TokenCounter,
+    missing_only: bool,
+) -> Option<Value> {
+    let text = payload.get(text_field)?.as_str()?;
+    let mut update = Map::new();
+    for field in fields {
+        let stored = payload.get(field.key());
+        if missing_only && stored.is_some() {
+            continue;
+        }
+        if let Some(value) = field.compute(text, counter)
+            && stored != Some(&value)
+        {
+            update.insert(field.key().to_string(), value);
+        }
+    }
+    (!update.is_empty()).then_some(Value::Object(update))
+}
+
+/// Point count per distinct `field` value, most points first; points without it count as "(none)"
+fn count_sources(payloads: &[Value], field: &str) -> Vec<(String, usize)> {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for payload in payloads {
+        let source = payload[field].as_str().unwrap_or("(none)");
+        *counts.entry(source.to_string()).or_default() += 1;
+    }
+    let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+    // Stable, so ties stay in name order
+    counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    counts
+}
+
+fn format_info(name: &str, info: &CollectionResult) -> String {
+    let or_unknown = |value: Option<String>| value.unwrap_or_else(|| "unknown".to_string());
+    [
+        format!("📚 Collection: {name}"),
+        format!("   Status: {}", or_unknown(info.status.clone())),
+        format!("   Points: {}", info.points_count.unwrap_or(0)),
+        format!(
+            "   Indexed vectors: {}",
+            info.indexed_vectors_count.unwrap_or(0)
+        ),
+        format!(
+            "   Vector size: {}",
+            or_unknown(info.vector_size().map(|size| size.to_string()))
+        ),
+        format!(
+            "   Distance: {}",
+            or_unknown(info.distance().map(str::to_string))
+        ),
+    ]
+    .join("\n")
+}
+
+fn main() -> Result<()> {
+    let args: Args = parse_args()?;
+    let qdrant = QdrantClient::new(&args.qdrant_url).api_key(args.qdrant_api_key.as_ref());
+
+    match &args.command {
+        Command::List => {
+            let mut names = qdrant.list_collections()?;
+            names.sort();
+            if names.is_empty() {
+                println!("No collections");
+            }
+            for name in names {
+                println!("{name}");
+            }
+        }
+        Command::Info { name } => {
+            qdrant.require_collection(name)?;
+            println!("{}", format_info(name, &qdrant.collection_info(name)?));
+        }
+        Command::Drop { name, yes } => {
+            qdrant.require_collection(name)?;
+            let points = qdrant.collection_info(name)?.points_count.unwrap_or(0);
+            if !yes {
+                anyhow::bail!("Refusing to drop '{name}' ({points} points) without --yes");
+            }
+            qdrant.delete_collection(name)?;
+            println!("🗑️  Dropped '{name}' ({points} points)");
+        }
+        Command::Sources { name, field, json } => {
+            qdrant.require_collection(name)?;
+            let payloads = qdrant.scroll_payloads(name, &[field], SCROLL_BATCH_SIZE)?;
+            let sources = count_sources(&payloads, field);
+            if *json {
+                let sources: Vec<Value> = sources
+                    .iter()
+                    .map(|(source, count)| json!({ "source": source, "count": count }))
+                    .collect();
+                println!("{}", serde_json::to_string_pretty(&sources)?);
+            } else if sources.is_empty() {
+                println!("No points in '{name}'");
+            } else {
+                println!("📚 {} sources in '{name}':", sources.len());
+                for (source, count) in sources {
+                    println!("{count:>8}  {source}");
+                }
+            }
+        }
+        Command::Count {
+            name,
+            filter,
+            exclude,
+            exact,
+            json,
+        } => {
+            qdrant.require_collection(name)?;
+            // An estimate is only worth its speed for the whole collection;
+            // a filter is usually being checked, so its count must be right
+            let filtered = !filter.is_empty() || !exclude.is_empty();
+            let exact = *exact || filtered;
+            let condition = if filtered {
+                build_filter(filter, exclude)?
+            } else {
+                json!({})
+            };
+            let count = qdrant.count_points(name, &condition, exact)?;
+            if *json {
+                let output = json!({
+                    "collection": name,
+                    "count": count,
+                    "exact": exact,
+                    "filter": filter,
+                    "exclude": exclude,
+                });
+                println!("{}", serde_json::to_string_pretty(&output)?);
+            } else {
+                println!("{count}");
+            }
+        }
+        Command::Optimize {
+            name,
+            wait,
+            timeout,
+        } => {
+            qdrant.require_collection(name)?;
+            qdrant.trigger_optimizers(name)?;
+            println!("⚙️  Optimizers started for '{name}'");
+            if *wait {
+                let info = qdrant.wait_for_green(
+                    name,
+                    Duration::from_secs(*timeout),
+                    OPTIMIZE_POLL,
+                    |info| {
+                        println!(
+                            "   {}: {}/{} vectors indexed",
+                            info.status.as_deref().unwrap_or("unknown"),
+                            info.indexed_vectors_count.unwrap_or(0),
+                            info.points_count.unwrap_or(0)
+                        )
+                    },
+                )?;
+                println!(
+                    "✅ '{name}' is green ({} vectors indexed)",
+                    info.indexed_vectors_count.unwrap_or(0)
+                );
+            }
+        }
+        Command::UpdatePayload {
+            name,
+            fields,
+            text_field,
+            tokenizer,
+            missing_only,
+            batch_size,
+            dry_run,
+        } => {
+            if *batch_size == 0 {
+                anyhow::bail!("--batch-size must be greater than zero");
+            }
+            qdrant.require_collection(name)?;
+            let counter = TokenCounter::load(*tokenizer);
+            let mut include: Vec<&str> = fields.iter().map(|f| f.key()).collect();
+            include.push(text_field);
+
+            let (mut scanned, mut changed) = (0, 0);
+            qdrant.scroll_pages(name, &include, *batch_size, |points| {
+                scanned += points.len();
+                let updates: Vec<(String, Value)> = points
+                    .into_iter()
+                    .filter_map(|point| {
+                        let update = recompute_payload(
+                            &point.payload,
+                            text_field,
+                            fields,
+                            counter,
+                            *missing_only,
+                        )?;
+                        Some((point.id, update))
+                    })
+                    .collect();
+                changed += updates.len();
+                if !dry_run {
+                    qdrant.set_payloads(name, &updates)?;
+                }
+                Ok(())
+            })?;
+
+            let keys: Vec<&str> = fields.iter().map(|f| f.key()).collect();
+            let verb = if *dry_run { "Would update" } else { "Updated" };
+            println!(
+                "🔄 {verb} {changed} of {scanned} points in '{name}' ({})",
+                keys.join(", ")
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_sources_groups_and_orders_by_count() {
+        let payloads = vec![
+            json!({"source": "b.pdf"}),
+            json!({"source": "a.pdf"}),
+            json!({"source": "c.md"}),
+            json!({"source": "c.md"}),
+            json!({}),
+        ];
+
+        assert_eq!(
+            count_sources(&payloads, "source"),
+            vec![
+                ("c.md".to_string(), 2),
+                ("(none)".to_string(), 1),
+                ("a.pdf".to_string(), 1),
+                ("b.pdf".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recompute_payload_only_returns_changed_fields() {
+        let counter = TokenCounter::estimate();
+        let all = [
+            PayloadField::Lang,
+            PayloadField::CodeLang,
+            PayloadField::CharCount,
+            PayloadField::TokenCount,
+        ];
+        let code = json!({"text": "```rust\nfn main() {}\n```\n", "char_count": 25});
+
+        let update = recompute_payload(&code, "text", &all, counter, false).unwrap();
+
+        assert_eq!(update["code_lang"], "rust");
+        assert_eq!(
+            update["token_count"],
+            counter.count("```rust\nfn main() {}\n```\n")
+        );
+        assert!(
+            update.get("char_count").is_none(),
+            "unchanged fields are left out"
+        );
+
+        let prose = json!({"text": "Ownership is the most unique feature of the language and it has deep implications.", "lang": "de"});
+        let update = recompute_payload(&prose, "text", &all, counter, false).unwrap();
+        assert_eq!(update["lang"], "en");
+        assert!(update.get("code_lang").is_none());
+
+        let update = recompute_payload(&prose, "text", &all, counter, true).unwrap();
+        assert!(
+            update.get("lang").is_none(),
+            "--missing-only keeps stored values"
+        );
+
+        assert_eq!(
+            recompute_payload(&json!({"content": "x"}), "text", &all, counter, false),
+            None
+        );
+        let args = Args::try_parse_from([
+            "collections",
+            "update-payload",
+            "docs",
+            "--fields",
+            "lang,code-lang",
+        ])
+        .unwrap();
+        assert!(matches!(
+            args.command,
+            Command::UpdatePayload { ref fields, .. } if *fields == [PayloadField::Lang, PayloadField::CodeLang]
+        ));
+    }
+
+    #[test]
+    fn test_drop_requires_explicit_yes() {
+        let args = Args::try_parse_from(["collections", "drop", "rust-books"]).unwrap();
+        assert!(matches!(args.command, Command::Drop { yes: false, .. }));
+
+        let args = Args::try_parse_from(["collections", "drop", "rust-books", "--yes"]).unwrap();
+        assert!(matches!(args.command, Command::Drop { yes: true, .. }));
+    }
+
+    #[test]
+    fn test_count_accepts_repeated_filters() {
+        let args = Args::try_parse_from([
+            "collections",
+            "count",
+            "docs",
+            "--filter",
+            "chunk_type=child_code|child_mixed",
+            "--filter",
+            "char_count>=500",
+        ])
+        .unwrap();
+
+        let Command::Count {
+            filter,
+            exact,
+            json,
+            ..
+        } = args.command
+        else {
+            panic!("expected count");
+        };
+        assert_eq!(filter.len(), 2);
+        assert!(!exact && !json);
+        assert_eq!(
+            build_filter(&filter, &[]).unwrap()["must"]
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_format_info_lists_vector_params() {
+        let info: CollectionResult = serde_json::from_value(json!({
+            "status": "green",
+            "points_count": 1200,
+            "indexed_vectors_count": 1100,
+            "config": {"params": {"vectors": {"size": 768, "distance": "Cosine"}}}
+        }))
+        .unwrap();
+
+        let text = format_info("rust-books", &info);
+
+        assert!(text.contains("Collection: rust-books"));
+        assert!(text.contains("Status: green"));
+        assert!(text.contains("Points: 1200"));
+        assert!(text.contains("Indexed vectors: 1100"));
+        assert!(text.contains("Vector size: 768"));
+        assert!(text.contains("Distance: Cosine"));
+    }
+}
@@ -0,0 +1,380 @@
+// Copyright (c) 2025 Michael A. Wright
+// Licensed under the MIT License
+
+//! Shared defaults from a `rag.toml` file, so the URLs, model and collection
+//! (and its tenant prefix or suffix) need not be passed to every tool.
+//!
+//! A value is taken from, in order: the command-line flag, its environment
+//! variable, the config file, and the built-in default. The file is
+//! `--config <path>` if given, else `./rag.toml`, else
+//! `$XDG_CONFIG_HOME/rag-demo/rag.toml` (`~/.config/rag-demo/rag.toml`).
+
+use crate::rag::{DEFAULT_CONNECT_TIMEOUT, DEFAULT_HTTP_TIMEOUT, HttpOptions, set_http_options};
+use anyhow::{Context, Result};
+use clap::{Arg, ArgAction, Command, Parser};
+use serde::Deserialize;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Name of the config file looked up in the working and config directories
+pub const CONFIG_FILE_NAME: &str = "rag.toml";
+
+/// Contents of `rag.toml`; every key is optional
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RagConfig {
+    pub qdrant_url: Option<String>,
+    pub ollama_url: Option<String>,
+    /// Embedding model
+    pub model: Option<String>,
+    pub collection: Option<String>,
+    /// Tenant namespace around every collection name
+    pub collection_prefix: Option<String>,
+    pub collection_suffix: Option<String>,
+}
+
+impl RagConfig {
+    /// Parse a config file
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    /// `explicit` if given (it must exist), else the first config file found
+    /// in the working directory or the user config directory, else no defaults
+    pub fn load(explicit: Option<&Path>) -> Result<Self> {
+        if let Some(path) = explicit {
+            return Self::from_file(path);
+        }
+        let candidates = [Some(PathBuf::from(CONFIG_FILE_NAME)), user_config_path()];
+        match candidates.into_iter().flatten().find(|path| path.is_file()) {
+            Some(path) => Self::from_file(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Parse `args` as `T`, with this config's values as the defaults of the
+    /// matching flags (`--collection` also covers `--base-collection`). Flags
+    /// without a built-in default, such as `import-collection --collection`,
+    /// are left alone.
+    pub fn parse_from<T, I>(&self, args: I) -> Result<T, clap::Error>
+    where
+        T: Parser,
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        self.parse_with_http(args).map(|(parsed, _)| parsed)
+    }
+
+    /// `parse_from`, also returning the TLS and timeout flags every tool accepts
+    fn parse_with_http<T, I>(&self, args: I) -> Result<(T, HttpOptions), clap::Error>
+    where
+        T: Parser,
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        let command = T::command()
+            .arg(config_arg())
+            .args(tls_args())
+            .args(timeout_args());
+        let matches = self.apply(command).try_get_matches_from(args)?;
+        let secs = |id| Duration::from_secs(*matches.get_one::<u64>(id).expect("has a default"));
+        let http = HttpOptions {
+            ca_cert: matches.get_one::<PathBuf>("ca_cert").cloned(),
+            insecure: matches.get_flag("insecure_skip_tls_verify"),
+            timeout: secs("http_timeout_secs"),
+            connect_timeout: secs("connect_timeout_secs"),
+        };
+        Ok((T::from_arg_matches(&matches)?, http))
+    }
+
+    /// Argument IDs (derive field names) each configured value is a default for
+    fn defaults(&self) -> Vec<(&'static str, &str)> {
+        let mut defaults = Vec::new();
+        let fields = [
+            (&self.qdrant_url, &["qdrant_url"][..]),
+            (&self.ollama_url, &["ollama_url"][..]),
+            (&self.model, &["model"][..]),
+            (&self.collection, &["collection", "base_collection"][..]),
+            (&self.collection_prefix, &["collection_prefix"][..]),
+            (&self.collection_suffix, &["collection_suffix"][..]),
+        ];
+        for (value, ids) in fields {
+            if let Some(value) = value {
+                defaults.extend(ids.iter().map(|id| (*id, value.as_str())));
+            }
+        }
+        defaults
+    }
+
+    fn apply(&self, mut command: Command) -> Command {
+        for (id, value) in self.defaults() {
+            let has_default = command
+                .get_arguments()
+                .any(|arg| arg.get_id() == id && !arg.get_default_values().is_empty());
+            if has_default {
+                command = command.mut_arg(id, |arg| arg.default_value(value.to_string()));
+            }
+        }
+        let names: Vec<String> = command
+            .get_subcommands()
+            .map(|sub| sub.get_name().to_string())
+            .collect();
+        for name in names {
+            command = command.mut_subcommand(name, |sub| self.apply(sub));
+        }
+        command
+    }
+}
+
+/// `$XDG_CONFIG_HOME/rag-demo/rag.toml`, or `~/.config/rag-demo/rag.toml`
+fn user_config_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("rag-demo").join(CONFIG_FILE_NAME))
+}
+
+fn config_arg() -> Arg {
+    Arg::new("config")
+        .long("config")
+        .global(true)
+        .action(ArgAction::Set)
+        .value_name("PATH")
+        .help("Config file with default URLs, model and collection (default: ./rag.toml, then ~/.config/rag-demo/rag.toml)")
+}
+
+/// `--ca-cert` and `--insecure-skip-tls-verify`, for HTTPS to a Qdrant or
+/// Ollama with a certificate from an internal CA or a self-signed one
+fn tls_args() -> [Arg; 2] {
+    [
+        Arg::new("ca_cert")
+            .long("ca-cert")
+            .global(true)
+            .action(ArgAction::Set)
+            .value_name("PATH")
+            .value_parser(clap::value_parser!(PathBuf))
+            .env("RAG_CA_CERT")
+            .help("PEM file of extra root certificates to trust for HTTPS to Qdrant and Ollama"),
+        Arg::new("insecure_skip_tls_verify")
+            .long("insecure-skip-tls-verify")
+            .global(true)
+            .action(ArgAction::SetTrue)
+            .env("RAG_INSECURE_SKIP_TLS_VERIFY")
+            .help("Accept any TLS certificate, even self-signed or expired ones (never in production)"),
+    ]
+}
+
+/// `--http-timeout-secs` and `--connect-timeout-secs`, so a hung Qdrant or
+/// Ollama fails the run instead of blocking it forever
+fn timeout_args() -> [Arg; 2] {
+    [
+        Arg::new("http_timeout_secs")
+            .long("http-timeout-secs")
+            .global(true)
+            .action(ArgAction::Set)
+            .value_name("SECS")
+            .value_parser(clap::value_parser!(u64).range(1..))
+            .default_value(DEFAULT_HTTP_TIMEOUT.as_secs().to_string())
+            .env("RAG_HTTP_TIMEOUT_SECS")
+            .help("Seconds to wait for any HTTP request to Qdrant or Ollama to complete"),
+        Arg::new("connect_timeout_secs")
+            .long("connect-timeout-secs")
+            .global(true)
+            .action(ArgAction::Set)
+            .value_name("SECS")
+            .value_parser(clap::value_parser!(u64).range(1..))
+            .default_value(DEFAULT_CONNECT_TIMEOUT.as_secs().to_string())
+            .env("RAG_CONNECT_TIMEOUT_SECS")
+            .help("Seconds to wait for a connection to Qdrant or Ollama"),
+    ]
+}
+
+/// Value of `--config` in `args`, looked up before parsing because the
+/// config supplies defaults to the parser
+fn config_flag(args: &[OsString]) -> Option<PathBuf> {
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        let arg = arg.to_str()?;
+        if arg == "--" {
+            return None;
+        }
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+/// Parse the command line as `T` with defaults from the config file, and
+/// apply the TLS and timeout flags to every HTTP client. Use in place of `T::parse()`;
+/// like it, exits on `--help` or a usage error.
+pub fn parse_args<T: Parser>() -> Result<T> {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let config = RagConfig::load(config_flag(&args).as_deref())?;
+    let (parsed, http) = config.parse_with_http(args).unwrap_or_else(|e| e.exit());
+    set_http_options(http)?;
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Subcommand;
+
+    #[derive(Parser, Debug)]
+    struct TestArgs {
+        #[arg(
+            long,
+            default_value = "http://localhost:6333",
+            env = "RAG_CONFIG_TEST_QDRANT_URL"
+        )]
+        qdrant_url: String,
+
+        #[arg(
+            long,
+            default_value = "nomic-embed-text",
+            env = "RAG_CONFIG_TEST_MODEL"
+        )]
+        model: String,
+
+        #[arg(long, default_value = "documents", env = "RAG_CONFIG_TEST_COLLECTION")]
+        collection: String,
+
+        #[arg(long, default_value = "http://localhost:11434")]
+        ollama_url: String,
+
+        #[arg(long, default_value = "")]
+        collection_prefix: String,
+    }
+
+    #[derive(Parser, Debug)]
+    struct NestedArgs {
+        #[command(subcommand)]
+        command: NestedCommand,
+    }
+
+    #[derive(Subcommand, Debug)]
+    enum NestedCommand {
+        Import {
+            #[arg(long)]
+            collection: Option<String>,
+            #[arg(long, default_value = "http://localhost:6333")]
+            qdrant_url: String,
+        },
+    }
+
+    fn config() -> RagConfig {
+        toml::from_str(
+            r#"
+                qdrant_url = "http://qdrant.lan:6333"
+                model = "mxbai-embed-large"
+                collection = "rust-books"
+                ollama_url = "http://gpu.lan:11434"
+                collection_prefix = "acme_"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_cli_beats_env_beats_config_beats_default() {
+        // SAFETY: only this test reads or writes these variables
+        unsafe {
+            std::env::set_var("RAG_CONFIG_TEST_QDRANT_URL", "http://env:6333");
+            std::env::set_var("RAG_CONFIG_TEST_MODEL", "env-model");
+        }
+
+        let args: TestArgs = config()
+            .parse_from(["tool", "--qdrant-url", "http://cli:6333"])
+            .unwrap();
+        let defaults: TestArgs = RagConfig::default().parse_from(["tool"]).unwrap();
+
+        assert_eq!(args.qdrant_url, "http://cli:6333", "flag beats env");
+        assert_eq!(args.model, "env-model", "env beats config");
+        assert_eq!(args.collection, "rust-books", "config beats default");
+        assert_eq!(args.ollama_url, "http://gpu.lan:11434");
+        assert_eq!(args.collection_prefix, "acme_");
+        assert_eq!(defaults.collection, "documents");
+    }
+
+    #[test]
+    fn test_config_reaches_subcommands_but_not_flags_without_defaults() {
+        let args: NestedArgs = config()
+            .parse_from(["tool", "import", "--config", "other.toml"])
+            .unwrap();
+
+        let NestedCommand::Import {
+            collection,
+            qdrant_url,
+        } = args.command;
+        assert_eq!(qdrant_url, "http://qdrant.lan:6333");
+        assert_eq!(collection, None);
+    }
+
+    #[test]
+    fn test_http_flags_are_accepted_by_every_tool() {
+        let (args, http): (NestedArgs, HttpOptions) = RagConfig::default()
+            .parse_with_http([
+                "tool",
+                "import",
+                "--ca-cert",
+                "/etc/ssl/internal-ca.pem",
+                "--insecure-skip-tls-verify",
+                "--http-timeout-secs",
+                "600",
+                "--connect-timeout-secs=3",
+            ])
+            .unwrap();
+        let (_, defaults): (TestArgs, HttpOptions) =
+            RagConfig::default().parse_with_http(["tool"]).unwrap();
+        let zero = RagConfig::default()
+            .parse_with_http::<TestArgs, _>(["tool", "--http-timeout-secs", "0"])
+            .unwrap_err();
+
+        assert!(matches!(args.command, NestedCommand::Import { .. }));
+        assert_eq!(
+            http.ca_cert.as_deref(),
+            Some(Path::new("/etc/ssl/internal-ca.pem"))
+        );
+        assert!(http.insecure);
+        assert_eq!(http.timeout, Duration::from_secs(600));
+        assert_eq!(http.connect_timeout, Duration::from_secs(3));
+        assert_eq!(defaults, HttpOptions::default());
+        assert_eq!(zero.kind(), clap::error::ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn test_config_flag_and_unknown_keys() {
+        let args: Vec<OsString> = ["tool", "query", "--config=team.toml"]
+            .iter()
+            .map(OsString::from)
+            .collect();
+        assert_eq!(config_flag(&args), Some(PathBuf::from("team.toml")));
+        let args: Vec<OsString> = ["tool", "--", "--config", "x"]
+            .iter()
+            .map(OsString::from)
+            .collect();
+        assert_eq!(config_flag(&args), None);
+
+        let err = toml::from_str::<RagConfig>("qdrant-url = \"x\"").unwrap_err();
+        assert!(err.to_string().contains("unknown field"), "{err}");
+
+        let dir = std::env::temp_dir().join(format!("rag-config-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(CONFIG_FILE_NAME);
+        fs::write(&path, "collection = \"notes\"\n").unwrap();
+        assert_eq!(
+            RagConfig::load(Some(&path)).unwrap().collection.as_deref(),
+            Some("notes")
+        );
+        assert!(RagConfig::load(Some(&dir.join("missing.toml"))).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
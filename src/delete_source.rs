@@ -0,0 +1,104 @@
+// Copyright (c) 2025 Michael A. Wright
+// Licensed under the MIT License
+
+//! Delete the points of one ingested document from a collection.
+//!
+//! Lets a collection be kept up to date when a source file is removed or
+//! replaced, without dropping and re-ingesting everything else.
+
+use anyhow::Result;
+use clap::Parser;
+use rag_demo::config::parse_args;
+use rag_demo::rag::{ApiKey, QdrantClient};
+use serde_json::{Value, json};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Delete a document's points from a Qdrant collection", long_about = None)]
+struct Args {
+    #[arg(long, help = "Source of the document, as stored in the payload")]
+    source: String,
+
+    #[arg(
+        long,
+        default_value = "documents",
+        env = "RAG_COLLECTION",
+        help = "Qdrant collection name"
+    )]
+    collection: String,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:6333",
+        env = "QDRANT_URL",
+        help = "Qdrant URL"
+    )]
+    qdrant_url: String,
+
+    #[arg(
+        long,
+        env = "QDRANT_API_KEY",
+        hide_env_values = true,
+        help = "Qdrant API key, sent as the api-key header (Qdrant Cloud)"
+    )]
+    qdrant_api_key: Option<ApiKey>,
+
+    #[arg(
+        long,
+        default_value = "source",
+        help = "Payload field to match (source_path holds the file path when --source-label was used)"
+    )]
+    field: String,
+
+    #[arg(long, help = "Only count the matching points; delete nothing")]
+    dry_run: bool,
+}
+
+/// Filter selecting the points whose payload `field` equals `source`
+fn source_filter(field: &str, source: &str) -> Value {
+    json!({
+        "must": [{
+            "key": field,
+            "match": { "value": source }
+        }]
+    })
+}
+
+fn main() -> Result<()> {
+    let args: Args = parse_args()?;
+    let qdrant = QdrantClient::new(&args.qdrant_url).api_key(args.qdrant_api_key.as_ref());
+    qdrant.require_collection(&args.collection)?;
+
+    let filter = source_filter(&args.field, &args.source);
+    let count = qdrant.count_points(&args.collection, &filter, true)?;
+    println!(
+        "🔍 {} points in '{}' have {} = {}",
+        count, args.collection, args.field, args.source
+    );
+
+    if count == 0 {
+        println!("Nothing to delete");
+        return Ok(());
+    }
+    if args.dry_run {
+        println!("Dry run - no points deleted");
+        return Ok(());
+    }
+
+    qdrant.delete_points(&args.collection, &filter)?;
+    println!("🗑️  Deleted {} points", count);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_filter_matches_field_exactly() {
+        let filter = source_filter("source_path", "./extracted/book.md");
+
+        assert_eq!(filter["must"][0]["key"], "source_path");
+        assert_eq!(filter["must"][0]["match"]["value"], "./extracted/book.md");
+    }
+}
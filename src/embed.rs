@@ -0,0 +1,174 @@
+// Copyright (c) 2025 Michael A. Wright
+// Licensed under the MIT License
+
+//! Embed a single string and print statistics about the vector.
+//!
+//! Useful for checking the dimension and scale a model produces before
+//! pointing it at an existing collection.
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use rag_demo::config::parse_args;
+use rag_demo::rag::{EmbedApi, Embedder, OnOversize, OversizePolicy, RetryPolicy, TokenCounter};
+use rag_demo::vecmath;
+use serde::Serialize;
+use std::io::Read;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Embed one string and print vector statistics as JSON", long_about = None)]
+struct Args {
+    #[arg(help = "Text to embed (reads stdin when omitted)")]
+    text: Option<String>,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:11434",
+        env = "OLLAMA_URL",
+        help = "Ollama URL"
+    )]
+    ollama_url: String,
+
+    #[arg(
+        short,
+        long,
+        default_value = "nomic-embed-text",
+        env = "RAG_MODEL",
+        help = "Embedding model"
+    )]
+    model: String,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "ollama",
+        help = "Embedding API: ollama (/api/embeddings), ollama-embed (/api/embed), openai (/v1/embeddings)"
+    )]
+    embed_api: EmbedApi,
+
+    #[arg(
+        long,
+        default_value = "4",
+        help = "Times to retry a failed embedding request (5xx, 429, connection errors)"
+    )]
+    embed_retries: u32,
+
+    #[arg(
+        long,
+        default_value = "500",
+        help = "Delay before the first embedding retry in ms, doubled on each retry"
+    )]
+    embed_retry_delay_ms: u64,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "split",
+        help = "Text over the embedding token limit: split embeds pieces and averages them, truncate keeps the start, error fails"
+    )]
+    on_oversize: OnOversize,
+
+    #[arg(
+        long,
+        default_value = "2048",
+        help = "Embedding model context in tokens, estimated at 4 characters per token"
+    )]
+    embed_max_tokens: usize,
+
+    #[arg(long, help = "Include the full vector in the output")]
+    full: bool,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+struct EmbeddingStats {
+    model: String,
+    dimension: usize,
+    l2_norm: f32,
+    min: f32,
+    max: f32,
+    mean: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vector: Option<Vec<f32>>,
+}
+
+impl EmbeddingStats {
+    fn from_vector(model: &str, vector: Vec<f32>, full: bool) -> Self {
+        let dimension = vector.len();
+        let l2_norm = vecmath::norm(&vector);
+        let min = vector.iter().copied().fold(f32::INFINITY, f32::min);
+        let max = vector.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let mean = vector.iter().sum::<f32>() / dimension.max(1) as f32;
+
+        Self {
+            model: model.to_string(),
+            dimension,
+            l2_norm,
+            min,
+            max,
+            mean,
+            vector: full.then_some(vector),
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let args: Args = parse_args()?;
+
+    let text = match &args.text {
+        Some(text) => text.clone(),
+        None => {
+            let mut buffer = String::new();
+            std::io::stdin()
+                .read_to_string(&mut buffer)
+                .context("Failed to read text from stdin")?;
+            buffer
+        }
+    };
+
+    let embedder = Embedder::new(
+        &args.ollama_url,
+        args.embed_api,
+        &args.model,
+        RetryPolicy {
+            retries: args.embed_retries,
+            base_delay_ms: args.embed_retry_delay_ms,
+        },
+    )
+    .oversize(OversizePolicy {
+        on_oversize: args.on_oversize,
+        max_tokens: args.embed_max_tokens,
+        counter: TokenCounter::estimate(),
+    });
+    let vector = embedder.embed(&text)?;
+
+    let stats = EmbeddingStats::from_vector(&args.model, vector, args.full);
+    println!("{}", serde_json::to_string_pretty(&stats)?);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_for_known_vector() {
+        let stats = EmbeddingStats::from_vector("test-model", vec![3.0, -4.0, 0.0, 1.0], false);
+
+        assert_eq!(stats.dimension, 4);
+        assert!((stats.l2_norm - 26f32.sqrt()).abs() < 1e-6);
+        assert_eq!(stats.min, -4.0);
+        assert_eq!(stats.max, 3.0);
+        assert_eq!(stats.mean, 0.0);
+        assert!(stats.vector.is_none());
+    }
+
+    #[test]
+    fn test_full_output_includes_vector() {
+        let stats = EmbeddingStats::from_vector("test-model", vec![0.6, 0.8], true);
+        let json = serde_json::to_value(&stats).unwrap();
+
+        assert!((stats.l2_norm - 1.0).abs() < 1e-6);
+        assert_eq!(json["dimension"], 2);
+        assert_eq!(json["vector"].as_array().unwrap().len(), 2);
+    }
+}
@@ -5,6 +5,8 @@
 
 use anyhow::{Context, Result};
 use clap::Parser;
+use rag_demo::config::parse_args;
+use rag_demo::rag::{ApiKey, QdrantClient, qdrant_http_client};
 use reqwest::blocking::Client;
 use serde::{Deserialize, Serialize};
 use std::fs;
@@ -13,15 +15,28 @@ use std::path::PathBuf;
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Export Qdrant collection to JSON", long_about = None)]
 struct Args {
-    #[arg(help = "Collection name to export")]
+    #[arg(env = "RAG_COLLECTION", help = "Collection name to export")]
     collection: String,
 
     #[arg(short, long, help = "Output file path (default: <collection>.json)")]
     output: Option<PathBuf>,
 
-    #[arg(long, default_value = "http://localhost:6333", help = "Qdrant URL")]
+    #[arg(
+        long,
+        default_value = "http://localhost:6333",
+        env = "QDRANT_URL",
+        help = "Qdrant URL"
+    )]
     qdrant_url: String,
 
+    #[arg(
+        long,
+        env = "QDRANT_API_KEY",
+        hide_env_values = true,
+        help = "Qdrant API key, sent as the api-key header (Qdrant Cloud)"
+    )]
+    qdrant_api_key: Option<ApiKey>,
+
     #[arg(
         long,
         help = "Include vectors in export (increases file size significantly)"
@@ -33,6 +48,12 @@ struct Args {
 
     #[arg(long, help = "Batch size for fetching points (default: 100)")]
     batch_size: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Only export points ingested at or after this RFC 3339 time (e.g. 2025-06-01T00:00:00Z)"
+    )]
+    since: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -44,10 +65,13 @@ struct CollectionInfo {
     config: serde_json::Value,
 }
 
+/// An exported point. `vector` is kept as Qdrant returns it: a plain dense
+/// vector, or named vectors such as hierarchical collections' dense and
+/// sparse `text` ones
 #[derive(Debug, Serialize, Deserialize)]
 struct PointData {
     id: String,
-    vector: Option<Vec<f32>>,
+    vector: Option<serde_json::Value>,
     payload: serde_json::Value,
 }
 
@@ -59,19 +83,6 @@ struct ExportData {
     points: Vec<PointData>,
 }
 
-#[derive(Debug, Deserialize)]
-struct QdrantCollectionResponse {
-    result: CollectionResult,
-}
-
-#[derive(Debug, Deserialize)]
-struct CollectionResult {
-    vectors_count: Option<usize>,
-    indexed_vectors_count: Option<usize>,
-    points_count: Option<usize>,
-    config: serde_json::Value,
-}
-
 #[derive(Debug, Deserialize)]
 struct ScrollResponse {
     result: ScrollResult,
@@ -86,38 +97,41 @@ struct ScrollResult {
 #[derive(Debug, Deserialize)]
 struct QdrantPoint {
     id: serde_json::Value,
-    vector: Option<Vec<f32>>,
+    vector: Option<serde_json::Value>,
     payload: Option<serde_json::Value>,
 }
 
-fn get_collection_info(
-    client: &Client,
-    qdrant_url: &str,
-    collection: &str,
-) -> Result<CollectionInfo> {
-    let url = format!("{}/collections/{}", qdrant_url, collection);
-    let response: QdrantCollectionResponse = client
-        .get(&url)
-        .send()
-        .context("Failed to get collection info")?
-        .json()
-        .context("Failed to parse collection info")?;
+fn get_collection_info(qdrant: &QdrantClient, collection: &str) -> Result<CollectionInfo> {
+    let result = qdrant.collection_info(collection)?;
 
     Ok(CollectionInfo {
         name: collection.to_string(),
-        vectors_count: response.result.vectors_count.unwrap_or(0),
-        indexed_vectors_count: response.result.indexed_vectors_count.unwrap_or(0),
-        points_count: response.result.points_count.unwrap_or(0),
-        config: response.result.config,
+        vectors_count: result.vectors_count.unwrap_or(0),
+        indexed_vectors_count: result.indexed_vectors_count.unwrap_or(0),
+        points_count: result.points_count.unwrap_or(0),
+        config: result.config,
     })
 }
 
+/// Scroll filter keeping points whose `ingested_at` stamp is at or after `since`
+fn since_filter(since: &str) -> Result<serde_json::Value> {
+    let since = chrono::DateTime::parse_from_rfc3339(since)
+        .with_context(|| format!("--since must be an RFC 3339 time, got {since:?}"))?;
+    Ok(serde_json::json!({
+        "must": [{
+            "key": "ingested_at",
+            "range": { "gte": since.to_rfc3339() }
+        }]
+    }))
+}
+
 fn export_points(
     client: &Client,
     qdrant_url: &str,
     collection: &str,
     include_vectors: bool,
     batch_size: usize,
+    filter: Option<&serde_json::Value>,
 ) -> Result<Vec<PointData>> {
     let mut all_points = Vec::new();
     let mut offset: Option<String> = None;
@@ -136,6 +150,9 @@ fn export_points(
         if let Some(ref off) = offset {
             request_body["offset"] = serde_json::json!(off);
         }
+        if let Some(filter) = filter {
+            request_body["filter"] = filter.clone();
+        }
 
         let response: ScrollResponse = client
             .post(&url)
@@ -179,12 +196,13 @@ fn export_points(
 }
 
 fn main() -> Result<()> {
-    let args = Args::parse();
-    let client = Client::new();
+    let args: Args = parse_args()?;
+    let client = qdrant_http_client(args.qdrant_api_key.as_ref());
 
     println!("🔍 Fetching collection info for '{}'...", args.collection);
 
-    let collection_info = get_collection_info(&client, &args.qdrant_url, &args.collection)
+    let qdrant = QdrantClient::new(&args.qdrant_url).api_key(args.qdrant_api_key.as_ref());
+    let collection_info = get_collection_info(&qdrant, &args.collection)
         .context("Failed to get collection information")?;
 
     println!("✅ Collection found:");
@@ -196,6 +214,13 @@ fn main() -> Result<()> {
         println!("\n⚠️  Vectors will NOT be included (use --include-vectors to include them)");
     }
 
+    let filter = args.since.as_deref().map(since_filter).transpose()?;
+    if let Some(since) = &args.since {
+        println!(
+            "🕒 Only exporting points ingested since {since} (points without ingested_at are skipped)"
+        );
+    }
+
     let batch_size = args.batch_size.unwrap_or(100);
     let points = export_points(
         &client,
@@ -203,6 +228,7 @@ fn main() -> Result<()> {
         &args.collection,
         args.include_vectors,
         batch_size,
+        filter.as_ref(),
     )?;
 
     let export_data = ExportData {
@@ -274,7 +300,7 @@ mod tests {
     fn test_point_data_with_vectors() {
         let point = PointData {
             id: "test-id".to_string(),
-            vector: Some(vec![0.1, 0.2, 0.3]),
+            vector: Some(json!([0.1, 0.2, 0.3])),
             payload: json!({"text": "Test"}),
         };
 
@@ -283,6 +309,43 @@ mod tests {
         assert_eq!(json["vector"].as_array().unwrap().len(), 3);
     }
 
+    #[test]
+    fn test_scroll_response_keeps_named_and_sparse_vectors() {
+        let response: ScrollResponse = serde_json::from_value(json!({
+            "result": {
+                "points": [
+                    {"id": 1, "payload": {}, "vector": [0.1, 0.2]},
+                    {"id": "p-2", "payload": {}, "vector": {
+                        "": [0.3, 0.4],
+                        "text": {"indices": [7, 9], "values": [1.0, 2.0]}
+                    }}
+                ],
+                "next_page_offset": null
+            }
+        }))
+        .unwrap();
+
+        let points = &response.result.points;
+        assert_eq!(points[0].vector, Some(json!([0.1, 0.2])));
+        assert_eq!(
+            points[1].vector.as_ref().unwrap()["text"]["indices"],
+            json!([7, 9])
+        );
+        assert_eq!(points[1].vector.as_ref().unwrap()[""], json!([0.3, 0.4]));
+    }
+
+    #[test]
+    fn test_since_filter_is_a_datetime_range() {
+        let filter = since_filter("2025-06-01T00:00:00Z").unwrap();
+
+        assert_eq!(filter["must"][0]["key"], "ingested_at");
+        assert_eq!(
+            filter["must"][0]["range"]["gte"],
+            "2025-06-01T00:00:00+00:00"
+        );
+        assert!(since_filter("yesterday").is_err());
+    }
+
     #[test]
     fn test_export_data_structure() {
         let info = CollectionInfo {
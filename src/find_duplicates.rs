@@ -0,0 +1,313 @@
+// Copyright (c) 2025 Michael A. Wright
+// Licensed under the MIT License
+
+//! Find near-duplicate points in a collection, such as the same passage
+//! ingested from two overlapping PDFs, and optionally delete all but one.
+//!
+//! Vectors are compared client-side, every point against every other, so
+//! this suits the collection sizes of this demo rather than millions of points.
+
+use anyhow::Result;
+use clap::Parser;
+use rag_demo::config::parse_args;
+use rag_demo::rag::{ApiKey, QdrantClient, RetrievedPoint, preview, progress_bar};
+use rag_demo::vecmath::{dot, normalize};
+use serde::Serialize;
+use serde_json::{Value, json};
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Report clusters of near-duplicate points in a Qdrant collection",
+    long_about = None
+)]
+struct Args {
+    #[arg(
+        long,
+        default_value = "documents",
+        env = "RAG_COLLECTION",
+        help = "Qdrant collection name"
+    )]
+    collection: String,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:6333",
+        env = "QDRANT_URL",
+        help = "Qdrant URL"
+    )]
+    qdrant_url: String,
+
+    #[arg(
+        long,
+        env = "QDRANT_API_KEY",
+        hide_env_values = true,
+        help = "Qdrant API key, sent as the api-key header (Qdrant Cloud)"
+    )]
+    qdrant_api_key: Option<ApiKey>,
+
+    #[arg(
+        long,
+        default_value = "0.98",
+        help = "Cosine similarity at or above which two points count as duplicates"
+    )]
+    threshold: f32,
+
+    #[arg(
+        long,
+        default_value = "text",
+        help = "Payload field that stores the chunk text"
+    )]
+    text_field: String,
+
+    #[arg(
+        long,
+        default_value = "256",
+        help = "Points fetched per scroll request"
+    )]
+    batch_size: usize,
+
+    #[arg(short, long, help = "Output as JSON")]
+    json: bool,
+
+    #[arg(
+        long,
+        help = "Keep the first point of each cluster and delete the others"
+    )]
+    delete_duplicates: bool,
+
+    #[arg(
+        long,
+        help = "Hide progress bars (also hidden when stdout is not a terminal)"
+    )]
+    quiet: bool,
+}
+
+/// A point kept as the representative of a cluster, and its near-duplicates
+/// with their similarity to it
+#[derive(Debug, PartialEq)]
+struct Cluster {
+    keep: usize,
+    duplicates: Vec<(usize, f32)>,
+}
+
+/// Group points by similarity to a representative: in order, each point not
+/// yet in a cluster collects every later unclustered point with cosine
+/// similarity of at least `threshold` to it. Unlike chaining neighbours
+/// together, every duplicate is close to the point that is kept. Clusters of
+/// one are left out.
+fn cluster(vectors: &[Vec<f32>], threshold: f32, mut compared: impl FnMut()) -> Vec<Cluster> {
+    // Unit vectors make each comparison a dot product
+    let units: Vec<Vec<f32>> = vectors.iter().map(|v| normalize(v)).collect();
+    let mut clustered = vec![false; units.len()];
+    let mut clusters = Vec::new();
+    for keep in 0..units.len() {
+        compared();
+        if clustered[keep] {
+            continue;
+        }
+        let duplicates: Vec<(usize, f32)> = (keep + 1..units.len())
+            .filter(|&other| !clustered[other])
+            .map(|other| (other, dot(&units[keep], &units[other])))
+            // A NaN from vectors of different sizes never passes
+            .filter(|&(_, similarity)| similarity >= threshold)
+            .collect();
+        if duplicates.is_empty() {
+            continue;
+        }
+        for &(other, _) in &duplicates {
+            clustered[other] = true;
+        }
+        clusters.push(Cluster { keep, duplicates });
+    }
+    clusters.sort_by_key(|c| std::cmp::Reverse(c.duplicates.len()));
+    clusters
+}
+
+#[derive(Debug, Serialize)]
+struct PointSummary<'a> {
+    id: &'a str,
+    source: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    similarity: Option<f32>,
+}
+
+fn summary<'a>(point: &'a RetrievedPoint, similarity: Option<f32>) -> PointSummary<'a> {
+    PointSummary {
+        id: &point.id,
+        source: point
+            .payload
+            .get("source")
+            .and_then(|v| v.as_str())
+            .unwrap_or("(unknown)"),
+        similarity,
+    }
+}
+
+fn clusters_json(clusters: &[Cluster], points: &[RetrievedPoint]) -> Value {
+    let clusters: Vec<Value> = clusters
+        .iter()
+        .map(|c| {
+            let duplicates: Vec<PointSummary> = c
+                .duplicates
+                .iter()
+                .map(|&(i, similarity)| summary(&points[i], Some(similarity)))
+                .collect();
+            json!({ "keep": summary(&points[c.keep], None), "duplicates": duplicates })
+        })
+        .collect();
+    json!(clusters)
+}
+
+fn main() -> Result<()> {
+    let args: Args = parse_args()?;
+    if args.batch_size == 0 {
+        anyhow::bail!("--batch-size must be greater than zero");
+    }
+    if !(-1.0..=1.0).contains(&args.threshold) {
+        anyhow::bail!("--threshold must be a cosine similarity between -1 and 1");
+    }
+    let qdrant = QdrantClient::new(&args.qdrant_url).api_key(args.qdrant_api_key.as_ref());
+    qdrant.require_collection(&args.collection)?;
+
+    let mut points = Vec::new();
+    let mut vectors = Vec::new();
+    qdrant.scroll_vectors(
+        &args.collection,
+        &["source", &args.text_field],
+        args.batch_size,
+        |page| {
+            for (point, vector) in page {
+                points.push(point);
+                vectors.push(vector);
+            }
+            Ok(())
+        },
+    )?;
+
+    let bar = progress_bar(points.len(), "Comparing", args.quiet || args.json);
+    let clusters = cluster(&vectors, args.threshold, || bar.inc(1));
+    bar.finish_and_clear();
+    let redundant: Vec<String> = clusters
+        .iter()
+        .flat_map(|c| c.duplicates.iter().map(|&(i, _)| points[i].id.clone()))
+        .collect();
+
+    if args.json {
+        let output = json!({
+            "collection": args.collection,
+            "threshold": args.threshold,
+            "points": points.len(),
+            "redundant": redundant.len(),
+            "clusters": clusters_json(&clusters, &points),
+        });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        println!(
+            "🔍 Scanned {} points in '{}': {} clusters of near-duplicates (similarity >= {}), {} redundant points",
+            points.len(),
+            args.collection,
+            clusters.len(),
+            args.threshold,
+            redundant.len()
+        );
+        for (n, c) in clusters.iter().enumerate() {
+            println!(
+                "\n--- Cluster {} ({} points) ---",
+                n + 1,
+                c.duplicates.len() + 1
+            );
+            let keep = &points[c.keep];
+            let text = keep
+                .payload
+                .get(&args.text_field)
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            println!("{}", preview(text, 200));
+            let kept = summary(keep, None);
+            println!("  keep       {}  {}", kept.id, kept.source);
+            for &(i, similarity) in &c.duplicates {
+                let duplicate = summary(&points[i], None);
+                println!(
+                    "  dup {similarity:.4} {}  {}",
+                    duplicate.id, duplicate.source
+                );
+            }
+        }
+    }
+
+    if !args.delete_duplicates || redundant.is_empty() {
+        return Ok(());
+    }
+    for ids in redundant.chunks(args.batch_size) {
+        qdrant.delete_point_ids(&args.collection, ids)?;
+    }
+    if !args.json {
+        println!(
+            "\n🗑️  Deleted {} duplicate points, kept one per cluster",
+            redundant.len()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clusters_gather_duplicates_of_each_representative() {
+        let vectors = vec![
+            vec![1.0, 0.0, 0.0],
+            vec![0.0, 1.0, 0.0],
+            vec![2.0, 0.01, 0.0],
+            vec![0.0, 0.99, 0.01],
+            vec![0.0, 0.0, 1.0],
+            vec![1.0, 0.0, 0.0],
+            vec![1.0, 0.0],
+        ];
+        let mut compared = 0;
+
+        let clusters = cluster(&vectors, 0.98, || compared += 1);
+
+        assert_eq!(compared, vectors.len());
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].keep, 0);
+        let members: Vec<usize> = clusters[0].duplicates.iter().map(|&(i, _)| i).collect();
+        assert_eq!(
+            members,
+            vec![2, 5],
+            "a vector of another size never matches"
+        );
+        assert_eq!(clusters[1].keep, 1);
+        assert_eq!(clusters[1].duplicates[0].0, 3);
+        assert!(clusters[1].duplicates[0].1 > 0.99);
+    }
+
+    #[test]
+    fn test_clusters_json_names_kept_and_duplicate_points() {
+        let points: Vec<RetrievedPoint> = ["a", "b"]
+            .iter()
+            .map(|id| RetrievedPoint {
+                id: id.to_string(),
+                payload: json!({"source": format!("{id}.pdf")}),
+            })
+            .collect();
+        let clusters = vec![Cluster {
+            keep: 0,
+            duplicates: vec![(1, 0.99)],
+        }];
+
+        let value = clusters_json(&clusters, &points);
+
+        assert_eq!(
+            value,
+            json!([{
+                "keep": {"id": "a", "source": "a.pdf"},
+                "duplicates": [{"id": "b", "source": "b.pdf", "similarity": 0.99_f32}]
+            }])
+        );
+    }
+}
@@ -6,11 +6,19 @@
 //! This binary performs both semantic (vector) search and keyword-based search,
 //! then combines the results for better precision and recall.
 
-use anyhow::{Context, Result};
+use anyhow::Result;
 use clap::Parser;
-use reqwest::blocking::Client;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use rag_demo::config::parse_args;
+use rag_demo::rag::{
+    ApiKey, Consistency, Distance, EmbedApi, Embedder, EmbeddingCache, Highlight, OutputFormat,
+    QdrantClient, RetrievedPoint, RetryPolicy, SNIPPET_RADIUS, SearchResult, SparseVector,
+    build_filter, markdown_result, namespaced_collection, preflight, query_terms, snippet,
+};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Hybrid search: vector + keyword matching", long_about = None)]
@@ -21,19 +29,61 @@ struct Args {
     #[arg(short, long, default_value = "10", help = "Number of results")]
     limit: usize,
 
-    #[arg(long, default_value = "documents", help = "Collection name")]
+    #[arg(
+        long,
+        default_value = "documents",
+        env = "RAG_COLLECTION",
+        help = "Collection name"
+    )]
     collection: String,
 
-    #[arg(long, default_value = "http://localhost:6333", help = "Qdrant URL")]
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_PREFIX",
+        help = "Prepended to the collection name, e.g. acme_ to keep one tenant's collections apart"
+    )]
+    collection_prefix: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_SUFFIX",
+        help = "Appended to the collection name"
+    )]
+    collection_suffix: String,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:6333",
+        env = "QDRANT_URL",
+        help = "Qdrant URL"
+    )]
     qdrant_url: String,
 
-    #[arg(long, default_value = "http://localhost:11434", help = "Ollama URL")]
+    #[arg(
+        long,
+        env = "QDRANT_API_KEY",
+        hide_env_values = true,
+        help = "Qdrant API key, sent as the api-key header (Qdrant Cloud)"
+    )]
+    qdrant_api_key: Option<ApiKey>,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:11434",
+        env = "OLLAMA_URL",
+        help = "Ollama URL"
+    )]
     ollama_url: String,
 
     #[arg(
         short,
         long,
         default_value = "nomic-embed-text",
+        env = "RAG_MODEL",
         help = "Model for embeddings"
     )]
     model: String,
@@ -44,34 +94,143 @@ struct Args {
     #[arg(long, default_value = "0.3", help = "Keyword search weight (0.0-1.0)")]
     keyword_weight: f32,
 
-    #[arg(long, help = "Output as JSON")]
+    #[arg(
+        long,
+        help = "Rescale --vector-weight and --keyword-weight to sum to 1.0 (0.9 and 0.9 become 0.5 and 0.5)"
+    )]
+    normalize_weights: bool,
+
+    #[arg(
+        long,
+        default_value = "1.2",
+        help = "BM25 k1: how quickly repeats of a query term stop raising the keyword score"
+    )]
+    bm25_k1: f32,
+
+    #[arg(
+        long,
+        default_value = "0.75",
+        help = "BM25 b: how much longer chunks are penalised, from 0 (not at all) to 1"
+    )]
+    bm25_b: f32,
+
+    #[arg(
+        long,
+        conflicts_with = "sparse",
+        help = "Let a query term match document words a few edits away (typos like asyncronous), scored lower the further they are"
+    )]
+    fuzzy: bool,
+
+    #[arg(
+        long,
+        default_value = "2",
+        requires = "fuzzy",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Most edits (Levenshtein distance) between a term and a word --fuzzy matches; short terms allow fewer"
+    )]
+    fuzzy_distance: u32,
+
+    #[arg(long, help = "Output as JSON (same as --format json)")]
     json: bool,
 
-    #[arg(long, help = "Filter by metadata field (format: key=value)")]
+    #[arg(
+        long,
+        value_enum,
+        default_value = "text",
+        help = "Output format: text (human-readable), json, or markdown (numbered blockquotes for pasting into docs)"
+    )]
+    format: OutputFormat,
+
+    #[arg(
+        long,
+        help = "Filter by metadata field: key=value, key=a|b|c (any of), or key>=N, key<=N, key>N, key<N"
+    )]
     filter: Option<Vec<String>>,
-}
 
-#[derive(Debug, Serialize, Deserialize)]
-struct EmbeddingRequest {
-    model: String,
-    prompt: String,
-}
+    #[arg(
+        long,
+        help = "Leave out points matching this condition, same syntax as --filter (repeatable)"
+    )]
+    exclude: Vec<String>,
 
-#[derive(Debug, Deserialize)]
-struct EmbeddingResponse {
-    embedding: Vec<f32>,
-}
+    #[arg(
+        long,
+        default_value = "text",
+        help = "Payload field that stores the chunk text"
+    )]
+    text_field: String,
 
-#[derive(Debug, Deserialize)]
-struct QdrantSearchResponse {
-    result: Vec<SearchResult>,
-}
+    #[arg(
+        long,
+        help = "Like --json, but compact and errors are reported as JSON too"
+    )]
+    strict_json: bool,
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
-struct SearchResult {
-    id: String,
-    score: f32,
-    payload: serde_json::Value,
+    #[arg(
+        long,
+        value_enum,
+        default_value = "ollama",
+        help = "Embedding API: ollama (/api/embeddings), ollama-embed (/api/embed), openai (/v1/embeddings)"
+    )]
+    embed_api: EmbedApi,
+
+    #[arg(
+        long,
+        help = "Skip checking that Qdrant, Ollama and the embedding model are available"
+    )]
+    skip_checks: bool,
+
+    #[arg(
+        long,
+        help = "Fail instead of warning when the collection was embedded with a model other than --model"
+    )]
+    strict: bool,
+
+    #[arg(
+        long,
+        default_value = "4",
+        help = "Times to retry a failed embedding request (5xx, 429, connection errors)"
+    )]
+    embed_retries: u32,
+
+    #[arg(
+        long,
+        default_value = "500",
+        help = "Delay before the first embedding retry in ms, doubled on each retry"
+    )]
+    embed_retry_delay_ms: u64,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "eventual",
+        help = "strong waits for upserts to be applied and searches exactly, so just-ingested points are always found"
+    )]
+    consistency: Consistency,
+
+    #[arg(
+        long,
+        help = "Directory for cached query embeddings (default: ~/.cache/rag-demo)"
+    )]
+    cache_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Always ask the embedding server instead of using the cache"
+    )]
+    no_cache: bool,
+
+    #[arg(
+        long,
+        help = "Score keywords with Qdrant's sparse \"text\" index (collections from ingest-hierarchical) instead of in-process"
+    )]
+    sparse: bool,
+
+    #[arg(
+        long,
+        help = "Drop results whose combined score is below this (vector cosine and keyword scores are 0-1, so is their weighted sum)"
+    )]
+    min_score: Option<f32>,
 }
 
 #[derive(Debug, Serialize)]
@@ -83,133 +242,190 @@ struct HybridSearchResult {
     payload: serde_json::Value,
 }
 
-fn get_embedding(client: &Client, ollama_url: &str, model: &str, text: &str) -> Result<Vec<f32>> {
-    let request = EmbeddingRequest {
-        model: model.to_string(),
-        prompt: text.to_string(),
-    };
-
-    let response = client
-        .post(format!("{}/api/embeddings", ollama_url))
-        .json(&request)
-        .send()
-        .context("Failed to get embedding from Ollama")?;
+/// Keyword candidates fetched per requested result
+const KEYWORD_SCAN_FACTOR: usize = 10;
+
+/// BM25 parameters: `k1` is how quickly repeats of a term stop adding to the
+/// score, `b` how much longer texts are penalised (0 = not at all). With
+/// `fuzzy`, words up to that many edits from a term count as partial matches.
+#[derive(Debug, Clone, Copy)]
+struct Bm25 {
+    k1: f32,
+    b: f32,
+    fuzzy: Option<usize>,
+}
 
-    if !response.status().is_success() {
-        anyhow::bail!("Ollama returned error: {}", response.status());
+/// How much `word` counts as an occurrence of `term`: 1 for the term itself
+/// and, within `fuzzy` edits, less the more edits apart they are. A term
+/// allows one edit per four characters, so "cat" never matches "car".
+fn match_weight(term: &str, word: &str, fuzzy: Option<usize>) -> Option<f32> {
+    if term == word {
+        return Some(1.0);
     }
-
-    let embedding: EmbeddingResponse = response
-        .json()
-        .context("Failed to parse embedding response")?;
-
-    Ok(embedding.embedding)
+    let max_distance = fuzzy?;
+    let allowed = max_distance.min(term.chars().count() / 4);
+    if allowed == 0 || term.chars().count().abs_diff(word.chars().count()) > allowed {
+        return None;
+    }
+    let distance = strsim::levenshtein(term, word);
+    (distance <= allowed).then(|| 1.0 - distance as f32 / (max_distance + 1) as f32)
 }
 
-fn vector_search(
-    client: &Client,
-    qdrant_url: &str,
-    collection: &str,
-    embedding: Vec<f32>,
-    limit: usize,
-    filter: Option<&serde_json::Value>,
-) -> Result<Vec<SearchResult>> {
-    let mut request_body = serde_json::json!({
-        "vector": embedding,
-        "limit": limit * 2, // Fetch more for hybrid merging
-        "with_payload": true,
-    });
-
-    if let Some(f) = filter {
-        request_body["filter"] = f.clone();
+/// Occurrences of `term` in a text's word counts, fuzzy matches weighted down
+fn term_frequency(term: &str, counts: &HashMap<String, usize>, fuzzy: Option<usize>) -> f32 {
+    if fuzzy.is_none() {
+        return counts.get(term).copied().unwrap_or(0) as f32;
     }
-
-    let url = format!("{}/collections/{}/points/search", qdrant_url, collection);
-
-    let response: QdrantSearchResponse = client
-        .post(&url)
-        .json(&request_body)
-        .send()
-        .context("Failed to search Qdrant")?
-        .json()
-        .context("Failed to parse search response")?;
-
-    Ok(response.result)
+    counts
+        .iter()
+        .filter_map(|(word, &count)| {
+            match_weight(term, word, fuzzy).map(|weight| weight * count as f32)
+        })
+        .sum()
 }
 
-fn keyword_score(query: &str, text: &str) -> f32 {
-    let query_lower = query.to_lowercase();
-    let text_lower = text.to_lowercase();
+/// Lowercased runs of letters and digits in `text`
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
 
-    // Extract query terms
-    let query_terms: Vec<&str> = query_lower
-        .split_whitespace()
-        .filter(|t| t.len() > 2) // Ignore very short words
+/// BM25 score of each of `texts` for the query's terms, divided by the best
+/// score so the results fall in 0-1 like normalized vector scores. Document
+/// frequencies and the average length come from `texts` themselves, the
+/// search's candidates, so a term found in only a few of them counts for
+/// more than one found in all.
+fn keyword_scores(query: &str, texts: &[&str], bm25: Bm25) -> Vec<f32> {
+    let mut terms: Vec<String> = query_terms(query)
+        .iter()
+        .flat_map(|term| words(term).collect::<Vec<_>>())
         .collect();
+    terms.sort();
+    terms.dedup();
 
-    if query_terms.is_empty() {
-        return 0.0;
-    }
-
-    let mut score = 0.0;
-    let text_words: Vec<&str> = text_lower.split_whitespace().collect();
-    let text_len = text_words.len() as f32;
-
-    // Calculate TF (term frequency) for each query term
-    for term in &query_terms {
-        let count = text_lower.matches(term).count() as f32;
-
-        if count > 0.0 {
-            // TF component: log-scaled frequency
-            let tf = (1.0 + count.ln()) / (1.0 + text_len.ln());
-
-            // Boost for exact phrase matches
-            let phrase_boost = if text_lower.contains(&query_lower) {
-                2.0
-            } else {
-                1.0
-            };
-
-            // Boost for term at start of text
-            let position_boost = if text_lower.starts_with(term) {
-                1.5
+    let docs: Vec<HashMap<String, usize>> = texts
+        .iter()
+        .map(|text| {
+            let mut counts = HashMap::new();
+            for word in words(text) {
+                *counts.entry(word).or_insert(0) += 1;
+            }
+            counts
+        })
+        .collect();
+    let lengths: Vec<f32> = docs
+        .iter()
+        .map(|counts| counts.values().sum::<usize>() as f32)
+        .collect();
+    let average_length = lengths.iter().sum::<f32>() / lengths.len().max(1) as f32;
+    let total_docs = docs.len() as f32;
+
+    let mut scores = vec![0.0; docs.len()];
+    for term in &terms {
+        let tfs: Vec<f32> = docs
+            .iter()
+            .map(|counts| term_frequency(term, counts, bm25.fuzzy))
+            .collect();
+        let doc_freq = tfs.iter().filter(|&&tf| tf > 0.0).count() as f32;
+        if doc_freq == 0.0 {
+            continue;
+        }
+        // The "+ 1" form of IDF stays positive for terms in most documents
+        let idf = (1.0 + (total_docs - doc_freq + 0.5) / (doc_freq + 0.5)).ln();
+        for ((score, &tf), &length) in scores.iter_mut().zip(&tfs).zip(&lengths) {
+            if tf == 0.0 {
+                continue;
+            }
+            let length_norm = if average_length > 0.0 {
+                1.0 - bm25.b + bm25.b * length / average_length
             } else {
                 1.0
             };
+            *score += idf * tf * (bm25.k1 + 1.0) / (tf + bm25.k1 * length_norm);
+        }
+    }
 
-            score += tf * phrase_boost * position_boost;
+    let best = scores.iter().copied().fold(0.0, f32::max);
+    if best > 0.0 {
+        for score in &mut scores {
+            *score /= best;
         }
     }
+    scores
+}
+
+/// Filter for points whose text contains any query term, as typed or
+/// lowercased, within the user's `--filter` conditions
+fn keyword_filter(
+    query: &str,
+    text_field: &str,
+    filter: Option<&serde_json::Value>,
+) -> Option<serde_json::Value> {
+    // Same terms as keyword_scores; Qdrant's substring match is case-sensitive without a text index
+    let mut terms: Vec<String> = query
+        .split_whitespace()
+        .filter(|t| t.len() > 2)
+        .flat_map(|t| [t.to_string(), t.to_lowercase()])
+        .collect();
+    terms.sort();
+    terms.dedup();
+    if terms.is_empty() {
+        return None;
+    }
 
-    // Normalize by query length
-    score / query_terms.len() as f32
+    let mut keyword_filter = filter.cloned().unwrap_or_else(|| json!({}));
+    keyword_filter["should"] = terms
+        .iter()
+        .map(|term| json!({"key": text_field, "match": {"text": term}}))
+        .collect();
+    Some(keyword_filter)
 }
 
+/// Combine vector hits with keyword scores. `keyword_candidates` are text
+/// matches the vector search may have missed; those enter with a vector score of 0.
 fn hybrid_search(
     query: &str,
     vector_results: Vec<SearchResult>,
+    keyword_candidates: Vec<RetrievedPoint>,
     vector_weight: f32,
     keyword_weight: f32,
+    text_field: &str,
+    bm25: Bm25,
 ) -> Vec<HybridSearchResult> {
     let mut results_map: HashMap<String, HybridSearchResult> = HashMap::new();
 
     // Normalize vector scores (0-1 range)
-    let max_vector_score = vector_results
+    let max_vector_score = max_score(&vector_results);
+
+    // Keyword matches the vector search missed enter with a vector score of 0
+    let vector_ids: HashSet<&str> = vector_results.iter().map(|r| r.id.as_str()).collect();
+    let mut seen = HashSet::new();
+    let keyword_candidates: Vec<RetrievedPoint> = keyword_candidates
+        .into_iter()
+        .filter(|c| !vector_ids.contains(c.id.as_str()) && seen.insert(c.id.clone()))
+        .collect();
+
+    // BM25 over every candidate, so document frequencies reflect the whole set
+    let text_of = |payload: &serde_json::Value| -> String {
+        payload
+            .get(text_field)
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string()
+    };
+    let texts: Vec<String> = vector_results
         .iter()
-        .map(|r| r.score)
-        .max_by(|a, b| a.partial_cmp(b).unwrap())
-        .unwrap_or(1.0);
+        .map(|r| text_of(&r.payload))
+        .chain(keyword_candidates.iter().map(|c| text_of(&c.payload)))
+        .collect();
+    let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+    let mut keyword_scores = keyword_scores(query, &texts, bm25).into_iter();
 
     // Process vector results
     for result in vector_results {
-        let text = result
-            .payload
-            .get("text")
-            .and_then(|v| v.as_str())
-            .unwrap_or("");
-
-        let keyword_score_val = keyword_score(query, text);
-        let normalized_vector_score = result.score / max_vector_score;
+        let keyword_score_val = keyword_scores.next().unwrap_or(0.0);
+        let normalized_vector_score = normalize(result.score, max_vector_score);
 
         let combined_score =
             (normalized_vector_score * vector_weight) + (keyword_score_val * keyword_weight);
@@ -226,74 +442,249 @@ fn hybrid_search(
         );
     }
 
+    for candidate in keyword_candidates {
+        let keyword_score_val = keyword_scores.next().unwrap_or(0.0);
+
+        results_map.insert(
+            candidate.id.clone(),
+            HybridSearchResult {
+                id: candidate.id,
+                vector_score: 0.0,
+                keyword_score: keyword_score_val,
+                combined_score: keyword_score_val * keyword_weight,
+                payload: candidate.payload,
+            },
+        );
+    }
+
     // Convert to vec and sort by combined score
     let mut results: Vec<HybridSearchResult> = results_map.into_values().collect();
-    results.sort_by(|a, b| b.combined_score.partial_cmp(&a.combined_score).unwrap());
+    results.sort_by(|a, b| b.combined_score.total_cmp(&a.combined_score));
 
     results
 }
 
-fn build_filter(filter_args: &[String]) -> Result<serde_json::Value> {
-    let mut must_conditions = Vec::new();
+/// Highest score in `results`, used to bring each result list into 0-1
+fn max_score(results: &[SearchResult]) -> f32 {
+    results
+        .iter()
+        .map(|r| r.score)
+        .max_by(f32::total_cmp)
+        .unwrap_or(1.0)
+}
+
+/// Dense scores as similarities, so that in Euclid collections, whose scores
+/// are distances, the closest match normalizes to the top like everywhere else
+fn dense_similarities(mut results: Vec<SearchResult>, distance: Distance) -> Vec<SearchResult> {
+    for result in &mut results {
+        result.score = distance.similarity(result.score);
+    }
+    results
+}
 
-    for filter_str in filter_args {
-        let parts: Vec<&str> = filter_str.splitn(2, '=').collect();
-        if parts.len() != 2 {
-            anyhow::bail!("Invalid filter format: '{}'. Use key=value", filter_str);
-        }
+/// `score` divided by the best score `max`; scores are left as they are when
+/// `max` is not positive (all-zero sparse scores, or Dot products), where
+/// dividing would give NaN or flip the order
+fn normalize(score: f32, max: f32) -> f32 {
+    if max > 0.0 { score / max } else { score }
+}
+
+/// Merge dense and sparse search results, each normalized by its best score.
+/// A point missing from one list scores zero on that side.
+fn fuse_sparse(
+    vector_results: Vec<SearchResult>,
+    sparse_results: Vec<SearchResult>,
+    vector_weight: f32,
+    keyword_weight: f32,
+) -> Vec<HybridSearchResult> {
+    let max_vector_score = max_score(&vector_results);
+    let max_sparse_score = max_score(&sparse_results);
+    let mut results_map: HashMap<String, HybridSearchResult> = HashMap::new();
+
+    for result in vector_results {
+        results_map.insert(
+            result.id.clone(),
+            HybridSearchResult {
+                id: result.id,
+                vector_score: result.score,
+                keyword_score: 0.0,
+                combined_score: normalize(result.score, max_vector_score) * vector_weight,
+                payload: result.payload,
+            },
+        );
+    }
+
+    for result in sparse_results {
+        let keyword_part = normalize(result.score, max_sparse_score) * keyword_weight;
+        let entry = results_map
+            .entry(result.id.clone())
+            .or_insert(HybridSearchResult {
+                id: result.id,
+                vector_score: 0.0,
+                keyword_score: 0.0,
+                combined_score: 0.0,
+                payload: result.payload,
+            });
+        entry.keyword_score = result.score;
+        entry.combined_score += keyword_part;
+    }
 
-        let (key, value) = (parts[0], parts[1]);
+    let mut results: Vec<HybridSearchResult> = results_map.into_values().collect();
+    results.sort_by(|a, b| b.combined_score.total_cmp(&a.combined_score));
+
+    results
+}
 
-        // Try to parse as bool
-        let filter_value = if value == "true" || value == "false" {
-            serde_json::json!({
-                "key": key,
-                "match": {"value": value == "true"}
-            })
+impl Args {
+    /// `--format`, which `--json` and `--strict-json` set to JSON
+    fn output_format(&self) -> OutputFormat {
+        if self.json || self.strict_json {
+            OutputFormat::Json
         } else {
-            // Treat as string
-            serde_json::json!({
-                "key": key,
-                "match": {"value": value}
-            })
-        };
+            self.format
+        }
+    }
+}
 
-        must_conditions.push(filter_value);
+/// Results as a markdown list for `--format markdown`
+fn write_markdown(
+    out: &mut impl Write,
+    query: &str,
+    results: &[HybridSearchResult],
+    text_field: &str,
+) -> Result<()> {
+    writeln!(out, "### Hybrid search results for: {query}\n")?;
+    if results.is_empty() {
+        writeln!(out, "_No results._")?;
+    }
+    let terms = query_terms(query);
+    for (i, result) in results.iter().enumerate() {
+        let text = result
+            .payload
+            .get(text_field)
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+        let source = result
+            .payload
+            .get("source")
+            .and_then(|v| v.as_str())
+            .unwrap_or("(unknown)");
+        let score = format!(
+            "{:.3} (vector {:.3}, keyword {:.3})",
+            result.combined_score, result.vector_score, result.keyword_score
+        );
+        writeln!(
+            out,
+            "{}",
+            markdown_result(
+                i + 1,
+                &snippet(text, &terms, SNIPPET_RADIUS, Highlight::Markdown),
+                source,
+                &score
+            )
+        )?;
     }
+    Ok(())
+}
 
-    Ok(serde_json::json!({
-        "must": must_conditions
-    }))
+/// Serialize output for `--json` (pretty) or `--strict-json` (single line)
+fn to_json_string<T: Serialize>(value: &T, strict: bool) -> Result<String> {
+    Ok(if strict {
+        serde_json::to_string(value)?
+    } else {
+        serde_json::to_string_pretty(value)?
+    })
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+/// The vector and keyword weights to use: negative weights are rejected, and
+/// with `normalize` the two are rescaled to sum to 1.0
+fn resolve_weights(vector_weight: f32, keyword_weight: f32, normalize: bool) -> Result<(f32, f32)> {
+    for (flag, weight) in [
+        ("--vector-weight", vector_weight),
+        ("--keyword-weight", keyword_weight),
+    ] {
+        if !(weight >= 0.0 && weight.is_finite()) {
+            anyhow::bail!("{flag} must be a number of at least zero, got {weight}");
+        }
+    }
+    let total_weight = vector_weight + keyword_weight;
+    if !normalize {
+        return Ok((vector_weight, keyword_weight));
+    }
+    if total_weight == 0.0 {
+        anyhow::bail!("--vector-weight and --keyword-weight cannot both be zero");
+    }
+    Ok((vector_weight / total_weight, keyword_weight / total_weight))
+}
 
-    // Validate weights
-    let total_weight = args.vector_weight + args.keyword_weight;
+fn run(args: &Args) -> Result<()> {
+    let (vector_weight, keyword_weight) = resolve_weights(
+        args.vector_weight,
+        args.keyword_weight,
+        args.normalize_weights,
+    )?;
+    if !(args.bm25_k1 >= 0.0 && args.bm25_k1.is_finite()) {
+        anyhow::bail!("--bm25-k1 must be at least zero");
+    }
+    if !(0.0..=1.0).contains(&args.bm25_b) {
+        anyhow::bail!("--bm25-b must be between 0 and 1");
+    }
+    let total_weight = vector_weight + keyword_weight;
     if (total_weight - 1.0).abs() > 0.01 {
         eprintln!(
-            "⚠️  Warning: Weights don't sum to 1.0 ({}). Continuing anyway...",
+            "⚠️  Warning: Weights don't sum to 1.0 ({}). Continuing anyway; --normalize-weights rescales them",
             total_weight
         );
     }
 
-    let client = Client::new();
+    if !args.skip_checks {
+        preflight(
+            &args.qdrant_url,
+            &args.ollama_url,
+            &args.model,
+            args.embed_api,
+        )?;
+    }
+    let embedder = Embedder::new(
+        &args.ollama_url,
+        args.embed_api,
+        &args.model,
+        RetryPolicy {
+            retries: args.embed_retries,
+            base_delay_ms: args.embed_retry_delay_ms,
+        },
+    )
+    .cache(EmbeddingCache::from_flags(
+        args.cache_dir.as_deref(),
+        args.no_cache,
+    ));
+    let qdrant = QdrantClient::new(&args.qdrant_url)
+        .api_key(args.qdrant_api_key.as_ref())
+        .consistency(args.consistency);
+    qdrant.require_collection(&args.collection)?;
+    qdrant.check_embed_model(&args.collection, &args.model, args.strict)?;
+    let info = qdrant.collection_info(&args.collection)?;
+    let distance = info.distance_metric();
+    let qdrant = qdrant.vector_name(info.default_vector_name());
+    let format = args.output_format();
+    // Progress lines would corrupt JSON and clutter markdown meant for pasting
+    let show_progress = format == OutputFormat::Text;
 
     // Build filter if provided
-    let filter = if let Some(ref filter_args) = args.filter {
-        Some(build_filter(filter_args)?)
-    } else {
+    let filter_args = args.filter.as_deref().unwrap_or_default();
+    let filter = if filter_args.is_empty() && args.exclude.is_empty() {
         None
+    } else {
+        Some(build_filter(filter_args, &args.exclude)?)
     };
 
-    if !args.json {
+    if show_progress {
         println!("🔍 Hybrid Search: Vector + Keyword");
         println!("   Query: {}", args.query);
         println!(
             "   Weights: {:.0}% vector, {:.0}% keyword",
-            args.vector_weight * 100.0,
-            args.keyword_weight * 100.0
+            vector_weight * 100.0,
+            keyword_weight * 100.0
         );
         if let Some(ref f) = filter {
             println!("   Filter: {:?}", f);
@@ -302,37 +693,88 @@ fn main() -> Result<()> {
     }
 
     // Step 1: Get query embedding
-    let embedding = get_embedding(&client, &args.ollama_url, &args.model, &args.query)?;
+    let embedding = embedder.embed(&args.query)?;
 
     // Step 2: Perform vector search
-    let vector_results = vector_search(
-        &client,
-        &args.qdrant_url,
-        &args.collection,
-        embedding,
-        args.limit,
-        filter.as_ref(),
-    )?;
+    // Fetch more than needed for hybrid merging
+    let vector_results = dense_similarities(
+        qdrant.search(
+            &args.collection,
+            &embedding,
+            args.limit * 2,
+            filter.as_ref(),
+        )?,
+        distance,
+    );
 
-    if !args.json {
+    if show_progress {
         println!("📊 Vector search found {} results", vector_results.len());
     }
 
     // Step 3: Combine with keyword scoring
-    let hybrid_results = hybrid_search(
-        &args.query,
-        vector_results,
-        args.vector_weight,
-        args.keyword_weight,
-    );
+    let hybrid_results = if args.sparse {
+        let sparse_results = qdrant.search_sparse(
+            &args.collection,
+            &SparseVector::from_text(&args.query),
+            args.limit * 2,
+            filter.as_ref(),
+        )?;
+        if show_progress {
+            println!("📊 Sparse search found {} results", sparse_results.len());
+        }
+        fuse_sparse(
+            vector_results,
+            sparse_results,
+            vector_weight,
+            keyword_weight,
+        )
+    } else {
+        // Pull in keyword matches the vector search missed. Scrolling returns them
+        // in ID order, so fetch plenty and let BM25 rank them.
+        let keyword_candidates =
+            match keyword_filter(&args.query, &args.text_field, filter.as_ref()) {
+                Some(keyword_filter) => qdrant.scroll_points(
+                    &args.collection,
+                    &keyword_filter,
+                    args.limit * KEYWORD_SCAN_FACTOR,
+                )?,
+                None => Vec::new(),
+            };
+        if show_progress {
+            println!(
+                "📊 Keyword scan found {} candidates",
+                keyword_candidates.len()
+            );
+        }
+        hybrid_search(
+            &args.query,
+            vector_results,
+            keyword_candidates,
+            vector_weight,
+            keyword_weight,
+            &args.text_field,
+            Bm25 {
+                k1: args.bm25_k1,
+                b: args.bm25_b,
+                fuzzy: args.fuzzy.then_some(args.fuzzy_distance as usize),
+            },
+        )
+    };
 
     // Step 4: Output results
-    let results_to_show: Vec<_> = hybrid_results.into_iter().take(args.limit).collect();
-
-    if args.json {
-        println!("{}", serde_json::to_string_pretty(&results_to_show)?);
+    let results_to_show = top_results(hybrid_results, args.limit, args.min_score);
+
+    if format == OutputFormat::Json {
+        println!("{}", to_json_string(&results_to_show, args.strict_json)?);
+    } else if format == OutputFormat::Markdown {
+        let mut stdout = std::io::stdout().lock();
+        write_markdown(&mut stdout, &args.query, &results_to_show, &args.text_field)?;
+    } else if results_to_show.is_empty() && args.min_score.is_some() {
+        println!("No results above threshold");
     } else {
         println!("🎯 Top {} Results:\n", results_to_show.len());
+        let terms = query_terms(&args.query);
+        let highlight = Highlight::for_stdout();
 
         for (i, result) in results_to_show.iter().enumerate() {
             println!("--- Result {} ---", i + 1);
@@ -341,13 +783,16 @@ fn main() -> Result<()> {
                 result.combined_score, result.vector_score, result.keyword_score
             );
 
-            if let Some(text) = result.payload.get("text").and_then(|v| v.as_str()) {
-                let preview = if text.len() > 200 {
-                    format!("{}...", &text[..200])
-                } else {
-                    text.to_string()
-                };
-                println!("{}", preview);
+            if let Some(text) = result
+                .payload
+                .get(args.text_field.as_str())
+                .and_then(|v| v.as_str())
+            {
+                println!("{}", snippet(text, &terms, SNIPPET_RADIUS, highlight));
+            }
+
+            if let Some(source) = result.payload.get("source").and_then(|v| v.as_str()) {
+                println!("Source: {}", source);
             }
 
             println!();
@@ -357,10 +802,122 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+fn main() -> Result<()> {
+    let mut args: Args = parse_args()?;
+    args.collection = namespaced_collection(
+        &args.collection_prefix,
+        &args.collection,
+        &args.collection_suffix,
+    );
+    if !args.strict_json {
+        return run(&args);
+    }
+
+    // Strict mode: even failures are reported as JSON on stdout
+    if let Err(e) = run(&args) {
+        println!("{}", json!({ "error": format!("{e:#}") }));
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// The best `limit` results, leaving out any with a combined score under `min_score`
+fn top_results(
+    results: Vec<HybridSearchResult>,
+    limit: usize,
+    min_score: Option<f32>,
+) -> Vec<HybridSearchResult> {
+    results
+        .into_iter()
+        .filter(|r| min_score.is_none_or(|min| r.combined_score >= min))
+        .take(limit)
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_keyword_only_match_surfaces() {
+        let vector_results = vec![SearchResult {
+            id: "semantic".to_string(),
+            score: 0.6,
+            payload: json!({"text": "Metaprogramming lets code write code"}),
+        }];
+        let keyword_candidates = vec![
+            RetrievedPoint {
+                id: "keyword".to_string(),
+                payload: json!({"text": "rust macros: macro_rules! and procedural macros"}),
+            },
+            RetrievedPoint {
+                id: "semantic".to_string(),
+                payload: json!({"text": "Metaprogramming lets code write code"}),
+            },
+        ];
+
+        let results = hybrid_search(
+            "rust macros",
+            vector_results,
+            keyword_candidates,
+            0.5,
+            0.5,
+            "text",
+            BM25,
+        );
+
+        assert_eq!(results.len(), 2);
+        let keyword = results.iter().find(|r| r.id == "keyword").unwrap();
+        assert_eq!(keyword.vector_score, 0.0);
+        assert!(keyword.keyword_score > 0.0);
+        assert_eq!(keyword.combined_score, keyword.keyword_score * 0.5);
+    }
+
+    #[test]
+    fn test_keyword_filter_matches_any_term_within_user_filter() {
+        let user_filter = json!({"must": [{"key": "is_code", "match": {"value": true}}]});
+
+        let filter = keyword_filter("Rust is fun", "content", Some(&user_filter)).unwrap();
+
+        assert_eq!(filter["must"], user_filter["must"]);
+        let terms: Vec<&str> = filter["should"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["match"]["text"].as_str().unwrap())
+            .collect();
+        assert_eq!(terms, vec!["Rust", "fun", "rust"]);
+        assert_eq!(filter["should"][0]["key"], "content");
+        assert!(keyword_filter("a is", "text", None).is_none());
+    }
+
+    #[test]
+    fn test_top_results_applies_min_combined_score() {
+        let result = |id: &str, combined_score: f32| HybridSearchResult {
+            id: id.to_string(),
+            vector_score: combined_score,
+            keyword_score: 0.0,
+            combined_score,
+            payload: json!({}),
+        };
+        let results = || vec![result("a", 0.8), result("b", 0.5), result("c", 0.2)];
+
+        let ids = |rs: Vec<HybridSearchResult>| rs.into_iter().map(|r| r.id).collect::<Vec<_>>();
+        assert_eq!(ids(top_results(results(), 5, Some(0.5))), vec!["a", "b"]);
+        assert_eq!(ids(top_results(results(), 2, None)), vec!["a", "b"]);
+        assert!(top_results(results(), 5, Some(0.9)).is_empty());
+    }
+
+    const BM25: Bm25 = Bm25 {
+        k1: 1.2,
+        b: 0.75,
+        fuzzy: None,
+    };
+
+    fn keyword_score(query: &str, text: &str) -> f32 {
+        keyword_scores(query, &[text], BM25)[0]
+    }
+
     #[test]
     fn test_keyword_score_exact_match() {
         let query = "rust macros";
@@ -389,52 +946,80 @@ mod tests {
     }
 
     #[test]
-    fn test_keyword_score_phrase_boost() {
-        let query = "rust macros";
-        let exact_text = "this is about rust macros and their uses";
-        let partial_text = "this is about rust and also macros";
+    fn test_bm25_ranks_rare_term_above_common_term() {
+        let texts = [
+            "rust ownership and moves",
+            "rust traits and generics",
+            "rust error handling",
+            "lifetimes and references",
+        ];
 
-        let exact_score = keyword_score(query, exact_text);
-        let partial_score = keyword_score(query, partial_text);
+        let scores = keyword_scores("rust lifetimes", &texts, BM25);
 
-        assert!(
-            exact_score > partial_score,
-            "Exact phrase should score higher"
+        assert_eq!(
+            scores[3], 1.0,
+            "the only text with the rare term scores best"
         );
+        assert!(scores[0] > 0.0 && scores[0] < scores[3], "{scores:?}");
+        assert_eq!(scores[0], scores[1]);
     }
 
     #[test]
-    fn test_keyword_score_case_insensitive() {
-        let query = "Rust Macros";
-        let text = "RUST MACROS are powerful";
+    fn test_bm25_saturates_repeats_and_penalises_length() {
+        let texts = [
+            "macros macros macros macros",
+            "macros",
+            "macros in a much longer chunk of text about many other topics",
+        ];
 
-        let score = keyword_score(query, text);
-        assert!(score > 0.0, "Should be case insensitive");
+        let scores = keyword_scores("macros", &texts, BM25);
+        assert!(scores[0] > scores[1], "{scores:?}");
+        assert!(scores[1] > scores[2], "{scores:?}");
+        assert!(scores[0] < 4.0 * scores[1], "repeats saturate: {scores:?}");
+
+        let flat = keyword_scores("macros", &texts, Bm25 { b: 0.0, ..BM25 });
+        assert_eq!(flat[1], flat[2], "b = 0 ignores length");
     }
 
     #[test]
-    fn test_build_filter_single() {
-        let filters = vec!["is_code=true".to_string()];
-        let result = build_filter(&filters).unwrap();
+    fn test_fuzzy_matches_one_edit_typo_with_reduced_score() {
+        let texts = ["asynchronous rust code", "asyncronous rust code"];
+        let fuzzy = Bm25 {
+            fuzzy: Some(2),
+            ..BM25
+        };
 
-        assert!(result["must"].is_array());
-        assert_eq!(result["must"].as_array().unwrap().len(), 1);
+        let exact = keyword_scores("asyncronous", &texts, BM25);
+        assert_eq!(exact[0], 0.0, "without --fuzzy a typo matches nothing");
+
+        let scores = keyword_scores("asyncronous", &texts, fuzzy);
+        assert_eq!(scores[1], 1.0);
+        assert!(scores[0] > 0.0 && scores[0] < scores[1], "{scores:?}");
+
+        let closer = keyword_scores("asyncronous", &["asynchronous", "asynchronus"], fuzzy);
+        assert!(
+            closer[1] > 0.0 && closer[0] > closer[1],
+            "two edits score below one: {closer:?}"
+        );
     }
 
     #[test]
-    fn test_build_filter_multiple() {
-        let filters = vec!["is_code=true".to_string(), "source=test.pdf".to_string()];
-        let result = build_filter(&filters).unwrap();
+    fn test_fuzzy_leaves_short_terms_exact() {
+        let fuzzy = Some(2);
 
-        assert_eq!(result["must"].as_array().unwrap().len(), 2);
+        assert_eq!(match_weight("cat", "car", fuzzy), None);
+        assert_eq!(match_weight("rust", "just", Some(1)), Some(0.5));
+        assert_eq!(match_weight("rust", "rust", None), Some(1.0));
+        assert_eq!(match_weight("rust", "just", None), None);
     }
 
     #[test]
-    fn test_build_filter_invalid() {
-        let filters = vec!["invalid_format".to_string()];
-        let result = build_filter(&filters);
+    fn test_keyword_score_case_insensitive() {
+        let query = "Rust Macros?";
+        let text = "RUST MACROS are powerful";
 
-        assert!(result.is_err());
+        let score = keyword_score(query, text);
+        assert!(score > 0.0, "Should be case insensitive");
     }
 
     #[test]
@@ -445,7 +1030,15 @@ mod tests {
             payload: serde_json::json!({"text": "rust macros are great"}),
         }];
 
-        let results = hybrid_search("rust macros", vector_results, 0.7, 0.3);
+        let results = hybrid_search(
+            "rust macros",
+            vector_results,
+            Vec::new(),
+            0.7,
+            0.3,
+            "text",
+            BM25,
+        );
 
         assert_eq!(results.len(), 1);
         assert!(results[0].combined_score > 0.0);
@@ -468,9 +1061,135 @@ mod tests {
             },
         ];
 
-        let results = hybrid_search("rust macros", vector_results, 0.5, 0.5);
+        let results = hybrid_search(
+            "rust macros",
+            vector_results,
+            Vec::new(),
+            0.5,
+            0.5,
+            "text",
+            BM25,
+        );
 
         // Second result should rank higher due to keyword match
         assert_eq!(results[0].id, "test2");
     }
+
+    #[test]
+    fn test_hybrid_search_reads_custom_text_field() {
+        let vector_results = vec![SearchResult {
+            id: "test1".to_string(),
+            score: 0.8,
+            payload: serde_json::json!({"content": "rust macros are great"}),
+        }];
+
+        let results = hybrid_search(
+            "rust macros",
+            vector_results,
+            Vec::new(),
+            0.5,
+            0.5,
+            "content",
+            BM25,
+        );
+
+        assert!(results[0].keyword_score > 0.0);
+    }
+
+    #[test]
+    fn test_fuse_sparse_merges_both_result_lists() {
+        let result = |id: &str, score: f32| SearchResult {
+            id: id.to_string(),
+            score,
+            payload: serde_json::json!({}),
+        };
+        let vector_results = vec![result("both", 0.8), result("dense-only", 0.9)];
+        let sparse_results = vec![result("both", 4.0), result("sparse-only", 2.0)];
+
+        let results = fuse_sparse(vector_results, sparse_results, 0.5, 0.5);
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].id, "both");
+        assert_eq!(results[0].keyword_score, 4.0);
+        let sparse_only = results.iter().find(|r| r.id == "sparse-only").unwrap();
+        assert_eq!(sparse_only.vector_score, 0.0);
+        assert!((sparse_only.combined_score - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_fuse_sparse_with_zero_or_negative_best_scores_stays_finite() {
+        let result = |id: &str, score: f32| SearchResult {
+            id: id.to_string(),
+            score,
+            payload: serde_json::json!({}),
+        };
+        let vector_results = vec![result("a", -0.2), result("b", -0.5)];
+        let sparse_results = vec![result("a", 0.0), result("c", 0.0)];
+
+        let results = fuse_sparse(vector_results, sparse_results, 0.5, 0.5);
+
+        assert!(results.iter().all(|r| r.combined_score.is_finite()));
+        assert_eq!(results[0].id, "c");
+        assert_eq!(results[1].id, "a");
+        assert_eq!(normalize(0.4, 0.8), 0.5);
+        assert_eq!(normalize(0.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn test_euclid_distances_fuse_closest_first() {
+        let result = |id: &str, score: f32| SearchResult {
+            id: id.to_string(),
+            score,
+            payload: serde_json::json!({}),
+        };
+        let vector_results = dense_similarities(
+            vec![result("near", 0.5), result("far", 3.0)],
+            Distance::Euclid,
+        );
+
+        let results = fuse_sparse(vector_results, Vec::new(), 1.0, 0.0);
+
+        assert_eq!(results[0].id, "near");
+        assert_eq!(results[0].combined_score, 1.0);
+        assert!(results[1].combined_score < 0.5);
+    }
+
+    #[test]
+    fn test_normalize_weights_rescales_to_one() {
+        assert_eq!(resolve_weights(0.9, 0.9, true).unwrap(), (0.5, 0.5));
+        assert_eq!(resolve_weights(0.9, 0.9, false).unwrap(), (0.9, 0.9));
+        let (vector, keyword) = resolve_weights(3.0, 1.0, true).unwrap();
+        assert!((vector - 0.75).abs() < 1e-6 && (keyword - 0.25).abs() < 1e-6);
+        assert!(resolve_weights(0.0, 0.0, true).is_err());
+    }
+
+    #[test]
+    fn test_negative_weights_are_rejected() {
+        for normalize in [false, true] {
+            let err = resolve_weights(-0.1, 1.1, normalize).unwrap_err();
+            assert!(err.to_string().contains("--vector-weight"), "{err}");
+            let err = resolve_weights(1.0, -0.5, normalize).unwrap_err();
+            assert!(err.to_string().contains("--keyword-weight"), "{err}");
+        }
+        assert!(resolve_weights(f32::NAN, 0.5, false).is_err());
+    }
+
+    #[test]
+    fn test_markdown_output_shows_all_three_scores() {
+        let results = vec![HybridSearchResult {
+            id: "a".to_string(),
+            vector_score: 0.9,
+            keyword_score: 0.5,
+            combined_score: 0.78,
+            payload: serde_json::json!({"text": "Macros write code", "source": "rust.md"}),
+        }];
+        let mut out = Vec::new();
+
+        write_markdown(&mut out, "macros", &results, "text").unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "### Hybrid search results for: macros\n\n1. > **Macros** write code\n\n   **Source:** rust.md · **Score:** 0.780 (vector 0.900, keyword 0.500)\n\n"
+        );
+    }
 }
@@ -4,9 +4,13 @@
 //! Import Qdrant collections from JSON backup files.
 
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use rag_demo::config::parse_args;
+use rag_demo::rag::{ApiKey, QdrantClient, qdrant_http_client};
 use reqwest::blocking::Client;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::PathBuf;
 
@@ -23,9 +27,22 @@ struct Args {
     )]
     collection: Option<String>,
 
-    #[arg(long, default_value = "http://localhost:6333", help = "Qdrant URL")]
+    #[arg(
+        long,
+        default_value = "http://localhost:6333",
+        env = "QDRANT_URL",
+        help = "Qdrant URL"
+    )]
     qdrant_url: String,
 
+    #[arg(
+        long,
+        env = "QDRANT_API_KEY",
+        hide_env_values = true,
+        help = "Qdrant API key, sent as the api-key header (Qdrant Cloud)"
+    )]
+    qdrant_api_key: Option<ApiKey>,
+
     #[arg(long, help = "Skip creating collection (assume it exists)")]
     skip_create: bool,
 
@@ -34,6 +51,40 @@ struct Args {
 
     #[arg(long, help = "Force import even if collection exists (will merge)")]
     force: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "overwrite",
+        help = "With --force, what to do with exported points whose ID is already in the collection"
+    )]
+    merge_strategy: MergeStrategy,
+
+    #[arg(
+        long,
+        help = "After uploading, check the collection's point count and a sample of payloads against the export"
+    )]
+    verify: bool,
+
+    #[arg(
+        long,
+        default_value = "100",
+        help = "Points whose payloads --verify compares with the export"
+    )]
+    verify_sample: usize,
+}
+
+/// What a merging import does with exported points whose ID the collection
+/// already has
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum MergeStrategy {
+    /// Replace the existing point, vector and whole payload
+    Overwrite,
+    /// Keep the existing point and upload only IDs the collection lacks
+    SkipExisting,
+    /// Upload the exported vector with the existing payload deep-merged with
+    /// the exported one, so fields only the collection has survive
+    PayloadMerge,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -45,10 +96,12 @@ struct CollectionInfo {
     config: serde_json::Value,
 }
 
+/// An exported point; `vector` is a plain dense vector or named vectors
+/// (dense and sparse), uploaded exactly as exported
 #[derive(Debug, Serialize, Deserialize)]
 struct PointData {
     id: String,
-    vector: Option<Vec<f32>>,
+    vector: Option<serde_json::Value>,
     payload: serde_json::Value,
 }
 
@@ -63,7 +116,7 @@ struct ExportData {
 #[derive(Debug, Serialize)]
 struct QdrantPoint {
     id: String,
-    vector: Vec<f32>,
+    vector: serde_json::Value,
     payload: serde_json::Value,
 }
 
@@ -72,84 +125,143 @@ struct UpsertRequest {
     points: Vec<QdrantPoint>,
 }
 
-fn check_collection_exists(client: &Client, qdrant_url: &str, collection: &str) -> Result<bool> {
-    let url = format!("{}/collections/{}", qdrant_url, collection);
-    let response = client.get(&url).send()?;
-    Ok(response.status().is_success())
+/// Collection settings recreating the exported `vectors` and
+/// `sparse_vectors` exactly, so named vectors (`content`/`summary`) and the
+/// sparse `text` vector of hierarchical collections come back too. Exports
+/// without vector params get a single 768-dimension cosine vector.
+fn create_request(config: &serde_json::Value) -> serde_json::Value {
+    let params = &config["params"];
+    let vectors = params
+        .get("vectors")
+        .filter(|v| v.is_object())
+        .cloned()
+        .unwrap_or_else(|| json!({"size": 768, "distance": "Cosine"}));
+    let mut request = json!({ "vectors": vectors });
+    if let Some(sparse) = params.get("sparse_vectors").filter(|v| v.is_object()) {
+        request["sparse_vectors"] = sparse.clone();
+    }
+    request
 }
 
-fn create_collection(
-    client: &Client,
-    qdrant_url: &str,
-    collection: &str,
-    config: &serde_json::Value,
-) -> Result<()> {
-    let url = format!("{}/collections/{}", qdrant_url, collection);
-
-    // Extract vector size from config
-    let vector_size = config
-        .get("params")
-        .and_then(|p| p.get("vectors"))
-        .and_then(|v| v.get("size"))
-        .and_then(|s| s.as_u64())
-        .unwrap_or(768);
-
-    let distance = config
-        .get("params")
-        .and_then(|p| p.get("vectors"))
-        .and_then(|v| v.get("distance"))
-        .and_then(|d| d.as_str())
-        .unwrap_or("Cosine");
-
-    let create_request = serde_json::json!({
-        "vectors": {
-            "size": vector_size,
-            "distance": distance
+/// Points actually upserted, and the IDs of those skipped for having no
+/// vector or, with `skip-existing`, for already being in the collection
+#[derive(Debug, Default, PartialEq)]
+struct UploadSummary {
+    uploaded: usize,
+    skipped_no_vector: Vec<String>,
+    skipped_existing: Vec<String>,
+    /// Points uploaded with `payload-merge` whose payload was merged into an existing one
+    merged: usize,
+}
+
+/// Merge `incoming` into `existing`: objects key by key, recursively, and
+/// any other value (arrays included) replaced by the incoming one
+fn deep_merge(existing: &mut serde_json::Value, incoming: serde_json::Value) {
+    match (existing, incoming) {
+        (serde_json::Value::Object(existing), serde_json::Value::Object(incoming)) => {
+            for (key, value) in incoming {
+                match existing.get_mut(&key) {
+                    Some(slot) => deep_merge(slot, value),
+                    None => {
+                        existing.insert(key, value);
+                    }
+                }
+            }
         }
-    });
+        (existing, incoming) => *existing = incoming,
+    }
+}
 
-    client
-        .put(&url)
-        .json(&create_request)
-        .send()
-        .context("Failed to create collection")?;
+/// Apply `strategy` to `points` given the payloads of those among them the
+/// collection already has. Returns the points to upload and the IDs skipped.
+fn resolve_collisions(
+    points: Vec<QdrantPoint>,
+    existing: &HashMap<String, serde_json::Value>,
+    strategy: MergeStrategy,
+    summary: &mut UploadSummary,
+) -> Vec<QdrantPoint> {
+    let mut upload = Vec::with_capacity(points.len());
+    for mut point in points {
+        match (existing.get(&point.id), strategy) {
+            (None, _) | (Some(_), MergeStrategy::Overwrite) => {}
+            (Some(_), MergeStrategy::SkipExisting) => {
+                summary.skipped_existing.push(point.id);
+                continue;
+            }
+            (Some(payload), MergeStrategy::PayloadMerge) => {
+                let mut merged = payload.clone();
+                deep_merge(&mut merged, point.payload);
+                point.payload = merged;
+                summary.merged += 1;
+            }
+        }
+        upload.push(point);
+    }
+    upload
+}
 
-    Ok(())
+/// Split `batch` into the points that can be upserted and the IDs of those
+/// without a vector
+fn split_by_vector(batch: &[PointData]) -> (Vec<QdrantPoint>, Vec<String>) {
+    let mut points = Vec::with_capacity(batch.len());
+    let mut skipped = Vec::new();
+    for p in batch {
+        match &p.vector {
+            Some(vector) => points.push(QdrantPoint {
+                id: p.id.clone(),
+                vector: vector.clone(),
+                payload: p.payload.clone(),
+            }),
+            None => skipped.push(p.id.clone()),
+        }
+    }
+    (points, skipped)
 }
 
+/// Upload `points` in batches. `collisions` is the client to look up IDs the
+/// collection already has with, and the strategy for them, when merging with
+/// anything but `overwrite`.
 fn upload_points(
     client: &Client,
     qdrant_url: &str,
     collection: &str,
     points: &[PointData],
     batch_size: usize,
-) -> Result<()> {
+    wait: bool,
+    collisions: Option<(&QdrantClient, MergeStrategy)>,
+) -> Result<UploadSummary> {
     let total_points = points.len();
-    let mut uploaded = 0;
+    let mut summary = UploadSummary::default();
 
     println!("Uploading points in batches of {}...", batch_size);
 
     for batch in points.chunks(batch_size) {
-        let qdrant_points: Vec<QdrantPoint> = batch
-            .iter()
-            .filter_map(|p| {
-                // Skip points without vectors
-                let vector = p.vector.as_ref()?;
-
-                Some(QdrantPoint {
-                    id: p.id.clone(),
-                    vector: vector.clone(),
-                    payload: p.payload.clone(),
-                })
-            })
-            .collect();
+        let (qdrant_points, skipped) = split_by_vector(batch);
+        summary.skipped_no_vector.extend(skipped);
+        let qdrant_points = match collisions {
+            Some((qdrant, strategy)) => {
+                let ids: Vec<&str> = qdrant_points.iter().map(|p| p.id.as_str()).collect();
+                let existing: HashMap<String, serde_json::Value> = qdrant
+                    .get_points_by_id(collection, &ids)
+                    .context("Failed to look up existing points")?
+                    .into_iter()
+                    .map(|point| (point.id, point.payload))
+                    .collect();
+                resolve_collisions(qdrant_points, &existing, strategy, &mut summary)
+            }
+            None => qdrant_points,
+        };
 
         if qdrant_points.is_empty() {
-            println!("\n⚠️  Batch has no vectors - skipping");
+            println!("\n⚠️  Batch has no new points with vectors - skipping");
             continue;
         }
+        let upserted = qdrant_points.len();
 
-        let url = format!("{}/collections/{}/points", qdrant_url, collection);
+        let url = format!(
+            "{}/collections/{}/points?wait={}",
+            qdrant_url, collection, wait
+        );
         let request = UpsertRequest {
             points: qdrant_points,
         };
@@ -158,20 +270,111 @@ fn upload_points(
             .put(&url)
             .json(&request)
             .send()
+            .and_then(|response| response.error_for_status())
             .context("Failed to upload batch")?;
 
-        uploaded += batch.len();
-        print!("\rUploaded {}/{} points...", uploaded, total_points);
+        summary.uploaded += upserted;
+        print!("\rUploaded {}/{} points...", summary.uploaded, total_points);
         std::io::Write::flush(&mut std::io::stdout())?;
     }
 
     println!("\n✅ Upload complete!");
 
-    Ok(())
+    Ok(summary)
+}
+
+/// Indices of up to `sample` points spread evenly over `len`
+fn sample_indices(len: usize, sample: usize) -> Vec<usize> {
+    let sample = sample.min(len);
+    (0..sample).map(|i| i * len / sample).collect()
+}
+
+/// Top-level payload keys whose values differ between `expected` and `actual`
+fn differing_keys(expected: &serde_json::Value, actual: &serde_json::Value) -> Vec<String> {
+    let empty = serde_json::Map::new();
+    let expected = expected.as_object().unwrap_or(&empty);
+    let actual = actual.as_object().unwrap_or(&empty);
+    let mut keys: Vec<String> = expected
+        .keys()
+        .chain(actual.keys())
+        .filter(|key| expected.get(*key) != actual.get(*key))
+        .cloned()
+        .collect();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+/// Compare the imported collection with the export: the point count (exactly
+/// for a new collection, at least if the collection `existed` already) and
+/// the payloads of a sample of points, as `strategy` should have left them;
+/// points in `skipped` were already there and are only checked for being
+/// present. Returns one line per problem found.
+fn verify_import(
+    qdrant: &QdrantClient,
+    collection: &str,
+    points: &[PointData],
+    existed: bool,
+    sample: usize,
+    strategy: MergeStrategy,
+    skipped: &HashSet<&str>,
+) -> Result<Vec<String>> {
+    let mut problems = Vec::new();
+
+    let count = qdrant.count_points(collection, &json!({}), true)?;
+    let expected = points.len();
+    if count < expected || (!existed && count != expected) {
+        let mut problem = format!("collection has {count} points, the export {expected}");
+        let without_vectors = points.iter().filter(|p| p.vector.is_none()).count();
+        if without_vectors > 0 {
+            problem.push_str(&format!(
+                " ({without_vectors} exported points have no vector and were not uploaded)"
+            ));
+        }
+        problems.push(problem);
+    }
+
+    let sampled: Vec<&PointData> = sample_indices(points.len(), sample)
+        .into_iter()
+        .map(|i| &points[i])
+        .collect();
+    let ids: Vec<&str> = sampled.iter().map(|p| p.id.as_str()).collect();
+    let found: HashMap<String, serde_json::Value> = ids
+        .chunks(100)
+        .map(|batch| qdrant.get_points_by_id(collection, batch))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .map(|point| (point.id, point.payload))
+        .collect();
+    for point in sampled {
+        match found.get(&point.id) {
+            None => problems.push(format!("point {} is missing", point.id)),
+            Some(_) if skipped.contains(point.id.as_str()) => {}
+            Some(payload) => {
+                let expected = if strategy == MergeStrategy::PayloadMerge {
+                    let mut expected = payload.clone();
+                    deep_merge(&mut expected, point.payload.clone());
+                    expected
+                } else {
+                    point.payload.clone()
+                };
+                let keys = differing_keys(&expected, payload);
+                if !keys.is_empty() {
+                    problems.push(format!(
+                        "point {} has a different payload in: {}",
+                        point.id,
+                        keys.join(", ")
+                    ));
+                }
+            }
+        }
+    }
+    Ok(problems)
 }
 
 fn main() -> Result<()> {
-    let args = Args::parse();
+    let args: Args = parse_args()?;
 
     println!("📂 Reading export file: {}", args.input.display());
 
@@ -202,10 +405,11 @@ fn main() -> Result<()> {
         );
     }
 
-    let client = Client::new();
+    let client = qdrant_http_client(args.qdrant_api_key.as_ref());
+    let qdrant = QdrantClient::new(&args.qdrant_url).api_key(args.qdrant_api_key.as_ref());
 
     // Check if collection exists
-    let exists = check_collection_exists(&client, &args.qdrant_url, &collection_name)?;
+    let exists = qdrant.collection_exists(&collection_name);
 
     if exists && !args.force {
         anyhow::bail!(
@@ -217,11 +421,9 @@ fn main() -> Result<()> {
 
     if !args.skip_create && !exists {
         println!("\n🔨 Creating collection '{}'...", collection_name);
-        create_collection(
-            &client,
-            &args.qdrant_url,
+        qdrant.create_collection(
             &collection_name,
-            &export_data.collection_info.config,
+            &create_request(&export_data.collection_info.config),
         )?;
         println!("✅ Collection created");
     } else if exists {
@@ -232,17 +434,73 @@ fn main() -> Result<()> {
     }
 
     let batch_size = args.batch_size.unwrap_or(100);
-    upload_points(
+    // A new collection has nothing to collide with
+    let collisions = (exists && args.merge_strategy != MergeStrategy::Overwrite)
+        .then_some((&qdrant, args.merge_strategy));
+    let summary = upload_points(
         &client,
         &args.qdrant_url,
         &collection_name,
         &export_data.points,
         batch_size,
+        args.verify,
+        collisions,
     )?;
 
+    if args.verify {
+        let skipped: HashSet<&str> = summary
+            .skipped_existing
+            .iter()
+            .map(String::as_str)
+            .collect();
+        let sample = args.verify_sample.min(export_data.points.len());
+        println!("\n🔎 Verifying point count and {sample} sampled payloads...");
+        let problems = verify_import(
+            &qdrant,
+            &collection_name,
+            &export_data.points,
+            exists,
+            args.verify_sample,
+            args.merge_strategy,
+            &skipped,
+        )?;
+        if !problems.is_empty() {
+            for problem in &problems {
+                eprintln!("   ❌ {problem}");
+            }
+            anyhow::bail!(
+                "Verification of '{}' found {} problems",
+                collection_name,
+                problems.len()
+            );
+        }
+        println!("✅ Verified: point count and sampled payloads match the export");
+    }
+
     println!("\n🎉 Import complete!");
     println!("   Collection: {}", collection_name);
-    println!("   Points imported: {}", export_data.points.len());
+    println!("   Points imported: {}", summary.uploaded);
+    if summary.merged > 0 {
+        println!(
+            "   Payloads merged into existing points: {}",
+            summary.merged
+        );
+    }
+    if !summary.skipped_existing.is_empty() {
+        println!(
+            "   Points skipped (already in the collection): {}",
+            summary.skipped_existing.len()
+        );
+    }
+    if !summary.skipped_no_vector.is_empty() {
+        println!(
+            "   Points skipped (no vector): {}",
+            summary.skipped_no_vector.len()
+        );
+        for id in &summary.skipped_no_vector {
+            println!("     - {id}");
+        }
+    }
 
     Ok(())
 }
@@ -282,15 +540,14 @@ mod tests {
 
         let point: PointData = serde_json::from_value(json_data).unwrap();
         assert_eq!(point.id, "test-123");
-        assert!(point.vector.is_some());
-        assert_eq!(point.vector.unwrap().len(), 3);
+        assert_eq!(point.vector.unwrap().as_array().unwrap().len(), 3);
     }
 
     #[test]
     fn test_qdrant_point_serialization() {
         let point = QdrantPoint {
             id: "test-id".to_string(),
-            vector: vec![0.1, 0.2, 0.3],
+            vector: json!([0.1, 0.2, 0.3]),
             payload: json!({"text": "Test"}),
         };
 
@@ -300,11 +557,168 @@ mod tests {
         assert_eq!(json["payload"]["text"], "Test");
     }
 
+    #[test]
+    fn test_mixed_batch_skips_only_points_without_vectors() {
+        let point = |id: &str, vector: Option<serde_json::Value>| PointData {
+            id: id.to_string(),
+            vector,
+            payload: json!({"text": id}),
+        };
+        let batch = vec![
+            point("a", Some(json!([0.1, 0.2]))),
+            point("b", None),
+            point(
+                "c",
+                Some(json!({"": [0.3, 0.4], "text": {"indices": [1], "values": [1.0]}})),
+            ),
+            point("d", None),
+        ];
+
+        let (points, skipped) = split_by_vector(&batch);
+
+        let ids: Vec<&str> = points.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "c"]);
+        assert_eq!(
+            points[1].vector,
+            json!({"": [0.3, 0.4], "text": {"indices": [1], "values": [1.0]}})
+        );
+        assert_eq!(skipped, vec!["b", "d"]);
+    }
+
+    #[test]
+    fn test_named_and_sparse_vectors_round_trip_from_export() {
+        let vector = json!({
+            "content": [0.1, 0.2],
+            "summary": [0.3, 0.4],
+            "text": {"indices": [3, 8], "values": [1.0, 2.0]}
+        });
+        let exported = serde_json::to_string(&PointData {
+            id: "p-1".to_string(),
+            vector: Some(vector.clone()),
+            payload: json!({"text": "Ownership"}),
+        })
+        .unwrap();
+
+        let point: PointData = serde_json::from_str(&exported).unwrap();
+        let (points, skipped) = split_by_vector(&[point]);
+        let request = serde_json::to_value(UpsertRequest { points }).unwrap();
+
+        assert!(skipped.is_empty());
+        assert_eq!(request["points"][0]["vector"], vector);
+    }
+
+    #[test]
+    fn test_create_request_recreates_named_and_sparse_vectors() {
+        let dense = json!({"size": 768, "distance": "Cosine"});
+        let hierarchical = json!({"params": {
+            "vectors": {"content": dense, "summary": dense},
+            "sparse_vectors": {"text": {"modifier": "idf"}},
+            "shard_number": 1
+        }});
+
+        let request = create_request(&hierarchical);
+
+        assert_eq!(request["vectors"]["content"], dense);
+        assert_eq!(request["vectors"]["summary"], dense);
+        assert_eq!(request["sparse_vectors"]["text"]["modifier"], "idf");
+        assert!(request.get("shard_number").is_none());
+
+        let single = json!({"params": {"vectors": {"size": 384, "distance": "Dot"}}});
+        assert_eq!(
+            create_request(&single),
+            json!({"vectors": {"size": 384, "distance": "Dot"}})
+        );
+        assert_eq!(
+            create_request(&json!({"vector_size": 768})),
+            json!({"vectors": {"size": 768, "distance": "Cosine"}})
+        );
+    }
+
+    #[test]
+    fn test_payload_merge_preserves_destination_only_fields() {
+        let point = |id: &str, payload: serde_json::Value| QdrantPoint {
+            id: id.to_string(),
+            vector: json!([0.1]),
+            payload,
+        };
+        let incoming = vec![
+            point(
+                "a",
+                json!({"text": "new", "meta": {"page": 2}, "tags": ["x"]}),
+            ),
+            point("b", json!({"text": "fresh"})),
+        ];
+        let existing = HashMap::from([(
+            "a".to_string(),
+            json!({"text": "old", "reviewed": true, "meta": {"page": 1, "note": "keep"}, "tags": ["y", "z"]}),
+        )]);
+        let mut summary = UploadSummary::default();
+
+        let upload = resolve_collisions(
+            incoming,
+            &existing,
+            MergeStrategy::PayloadMerge,
+            &mut summary,
+        );
+
+        assert_eq!(
+            upload[0].payload,
+            json!({"text": "new", "reviewed": true, "meta": {"page": 2, "note": "keep"}, "tags": ["x"]})
+        );
+        assert_eq!(upload[1].payload, json!({"text": "fresh"}));
+        assert_eq!(summary.merged, 1);
+    }
+
+    #[test]
+    fn test_skip_existing_uploads_only_new_ids() {
+        let incoming: Vec<QdrantPoint> = ["a", "b"]
+            .iter()
+            .map(|id| QdrantPoint {
+                id: id.to_string(),
+                vector: json!([0.1]),
+                payload: json!({"text": id}),
+            })
+            .collect();
+        let existing = HashMap::from([("a".to_string(), json!({"text": "kept"}))]);
+        let mut summary = UploadSummary::default();
+
+        let upload = resolve_collisions(
+            incoming,
+            &existing,
+            MergeStrategy::SkipExisting,
+            &mut summary,
+        );
+
+        assert_eq!(upload.len(), 1);
+        assert_eq!(upload[0].id, "b");
+        assert_eq!(summary.skipped_existing, vec!["a"]);
+    }
+
+    #[test]
+    fn test_sample_spreads_over_all_points() {
+        assert_eq!(sample_indices(10, 3), vec![0, 3, 6]);
+        assert_eq!(sample_indices(2, 100), vec![0, 1]);
+        assert!(sample_indices(0, 100).is_empty());
+        assert!(sample_indices(5, 0).is_empty());
+    }
+
+    #[test]
+    fn test_differing_keys_names_changed_added_and_lost_fields() {
+        let exported = json!({"text": "Ownership", "source": "rust.pdf", "page": 4});
+        let imported = json!({"text": "Ownership", "source": "rust.md", "lang": "en"});
+
+        assert_eq!(
+            differing_keys(&exported, &imported),
+            vec!["lang", "page", "source"]
+        );
+        assert!(differing_keys(&exported, &exported).is_empty());
+    }
+
     #[test]
     fn test_upsert_request_structure() {
         let point = QdrantPoint {
             id: "1".to_string(),
-            vector: vec![0.5],
+            vector: json!([0.5]),
             payload: json!({}),
         };
 
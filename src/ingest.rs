@@ -0,0 +1,452 @@
+// Copyright (c) 2025 Michael A. Wright
+// Licensed under the MIT License
+
+//! Ingest a list of sources described by a JSON manifest.
+//!
+//! Each manifest entry names a file, the collection it goes into, and the
+//! chunker to use. Entries are handed to the existing ingestion binaries one
+//! at a time, so every chunker keeps its own behaviour; this tool only adds
+//! the orchestration and a combined summary.
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use colored::*;
+use rag_demo::config::parse_args;
+use rag_demo::rag::{ApiKey, http_options, namespaced_collection, qdrant_http_client};
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Ingest every source listed in a manifest with per-file settings"
+)]
+struct Args {
+    #[arg(long, help = "Path to the JSON ingestion manifest")]
+    manifest: PathBuf,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:6333",
+        env = "QDRANT_URL",
+        help = "Qdrant URL"
+    )]
+    qdrant_url: String,
+
+    #[arg(
+        long,
+        env = "QDRANT_API_KEY",
+        hide_env_values = true,
+        help = "Qdrant API key, sent as the api-key header (Qdrant Cloud)"
+    )]
+    qdrant_api_key: Option<ApiKey>,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:11434",
+        env = "OLLAMA_URL",
+        help = "Ollama URL"
+    )]
+    ollama_url: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_PREFIX",
+        help = "Prepended to the collection name, e.g. acme_ to keep one tenant's collections apart"
+    )]
+    collection_prefix: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_SUFFIX",
+        help = "Appended to the collection name"
+    )]
+    collection_suffix: String,
+
+    #[arg(long, help = "Dry run - show what would be ingested without doing it")]
+    dry_run: bool,
+}
+
+/// Chunking strategy for an entry, each backed by one ingestion binary
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Chunker {
+    /// Fixed-size chunks from a PDF (pdf-to-embeddings)
+    Pdf,
+    /// Code-aware markdown chunks (ingest-markdown)
+    Markdown,
+    /// Small/medium/large markdown chunks (ingest-markdown-multi --multi-scale)
+    MultiScale,
+    /// Parent-child markdown chunks (ingest-hierarchical)
+    Hierarchical,
+}
+
+impl Chunker {
+    fn binary(self) -> &'static str {
+        match self {
+            Chunker::Pdf => "pdf-to-embeddings",
+            Chunker::Markdown => "ingest-markdown",
+            Chunker::MultiScale => "ingest-markdown-multi",
+            Chunker::Hierarchical => "ingest-hierarchical",
+        }
+    }
+
+    fn supports_chunk_size(self) -> bool {
+        matches!(self, Chunker::Pdf | Chunker::Markdown)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    entries: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestEntry {
+    path: PathBuf,
+    collection: String,
+    chunker: Chunker,
+    #[serde(default)]
+    chunk_size: Option<usize>,
+    /// Extra payload fields written onto every point from this source
+    #[serde(default)]
+    meta: Map<String, Value>,
+}
+
+impl ManifestEntry {
+    /// Command-line arguments for the ingestion binary behind this entry
+    fn ingest_args(&self, qdrant_url: &str, ollama_url: &str) -> Vec<String> {
+        let mut args = vec![
+            self.path.to_string_lossy().into_owned(),
+            "--collection".to_string(),
+            self.collection.clone(),
+            "--qdrant-url".to_string(),
+            qdrant_url.to_string(),
+            "--ollama-url".to_string(),
+            ollama_url.to_string(),
+        ];
+        if self.chunker == Chunker::MultiScale {
+            args.push("--multi-scale".to_string());
+        }
+        if let Some(size) = self.chunk_size {
+            args.push("--chunk-size".to_string());
+            args.push(size.to_string());
+        }
+        args
+    }
+
+    /// Collections that receive points for this entry
+    fn target_collections(&self) -> Vec<String> {
+        match self.chunker {
+            Chunker::MultiScale => ["small", "medium", "large"]
+                .iter()
+                .map(|scale| format!("{}_{scale}", self.collection))
+                .collect(),
+            _ => vec![self.collection.clone()],
+        }
+    }
+}
+
+/// Parse a manifest, resolving relative entry paths against `base_dir`
+fn parse_manifest(content: &str, base_dir: &Path) -> Result<Manifest> {
+    let mut manifest: Manifest =
+        serde_json::from_str(content).context("Failed to parse ingestion manifest")?;
+
+    if manifest.entries.is_empty() {
+        anyhow::bail!("Manifest has no entries");
+    }
+
+    for (i, entry) in manifest.entries.iter_mut().enumerate() {
+        if entry.chunk_size.is_some() && !entry.chunker.supports_chunk_size() {
+            anyhow::bail!(
+                "Entry {} ({}): chunk_size is not supported by the {:?} chunker",
+                i + 1,
+                entry.path.display(),
+                entry.chunker
+            );
+        }
+        if entry.path.is_relative() {
+            entry.path = base_dir.join(&entry.path);
+        }
+    }
+
+    Ok(manifest)
+}
+
+/// Locate an ingestion binary next to this one, falling back to PATH
+fn binary_path(name: &str) -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(name)))
+        .filter(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from(name))
+}
+
+fn run_entry(entry: &ManifestEntry, args: &Args) -> Result<()> {
+    let binary = entry.chunker.binary();
+    let mut command = Command::new(binary_path(binary));
+    command
+        .args(entry.ingest_args(&args.qdrant_url, &args.ollama_url))
+        // The collection name is already namespaced
+        .env("RAG_COLLECTION_PREFIX", "")
+        .env("RAG_COLLECTION_SUFFIX", "");
+    // Through the environment rather than argv, where other users could see it
+    if let Some(key) = &args.qdrant_api_key {
+        command.env("QDRANT_API_KEY", key.as_str());
+    }
+    http_options().forward(&mut command);
+    let status = command
+        .status()
+        .with_context(|| format!("Failed to run {binary}"))?;
+
+    if !status.success() {
+        anyhow::bail!("{binary} exited with {status}");
+    }
+
+    Ok(())
+}
+
+/// Attach the entry's `meta` fields to every point ingested from its path
+fn apply_meta(client: &Client, qdrant_url: &str, entry: &ManifestEntry) -> Result<()> {
+    if entry.meta.is_empty() {
+        return Ok(());
+    }
+
+    for collection in entry.target_collections() {
+        let response = client
+            .post(format!(
+                "{}/collections/{}/points/payload?wait=true",
+                qdrant_url, collection
+            ))
+            .json(&json!({
+                "payload": entry.meta,
+                "filter": {
+                    "must": [{
+                        "key": "source_path",
+                        "match": { "value": entry.path.to_string_lossy() }
+                    }]
+                }
+            }))
+            .send()
+            .context("Failed to set payload metadata")?;
+
+        if !response.status().is_success() {
+            anyhow::bail!(
+                "Failed to set metadata on {}: {}",
+                collection,
+                response.status()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+struct EntryOutcome {
+    path: PathBuf,
+    collection: String,
+    elapsed: Duration,
+    error: Option<String>,
+}
+
+fn main() -> Result<()> {
+    let args: Args = parse_args()?;
+    let client = qdrant_http_client(args.qdrant_api_key.as_ref());
+
+    let content = fs::read_to_string(&args.manifest)
+        .with_context(|| format!("Failed to read manifest {}", args.manifest.display()))?;
+    let base_dir = args.manifest.parent().unwrap_or(Path::new("."));
+    let mut manifest = parse_manifest(&content, base_dir)?;
+    for entry in &mut manifest.entries {
+        entry.collection = namespaced_collection(
+            &args.collection_prefix,
+            &entry.collection,
+            &args.collection_suffix,
+        );
+    }
+
+    println!("{}", "📋 Manifest Ingestion".cyan().bold());
+    println!("{}", "═".repeat(50).blue());
+    println!(
+        "   Manifest: {} ({} entries)",
+        args.manifest.display(),
+        manifest.entries.len()
+    );
+    println!();
+
+    if args.dry_run {
+        println!("{} Dry run - would process:", "🔍".yellow());
+        for entry in &manifest.entries {
+            println!(
+                "   • {} → {} ({})",
+                entry.path.display(),
+                entry.collection.cyan(),
+                entry.chunker.binary()
+            );
+        }
+        return Ok(());
+    }
+
+    let mut outcomes = Vec::new();
+    for (i, entry) in manifest.entries.iter().enumerate() {
+        println!("{}", "━".repeat(50).blue());
+        println!(
+            "{} [{}/{}] {}",
+            "📄".cyan(),
+            i + 1,
+            manifest.entries.len(),
+            entry.path.display().to_string().bold()
+        );
+        println!("   Collection: {}", entry.collection.cyan());
+        println!("   Chunker: {}", entry.chunker.binary());
+        println!("{}", "━".repeat(50).blue());
+
+        let start = Instant::now();
+        let result =
+            run_entry(entry, &args).and_then(|_| apply_meta(&client, &args.qdrant_url, entry));
+
+        outcomes.push(EntryOutcome {
+            path: entry.path.clone(),
+            collection: entry.collection.clone(),
+            elapsed: start.elapsed(),
+            error: result.err().map(|e| format!("{e:#}")),
+        });
+    }
+
+    // Combined summary
+    let failed = outcomes.iter().filter(|o| o.error.is_some()).count();
+
+    println!();
+    println!("{}", "═".repeat(50).blue());
+    println!("{} Manifest Ingestion Complete!", "✨".green().bold());
+    println!("{}", "═".repeat(50).blue());
+    for outcome in &outcomes {
+        match &outcome.error {
+            None => println!(
+                "   {} {} → {} ({:.1}s)",
+                "✓".green(),
+                outcome.path.display(),
+                outcome.collection.cyan(),
+                outcome.elapsed.as_secs_f64()
+            ),
+            Some(e) => println!(
+                "   {} {} → {}: {}",
+                "✗".red(),
+                outcome.path.display(),
+                outcome.collection.cyan(),
+                e
+            ),
+        }
+    }
+    println!();
+    println!(
+        "   • Entries ingested: {}",
+        (outcomes.len() - failed).to_string().green()
+    );
+    if failed > 0 {
+        println!("   • Entries failed: {}", failed.to_string().red());
+        anyhow::bail!("{failed} of {} manifest entries failed", outcomes.len());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TWO_ENTRIES: &str = r#"{
+        "entries": [
+            {
+                "path": "books/rust.pdf",
+                "collection": "rust-books",
+                "chunker": "pdf",
+                "chunk_size": 800,
+                "meta": { "topic": "rust", "edition": 2 }
+            },
+            {
+                "path": "/data/notes.md",
+                "collection": "notes",
+                "chunker": "hierarchical"
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn test_parse_manifest_with_heterogeneous_entries() {
+        let manifest = parse_manifest(TWO_ENTRIES, Path::new("/srv/corpus")).unwrap();
+
+        assert_eq!(manifest.entries.len(), 2);
+
+        let pdf = &manifest.entries[0];
+        assert_eq!(pdf.path, PathBuf::from("/srv/corpus/books/rust.pdf"));
+        assert_eq!(pdf.collection, "rust-books");
+        assert_eq!(pdf.chunker, Chunker::Pdf);
+        assert_eq!(pdf.chunk_size, Some(800));
+        assert_eq!(pdf.meta["topic"], "rust");
+        assert_eq!(pdf.meta["edition"], 2);
+
+        let notes = &manifest.entries[1];
+        assert_eq!(notes.path, PathBuf::from("/data/notes.md"));
+        assert_eq!(notes.chunker, Chunker::Hierarchical);
+        assert_eq!(notes.chunk_size, None);
+        assert!(notes.meta.is_empty());
+    }
+
+    #[test]
+    fn test_ingest_args_follow_entry_settings() {
+        let manifest = parse_manifest(TWO_ENTRIES, Path::new("/srv/corpus")).unwrap();
+        let args = manifest.entries[0].ingest_args("http://q:6333", "http://o:11434");
+
+        assert_eq!(args[0], "/srv/corpus/books/rust.pdf");
+        assert!(args.windows(2).any(|w| w == ["--collection", "rust-books"]));
+        assert!(args.windows(2).any(|w| w == ["--chunk-size", "800"]));
+        assert!(
+            args.windows(2)
+                .any(|w| w == ["--qdrant-url", "http://q:6333"])
+        );
+    }
+
+    #[test]
+    fn test_multi_scale_targets_every_scale_collection() {
+        let manifest = parse_manifest(
+            r#"{"entries": [{"path": "a.md", "collection": "docs", "chunker": "multi-scale"}]}"#,
+            Path::new("."),
+        )
+        .unwrap();
+        let entry = &manifest.entries[0];
+
+        assert!(
+            entry
+                .ingest_args("q", "o")
+                .contains(&"--multi-scale".to_string())
+        );
+        assert_eq!(
+            entry.target_collections(),
+            vec!["docs_small", "docs_medium", "docs_large"]
+        );
+    }
+
+    #[test]
+    fn test_chunk_size_rejected_for_unsupported_chunker() {
+        let err = parse_manifest(
+            r#"{"entries": [{"path": "a.md", "collection": "docs", "chunker": "hierarchical", "chunk_size": 500}]}"#,
+            Path::new("."),
+        )
+        .unwrap_err();
+
+        assert!(err.to_string().contains("chunk_size"));
+    }
+}
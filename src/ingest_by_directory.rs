@@ -4,133 +4,599 @@
 use anyhow::{Context, Result};
 use clap::Parser;
 use colored::*;
+use rag_demo::config::parse_args;
+use rag_demo::rag::{
+    ApiKey, DEFAULT_INDEX_FIELDS, Distance, EmbedApi, IndexFields, QdrantClient, Quantization,
+    http_options, namespaced_collection, normalize_collection_name, ollama_has_model, preflight,
+    qdrant_http_client,
+};
 use reqwest::blocking::Client;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Duration;
 
 #[derive(Parser, Debug)]
 #[command(
     author,
     version,
-    about = "Ingest PDFs organized by directory into separate collections"
+    about = "Ingest PDF, markdown and text files organized by directory into separate collections"
 )]
 struct Args {
     #[arg(
         default_value = "./ingest",
-        help = "Root directory containing subdirectories of PDFs"
+        help = "Root directory containing subdirectories of PDF, markdown and text files"
     )]
     ingest_dir: PathBuf,
 
-    #[arg(long, default_value = "http://localhost:6333", help = "Qdrant URL")]
+    #[arg(
+        long,
+        default_value = "http://localhost:6333",
+        env = "QDRANT_URL",
+        help = "Qdrant URL"
+    )]
     qdrant_url: String,
 
-    #[arg(long, default_value = "http://localhost:11434", help = "Ollama URL")]
+    #[arg(
+        long,
+        env = "QDRANT_API_KEY",
+        hide_env_values = true,
+        help = "Qdrant API key, sent as the api-key header (Qdrant Cloud)"
+    )]
+    qdrant_api_key: Option<ApiKey>,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:11434",
+        env = "OLLAMA_URL",
+        help = "Ollama URL"
+    )]
     ollama_url: String,
 
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_PREFIX",
+        help = "Prepended to the collection name, e.g. acme_ to keep one tenant's collections apart"
+    )]
+    collection_prefix: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_SUFFIX",
+        help = "Appended to the collection name"
+    )]
+    collection_suffix: String,
+
     #[arg(long, help = "Skip health checks")]
     skip_checks: bool,
 
     #[arg(long, help = "Dry run - show what would be ingested without doing it")]
     dry_run: bool,
+
+    #[arg(
+        long,
+        help = "Continue a previous run, skipping files its state file lists as done"
+    )]
+    resume: bool,
+
+    #[arg(
+        long,
+        help = "Run state file (default: <ingest_dir>/.ingest-run-state.json)"
+    )]
+    state_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Ingest every file even if its content hash matches the last successful ingest"
+    )]
+    force_reingest: bool,
+
+    #[arg(
+        long,
+        help = "Also ingest files in nested subdirectories, into the collection of their top-level directory"
+    )]
+    recursive: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "cosine",
+        help = "Vector distance for newly created collections; existing collections keep theirs"
+    )]
+    distance: Distance,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "none",
+        help = "Quantization for newly created collections: scalar keeps int8 vectors in RAM (~4x less memory, slightly lower recall)"
+    )]
+    quantization: Quantization,
+
+    #[arg(
+        long,
+        default_value = DEFAULT_INDEX_FIELDS,
+        help = "Payload fields to index after ingesting, comma-separated as name or name:schema (keyword, integer, float, bool, text); \"\" indexes none"
+    )]
+    index_fields: IndexFields,
+
+    #[arg(
+        long,
+        help = "After ingesting each collection, wait until Qdrant has finished indexing it (status green)"
+    )]
+    wait_index: bool,
+
+    #[arg(
+        long,
+        default_value = "600",
+        help = "Seconds --wait-index waits for a collection before moving on"
+    )]
+    wait_index_timeout: u64,
+
+    #[arg(
+        long,
+        default_value_t = true,
+        action = clap::ArgAction::Set,
+        help = "Exit non-zero when any file failed to ingest (--fail-on-error false always exits 0)"
+    )]
+    fail_on_error: bool,
+
+    #[arg(
+        long,
+        help = "Write per-collection statistics and overall totals as JSON to this path"
+    )]
+    summary_json: Option<PathBuf>,
 }
 
-#[derive(Debug)]
+const STATE_FILE_NAME: &str = ".ingest-run-state.json";
+/// Sidecar in the ingest directory that remembers content hashes across runs
+const HASH_STATE_FILE_NAME: &str = ".rag-ingest-state.json";
+/// Model used by the ingestion scripts this tool drives
+const EMBED_MODEL: &str = "nomic-embed-text";
+/// How often `--wait-index` polls the collection status
+const WAIT_INDEX_POLL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize)]
 struct CollectionStats {
-    pdfs_processed: usize,
-    pdfs_failed: usize,
+    files_processed: usize,
+    files_failed: usize,
+    files_unchanged: usize,
     total_vectors: usize,
     indexed_vectors: usize,
     status: String,
 }
 
-fn check_services(qdrant_url: &str, ollama_url: &str) -> Result<()> {
-    let client = Client::new();
+/// File counts for one collection in a run
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct CollectionProgress {
+    processed: usize,
+    failed: usize,
+    pending: usize,
+    #[serde(default)]
+    unchanged: usize,
+}
 
-    // Check Qdrant
-    client
-        .get(format!("{}/health", qdrant_url))
-        .send()
-        .context("Qdrant is not running. Please run: ./scripts/setup-qdrant.sh")?;
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct FailedPdf {
+    collection: String,
+    error: String,
+}
 
-    // Check Ollama
-    client
-        .get(format!("{}/api/tags", ollama_url))
-        .send()
-        .context("Ollama is not running. Please start it with: ollama serve")?;
+/// Progress of a directory run, saved after every file so that `--resume`
+/// and the final report survive a crash. Maps are keyed by file path.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct RunState {
+    /// Ingested files and their collection
+    done: BTreeMap<String, String>,
+    /// Files not attempted yet in this run
+    pending: BTreeMap<String, String>,
+    /// Files whose last attempt failed
+    failed: BTreeMap<String, FailedPdf>,
+    /// Files skipped because their content matched the last successful ingest
+    #[serde(default)]
+    unchanged: BTreeMap<String, String>,
+    /// Per-collection counts derived from the maps above
+    collections: BTreeMap<String, CollectionProgress>,
+}
+
+impl RunState {
+    fn is_done(&self, file: &Path) -> bool {
+        let key = file_key(file);
+        self.done.contains_key(&key) || self.unchanged.contains_key(&key)
+    }
+
+    /// How many of `files` are already handled, and how many are left to ingest
+    fn resume_counts<'a>(&self, files: impl IntoIterator<Item = &'a PathBuf>) -> (usize, usize) {
+        files.into_iter().fold((0, 0), |(done, remaining), file| {
+            if self.is_done(file) {
+                (done + 1, remaining)
+            } else {
+                (done, remaining + 1)
+            }
+        })
+    }
+
+    /// Queue a file unless it was already handled; earlier failures are retried
+    fn plan(&mut self, file: &Path, collection: &str) {
+        let key = file_key(file);
+        if !self.is_done(file) {
+            self.failed.remove(&key);
+            self.pending.insert(key, collection.to_string());
+            self.refresh_counts();
+        }
+    }
+
+    fn record(&mut self, file: &Path, collection: &str, outcome: Result<(), &anyhow::Error>) {
+        let key = file_key(file);
+        self.pending.remove(&key);
+        self.failed.remove(&key);
+        match outcome {
+            Ok(()) => {
+                self.done.insert(key, collection.to_string());
+            }
+            Err(e) => {
+                self.failed.insert(
+                    key,
+                    FailedPdf {
+                        collection: collection.to_string(),
+                        error: format!("{e:#}"),
+                    },
+                );
+            }
+        }
+        self.refresh_counts();
+    }
+
+    fn record_unchanged(&mut self, file: &Path, collection: &str) {
+        let key = file_key(file);
+        self.pending.remove(&key);
+        self.failed.remove(&key);
+        self.unchanged.insert(key, collection.to_string());
+        self.refresh_counts();
+    }
+
+    fn refresh_counts(&mut self) {
+        let mut counts: BTreeMap<String, CollectionProgress> = BTreeMap::new();
+        for collection in self.done.values() {
+            counts.entry(collection.clone()).or_default().processed += 1;
+        }
+        for failed in self.failed.values() {
+            counts.entry(failed.collection.clone()).or_default().failed += 1;
+        }
+        for collection in self.pending.values() {
+            counts.entry(collection.clone()).or_default().pending += 1;
+        }
+        for collection in self.unchanged.values() {
+            counts.entry(collection.clone()).or_default().unchanged += 1;
+        }
+        self.collections = counts;
+    }
+}
+
+/// Overall counts of a run, as written by `--summary-json`
+#[derive(Debug, PartialEq, Serialize)]
+struct RunTotals {
+    collections: usize,
+    files_ingested: usize,
+    files_unchanged: usize,
+    files_failed: usize,
+}
+
+/// Machine-readable report of a run for `--summary-json`
+#[derive(Debug, Serialize)]
+struct RunSummary<'a> {
+    totals: RunTotals,
+    collections: &'a BTreeMap<String, CollectionStats>,
+    /// Failed files and why, keyed by file path
+    failed: &'a BTreeMap<String, FailedPdf>,
+}
+
+impl<'a> RunSummary<'a> {
+    fn new(state: &'a RunState, collections: &'a BTreeMap<String, CollectionStats>) -> Self {
+        Self {
+            totals: RunTotals {
+                collections: collections.len(),
+                files_ingested: state.done.len(),
+                files_unchanged: state.unchanged.len(),
+                files_failed: state.failed.len(),
+            },
+            collections,
+            failed: &state.failed,
+        }
+    }
+}
+
+/// Content hash of a file at its last successful ingest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct IngestedFile {
+    sha256: String,
+    collection: String,
+}
+
+/// Hashes kept across runs so unchanged files are not ingested again
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct HashState {
+    /// File path -> hash and collection of its last successful ingest
+    files: BTreeMap<String, IngestedFile>,
+}
+
+impl HashState {
+    /// Whether `file` was last ingested into `collection` with exactly this content
+    fn is_unchanged(&self, file: &Path, sha256: &str, collection: &str) -> bool {
+        self.files
+            .get(&file_key(file))
+            .is_some_and(|f| f.sha256 == sha256 && f.collection == collection)
+    }
+
+    fn previous(&self, file: &Path) -> Option<&IngestedFile> {
+        self.files.get(&file_key(file))
+    }
+
+    fn record(&mut self, file: &Path, sha256: &str, collection: &str) {
+        self.files.insert(
+            file_key(file),
+            IngestedFile {
+                sha256: sha256.to_string(),
+                collection: collection.to_string(),
+            },
+        );
+    }
+}
+
+/// Load JSON state from `path`, or start empty if there is none yet
+fn load_state<T: DeserializeOwned + Default>(path: &Path) -> Result<T> {
+    if !path.exists() {
+        return Ok(T::default());
+    }
+    let json = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read state file {}", path.display()))?;
+    serde_json::from_str(&json)
+        .with_context(|| format!("Failed to parse state file {}", path.display()))
+}
+
+/// Write state through a temporary file so a crash never leaves it half written
+fn save_state<T: Serialize>(state: &T, path: &Path) -> Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(state)?)
+        .with_context(|| format!("Failed to write state file {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("Failed to write state file {}", path.display()))
+}
+
+fn file_key(file: &Path) -> String {
+    file.to_string_lossy().into_owned()
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(Sha256::digest(&bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
+
+/// Kind of document, which decides how it is chunked and ingested
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DocKind {
+    /// Converted to markdown (pdf-to-markdown.sh), then ingested like markdown
+    Pdf,
+    /// Code-aware markdown chunks (ingest-markdown)
+    Markdown,
+    /// Fixed-size token chunks (pdf-to-embeddings)
+    Text,
+}
 
-    // Check embedding model
-    let output = Command::new("ollama")
-        .args(["list"])
-        .output()
-        .context("Failed to run ollama list")?;
+impl DocKind {
+    fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "pdf" => Some(DocKind::Pdf),
+            "md" | "markdown" => Some(DocKind::Markdown),
+            "txt" => Some(DocKind::Text),
+            _ => None,
+        }
+    }
+}
 
-    if !String::from_utf8_lossy(&output.stdout).contains("nomic-embed-text") {
+/// Markdown file pdf-to-markdown.sh writes for `pdf`, mirroring its path
+/// under `root` so same-named PDFs in different subdirectories stay apart;
+/// its chunks store this as `source_path`
+fn extracted_markdown_path(root: &Path, pdf: &Path) -> PathBuf {
+    let relative = pdf.strip_prefix(root).unwrap_or(pdf);
+    Path::new("./extracted").join(relative.with_extension("md"))
+}
+
+/// `source_path` stored on the chunks of `file`
+fn ingested_source_path(root: &Path, file: &Path, kind: DocKind) -> String {
+    match kind {
+        DocKind::Pdf => extracted_markdown_path(root, file)
+            .to_string_lossy()
+            .into_owned(),
+        DocKind::Markdown | DocKind::Text => file.to_string_lossy().into_owned(),
+    }
+}
+
+/// Locate an ingestion binary next to this one, falling back to PATH
+fn binary_path(name: &str) -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(name)))
+        .filter(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from(name))
+}
+
+/// Delete the points from a previous ingest of `file`, then ingest it again
+fn reingest_file(
+    qdrant: &QdrantClient,
+    args: &Args,
+    previous: Option<&IngestedFile>,
+    file: &Path,
+    collection: &str,
+) -> Result<()> {
+    let kind = DocKind::from_path(file).context("Unsupported file type")?;
+    if let Some(previous) = previous {
+        println!(
+            "   {} Removing stale vectors from {}",
+            "🧹".yellow(),
+            previous.collection
+        );
+        qdrant.delete_points(
+            &previous.collection,
+            &json!({
+                "must": [{
+                    "key": "source_path",
+                    "match": { "value": ingested_source_path(&args.ingest_dir, file, kind) }
+                }]
+            }),
+        )?;
+    }
+    ingest_file(args, file, kind, collection)
+}
+
+/// Supported documents directly in `dir`, or anywhere beneath it when
+/// `recursive`, skipping hidden subdirectories
+fn find_documents(dir: &Path, recursive: bool) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            let hidden = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if recursive && !hidden {
+                files.extend(find_documents(&path, recursive)?);
+            }
+        } else if DocKind::from_path(&path).is_some() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn check_services(qdrant_url: &str, ollama_url: &str) -> Result<()> {
+    // Pull the embedding model if needed, then let preflight confirm it worked
+    if !ollama_has_model(ollama_url, EMBED_MODEL)? {
         println!("{}", "📦 Pulling embedding model...".yellow());
         Command::new("ollama")
-            .args(["pull", "nomic-embed-text"])
+            .args(["pull", EMBED_MODEL])
             .status()
             .context("Failed to pull embedding model")?;
     }
 
-    Ok(())
+    preflight(qdrant_url, ollama_url, EMBED_MODEL, EmbedApi::Ollama)
 }
 
 fn ensure_collection_exists(
-    client: &Client,
-    qdrant_url: &str,
+    qdrant: &QdrantClient,
     collection_name: &str,
+    distance: Distance,
+    quantization: Quantization,
 ) -> Result<()> {
-    // Check if collection exists
-    let check_response = client
-        .get(format!("{}/collections/{}", qdrant_url, collection_name))
-        .send();
-
-    if check_response.is_err() || !check_response.unwrap().status().is_success() {
-        println!(
-            "   {} Creating collection: {}",
-            "📦".yellow(),
-            collection_name.cyan()
-        );
-
-        // Create collection
-        let response = client
-            .put(format!("{}/collections/{}", qdrant_url, collection_name))
-            .json(&json!({
-                "vectors": {
-                    "size": 768,
-                    "distance": "Cosine"
-                },
-                "optimizers_config": {
-                    "default_segment_number": 2,
-                    "indexing_threshold": 1000
-                }
-            }))
-            .send()
-            .context("Failed to create collection")?;
+    if qdrant.collection_exists(collection_name) {
+        qdrant.warn_existing_settings(collection_name, distance, quantization);
+        return Ok(());
+    }
 
-        if !response.status().is_success() {
-            anyhow::bail!("Failed to create collection: {}", response.status());
+    println!(
+        "   {} Creating collection: {}",
+        "📦".yellow(),
+        collection_name.cyan()
+    );
+    let mut config = json!({
+        "vectors": {
+            "size": 768,
+            "distance": distance.qdrant_name()
+        },
+        "optimizers_config": {
+            "default_segment_number": 2,
+            "indexing_threshold": 1000
         }
-    }
+    });
+    quantization.apply(&mut config);
+    qdrant.create_collection(collection_name, &config)
+}
 
-    Ok(())
+/// Wait for Qdrant to finish indexing `collection_name`, showing indexed
+/// vectors as it goes. A timeout is reported but does not fail the run.
+fn wait_for_index(qdrant: &QdrantClient, collection_name: &str, timeout: Duration) {
+    println!("   {} Waiting for indexing to finish...", "⏳".yellow());
+    let waited = qdrant.wait_for_green(collection_name, timeout, WAIT_INDEX_POLL, |info| {
+        print!(
+            "   Indexed {}/{} vectors ({})\r",
+            info.indexed_vectors_count.unwrap_or(0),
+            info.points_count.unwrap_or(0),
+            info.status.as_deref().unwrap_or("unknown")
+        );
+    });
+    println!();
+    match waited {
+        Ok(_) => println!("   {} Index ready", "✓".green()),
+        Err(e) => println!("   {} {e:#}", "⚠️ ".yellow()),
+    }
 }
 
-fn ingest_pdf(pdf_path: &Path, collection: &str) -> Result<()> {
-    let pdf_str = pdf_path.to_str().context("Invalid path")?;
+fn ingest_file(args: &Args, path: &Path, kind: DocKind, collection: &str) -> Result<()> {
+    let path_str = path.to_str().context("Invalid path")?;
 
-    // Use the smart ingestion script with hierarchical chunking
-    let status = Command::new("bash")
-        .env("RAG_COLLECTION", collection)
-        .args(["./scripts/ingest-pdf-smart.sh", pdf_str])
-        .status()
-        .context("Failed to run ingestion script")?;
+    let extracted = extracted_markdown_path(&args.ingest_dir, path);
+    let (binary, input) = match kind {
+        // Convert here and ingest the markdown directly, so PDFs go to the
+        // same --qdrant-url and --ollama-url as every other file
+        DocKind::Pdf => {
+            let out_dir = extracted.parent().context("Invalid path")?;
+            let status = Command::new("bash")
+                .arg("./scripts/pdf-to-markdown.sh")
+                .args([path.as_os_str(), out_dir.as_os_str()])
+                .status()
+                .context("Failed to run PDF conversion")?;
+            if !status.success() {
+                anyhow::bail!("Markdown conversion failed for {}", path_str);
+            }
+            (
+                "ingest-markdown",
+                extracted.to_str().context("Invalid path")?,
+            )
+        }
+        DocKind::Markdown => ("ingest-markdown", path_str),
+        DocKind::Text => ("pdf-to-embeddings", path_str),
+    };
+    let mut command = Command::new(binary_path(binary));
+    if kind == DocKind::Pdf {
+        // Cite the PDF rather than the intermediate markdown
+        command.args(["--source-label", path_str]);
+    }
+    command.args([
+        input,
+        "--collection",
+        collection,
+        "--qdrant-url",
+        &args.qdrant_url,
+        "--ollama-url",
+        &args.ollama_url,
+        "--skip-checks",
+    ]);
+    // The collection name is already namespaced
+    command
+        .env("RAG_COLLECTION_PREFIX", "")
+        .env("RAG_COLLECTION_SUFFIX", "");
+    // Through the environment rather than argv, where other users could see it
+    if let Some(key) = &args.qdrant_api_key {
+        command.env("QDRANT_API_KEY", key.as_str());
+    }
+    http_options().forward(&mut command);
+    let status = command.status().context("Failed to run ingestion")?;
 
     if !status.success() {
-        anyhow::bail!("Ingestion failed for {}", pdf_str);
+        anyhow::bail!("Ingestion failed for {}", path_str);
     }
 
     Ok(())
@@ -150,17 +616,35 @@ fn get_collection_stats(
     let result = &json["result"];
 
     Ok(CollectionStats {
-        pdfs_processed: 0, // Will be tracked during processing
-        pdfs_failed: 0,
+        files_processed: 0, // Will be tracked during processing
+        files_failed: 0,
+        files_unchanged: 0,
         total_vectors: result["points_count"].as_u64().unwrap_or(0) as usize,
         indexed_vectors: result["indexed_vectors_count"].as_u64().unwrap_or(0) as usize,
         status: result["status"].as_str().unwrap_or("unknown").to_string(),
     })
 }
 
+fn print_directory_header(dir_name: &str, collection_name: &str, file_count: usize) {
+    println!("{}", "━".repeat(50).blue());
+    println!("{} Processing: {}", "📂".cyan(), dir_name.bold());
+    let derived = format!("{dir_name}-books");
+    if derived == collection_name {
+        println!("   Collection: {}", collection_name.cyan());
+    } else {
+        println!(
+            "   Collection: {} (normalized from \"{}\")",
+            collection_name.cyan(),
+            derived
+        );
+    }
+    println!("   Files: {}", file_count.to_string().green());
+    println!("{}", "━".repeat(50).blue());
+}
+
 fn main() -> Result<()> {
-    let args = Args::parse();
-    let client = Client::new();
+    let args: Args = parse_args()?;
+    let client = qdrant_http_client(args.qdrant_api_key.as_ref());
 
     println!("{}", "📚 Directory-Based Ingestion System".cyan().bold());
     println!("{}", "═".repeat(50).blue());
@@ -179,12 +663,22 @@ fn main() -> Result<()> {
         println!();
     }
 
+    let state_path = args
+        .state_file
+        .clone()
+        .unwrap_or_else(|| args.ingest_dir.join(STATE_FILE_NAME));
+    let mut state = if args.resume {
+        load_state(&state_path)?
+    } else {
+        RunState::default()
+    };
+
     // Track overall statistics
-    let mut total_pdfs = 0;
-    let mut total_failed = 0;
-    let mut collections_processed: HashMap<String, CollectionStats> = HashMap::new();
+    let mut collections_processed: BTreeMap<String, CollectionStats> = BTreeMap::new();
 
-    // Process each subdirectory
+    // Find the files in each subdirectory before ingesting, so the state lists everything pending
+    let mut directories: Vec<(String, String, Vec<PathBuf>)> = Vec::new();
+    let mut collection_dirs: HashMap<String, String> = HashMap::new();
     let entries = fs::read_dir(&args.ingest_dir).context("Failed to read ingest directory")?;
 
     for entry in entries {
@@ -205,72 +699,144 @@ fn main() -> Result<()> {
             continue;
         }
 
-        let collection_name = format!("{}-books", dir_name);
-
-        // Find PDFs in this directory
-        let pdfs: Vec<PathBuf> = fs::read_dir(&path)?
-            .filter_map(|e| e.ok())
-            .map(|e| e.path())
-            .filter(|p| {
-                p.extension()
-                    .and_then(|ext| ext.to_str())
-                    .map(|ext| ext.eq_ignore_ascii_case("pdf"))
-                    .unwrap_or(false)
-            })
-            .collect();
+        let collection_name = namespaced_collection(
+            &args.collection_prefix,
+            &normalize_collection_name(&format!("{dir_name}-books")),
+            &args.collection_suffix,
+        );
+
+        // Find PDF, markdown and text files in this directory
+        let files = find_documents(&path, args.recursive)?;
 
-        if pdfs.is_empty() {
-            println!("{} No PDFs found in {}/", "⚠️ ".yellow(), dir_name);
+        if files.is_empty() {
+            println!(
+                "{} No PDF, markdown or text files found in {}/",
+                "⚠️ ".yellow(),
+                dir_name
+            );
             continue;
         }
 
-        println!("{}", "━".repeat(50).blue());
-        println!("{} Processing: {}", "📂".cyan(), dir_name.bold());
-        println!("   Collection: {}", collection_name.cyan());
-        println!("   PDF files: {}", pdfs.len().to_string().green());
-        println!("{}", "━".repeat(50).blue());
+        if let Some(other) = collection_dirs.insert(collection_name.clone(), dir_name.to_string()) {
+            println!(
+                "{} {}/ and {}/ both map to collection {}; their files will share it",
+                "⚠️ ".yellow(),
+                other,
+                dir_name,
+                collection_name
+            );
+        }
 
         if args.dry_run {
+            print_directory_header(dir_name, &collection_name, files.len());
             println!("{} Dry run - would process:", "🔍".yellow());
-            for pdf in &pdfs {
-                if let Some(name) = pdf.file_name() {
-                    println!("   • {}", name.to_string_lossy());
-                }
+            for file in &files {
+                println!(
+                    "   • {}",
+                    file.strip_prefix(&path).unwrap_or(file).display()
+                );
             }
             continue;
         }
 
-        // Ensure collection exists
-        ensure_collection_exists(&client, &args.qdrant_url, &collection_name)?;
+        for file in &files {
+            state.plan(file, &collection_name);
+        }
+        directories.push((dir_name.to_string(), collection_name, files));
+    }
+    if !args.dry_run {
+        save_state(&state, &state_path)?;
+        if args.resume {
+            let (done, remaining) =
+                state.resume_counts(directories.iter().flat_map(|(_, _, files)| files));
+            println!(
+                "{} Resuming from {}: {} already done, {} remaining",
+                "↻".cyan(),
+                state_path.display(),
+                done,
+                remaining
+            );
+        }
+    }
+
+    let hash_path = args.ingest_dir.join(HASH_STATE_FILE_NAME);
+    let mut hashes: HashState = load_state(&hash_path)?;
+    let qdrant = QdrantClient::new(&args.qdrant_url).api_key(args.qdrant_api_key.as_ref());
 
-        // Process each PDF
-        let mut processed = 0;
-        let mut failed = 0;
+    // Process each subdirectory
+    for (dir_name, collection_name, files) in &directories {
+        print_directory_header(dir_name, collection_name, files.len());
 
-        for pdf in &pdfs {
-            if let Some(pdf_name) = pdf.file_name() {
+        // Ensure collection exists
+        ensure_collection_exists(&qdrant, collection_name, args.distance, args.quantization)?;
+
+        // Process each file, saving the run state after every one
+        for file in files {
+            if let Some(file_name) = file.file_name() {
                 println!();
-                println!("{} Ingesting: {}", "📄".cyan(), pdf_name.to_string_lossy());
+                if state.is_done(file) {
+                    println!(
+                        "{} Already ingested: {}",
+                        "⏭".yellow(),
+                        file_name.to_string_lossy()
+                    );
+                    continue;
+                }
+
+                let sha256 = sha256_file(file);
+                if let Ok(sha256) = &sha256
+                    && !args.force_reingest
+                    && hashes.is_unchanged(file, sha256, collection_name)
+                {
+                    println!(
+                        "{} Unchanged since last ingest: {}",
+                        "⏭".yellow(),
+                        file_name.to_string_lossy()
+                    );
+                    state.record_unchanged(file, collection_name);
+                    save_state(&state, &state_path)?;
+                    continue;
+                }
+                println!("{} Ingesting: {}", "📄".cyan(), file_name.to_string_lossy());
 
-                match ingest_pdf(pdf, &collection_name) {
-                    Ok(_) => {
-                        processed += 1;
-                        total_pdfs += 1;
+                let outcome = sha256.and_then(|sha256| {
+                    reingest_file(&qdrant, &args, hashes.previous(file), file, collection_name)?;
+                    Ok(sha256)
+                });
+                match &outcome {
+                    Ok(sha256) => {
+                        hashes.record(file, sha256, collection_name);
+                        save_state(&hashes, &hash_path)?;
                         println!("   {} Successfully ingested", "✓".green());
                     }
-                    Err(e) => {
-                        failed += 1;
-                        total_failed += 1;
-                        println!("   {} Failed: {}", "✗".red(), e);
-                    }
+                    Err(e) => println!("   {} Failed: {}", "✗".red(), e),
                 }
+                state.record(file, collection_name, outcome.as_ref().map(|_| ()));
+                save_state(&state, &state_path)?;
             }
         }
 
+        if let Err(e) = qdrant.create_payload_indexes(collection_name, &args.index_fields) {
+            eprintln!("⚠️  Could not index payload fields: {e:#}");
+        }
+        if args.wait_index {
+            wait_for_index(
+                &qdrant,
+                collection_name,
+                Duration::from_secs(args.wait_index_timeout),
+            );
+        }
+
         // Get collection statistics
-        if let Ok(mut stats) = get_collection_stats(&client, &args.qdrant_url, &collection_name) {
-            stats.pdfs_processed = processed;
-            stats.pdfs_failed = failed;
+        if let Ok(mut stats) = get_collection_stats(&client, &args.qdrant_url, collection_name) {
+            let progress = state
+                .collections
+                .get(collection_name)
+                .copied()
+                .unwrap_or_default();
+            stats.files_processed = progress.processed;
+            stats.files_failed = progress.failed;
+            stats.files_unchanged = progress.unchanged;
 
             println!();
             println!(
@@ -279,11 +845,20 @@ fn main() -> Result<()> {
                 collection_name
             );
             println!(
-                "   • PDFs processed: {}",
-                stats.pdfs_processed.to_string().green()
+                "   • Files processed: {}",
+                stats.files_processed.to_string().green()
             );
-            if stats.pdfs_failed > 0 {
-                println!("   • PDFs failed: {}", stats.pdfs_failed.to_string().red());
+            if stats.files_failed > 0 {
+                println!(
+                    "   • Files failed: {}",
+                    stats.files_failed.to_string().red()
+                );
+            }
+            if stats.files_unchanged > 0 {
+                println!(
+                    "   • Files unchanged (skipped): {}",
+                    stats.files_unchanged.to_string().yellow()
+                );
             }
             println!(
                 "   • Total vectors: {}",
@@ -322,11 +897,21 @@ fn main() -> Result<()> {
         collections_processed.len().to_string().green()
     );
     println!(
-        "   • Total PDFs ingested: {}",
-        total_pdfs.to_string().green()
+        "   • Total files ingested: {}",
+        state.done.len().to_string().green()
+    );
+    println!(
+        "   • Unchanged files skipped: {}",
+        state.unchanged.len().to_string().yellow()
     );
-    if total_failed > 0 {
-        println!("   • Total PDFs failed: {}", total_failed.to_string().red());
+    if !state.failed.is_empty() {
+        println!(
+            "   • Total files failed: {}",
+            state.failed.len().to_string().red()
+        );
+    }
+    if !args.dry_run {
+        println!("   • Run state: {}", state_path.display());
     }
     println!();
 
@@ -335,7 +920,10 @@ fn main() -> Result<()> {
         for (name, stats) in &collections_processed {
             println!();
             println!("   {}:", name.bold());
-            println!("      • PDFs: {}", stats.pdfs_processed.to_string().green());
+            println!(
+                "      • Files: {}",
+                stats.files_processed.to_string().green()
+            );
             println!(
                 "      • Vectors: {}",
                 stats.total_vectors.to_string().cyan()
@@ -375,5 +963,228 @@ fn main() -> Result<()> {
         "http://localhost:6333/dashboard".blue().underline()
     );
 
+    if let Some(path) = &args.summary_json {
+        save_state(&RunSummary::new(&state, &collections_processed), path)?;
+        println!("   Summary written to {}", path.display());
+    }
+
+    if args.fail_on_error && !state.failed.is_empty() {
+        anyhow::bail!(
+            "{} files failed to ingest (see {})",
+            state.failed.len(),
+            state_path.display()
+        );
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_file_tracks_processed_and_pending() {
+        let path = std::env::temp_dir().join(format!("rag-run-state-{}.json", std::process::id()));
+        let (a, b, c) = (
+            Path::new("ingest/rust/a.pdf"),
+            Path::new("ingest/rust/b.pdf"),
+            Path::new("ingest/lisp/c.pdf"),
+        );
+
+        let mut state = RunState::default();
+        state.plan(a, "rust-books");
+        state.plan(b, "rust-books");
+        state.plan(c, "lisp-books");
+        state.record(a, "rust-books", Ok(()));
+        state.record(b, "rust-books", Err(&anyhow::anyhow!("script exited 1")));
+        save_state(&state, &path).unwrap();
+
+        let saved: RunState = load_state(&path).unwrap();
+        assert_eq!(saved, state);
+        assert!(saved.is_done(a));
+        assert_eq!(saved.failed[&file_key(b)].error, "script exited 1");
+        assert!(saved.pending.contains_key(&file_key(c)));
+        assert_eq!(
+            saved.collections["rust-books"],
+            CollectionProgress {
+                processed: 1,
+                failed: 1,
+                pending: 0,
+                unchanged: 0,
+            }
+        );
+        assert_eq!(saved.collections["lisp-books"].pending, 1);
+
+        // Resuming re-plans everything but keeps finished PDFs done
+        let mut resumed = saved;
+        resumed.plan(a, "rust-books");
+        resumed.plan(b, "rust-books");
+        let files = [a, b, c].map(Path::to_path_buf);
+        assert_eq!(resumed.resume_counts(&files), (1, 2));
+        resumed.record(b, "rust-books", Ok(()));
+        assert!(resumed.failed.is_empty());
+        assert_eq!(resumed.collections["rust-books"].processed, 2);
+        assert_eq!(resumed.collections["rust-books"].pending, 0);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_summary_totals_match_run_state() {
+        let mut state = RunState::default();
+        let (a, b, c) = (
+            Path::new("ingest/rust/a.pdf"),
+            Path::new("ingest/rust/b.pdf"),
+            Path::new("ingest/rust/c.md"),
+        );
+        state.record(a, "rust-books", Ok(()));
+        state.record(b, "rust-books", Err(&anyhow::anyhow!("script exited 1")));
+        state.record_unchanged(c, "rust-books");
+        let mut collections = BTreeMap::new();
+        collections.insert(
+            "rust-books".to_string(),
+            CollectionStats {
+                files_processed: 1,
+                files_failed: 1,
+                files_unchanged: 1,
+                total_vectors: 42,
+                indexed_vectors: 40,
+                status: "green".to_string(),
+            },
+        );
+
+        let summary = serde_json::to_value(RunSummary::new(&state, &collections)).unwrap();
+
+        assert_eq!(
+            summary["totals"],
+            json!({
+                "collections": 1,
+                "files_ingested": 1,
+                "files_unchanged": 1,
+                "files_failed": 1,
+            })
+        );
+        assert_eq!(summary["collections"]["rust-books"]["total_vectors"], 42);
+        assert_eq!(summary["failed"][file_key(b)]["error"], "script exited 1");
+    }
+
+    #[test]
+    fn test_doc_kind_dispatch_and_source_path() {
+        let kind = |path: &str| DocKind::from_path(Path::new(path));
+        assert_eq!(kind("ingest/rust/book.PDF"), Some(DocKind::Pdf));
+        assert_eq!(kind("ingest/rust/notes.md"), Some(DocKind::Markdown));
+        assert_eq!(kind("ingest/rust/notes.markdown"), Some(DocKind::Markdown));
+        assert_eq!(kind("ingest/rust/errata.txt"), Some(DocKind::Text));
+        assert_eq!(kind("ingest/rust/cover.png"), None);
+        assert_eq!(kind("ingest/rust/README"), None);
+
+        assert_eq!(
+            ingested_source_path(
+                Path::new("ingest"),
+                Path::new("ingest/rust/book.pdf"),
+                DocKind::Pdf
+            ),
+            "./extracted/rust/book.md"
+        );
+        assert_eq!(
+            ingested_source_path(
+                Path::new("ingest"),
+                Path::new("ingest/rust/notes.md"),
+                DocKind::Markdown
+            ),
+            "ingest/rust/notes.md"
+        );
+    }
+
+    #[test]
+    fn test_find_documents_only_recurses_when_asked() {
+        let root = std::env::temp_dir().join(format!("rag-find-docs-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in ["advanced/async", ".cache"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "intro.pdf",
+            "cover.png",
+            "advanced/traits.md",
+            "advanced/async/pinning.txt",
+            ".cache/stale.pdf",
+        ] {
+            fs::write(root.join(file), b"").unwrap();
+        }
+        let relative = |files: Vec<PathBuf>| -> Vec<String> {
+            files
+                .iter()
+                .map(|f| {
+                    f.strip_prefix(&root)
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            relative(find_documents(&root, false).unwrap()),
+            vec!["intro.pdf"]
+        );
+        assert_eq!(
+            relative(find_documents(&root, true).unwrap()),
+            vec![
+                "advanced/async/pinning.txt",
+                "advanced/traits.md",
+                "intro.pdf"
+            ]
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_sha256_of_known_bytes() {
+        let path = std::env::temp_dir().join(format!("rag-sha-{}.pdf", std::process::id()));
+        fs::write(&path, b"abc").unwrap();
+
+        let hash = sha256_file(&path).unwrap();
+
+        assert_eq!(
+            hash,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_hash_state_detects_unchanged_and_changed_files() {
+        let pdf = Path::new("ingest/rust/book.pdf");
+        let mut hashes = HashState::default();
+        assert!(!hashes.is_unchanged(pdf, "aaa", "rust-books"));
+
+        hashes.record(pdf, "aaa", "rust-books");
+
+        assert!(hashes.is_unchanged(pdf, "aaa", "rust-books"));
+        assert!(!hashes.is_unchanged(pdf, "bbb", "rust-books"));
+        assert!(!hashes.is_unchanged(pdf, "aaa", "other-books"));
+        assert_eq!(hashes.previous(pdf).unwrap().collection, "rust-books");
+    }
+
+    #[test]
+    fn test_same_named_pdfs_in_different_directories_stay_apart() {
+        let root = Path::new("ingest/rust");
+        let a = Path::new("ingest/rust/a/intro.pdf");
+        let b = Path::new("ingest/rust/b/intro.pdf");
+
+        assert_eq!(
+            extracted_markdown_path(root, a),
+            Path::new("./extracted/a/intro.md")
+        );
+        assert_ne!(
+            extracted_markdown_path(root, a),
+            extracted_markdown_path(root, b)
+        );
+        assert_ne!(
+            ingested_source_path(root, a, DocKind::Pdf),
+            ingested_source_path(root, b, DocKind::Pdf)
+        );
+    }
+}
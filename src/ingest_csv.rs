@@ -0,0 +1,581 @@
+// Copyright (c) 2025 Michael A. Wright
+// Licensed under the MIT License
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use rag_demo::config::parse_args;
+use rag_demo::rag::{
+    ApiKey, ChunkPlan, Consistency, EmbedApi, Embedder, OnOversize, OversizePolicy, QdrantClient,
+    QdrantPoint, RetryPolicy, TokenCounter, Tokenizer, detect_lang, limit_chunks,
+    namespaced_collection, preflight, print_chunk_plan, progress_bar,
+};
+use serde_json::{Map, Value, json};
+use std::collections::HashSet;
+use std::path::Path;
+use uuid::Uuid;
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Ingest CSV/TSV files, one point per row with the columns as payload fields",
+    long_about = None
+)]
+struct Args {
+    #[arg(help = "Path to a CSV or TSV file with a header row")]
+    csv_path: String,
+
+    #[arg(
+        long,
+        help = "Field delimiter: one ASCII character, or \"tab\" (default: tab for .tsv files, comma otherwise)"
+    )]
+    delimiter: Option<String>,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Columns whose values make up the embedded text, comma-separated (default: all); the rest are payload only"
+    )]
+    text_columns: Vec<String>,
+
+    #[arg(
+        long,
+        default_value = "documents",
+        env = "RAG_COLLECTION",
+        help = "Qdrant collection name"
+    )]
+    collection: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_PREFIX",
+        help = "Prepended to the collection name, e.g. acme_ to keep one tenant's collections apart"
+    )]
+    collection_prefix: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_SUFFIX",
+        help = "Appended to the collection name"
+    )]
+    collection_suffix: String,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:6333",
+        env = "QDRANT_URL",
+        help = "Qdrant URL"
+    )]
+    qdrant_url: String,
+
+    #[arg(
+        long,
+        env = "QDRANT_API_KEY",
+        hide_env_values = true,
+        help = "Qdrant API key, sent as the api-key header (Qdrant Cloud)"
+    )]
+    qdrant_api_key: Option<ApiKey>,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:11434",
+        env = "OLLAMA_URL",
+        help = "Ollama URL"
+    )]
+    ollama_url: String,
+
+    #[arg(
+        short,
+        long,
+        default_value = "nomic-embed-text",
+        env = "RAG_MODEL",
+        help = "Embedding model"
+    )]
+    model: String,
+
+    #[arg(
+        long,
+        default_value = "text",
+        help = "Payload field that stores the chunk text"
+    )]
+    text_field: String,
+
+    #[arg(
+        long,
+        help = "Human-friendly name stored as the payload source (default: file path); also keys the point IDs"
+    )]
+    source_label: Option<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "ollama",
+        help = "Embedding API: ollama (/api/embeddings), ollama-embed (/api/embed), openai (/v1/embeddings)"
+    )]
+    embed_api: EmbedApi,
+
+    #[arg(
+        long,
+        help = "Skip checking that Qdrant, Ollama and the embedding model are available"
+    )]
+    skip_checks: bool,
+
+    #[arg(
+        long,
+        default_value = "4",
+        help = "Times to retry a failed embedding request (5xx, 429, connection errors)"
+    )]
+    embed_retries: u32,
+
+    #[arg(
+        long,
+        default_value = "500",
+        help = "Delay before the first embedding retry in ms, doubled on each retry"
+    )]
+    embed_retry_delay_ms: u64,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "strong",
+        help = "strong waits for each upsert to be applied, so points are searchable and counted when ingestion ends; eventual returns as soon as Qdrant accepts them"
+    )]
+    consistency: Consistency,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "cl100k",
+        help = "Tokenizer used to measure row sizes (chars estimates 4 characters per token)"
+    )]
+    tokenizer: Tokenizer,
+
+    #[arg(
+        long,
+        default_value = "2048",
+        help = "Token limit for a row's text at embedding time"
+    )]
+    max_tokens: usize,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "split",
+        help = "Text over --max-tokens at embedding time: split embeds pieces and averages them, truncate keeps the start, error fails"
+    )]
+    on_oversize: OnOversize,
+
+    #[arg(
+        long,
+        default_value = "32",
+        help = "Rows embedded per request to Ollama's /api/embed"
+    )]
+    embed_batch_size: usize,
+
+    #[arg(
+        long,
+        help = "Embed one row per request with --embed-api instead of batching through /api/embed"
+    )]
+    legacy_embed: bool,
+
+    #[arg(
+        long,
+        help = "Print each row's size and line, then exit without embedding or uploading"
+    )]
+    dry_run: bool,
+
+    #[arg(long, help = "Like --dry-run, but print the rows as JSON")]
+    dry_run_json: bool,
+
+    #[arg(
+        long,
+        help = "Only embed and upload the first N rows, for quick trial runs on big files"
+    )]
+    max_chunks: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Hide progress bars (also hidden when stdout is not a terminal)"
+    )]
+    quiet: bool,
+}
+
+/// Payload fields this tool writes itself; columns with these names are
+/// stored as `column_<name>` instead
+const RESERVED_FIELDS: &[&str] = &[
+    "source",
+    "source_path",
+    "ingested_at",
+    "embed_model",
+    "row",
+    "line",
+    "chunk_index",
+    "total_chunks",
+    "chunk_type",
+    "lang",
+];
+
+/// A data row: its embedded text and the column values to store
+#[derive(Debug, PartialEq)]
+struct Row {
+    /// Data row index, counting from 0 after the header
+    index: usize,
+    /// Line of the file the row starts on (1-based, as the csv reader counts)
+    line: u64,
+    text: String,
+    /// Column name -> value; empty cells are left out
+    fields: Map<String, Value>,
+}
+
+/// Delimiter byte from `--delimiter`, or from the file extension
+fn delimiter_byte(flag: Option<&str>, path: &str) -> Result<u8> {
+    let Some(flag) = flag else {
+        let tsv = Path::new(path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("tsv") || ext.eq_ignore_ascii_case("tab"));
+        return Ok(if tsv { b'\t' } else { b',' });
+    };
+    match flag {
+        "tab" | "\\t" | "\t" => Ok(b'\t'),
+        _ if flag.len() == 1 && flag.is_ascii() => Ok(flag.as_bytes()[0]),
+        _ => anyhow::bail!("--delimiter must be a single ASCII character or \"tab\", got {flag:?}"),
+    }
+}
+
+/// Header names made usable as payload keys: trimmed, unnamed columns called
+/// `column_<n>`, repeats numbered, and names the tool writes itself prefixed
+fn column_names(headers: &csv::StringRecord, text_field: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    headers
+        .iter()
+        .enumerate()
+        .map(|(i, header)| {
+            let header = header.trim();
+            let mut name = if header.is_empty() {
+                format!("column_{}", i + 1)
+            } else if RESERVED_FIELDS.contains(&header) || header == text_field {
+                format!("column_{header}")
+            } else {
+                header.to_string()
+            };
+            let base = name.clone();
+            let mut n = 2;
+            while !seen.insert(name.clone()) {
+                name = format!("{base}_{n}");
+                n += 1;
+            }
+            name
+        })
+        .collect()
+}
+
+/// A cell as JSON: a number when it reads back exactly as written (so "42"
+/// and "3.5" can be range-filtered but "007" and "1e3" stay strings)
+fn cell_value(cell: &str) -> Value {
+    if let Ok(n) = cell.parse::<i64>()
+        && n.to_string() == cell
+    {
+        return json!(n);
+    }
+    if let Ok(x) = cell.parse::<f64>()
+        && x.is_finite()
+        && x.to_string() == cell
+    {
+        return json!(x);
+    }
+    json!(cell)
+}
+
+/// Read every data row of `data`. Rows may have more or fewer cells than
+/// the header; extra cells get `column_<n>` names.
+fn read_rows(
+    data: impl std::io::Read,
+    delimiter: u8,
+    text_columns: &[String],
+    text_field: &str,
+) -> Result<(Vec<String>, Vec<Row>)> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(data);
+    let headers = reader.headers().context("Failed to read the header row")?;
+    let columns = column_names(headers, text_field);
+    let raw_headers: Vec<String> = headers.iter().map(|h| h.trim().to_string()).collect();
+
+    // --text-columns may use the header as written or the stored name
+    let mut text_indices = Vec::new();
+    for wanted in text_columns {
+        let index = raw_headers
+            .iter()
+            .position(|h| h == wanted)
+            .or_else(|| columns.iter().position(|c| c == wanted))
+            .with_context(|| {
+                format!(
+                    "--text-columns names unknown column '{wanted}'; the columns are: {}",
+                    raw_headers.join(", ")
+                )
+            })?;
+        text_indices.push(index);
+    }
+    if text_indices.is_empty() {
+        text_indices = (0..columns.len()).collect();
+    }
+
+    let mut rows = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let record = record.with_context(|| format!("Failed to read data row {}", index + 1))?;
+        let line = record.position().map_or(0, |p| p.line());
+        let name = |i: usize| {
+            columns
+                .get(i)
+                .cloned()
+                .unwrap_or_else(|| format!("column_{}", i + 1))
+        };
+
+        let mut fields = Map::new();
+        for (i, cell) in record.iter().enumerate() {
+            let cell = cell.trim();
+            if !cell.is_empty() {
+                fields.insert(name(i), cell_value(cell));
+            }
+        }
+        let text = text_indices
+            .iter()
+            .filter_map(|&i| {
+                let cell = record.get(i)?.trim();
+                let label = raw_headers.get(i).filter(|h| !h.is_empty()).cloned();
+                (!cell.is_empty()).then(|| format!("{}: {cell}", label.unwrap_or_else(|| name(i))))
+            })
+            .collect::<Vec<_>>()
+            .join(" | ");
+        rows.push(Row {
+            index,
+            line,
+            text,
+            fields,
+        });
+    }
+    Ok((columns, rows))
+}
+
+/// Point ID of a row: the same file (or `--source-label`) and row index
+/// always give the same ID, so re-ingesting a file overwrites its rows
+fn row_id(source: &str, row: usize) -> String {
+    Uuid::new_v5(
+        &Uuid::NAMESPACE_URL,
+        format!("{source}#row={row}").as_bytes(),
+    )
+    .to_string()
+}
+
+fn main() -> Result<()> {
+    let mut args: Args = parse_args()?;
+    args.collection = namespaced_collection(
+        &args.collection_prefix,
+        &args.collection,
+        &args.collection_suffix,
+    );
+    let counter = TokenCounter::load(args.tokenizer);
+    if args.max_tokens == 0 {
+        anyhow::bail!("--max-tokens must be greater than zero");
+    }
+    if args.embed_batch_size == 0 {
+        anyhow::bail!("--embed-batch-size must be greater than zero");
+    }
+    let delimiter = delimiter_byte(args.delimiter.as_deref(), &args.csv_path)?;
+
+    if !args.dry_run_json {
+        println!("📄 Reading CSV: {}", args.csv_path);
+    }
+    let file = std::fs::File::open(&args.csv_path)
+        .with_context(|| format!("Failed to open {}", args.csv_path))?;
+    let (columns, rows) = read_rows(file, delimiter, &args.text_columns, &args.text_field)?;
+    let total_rows = rows.len();
+    let (mut rows, empty): (Vec<Row>, Vec<Row>) =
+        rows.into_iter().partition(|row| !row.text.is_empty());
+    if !empty.is_empty() {
+        eprintln!(
+            "⚠️  Skipping {} rows with no text in the text columns",
+            empty.len()
+        );
+    }
+    limit_chunks(&mut rows, args.max_chunks)?;
+    if args.dry_run || args.dry_run_json {
+        let plan: Vec<ChunkPlan> = rows
+            .iter()
+            .enumerate()
+            .map(|(index, row)| ChunkPlan {
+                index,
+                chunk_type: "row".to_string(),
+                chars: row.text.chars().count(),
+                tokens: counter.count(&row.text),
+                header_context: String::new(),
+                start_line: Some(row.line as usize),
+                end_line: Some(row.line as usize),
+            })
+            .collect();
+        return print_chunk_plan(&plan, args.dry_run_json);
+    }
+
+    if !args.skip_checks {
+        preflight(
+            &args.qdrant_url,
+            &args.ollama_url,
+            &args.model,
+            args.embed_api,
+        )?;
+    }
+    let embedder = Embedder::new(
+        &args.ollama_url,
+        args.embed_api,
+        &args.model,
+        RetryPolicy {
+            retries: args.embed_retries,
+            base_delay_ms: args.embed_retry_delay_ms,
+        },
+    )
+    .oversize(OversizePolicy {
+        on_oversize: args.on_oversize,
+        max_tokens: args.max_tokens,
+        counter,
+    })
+    .legacy_embed(args.legacy_embed);
+    let qdrant = QdrantClient::new(&args.qdrant_url)
+        .api_key(args.qdrant_api_key.as_ref())
+        .consistency(args.consistency)
+        .ingest_model(&args.model);
+    let source = args.source_label.as_deref().unwrap_or(&args.csv_path);
+
+    println!(
+        "📦 {} rows, {} columns: {}",
+        rows.len(),
+        columns.len(),
+        columns.join(", ")
+    );
+
+    println!("🧮 Generating embeddings with model: {}", args.model);
+    let texts: Vec<String> = rows.iter().map(|row| row.text.clone()).collect();
+    let mut embeddings = Vec::new();
+    let bar = progress_bar(rows.len(), "Embedding", args.quiet);
+    for batch in texts.chunks(args.embed_batch_size) {
+        embeddings.extend(embedder.embed_batch(batch)?);
+        bar.inc(batch.len() as u64);
+    }
+    bar.finish_and_clear();
+
+    let mut points = Vec::with_capacity(rows.len());
+    for (row, embedding) in rows.into_iter().zip(embeddings) {
+        let mut payload = json!({
+            "source": source,
+            "source_path": args.csv_path,
+            "row": row.index,
+            "line": row.line,
+            "chunk_index": row.index,
+            "total_chunks": total_rows,
+            "chunk_type": "row",
+            "lang": detect_lang(&row.text),
+        });
+        payload[args.text_field.as_str()] = json!(row.text);
+        payload
+            .as_object_mut()
+            .expect("payload is an object")
+            .extend(row.fields);
+
+        points.push(QdrantPoint {
+            id: row_id(source, row.index),
+            vector: embedding,
+            sparse: None,
+            summary_vector: None,
+            payload,
+        });
+    }
+    println!("✅ Generated embeddings for all rows");
+
+    // Upload to Qdrant in batches
+    println!("📤 Uploading to Qdrant collection: {}", args.collection);
+    let batch_size = 100;
+    let total_batches = points.len().div_ceil(batch_size);
+
+    let bar = progress_bar(total_batches, "Uploading batches", args.quiet);
+    for (i, batch) in points.chunks(batch_size).enumerate() {
+        qdrant
+            .upsert_points(&args.collection, batch)
+            .with_context(|| format!("Qdrant returned error in batch {}", i + 1))?;
+        bar.inc(1);
+    }
+    bar.finish_and_clear();
+
+    println!("✅ Successfully ingested CSV into Qdrant!");
+    println!("📊 Summary:");
+    println!("   Rows: {}", points.len());
+    println!("   Source: {source}");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSV: &str = "name,year,notes,source\n\
+        \"Rust, the book\",2018,\"Says \"\"hello\"\"\nover two lines\",nostarch\n\
+        Programming Rust,2021,,oreilly\n\
+        ,,,\n\
+        Zero to Production,007,extra,self,surplus\n";
+
+    #[test]
+    fn test_rows_become_labelled_text_and_typed_fields() {
+        let (columns, rows) = read_rows(CSV.as_bytes(), b',', &[], "text").unwrap();
+
+        assert_eq!(columns, vec!["name", "year", "notes", "column_source"]);
+        assert_eq!(rows.len(), 4);
+        assert_eq!(
+            rows[0].text,
+            "name: Rust, the book | year: 2018 | notes: Says \"hello\"\nover two lines | source: nostarch"
+        );
+        assert_eq!(rows[0].fields["year"], json!(2018));
+        assert_eq!(rows[0].fields["column_source"], json!("nostarch"));
+        assert_eq!((rows[1].index, rows[1].line), (1, 4));
+        assert!(
+            rows[1].fields.get("notes").is_none(),
+            "empty cells are left out"
+        );
+        assert_eq!(rows[2].text, "");
+        assert_eq!(rows[3].fields["year"], json!("007"));
+        assert_eq!(rows[3].fields["column_5"], json!("surplus"));
+    }
+
+    #[test]
+    fn test_text_columns_limit_the_embedded_text() {
+        let tsv = "title\tyear\nRust\t2018\n";
+        let columns = ["title".to_string()];
+
+        let (_, rows) = read_rows(tsv.as_bytes(), b'\t', &columns, "text").unwrap();
+
+        assert_eq!(rows[0].text, "title: Rust");
+        assert_eq!(rows[0].fields["year"], json!(2018));
+        let unknown = ["author".to_string()];
+        let err = read_rows(tsv.as_bytes(), b'\t', &unknown, "text").unwrap_err();
+        assert!(err.to_string().contains("title, year"), "{err}");
+    }
+
+    #[test]
+    fn test_delimiter_from_flag_or_extension() {
+        assert_eq!(delimiter_byte(None, "data.tsv").unwrap(), b'\t');
+        assert_eq!(delimiter_byte(None, "data.csv").unwrap(), b',');
+        assert_eq!(delimiter_byte(Some("tab"), "data.csv").unwrap(), b'\t');
+        assert_eq!(delimiter_byte(Some(";"), "data.csv").unwrap(), b';');
+        assert!(delimiter_byte(Some("||"), "data.csv").is_err());
+    }
+
+    #[test]
+    fn test_row_ids_are_stable_per_source_and_row() {
+        assert_eq!(row_id("books.csv", 3), row_id("books.csv", 3));
+        assert_ne!(row_id("books.csv", 3), row_id("books.csv", 4));
+        assert_ne!(row_id("books.csv", 3), row_id("films.csv", 3));
+    }
+}
@@ -0,0 +1,688 @@
+// Copyright (c) 2025 Michael A. Wright
+// Licensed under the MIT License
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use rag_demo::config::parse_args;
+use rag_demo::rag::{
+    ApiKey, Consistency, EmbedApi, Embedder, IdGenerator, MarkdownChunk, OnOversize,
+    OversizePolicy, QdrantClient, QdrantPoint, RetryPolicy, SmallChunkStats, TokenCounter,
+    Tokenizer, detect_lang, enforce_max_tokens, limit_chunks, markdown_chunk_plan,
+    merge_small_chunks, namespaced_collection, preflight, print_chunk_plan, progress_bar,
+    smart_chunk_markdown,
+};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+use zip::result::ZipError;
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Ingest Word documents with the markdown chunker, keeping heading structure",
+    long_about = None
+)]
+struct Args {
+    #[arg(required = true, help = "Paths to .docx files")]
+    docx_paths: Vec<String>,
+
+    #[arg(
+        short,
+        long,
+        default_value = "375",
+        help = "Tokens per text chunk before breaking at the next paragraph"
+    )]
+    chunk_size: usize,
+
+    #[arg(
+        long,
+        default_value = "50",
+        help = "Tokens under which a text chunk, such as a lone heading or a stray line, is merged into its neighbours (0 keeps every chunk)"
+    )]
+    min_chunk_size: usize,
+
+    #[arg(
+        long,
+        default_value = "documents",
+        env = "RAG_COLLECTION",
+        help = "Qdrant collection name"
+    )]
+    collection: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_PREFIX",
+        help = "Prepended to the collection name, e.g. acme_ to keep one tenant's collections apart"
+    )]
+    collection_prefix: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_SUFFIX",
+        help = "Appended to the collection name"
+    )]
+    collection_suffix: String,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:6333",
+        env = "QDRANT_URL",
+        help = "Qdrant URL"
+    )]
+    qdrant_url: String,
+
+    #[arg(
+        long,
+        env = "QDRANT_API_KEY",
+        hide_env_values = true,
+        help = "Qdrant API key, sent as the api-key header (Qdrant Cloud)"
+    )]
+    qdrant_api_key: Option<ApiKey>,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:11434",
+        env = "OLLAMA_URL",
+        help = "Ollama URL"
+    )]
+    ollama_url: String,
+
+    #[arg(
+        short,
+        long,
+        default_value = "nomic-embed-text",
+        env = "RAG_MODEL",
+        help = "Embedding model"
+    )]
+    model: String,
+
+    #[arg(
+        long,
+        default_value = "text",
+        help = "Payload field that stores the chunk text"
+    )]
+    text_field: String,
+
+    #[arg(
+        long,
+        help = "Human-friendly name stored as the payload source of a single document (default: file path)"
+    )]
+    source_label: Option<String>,
+
+    #[arg(long, help = "Seed for reproducible point IDs (default: OS entropy)")]
+    seed: Option<u64>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "ollama",
+        help = "Embedding API: ollama (/api/embeddings), ollama-embed (/api/embed), openai (/v1/embeddings)"
+    )]
+    embed_api: EmbedApi,
+
+    #[arg(
+        long,
+        help = "Skip checking that Qdrant, Ollama and the embedding model are available"
+    )]
+    skip_checks: bool,
+
+    #[arg(
+        long,
+        default_value = "4",
+        help = "Times to retry a failed embedding request (5xx, 429, connection errors)"
+    )]
+    embed_retries: u32,
+
+    #[arg(
+        long,
+        default_value = "500",
+        help = "Delay before the first embedding retry in ms, doubled on each retry"
+    )]
+    embed_retry_delay_ms: u64,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "strong",
+        help = "strong waits for each upsert to be applied, so points are searchable and counted when ingestion ends; eventual returns as soon as Qdrant accepts them"
+    )]
+    consistency: Consistency,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "cl100k",
+        help = "Tokenizer used to measure chunk sizes (chars estimates 4 characters per token)"
+    )]
+    tokenizer: Tokenizer,
+
+    #[arg(
+        long,
+        default_value = "2048",
+        help = "Hard limit in tokens for every chunk, splitting code blocks if needed"
+    )]
+    max_tokens: usize,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "split",
+        help = "Text over --max-tokens at embedding time: split embeds pieces and averages them, truncate keeps the start, error fails"
+    )]
+    on_oversize: OnOversize,
+
+    #[arg(
+        long,
+        default_value = "32",
+        help = "Chunks embedded per request to Ollama's /api/embed"
+    )]
+    embed_batch_size: usize,
+
+    #[arg(
+        long,
+        help = "Embed one chunk per request with --embed-api instead of batching through /api/embed"
+    )]
+    legacy_embed: bool,
+
+    #[arg(
+        long,
+        help = "Print each chunk's type, size and header context, then exit without embedding or uploading"
+    )]
+    dry_run: bool,
+
+    #[arg(long, help = "Like --dry-run, but print the chunks as JSON")]
+    dry_run_json: bool,
+
+    #[arg(
+        long,
+        help = "Only embed and upload the first N chunks, for quick trial runs on big files"
+    )]
+    max_chunks: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Hide progress bars (also hidden when stdout is not a terminal)"
+    )]
+    quiet: bool,
+}
+
+/// WordprocessingML namespace of the elements in `word/document.xml`
+const W_NS: &str = "http://schemas.openxmlformats.org/wordprocessingml/2006/main";
+
+/// Leading bytes of an OLE compound file: what Word writes for encrypted
+/// documents (and for legacy .doc files) instead of a zip archive
+const OLE_MAGIC: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+
+/// Top-level content of a document body, in order
+#[derive(Debug, PartialEq)]
+enum Block {
+    Heading(usize, String),
+    Paragraph(String),
+    /// Rows of cell texts
+    Table(Vec<Vec<String>>),
+}
+
+/// A parsed document: its text as markdown and the source to store for it
+struct DocxDocument {
+    path: String,
+    source: String,
+    markdown: String,
+    /// Index of the body block (paragraph, or whole table) each markdown line came from
+    paragraphs: Vec<usize>,
+}
+
+/// Heading level of each paragraph style, by style ID, from `word/styles.xml`
+fn heading_styles(styles_xml: &str) -> Result<HashMap<String, usize>> {
+    let doc = roxmltree::Document::parse(styles_xml).context("Malformed word/styles.xml")?;
+    Ok(doc
+        .descendants()
+        .filter(|n| n.has_tag_name((W_NS, "style")))
+        .filter_map(|style| {
+            let id = style.attribute((W_NS, "styleId"))?;
+            let name = child(style, "name").and_then(|n| n.attribute((W_NS, "val")));
+            let outline = child(style, "pPr")
+                .and_then(|p| child(p, "outlineLvl"))
+                .and_then(|o| o.attribute((W_NS, "val")));
+            let level = name
+                .and_then(style_name_level)
+                .or_else(|| outline_level(outline?));
+            Some((id.to_string(), level?))
+        })
+        .collect())
+}
+
+/// "heading 2" is level 2 and "Title" level 1; Word's nine levels are capped at six
+fn style_name_level(name: &str) -> Option<usize> {
+    let name = name.to_ascii_lowercase();
+    if name == "title" {
+        return Some(1);
+    }
+    let level: usize = name.strip_prefix("heading")?.trim().parse().ok()?;
+    (level >= 1).then(|| level.min(6))
+}
+
+/// `w:outlineLvl` counts from 0 and 9 means body text
+fn outline_level(val: &str) -> Option<usize> {
+    let level: usize = val.parse().ok()?;
+    (level < 9).then(|| (level + 1).min(6))
+}
+
+fn child<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name((W_NS, name)))
+}
+
+/// Visible text of a paragraph or cell with whitespace collapsed; tabs,
+/// line breaks and paragraph ends become spaces, and deleted tracked changes are left out
+fn node_text(node: roxmltree::Node) -> String {
+    let mut text = String::new();
+    for n in node.descendants() {
+        if n.has_tag_name((W_NS, "t")) {
+            text.push_str(n.text().unwrap_or(""));
+        } else if ["tab", "br", "cr", "p"]
+            .iter()
+            .any(|name| n.has_tag_name((W_NS, *name)))
+        {
+            // Paragraphs within a table cell run together like the lines they are
+            text.push(' ');
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Heading level of a paragraph from its style, or from a direct outline level
+fn paragraph_level(paragraph: roxmltree::Node, styles: &HashMap<String, usize>) -> Option<usize> {
+    let properties = child(paragraph, "pPr")?;
+    let style = child(properties, "pStyle")
+        .and_then(|s| s.attribute((W_NS, "val")))
+        .and_then(|id| {
+            styles
+                .get(id)
+                .copied()
+                .or_else(|| style_name_level(&id.replace("Heading", "heading ")))
+        });
+    style.or_else(|| outline_level(child(properties, "outlineLvl")?.attribute((W_NS, "val"))?))
+}
+
+/// Paragraphs, headings and tables of `word/document.xml`, in order
+fn body_blocks(document_xml: &str, styles: &HashMap<String, usize>) -> Result<Vec<Block>> {
+    let doc = roxmltree::Document::parse(document_xml).context("Malformed word/document.xml")?;
+    let body = doc
+        .descendants()
+        .find(|n| n.has_tag_name((W_NS, "body")))
+        .context("word/document.xml has no body")?;
+
+    let mut blocks = Vec::new();
+    for node in body.children() {
+        if node.has_tag_name((W_NS, "p")) {
+            let text = node_text(node);
+            blocks.push(match paragraph_level(node, styles) {
+                Some(level) if !text.is_empty() => Block::Heading(level, text),
+                _ => Block::Paragraph(text),
+            });
+        } else if node.has_tag_name((W_NS, "tbl")) {
+            let rows = node
+                .children()
+                .filter(|n| n.has_tag_name((W_NS, "tr")))
+                .map(|row| {
+                    row.children()
+                        .filter(|n| n.has_tag_name((W_NS, "tc")))
+                        .map(node_text)
+                        .collect()
+                })
+                .collect();
+            blocks.push(Block::Table(rows));
+        }
+    }
+    Ok(blocks)
+}
+
+/// Markdown for the markdown chunker, with the block each line came from.
+/// Tables become one line per row with cells separated by " | ".
+fn blocks_to_markdown(blocks: &[Block]) -> (String, Vec<usize>) {
+    let mut lines: Vec<String> = Vec::new();
+    let mut paragraphs = Vec::new();
+    let mut push = |line: String, index: usize| {
+        lines.push(line);
+        paragraphs.push(index);
+    };
+    for (index, block) in blocks.iter().enumerate() {
+        match block {
+            Block::Heading(level, text) => push(format!("{} {text}", "#".repeat(*level)), index),
+            Block::Paragraph(text) if text.is_empty() => continue,
+            Block::Paragraph(text) => push(escape_line(text), index),
+            Block::Table(rows) => {
+                let rows: Vec<String> = rows
+                    .iter()
+                    .filter(|cells| cells.iter().any(|c| !c.is_empty()))
+                    .map(|cells| escape_line(&cells.join(" | ")))
+                    .collect();
+                if rows.is_empty() {
+                    continue;
+                }
+                for row in rows {
+                    push(row, index);
+                }
+            }
+        }
+        push(String::new(), index);
+    }
+    (lines.join("\n"), paragraphs)
+}
+
+/// Keep body text from reading as a heading or a code fence to the markdown chunker
+fn escape_line(text: &str) -> String {
+    if text.starts_with('#') || text.starts_with("```") {
+        format!("\\{text}")
+    } else {
+        text.to_string()
+    }
+}
+
+/// Contents of one file in the archive, or `None` if it is missing
+fn zip_entry(archive: &mut ZipArchive<Cursor<Vec<u8>>>, name: &str) -> Result<Option<String>> {
+    let mut file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to open {name}")),
+    };
+    let mut text = String::new();
+    file.read_to_string(&mut text)
+        .with_context(|| format!("Failed to read {name}"))?;
+    Ok(Some(text))
+}
+
+/// Body blocks of a .docx file given as bytes
+fn parse_docx(bytes: Vec<u8>) -> Result<Vec<Block>> {
+    if bytes.starts_with(&OLE_MAGIC) {
+        anyhow::bail!(
+            "the file is password-protected or a legacy .doc; save an unprotected copy as .docx"
+        );
+    }
+    let mut archive = ZipArchive::new(Cursor::new(bytes))
+        .context("not a .docx (zip archive); the file may be corrupt")?;
+    let styles = match zip_entry(&mut archive, "word/styles.xml")? {
+        Some(xml) => heading_styles(&xml)?,
+        None => HashMap::new(),
+    };
+    let document = zip_entry(&mut archive, "word/document.xml")?
+        .context("no word/document.xml in the archive; is this a Word document?")?;
+    body_blocks(&document, &styles)
+}
+
+fn read_document(path: &str, source_label: Option<&str>) -> Result<DocxDocument> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {path}"))?;
+    let blocks = parse_docx(bytes)?;
+    let (markdown, paragraphs) = blocks_to_markdown(&blocks);
+    Ok(DocxDocument {
+        path: path.to_string(),
+        source: source_label.unwrap_or(path).to_string(),
+        markdown,
+        paragraphs,
+    })
+}
+
+fn main() -> Result<()> {
+    let mut args: Args = parse_args()?;
+    args.collection = namespaced_collection(
+        &args.collection_prefix,
+        &args.collection,
+        &args.collection_suffix,
+    );
+    let counter = TokenCounter::load(args.tokenizer);
+    if args.max_tokens == 0 {
+        anyhow::bail!("--max-tokens must be greater than zero");
+    }
+    if args.embed_batch_size == 0 {
+        anyhow::bail!("--embed-batch-size must be greater than zero");
+    }
+    if args.source_label.is_some() && args.docx_paths.len() > 1 {
+        anyhow::bail!("--source-label names a single document; pass one .docx file with it");
+    }
+
+    // Convert each document to markdown and chunk it like ingest-markdown does
+    let mut documents = Vec::with_capacity(args.docx_paths.len());
+    let mut chunks: Vec<(usize, MarkdownChunk)> = Vec::new();
+    let mut small = SmallChunkStats::default();
+    for path in &args.docx_paths {
+        if !args.dry_run_json {
+            println!("📄 Reading DOCX: {path}");
+        }
+        let document = match read_document(path, args.source_label.as_deref()) {
+            Ok(document) => document,
+            Err(e) => {
+                eprintln!("⚠️  Skipping {path}: {e:#}");
+                continue;
+            }
+        };
+        let (doc_chunks, stats) = merge_small_chunks(
+            smart_chunk_markdown(&document.markdown, args.chunk_size, counter),
+            args.min_chunk_size,
+            counter,
+        );
+        small += stats;
+        let doc_chunks = enforce_max_tokens(doc_chunks, args.max_tokens, counter);
+        chunks.extend(doc_chunks.into_iter().map(|c| (documents.len(), c)));
+        documents.push(document);
+    }
+    if documents.is_empty() {
+        anyhow::bail!("None of the documents could be read");
+    }
+    if !args.dry_run_json {
+        small.print();
+    }
+    limit_chunks(&mut chunks, args.max_chunks)?;
+    if args.dry_run || args.dry_run_json {
+        let plain: Vec<MarkdownChunk> = chunks.iter().map(|(_, c)| c.clone()).collect();
+        return print_chunk_plan(&markdown_chunk_plan(&plain, counter), args.dry_run_json);
+    }
+
+    if !args.skip_checks {
+        preflight(
+            &args.qdrant_url,
+            &args.ollama_url,
+            &args.model,
+            args.embed_api,
+        )?;
+    }
+    let embedder = Embedder::new(
+        &args.ollama_url,
+        args.embed_api,
+        &args.model,
+        RetryPolicy {
+            retries: args.embed_retries,
+            base_delay_ms: args.embed_retry_delay_ms,
+        },
+    )
+    .oversize(OversizePolicy {
+        on_oversize: args.on_oversize,
+        max_tokens: args.max_tokens,
+        counter,
+    })
+    .legacy_embed(args.legacy_embed);
+    let qdrant = QdrantClient::new(&args.qdrant_url)
+        .api_key(args.qdrant_api_key.as_ref())
+        .consistency(args.consistency)
+        .ingest_model(&args.model);
+    let mut ids = IdGenerator::new(args.seed);
+
+    println!(
+        "📦 Created {} chunks from {} documents",
+        chunks.len(),
+        documents.len()
+    );
+
+    println!("🧮 Generating embeddings with model: {}", args.model);
+    let embedding_texts: Vec<String> = chunks.iter().map(|(_, c)| c.embedding_text()).collect();
+    let mut embeddings = Vec::new();
+    let bar = progress_bar(chunks.len(), "Embedding", args.quiet);
+    for texts in embedding_texts.chunks(args.embed_batch_size) {
+        embeddings.extend(embedder.embed_batch(texts)?);
+        bar.inc(texts.len() as u64);
+    }
+    bar.finish_and_clear();
+
+    let mut totals = vec![0; documents.len()];
+    for (doc, _) in &chunks {
+        totals[*doc] += 1;
+    }
+    let mut points = Vec::with_capacity(chunks.len());
+    for ((doc, chunk), embedding) in chunks.iter().zip(embeddings) {
+        let document = &documents[*doc];
+        let mut payload = json!({
+            "source": document.source,
+            "source_path": document.path,
+            "chunk_index": chunk.index,
+            "total_chunks": totals[*doc],
+            "chunk_type": chunk.chunk_type,
+            "lang": detect_lang(&chunk.content),
+            "header_context": chunk.header_context,
+            "breadcrumb": chunk.breadcrumb,
+            "paragraph_start": document.paragraphs[chunk.start_line],
+            "paragraph_end": document.paragraphs[chunk.end_line],
+        });
+        payload[args.text_field.as_str()] = json!(chunk.content);
+
+        points.push(QdrantPoint {
+            id: ids.next_id(),
+            vector: embedding,
+            sparse: None,
+            summary_vector: None,
+            payload,
+        });
+    }
+    println!("✅ Generated embeddings for all chunks");
+
+    // Upload to Qdrant in batches
+    println!("📤 Uploading to Qdrant collection: {}", args.collection);
+    let batch_size = 100;
+    let total_batches = points.len().div_ceil(batch_size);
+
+    let bar = progress_bar(total_batches, "Uploading batches", args.quiet);
+    for (i, batch) in points.chunks(batch_size).enumerate() {
+        qdrant
+            .upsert_points(&args.collection, batch)
+            .with_context(|| format!("Qdrant returned error in batch {}", i + 1))?;
+        bar.inc(1);
+    }
+    bar.finish_and_clear();
+
+    println!("✅ Successfully ingested DOCX into Qdrant!");
+    for document in &documents {
+        println!("   Source: {}", document.source);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    fn docx(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        for (name, content) in files {
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    const STYLES: &str = r#"<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+  <w:style w:type="paragraph" w:styleId="Title"><w:name w:val="Title"/></w:style>
+  <w:style w:type="paragraph" w:styleId="berschrift2"><w:name w:val="heading 2"/></w:style>
+  <w:style w:type="paragraph" w:styleId="Normal"><w:name w:val="Normal"/></w:style>
+</w:styles>"#;
+
+    const DOCUMENT: &str = r#"<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+  <w:body>
+    <w:p><w:pPr><w:pStyle w:val="Title"/></w:pPr><w:r><w:t>Field Guide</w:t></w:r></w:p>
+    <w:p><w:r><w:t xml:space="preserve">Ownership is </w:t></w:r><w:r><w:t>unique.</w:t></w:r><w:r><w:delText>removed</w:delText></w:r></w:p>
+    <w:p/>
+    <w:p><w:pPr><w:pStyle w:val="berschrift2"/></w:pPr><w:r><w:t>Rules</w:t></w:r></w:p>
+    <w:tbl>
+      <w:tr><w:tc><w:p><w:r><w:t>Rule</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>Meaning</w:t></w:r></w:p></w:tc></w:tr>
+      <w:tr><w:tc><w:p><w:r><w:t>One owner</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>Moves</w:t></w:r><w:r><w:tab/><w:t>transfer</w:t></w:r></w:p></w:tc></w:tr>
+    </w:tbl>
+    <w:p><w:pPr><w:outlineLvl w:val="2"/></w:pPr><w:r><w:t>Details</w:t></w:r></w:p>
+    <w:p><w:r><w:t># not a heading</w:t></w:r></w:p>
+    <w:sectPr/>
+  </w:body>
+</w:document>"#;
+
+    #[test]
+    fn test_docx_headings_paragraphs_and_tables() {
+        let bytes = docx(&[("word/styles.xml", STYLES), ("word/document.xml", DOCUMENT)]);
+
+        let blocks = parse_docx(bytes).unwrap();
+
+        assert_eq!(
+            blocks,
+            vec![
+                Block::Heading(1, "Field Guide".to_string()),
+                Block::Paragraph("Ownership is unique.".to_string()),
+                Block::Paragraph(String::new()),
+                Block::Heading(2, "Rules".to_string()),
+                Block::Table(vec![
+                    vec!["Rule".to_string(), "Meaning".to_string()],
+                    vec!["One owner".to_string(), "Moves transfer".to_string()],
+                ]),
+                Block::Heading(3, "Details".to_string()),
+                Block::Paragraph("# not a heading".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chunks_map_back_to_paragraphs() {
+        let blocks = parse_docx(docx(&[
+            ("word/styles.xml", STYLES),
+            ("word/document.xml", DOCUMENT),
+        ]))
+        .unwrap();
+        let (markdown, paragraphs) = blocks_to_markdown(&blocks);
+
+        let chunks = smart_chunk_markdown(&markdown, 375, TokenCounter::estimate());
+
+        let table = chunks
+            .iter()
+            .find(|c| c.content.contains("One owner | Moves transfer"))
+            .unwrap();
+        assert_eq!(table.breadcrumb, "Field Guide > Rules");
+        assert_eq!(
+            (paragraphs[table.start_line], paragraphs[table.end_line]),
+            (3, 4)
+        );
+        let last = chunks.last().unwrap();
+        assert_eq!(last.breadcrumb, "Field Guide > Rules > Details");
+        assert!(last.content.contains("\\# not a heading"));
+        assert_eq!(paragraphs[last.end_line], 6);
+    }
+
+    #[test]
+    fn test_unreadable_files_explain_why() {
+        let mut encrypted = OLE_MAGIC.to_vec();
+        encrypted.extend([0; 64]);
+        let err = parse_docx(encrypted).unwrap_err();
+        assert!(err.to_string().contains("password-protected"));
+
+        let err = parse_docx(b"PK\x03\x04 truncated".to_vec()).unwrap_err();
+        assert!(err.to_string().contains("may be corrupt"));
+
+        let err = parse_docx(docx(&[("readme.txt", "hi")])).unwrap_err();
+        assert!(err.to_string().contains("no word/document.xml"));
+    }
+}
@@ -0,0 +1,815 @@
+// Copyright (c) 2025 Michael A. Wright
+// Licensed under the MIT License
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use rag_demo::config::parse_args;
+use rag_demo::rag::{
+    ApiKey, Consistency, EmbedApi, Embedder, IdGenerator, MarkdownChunk, OnOversize,
+    OversizePolicy, QdrantClient, QdrantPoint, RetryPolicy, SmallChunkStats, TokenCounter,
+    Tokenizer, breadcrumb, detect_lang, enforce_max_tokens, heading_level, html_to_markdown,
+    limit_chunks, markdown_chunk_plan, merge_small_chunks, namespaced_collection, preflight,
+    print_chunk_plan, progress_bar, smart_chunk_markdown,
+};
+use scraper::{Html, Selector};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+use zip::result::ZipError;
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Ingest EPUB books chapter by chapter with the markdown chunker",
+    long_about = None
+)]
+struct Args {
+    #[arg(required = true, help = "Paths to .epub files")]
+    epub_paths: Vec<String>,
+
+    #[arg(
+        short,
+        long,
+        default_value = "375",
+        help = "Tokens per text chunk before breaking at the next paragraph"
+    )]
+    chunk_size: usize,
+
+    #[arg(
+        long,
+        default_value = "50",
+        help = "Tokens under which a text chunk, such as a lone heading or a stray line, is merged into its neighbours (0 keeps every chunk)"
+    )]
+    min_chunk_size: usize,
+
+    #[arg(
+        long,
+        default_value = "documents",
+        env = "RAG_COLLECTION",
+        help = "Qdrant collection name"
+    )]
+    collection: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_PREFIX",
+        help = "Prepended to the collection name, e.g. acme_ to keep one tenant's collections apart"
+    )]
+    collection_prefix: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_SUFFIX",
+        help = "Appended to the collection name"
+    )]
+    collection_suffix: String,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:6333",
+        env = "QDRANT_URL",
+        help = "Qdrant URL"
+    )]
+    qdrant_url: String,
+
+    #[arg(
+        long,
+        env = "QDRANT_API_KEY",
+        hide_env_values = true,
+        help = "Qdrant API key, sent as the api-key header (Qdrant Cloud)"
+    )]
+    qdrant_api_key: Option<ApiKey>,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:11434",
+        env = "OLLAMA_URL",
+        help = "Ollama URL"
+    )]
+    ollama_url: String,
+
+    #[arg(
+        short,
+        long,
+        default_value = "nomic-embed-text",
+        env = "RAG_MODEL",
+        help = "Embedding model"
+    )]
+    model: String,
+
+    #[arg(
+        long,
+        default_value = "text",
+        help = "Payload field that stores the chunk text"
+    )]
+    text_field: String,
+
+    #[arg(
+        long,
+        help = "Human-friendly name stored as the payload source of a single book (default: file path)"
+    )]
+    source_label: Option<String>,
+
+    #[arg(long, help = "Seed for reproducible point IDs (default: OS entropy)")]
+    seed: Option<u64>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "ollama",
+        help = "Embedding API: ollama (/api/embeddings), ollama-embed (/api/embed), openai (/v1/embeddings)"
+    )]
+    embed_api: EmbedApi,
+
+    #[arg(
+        long,
+        help = "Skip checking that Qdrant, Ollama and the embedding model are available"
+    )]
+    skip_checks: bool,
+
+    #[arg(
+        long,
+        default_value = "4",
+        help = "Times to retry a failed embedding request (5xx, 429, connection errors)"
+    )]
+    embed_retries: u32,
+
+    #[arg(
+        long,
+        default_value = "500",
+        help = "Delay before the first embedding retry in ms, doubled on each retry"
+    )]
+    embed_retry_delay_ms: u64,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "strong",
+        help = "strong waits for each upsert to be applied, so points are searchable and counted when ingestion ends; eventual returns as soon as Qdrant accepts them"
+    )]
+    consistency: Consistency,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "cl100k",
+        help = "Tokenizer used to measure chunk sizes (chars estimates 4 characters per token)"
+    )]
+    tokenizer: Tokenizer,
+
+    #[arg(
+        long,
+        default_value = "2048",
+        help = "Hard limit in tokens for every chunk, splitting code blocks if needed"
+    )]
+    max_tokens: usize,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "split",
+        help = "Text over --max-tokens at embedding time: split embeds pieces and averages them, truncate keeps the start, error fails"
+    )]
+    on_oversize: OnOversize,
+
+    #[arg(
+        long,
+        default_value = "32",
+        help = "Chunks embedded per request to Ollama's /api/embed"
+    )]
+    embed_batch_size: usize,
+
+    #[arg(
+        long,
+        help = "Embed one chunk per request with --embed-api instead of batching through /api/embed"
+    )]
+    legacy_embed: bool,
+
+    #[arg(
+        long,
+        help = "Print each chunk's type, size and header context, then exit without embedding or uploading"
+    )]
+    dry_run: bool,
+
+    #[arg(long, help = "Like --dry-run, but print the chunks as JSON")]
+    dry_run_json: bool,
+
+    #[arg(
+        long,
+        help = "Only embed and upload the first N chunks, for quick trial runs on big files"
+    )]
+    max_chunks: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Hide progress bars (also hidden when stdout is not a terminal)"
+    )]
+    quiet: bool,
+}
+
+/// A chapter of a book: one spine item with text
+#[derive(Debug)]
+struct Chapter {
+    /// Position in the spine, counting items without text too
+    spine_index: usize,
+    title: String,
+    markdown: String,
+}
+
+struct Book {
+    path: String,
+    source: String,
+    title: String,
+    chapters: Vec<Chapter>,
+}
+
+/// A chunk and the book and chapter (indexes into `Book::chapters`) it belongs to
+struct BookChunk {
+    book: usize,
+    chapter: usize,
+    chunk: MarkdownChunk,
+}
+
+/// A manifest entry of the package document
+struct ManifestItem {
+    href: String,
+    media_type: String,
+    properties: String,
+}
+
+/// Whether `node` is an element with local name `name`; EPUBs in the wild
+/// are loose about namespaces, so prefixes are ignored
+fn named(node: &roxmltree::Node, name: &str) -> bool {
+    node.is_element() && node.tag_name().name() == name
+}
+
+/// Attribute by local name, whatever its namespace
+fn attr<'a>(node: &roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attributes()
+        .find(|a| a.name() == name)
+        .map(|a| a.value())
+}
+
+/// Directory part of a path inside the archive, with its trailing '/'
+fn dir_of(path: &str) -> &str {
+    path.rfind('/').map_or("", |i| &path[..=i])
+}
+
+/// Archive path of `href` relative to `base_dir`, without fragment and with
+/// `..` segments and percent-escapes resolved
+fn resolve_href(base_dir: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or("");
+    let mut parts: Vec<String> = Vec::new();
+    for part in format!("{base_dir}{href}").split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(percent_decode(part)),
+        }
+    }
+    parts.join("/")
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Contents of one file in the archive, or `None` if it is missing
+fn zip_entry(archive: &mut ZipArchive<Cursor<Vec<u8>>>, name: &str) -> Result<Option<String>> {
+    let mut file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to open {name}")),
+    };
+    let mut text = String::new();
+    file.read_to_string(&mut text)
+        .with_context(|| format!("Failed to read {name}"))?;
+    Ok(Some(text))
+}
+
+fn parse_xml<'a>(xml: &'a str, name: &str) -> Result<roxmltree::Document<'a>> {
+    roxmltree::Document::parse(xml).with_context(|| format!("Malformed {name}"))
+}
+
+/// Chapter titles by archive path, from the EPUB 3 navigation document or
+/// the EPUB 2 NCX; the first entry pointing into a file names it
+fn toc_titles(
+    archive: &mut ZipArchive<Cursor<Vec<u8>>>,
+    manifest: &HashMap<String, ManifestItem>,
+    ncx_id: Option<&str>,
+) -> Result<HashMap<String, String>> {
+    let mut titles = HashMap::new();
+    let mut add = |path: String, title: String| {
+        let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !title.is_empty() {
+            titles.entry(path).or_insert(title);
+        }
+    };
+
+    let nav = manifest
+        .values()
+        .find(|item| item.properties.split_whitespace().any(|p| p == "nav"));
+    if let Some(nav) = nav
+        && let Some(xml) = zip_entry(archive, &nav.href)?
+    {
+        let doc = parse_xml(&xml, &nav.href)?;
+        let navs: Vec<_> = doc.descendants().filter(|n| named(n, "nav")).collect();
+        let toc = navs
+            .iter()
+            .find(|n| attr(n, "type") == Some("toc"))
+            .or(navs.first());
+        for link in toc.into_iter().flat_map(|n| n.descendants()) {
+            if named(&link, "a")
+                && let Some(href) = attr(&link, "href")
+            {
+                let text: String = link
+                    .descendants()
+                    .filter(|n| n.is_text())
+                    .filter_map(|n| n.text())
+                    .collect();
+                add(resolve_href(dir_of(&nav.href), href), text);
+            }
+        }
+        return Ok(titles);
+    }
+
+    if let Some(ncx) = ncx_id.and_then(|id| manifest.get(id))
+        && let Some(xml) = zip_entry(archive, &ncx.href)?
+    {
+        let doc = parse_xml(&xml, &ncx.href)?;
+        for point in doc.descendants().filter(|n| named(n, "navPoint")) {
+            let label = point
+                .children()
+                .find(|n| named(n, "navLabel"))
+                .and_then(|l| l.descendants().find(|n| named(n, "text")))
+                .and_then(|t| t.text());
+            let src = point
+                .children()
+                .find(|n| named(n, "content"))
+                .and_then(|c| attr(&c, "src"));
+            if let (Some(label), Some(src)) = (label, src) {
+                add(resolve_href(dir_of(&ncx.href), src), label.to_string());
+            }
+        }
+    }
+    Ok(titles)
+}
+
+/// Fail on DRM-encrypted chapters, whose text would only be noise; obfuscated
+/// fonts are common and harmless
+fn check_not_encrypted(archive: &mut ZipArchive<Cursor<Vec<u8>>>) -> Result<()> {
+    let Some(xml) = zip_entry(archive, "META-INF/encryption.xml")? else {
+        return Ok(());
+    };
+    let doc = parse_xml(&xml, "META-INF/encryption.xml")?;
+    let encrypted_text = doc
+        .descendants()
+        .filter(|n| named(n, "CipherReference"))
+        .filter_map(|n| attr(&n, "URI"))
+        .any(|uri| {
+            let uri = uri.to_ascii_lowercase();
+            [".xhtml", ".html", ".htm"]
+                .iter()
+                .any(|ext| uri.ends_with(ext))
+        });
+    if encrypted_text {
+        anyhow::bail!("the book is DRM-protected; its chapters are encrypted");
+    }
+    Ok(())
+}
+
+/// Title and chapters of an EPUB given as bytes, following the spine order
+fn parse_epub(bytes: Vec<u8>) -> Result<(Option<String>, Vec<Chapter>)> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes))
+        .context("not an EPUB (zip archive); the file may be corrupt")?;
+    check_not_encrypted(&mut archive)?;
+
+    let container = zip_entry(&mut archive, "META-INF/container.xml")?
+        .context("no META-INF/container.xml in the archive; is this an EPUB?")?;
+    let container = parse_xml(&container, "META-INF/container.xml")?;
+    let opf_path = container
+        .descendants()
+        .find(|n| named(n, "rootfile"))
+        .and_then(|n| attr(&n, "full-path"))
+        .context("META-INF/container.xml names no package document")?
+        .to_string();
+    let opf = zip_entry(&mut archive, &opf_path)?
+        .with_context(|| format!("package document {opf_path} is missing"))?;
+    let opf = parse_xml(&opf, &opf_path)?;
+
+    let title = opf
+        .descendants()
+        .find(|n| named(n, "title"))
+        .and_then(|n| n.text())
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty());
+    let manifest: HashMap<String, ManifestItem> = opf
+        .descendants()
+        .filter(|n| named(n, "item"))
+        .filter_map(|n| {
+            let item = ManifestItem {
+                href: resolve_href(dir_of(&opf_path), attr(&n, "href")?),
+                media_type: attr(&n, "media-type").unwrap_or("").to_string(),
+                properties: attr(&n, "properties").unwrap_or("").to_string(),
+            };
+            Some((attr(&n, "id")?.to_string(), item))
+        })
+        .collect();
+    let spine = opf
+        .descendants()
+        .find(|n| named(n, "spine"))
+        .context("package document has no spine")?;
+    let toc = toc_titles(&mut archive, &manifest, attr(&spine, "toc"))?;
+
+    let mut chapters = Vec::new();
+    let itemrefs = spine.children().filter(|n| named(n, "itemref"));
+    for (spine_index, itemref) in itemrefs.enumerate() {
+        let Some(item) = attr(&itemref, "idref").and_then(|id| manifest.get(id)) else {
+            continue;
+        };
+        if !item.media_type.contains("html") {
+            continue;
+        }
+        let xhtml = zip_entry(&mut archive, &item.href)?
+            .with_context(|| format!("spine item {} is missing", item.href))?;
+        let html = Html::parse_document(&xhtml);
+        let markdown = html_to_markdown(&html);
+        if markdown.trim().is_empty() {
+            continue;
+        }
+        let title = toc
+            .get(&item.href)
+            .cloned()
+            .or_else(|| {
+                markdown
+                    .lines()
+                    .find(|line| heading_level(line).is_some())
+                    .map(|line| breadcrumb(&[line.to_string()]))
+            })
+            .or_else(|| {
+                let selector = Selector::parse("title").expect("selector is valid");
+                let title: String = html.select(&selector).next()?.text().collect();
+                Some(title.trim().to_string()).filter(|t| !t.is_empty())
+            })
+            .unwrap_or_else(|| format!("Chapter {}", spine_index + 1));
+        chapters.push(Chapter {
+            spine_index,
+            title,
+            markdown,
+        });
+    }
+    Ok((title, chapters))
+}
+
+fn read_book(path: &str, source_label: Option<&str>) -> Result<Book> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {path}"))?;
+    let (title, chapters) = parse_epub(bytes)?;
+    if chapters.is_empty() {
+        anyhow::bail!("no chapter with text in the spine");
+    }
+    Ok(Book {
+        path: path.to_string(),
+        source: source_label.unwrap_or(path).to_string(),
+        title: title.unwrap_or_else(|| path.to_string()),
+        chapters,
+    })
+}
+
+fn main() -> Result<()> {
+    let mut args: Args = parse_args()?;
+    args.collection = namespaced_collection(
+        &args.collection_prefix,
+        &args.collection,
+        &args.collection_suffix,
+    );
+    let counter = TokenCounter::load(args.tokenizer);
+    if args.max_tokens == 0 {
+        anyhow::bail!("--max-tokens must be greater than zero");
+    }
+    if args.embed_batch_size == 0 {
+        anyhow::bail!("--embed-batch-size must be greater than zero");
+    }
+    if args.source_label.is_some() && args.epub_paths.len() > 1 {
+        anyhow::bail!("--source-label names a single book; pass one .epub file with it");
+    }
+
+    // Chunk each chapter on its own, so no chunk spans two chapters
+    let mut books = Vec::with_capacity(args.epub_paths.len());
+    let mut chunks: Vec<BookChunk> = Vec::new();
+    let mut small = SmallChunkStats::default();
+    for path in &args.epub_paths {
+        if !args.dry_run_json {
+            println!("📖 Reading EPUB: {path}");
+        }
+        let book = match read_book(path, args.source_label.as_deref()) {
+            Ok(book) => book,
+            Err(e) => {
+                eprintln!("⚠️  Skipping {path}: {e:#}");
+                continue;
+            }
+        };
+        for (chapter, content) in book.chapters.iter().enumerate() {
+            let (chapter_chunks, stats) = merge_small_chunks(
+                smart_chunk_markdown(&content.markdown, args.chunk_size, counter),
+                args.min_chunk_size,
+                counter,
+            );
+            small += stats;
+            let chapter_chunks = enforce_max_tokens(chapter_chunks, args.max_tokens, counter);
+            chunks.extend(chapter_chunks.into_iter().map(|chunk| BookChunk {
+                book: books.len(),
+                chapter,
+                chunk,
+            }));
+        }
+        if !args.dry_run_json {
+            println!("   {} chapters", book.chapters.len());
+        }
+        books.push(book);
+    }
+    if books.is_empty() {
+        anyhow::bail!("None of the books could be read");
+    }
+    if !args.dry_run_json {
+        small.print();
+    }
+    limit_chunks(&mut chunks, args.max_chunks)?;
+    if args.dry_run || args.dry_run_json {
+        let plain: Vec<MarkdownChunk> = chunks.iter().map(|c| c.chunk.clone()).collect();
+        let mut plan = markdown_chunk_plan(&plain, counter);
+        // The chapter matters more for a book than the headings within it
+        for (row, c) in plan.iter_mut().zip(&chunks) {
+            let title = &books[c.book].chapters[c.chapter].title;
+            row.header_context = format!("[{title}] {}", c.chunk.breadcrumb)
+                .trim_end()
+                .to_string();
+        }
+        return print_chunk_plan(&plan, args.dry_run_json);
+    }
+
+    if !args.skip_checks {
+        preflight(
+            &args.qdrant_url,
+            &args.ollama_url,
+            &args.model,
+            args.embed_api,
+        )?;
+    }
+    let embedder = Embedder::new(
+        &args.ollama_url,
+        args.embed_api,
+        &args.model,
+        RetryPolicy {
+            retries: args.embed_retries,
+            base_delay_ms: args.embed_retry_delay_ms,
+        },
+    )
+    .oversize(OversizePolicy {
+        on_oversize: args.on_oversize,
+        max_tokens: args.max_tokens,
+        counter,
+    })
+    .legacy_embed(args.legacy_embed);
+    let qdrant = QdrantClient::new(&args.qdrant_url)
+        .api_key(args.qdrant_api_key.as_ref())
+        .consistency(args.consistency)
+        .ingest_model(&args.model);
+    let mut ids = IdGenerator::new(args.seed);
+
+    println!(
+        "📦 Created {} chunks from {} books",
+        chunks.len(),
+        books.len()
+    );
+
+    println!("🧮 Generating embeddings with model: {}", args.model);
+    let embedding_texts: Vec<String> = chunks.iter().map(|c| c.chunk.embedding_text()).collect();
+    let mut embeddings = Vec::new();
+    let bar = progress_bar(chunks.len(), "Embedding", args.quiet);
+    for texts in embedding_texts.chunks(args.embed_batch_size) {
+        embeddings.extend(embedder.embed_batch(texts)?);
+        bar.inc(texts.len() as u64);
+    }
+    bar.finish_and_clear();
+
+    let mut totals = vec![0; books.len()];
+    for c in &chunks {
+        totals[c.book] += 1;
+    }
+    let mut next_index = vec![0; books.len()];
+    let mut points = Vec::with_capacity(chunks.len());
+    for (c, embedding) in chunks.iter().zip(embeddings) {
+        let (book, chunk) = (&books[c.book], &c.chunk);
+        let chapter = &book.chapters[c.chapter];
+        let chunk_index = next_index[c.book];
+        next_index[c.book] += 1;
+        let mut payload = json!({
+            "source": book.source,
+            "source_path": book.path,
+            "chunk_index": chunk_index,
+            "total_chunks": totals[c.book],
+            "chunk_type": chunk.chunk_type,
+            "lang": detect_lang(&chunk.content),
+            "header_context": chunk.header_context,
+            "breadcrumb": chunk.breadcrumb,
+            "book_title": book.title,
+            "chapter_title": chapter.title,
+            "chapter_index": chapter.spine_index,
+        });
+        payload[args.text_field.as_str()] = json!(chunk.content);
+
+        points.push(QdrantPoint {
+            id: ids.next_id(),
+            vector: embedding,
+            sparse: None,
+            summary_vector: None,
+            payload,
+        });
+    }
+    println!("✅ Generated embeddings for all chunks");
+
+    // Upload to Qdrant in batches
+    println!("📤 Uploading to Qdrant collection: {}", args.collection);
+    let batch_size = 100;
+    let total_batches = points.len().div_ceil(batch_size);
+
+    let bar = progress_bar(total_batches, "Uploading batches", args.quiet);
+    for (i, batch) in points.chunks(batch_size).enumerate() {
+        qdrant
+            .upsert_points(&args.collection, batch)
+            .with_context(|| format!("Qdrant returned error in batch {}", i + 1))?;
+        bar.inc(1);
+    }
+    bar.finish_and_clear();
+
+    println!("✅ Successfully ingested EPUB into Qdrant!");
+    for book in &books {
+        println!(
+            "   Source: {} ({} chapters)",
+            book.source,
+            book.chapters.len()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    fn epub(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        for (name, content) in files {
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    const CONTAINER: &str = r#"<container xmlns="urn:oasis:names:tc:opendocument:xmlns:container" version="1.0">
+  <rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles>
+</container>"#;
+
+    const OPF: &str = r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:title> The Rust Book </dc:title></metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+    <item id="cover" href="images/cover.jpg" media-type="image/jpeg"/>
+    <item id="c1" href="text/ch%201.xhtml" media-type="application/xhtml+xml"/>
+    <item id="c2" href="text/ch2.xhtml" media-type="application/xhtml+xml"/>
+  </manifest>
+  <spine><itemref idref="cover"/><itemref idref="c1"/><itemref idref="c2"/></spine>
+</package>"#;
+
+    const NAV: &str = r#"<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops"><body>
+  <nav epub:type="toc"><ol>
+    <li><a href="text/ch%201.xhtml">1. Getting   Started</a></li>
+    <li><a href="text/ch%201.xhtml#install">Installation</a></li>
+  </ol></nav>
+</body></html>"#;
+
+    const CHAPTER_1: &str = r#"<html xmlns="http://www.w3.org/1999/xhtml"><body>
+  <h1>Getting Started</h1><p>Install rustup first.</p>
+  <h2 id="install">Installation</h2><p>Run the installer.</p>
+</body></html>"#;
+
+    const CHAPTER_2: &str = r#"<html xmlns="http://www.w3.org/1999/xhtml"><head><title>ch2</title></head><body>
+  <h1>Guessing Game</h1><p>Let us write a game.</p>
+</body></html>"#;
+
+    fn book() -> Vec<u8> {
+        epub(&[
+            ("mimetype", "application/epub+zip"),
+            ("META-INF/container.xml", CONTAINER),
+            ("OEBPS/content.opf", OPF),
+            ("OEBPS/nav.xhtml", NAV),
+            ("OEBPS/text/ch 1.xhtml", CHAPTER_1),
+            ("OEBPS/text/ch2.xhtml", CHAPTER_2),
+        ])
+    }
+
+    #[test]
+    fn test_chapters_follow_the_spine_with_toc_titles() {
+        let (title, chapters) = parse_epub(book()).unwrap();
+
+        assert_eq!(title.as_deref(), Some("The Rust Book"));
+        let summary: Vec<(usize, &str)> = chapters
+            .iter()
+            .map(|c| (c.spine_index, c.title.as_str()))
+            .collect();
+        // Not in the TOC, so the second chapter is named by its first heading
+        assert_eq!(
+            summary,
+            vec![(1, "1. Getting Started"), (2, "Guessing Game")]
+        );
+    }
+
+    #[test]
+    fn test_chunks_stay_within_their_chapter() {
+        let (_, chapters) = parse_epub(book()).unwrap();
+
+        for chapter in &chapters {
+            let chunks = smart_chunk_markdown(&chapter.markdown, 10_000, TokenCounter::estimate());
+            let text: String = chunks.iter().map(|c| c.content.as_str()).collect();
+            assert_eq!(
+                text.contains("rustup"),
+                chapter.title.contains("Getting Started")
+            );
+            assert_eq!(text.contains("game"), chapter.title == "Guessing Game");
+        }
+    }
+
+    #[test]
+    fn test_ncx_titles_and_relative_hrefs() {
+        let opf = OPF
+            .replace(
+                r#"<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>"#,
+                r#"<item id="ncx" href="../toc.ncx" media-type="application/x-dtbncx+xml"/>"#,
+            )
+            .replace("<spine>", r#"<spine toc="ncx">"#);
+        let ncx = r#"<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/"><navMap>
+  <navPoint id="p1"><navLabel><text>Chapter Two</text></navLabel><content src="OEBPS/text/ch2.xhtml"/></navPoint>
+</navMap></ncx>"#;
+        let bytes = epub(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("OEBPS/content.opf", &opf),
+            ("toc.ncx", ncx),
+            ("OEBPS/text/ch 1.xhtml", CHAPTER_1),
+            ("OEBPS/text/ch2.xhtml", CHAPTER_2),
+        ]);
+
+        let (_, chapters) = parse_epub(bytes).unwrap();
+
+        assert_eq!(chapters[0].title, "Getting Started");
+        assert_eq!(chapters[1].title, "Chapter Two");
+        assert_eq!(
+            resolve_href("OEBPS/text/", "../img/a%20b.png#x"),
+            "OEBPS/img/a b.png"
+        );
+    }
+
+    #[test]
+    fn test_unreadable_books_explain_why() {
+        let err = parse_epub(b"not a zip".to_vec()).unwrap_err();
+        assert!(err.to_string().contains("may be corrupt"));
+
+        let encryption = r#"<encryption xmlns="urn:oasis:names:tc:opendocument:xmlns:container" xmlns:enc="http://www.w3.org/2001/04/xmlenc#">
+  <enc:EncryptedData><enc:CipherData><enc:CipherReference URI="OEBPS/text/ch2.xhtml"/></enc:CipherData></enc:EncryptedData>
+</encryption>"#;
+        let err = parse_epub(epub(&[("META-INF/encryption.xml", encryption)])).unwrap_err();
+        assert!(err.to_string().contains("DRM-protected"));
+    }
+}
@@ -3,10 +3,17 @@
 
 use anyhow::{Context, Result};
 use clap::Parser;
-use reqwest::blocking::Client;
-use serde::{Deserialize, Serialize};
+use rag_demo::config::parse_args;
+use rag_demo::rag::{
+    ApiKey, CONTENT_VECTOR_NAME, ChunkPlan, Consistency, DEFAULT_INDEX_FIELDS, Distance, EmbedApi,
+    Embedder, HeadingStack, IdGenerator, IndexFields, QdrantClient, QdrantPoint, Quantization,
+    RetryPolicy, SPARSE_VECTOR_NAME, SUMMARY_VECTOR_NAME, SparseVector, TokenCounter, Tokenizer,
+    breadcrumb, detect_lang, fence_lang, limit_chunks, namespaced_collection, preflight,
+    print_chunk_plan, progress_bar, safe_truncate,
+};
+use serde::Serialize;
 use serde_json::json;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use uuid::Uuid;
 
@@ -24,37 +31,208 @@ struct Args {
     )]
     collection: String,
 
-    #[arg(long, default_value = "http://localhost:6333", help = "Qdrant URL")]
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_PREFIX",
+        help = "Prepended to the collection name, e.g. acme_ to keep one tenant's collections apart"
+    )]
+    collection_prefix: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_SUFFIX",
+        help = "Appended to the collection name"
+    )]
+    collection_suffix: String,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:6333",
+        env = "QDRANT_URL",
+        help = "Qdrant URL"
+    )]
     qdrant_url: String,
 
-    #[arg(long, default_value = "http://localhost:11434", help = "Ollama URL")]
+    #[arg(
+        long,
+        env = "QDRANT_API_KEY",
+        hide_env_values = true,
+        help = "Qdrant API key, sent as the api-key header (Qdrant Cloud)"
+    )]
+    qdrant_api_key: Option<ApiKey>,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:11434",
+        env = "OLLAMA_URL",
+        help = "Ollama URL"
+    )]
     ollama_url: String,
 
     #[arg(
         short,
         long,
         default_value = "nomic-embed-text",
+        env = "RAG_MODEL",
         help = "Embedding model"
     )]
     model: String,
-}
 
-#[derive(Debug, Serialize, Deserialize)]
-struct EmbeddingRequest {
-    model: String,
-    prompt: String,
-}
+    #[arg(
+        long,
+        default_value = "text",
+        help = "Payload field that stores the chunk text"
+    )]
+    text_field: String,
 
-#[derive(Debug, Deserialize)]
-struct EmbeddingResponse {
-    embedding: Vec<f32>,
-}
+    #[arg(
+        long,
+        help = "Human-friendly name stored as the payload source (default: file path)"
+    )]
+    source_label: Option<String>,
 
-#[derive(Debug, Serialize)]
-struct QdrantPoint {
-    id: String,
-    vector: Vec<f32>,
-    payload: serde_json::Value,
+    #[arg(long, help = "Seed for reproducible point IDs (default: OS entropy)")]
+    seed: Option<u64>,
+
+    #[arg(
+        long,
+        conflicts_with = "seed",
+        help = "Derive each point ID from the source and the chunk's type and lines, so re-ingesting a file overwrites its points instead of duplicating them"
+    )]
+    deterministic_ids: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "ollama",
+        help = "Embedding API: ollama (/api/embeddings), ollama-embed (/api/embed), openai (/v1/embeddings)"
+    )]
+    embed_api: EmbedApi,
+
+    #[arg(
+        long,
+        help = "Skip checking that Qdrant, Ollama and the embedding model are available"
+    )]
+    skip_checks: bool,
+
+    #[arg(
+        long,
+        default_value = "4",
+        help = "Times to retry a failed embedding request (5xx, 429, connection errors)"
+    )]
+    embed_retries: u32,
+
+    #[arg(
+        long,
+        default_value = "500",
+        help = "Delay before the first embedding retry in ms, doubled on each retry"
+    )]
+    embed_retry_delay_ms: u64,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "strong",
+        help = "strong waits for each upsert to be applied, so points are searchable and counted when ingestion ends; eventual returns as soon as Qdrant accepts them"
+    )]
+    consistency: Consistency,
+
+    #[arg(
+        long,
+        default_value_t = CHILD_TARGET_SIZE,
+        help = "Target child chunk size in tokens"
+    )]
+    child_size: usize,
+
+    #[arg(
+        long,
+        default_value_t = PARENT_TARGET_SIZE,
+        help = "Parent chunk size in tokens at which a parent is split off"
+    )]
+    parent_size: usize,
+
+    #[arg(
+        long,
+        default_value_t = MIN_PARENT_SIZE,
+        help = "Minimum parent size in tokens before an H2 heading closes it"
+    )]
+    min_parent_size: usize,
+
+    #[arg(
+        long,
+        default_value_t = 0,
+        help = "Tokens of whole trailing lines carried from a size-split parent into the next (0 disables)"
+    )]
+    parent_overlap: usize,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "cl100k",
+        help = "Tokenizer used to measure chunk sizes (chars estimates 4 characters per token)"
+    )]
+    tokenizer: Tokenizer,
+
+    #[arg(
+        long,
+        default_value_t = MAX_TOKENS,
+        help = "Hard limit in tokens for every child chunk and embedding input"
+    )]
+    max_tokens: usize,
+
+    #[arg(
+        long,
+        help = "Prefix each child's embedding input with its parent's summary instead of just the headers"
+    )]
+    child_embed_parent_summary: bool,
+
+    #[arg(
+        long,
+        help = "Print each parent and child chunk's type, size, headers and lines, then exit without embedding or uploading"
+    )]
+    dry_run: bool,
+
+    #[arg(long, help = "Like --dry-run, but print the chunks as JSON")]
+    dry_run_json: bool,
+
+    #[arg(
+        long,
+        help = "Only embed and upload the first N parent chunks and their children, for quick trial runs on big files"
+    )]
+    max_chunks: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Hide progress bars (also hidden when stdout is not a terminal)"
+    )]
+    quiet: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "cosine",
+        help = "Vector distance for newly created collections; existing collections keep theirs"
+    )]
+    distance: Distance,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "none",
+        help = "Quantization for newly created collections: scalar keeps int8 vectors in RAM (~4x less memory, slightly lower recall)"
+    )]
+    quantization: Quantization,
+
+    #[arg(
+        long,
+        default_value = DEFAULT_INDEX_FIELDS,
+        help = "Payload fields to index after ingesting, comma-separated as name or name:schema (keyword, integer, float, bool, text); \"\" indexes none"
+    )]
+    index_fields: IndexFields,
 }
 
 #[derive(Debug, Clone)]
@@ -76,6 +254,8 @@ struct ChildChunk {
     start_line: usize,
     end_line: usize,
     chunk_type: ChunkType,
+    /// Fence language of the first code block opened in this chunk
+    code_lang: Option<String>,
     index_in_parent: usize,
 }
 
@@ -90,19 +270,154 @@ enum ChunkType {
 
 /// Based on research: ~400 tokens for children, ~1000 tokens for parents
 /// Reduced to stay under embedding model's ~2000 char limit
-const CHILD_TARGET_SIZE: usize = 1200;
-const PARENT_TARGET_SIZE: usize = 1800;
-const MIN_PARENT_SIZE: usize = 800;
+const CHILD_TARGET_SIZE: usize = 300;
+const PARENT_TARGET_SIZE: usize = 450;
+const MIN_PARENT_SIZE: usize = 200;
+/// Ollama's default context length for embedding models
+const MAX_TOKENS: usize = 2048;
+/// Text before a code block is only split off into its own child above this many tokens
+const MIN_CHILD_BEFORE_CODE: usize = 75;
+
+/// Token sizes that shape the parent/child hierarchy
+#[derive(Debug, Clone, Copy)]
+struct ChunkSizes {
+    /// Target size of a child chunk
+    child: usize,
+    /// Size at which a parent is split off
+    parent: usize,
+    /// Smallest parent that an H2 boundary will close
+    min_parent: usize,
+    /// Tokens of trailing lines repeated at the start of the next parent
+    /// when a parent is split for size
+    parent_overlap: usize,
+    /// Hard limit for a child chunk or embedding input
+    max_tokens: usize,
+    /// Measures text in tokens
+    counter: TokenCounter,
+}
 
-fn create_hierarchical_chunks(content: &str) -> (Vec<ParentChunk>, Vec<ChildChunk>) {
+impl Default for ChunkSizes {
+    fn default() -> Self {
+        Self {
+            child: CHILD_TARGET_SIZE,
+            parent: PARENT_TARGET_SIZE,
+            min_parent: MIN_PARENT_SIZE,
+            parent_overlap: 0,
+            max_tokens: MAX_TOKENS,
+            counter: TokenCounter::estimate(),
+        }
+    }
+}
+
+impl ChunkSizes {
+    fn validate(&self) -> Result<()> {
+        if self.child == 0 || self.parent == 0 || self.max_tokens == 0 {
+            anyhow::bail!("--child-size, --parent-size and --max-tokens must be greater than zero");
+        }
+        if self.child > self.max_tokens {
+            anyhow::bail!(
+                "--child-size ({}) must not exceed --max-tokens ({})",
+                self.child,
+                self.max_tokens
+            );
+        }
+        if self.min_parent > self.parent {
+            anyhow::bail!(
+                "--min-parent-size ({}) must not exceed --parent-size ({})",
+                self.min_parent,
+                self.parent
+            );
+        }
+        if self.parent_overlap >= self.parent {
+            anyhow::bail!(
+                "--parent-overlap ({}) must be less than --parent-size ({})",
+                self.parent_overlap,
+                self.parent
+            );
+        }
+        if self.child > self.parent {
+            anyhow::bail!(
+                "--child-size ({}) must not exceed --parent-size ({})",
+                self.child,
+                self.parent
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Emit document lines `start_line..=end_line` as a parent chunk along with
+/// its children, linking them both ways
+fn emit_parent(
+    parents: &mut Vec<ParentChunk>,
+    children: &mut Vec<ChildChunk>,
+    lines: &[&str],
+    (start_line, end_line): (usize, usize),
+    headers: &[String],
+    sizes: ChunkSizes,
+    ids: &mut IdGenerator,
+) {
+    let parent_id = ids.next_id();
+    let parent_lines = &lines[start_line..=end_line];
+    let content = parent_lines.join("\n");
+    let new_children = create_child_chunks(parent_lines, start_line, &parent_id, sizes, ids);
+    let child_ids = new_children.iter().map(|c| c.id.clone()).collect();
+    children.extend(new_children);
+
+    let summary = create_summary(&content, headers);
+    parents.push(ParentChunk {
+        id: parent_id,
+        content,
+        start_line,
+        end_line,
+        headers: headers.to_vec(),
+        child_ids,
+        summary,
+    });
+}
+
+/// First line of the overlap carried from a parent spanning `start..=end`
+/// into the next one: the longest run of whole trailing lines within
+/// `overlap` tokens, never reaching back to `start` so parents always advance
+fn overlap_start(
+    lines: &[&str],
+    (start, end): (usize, usize),
+    overlap: usize,
+    counter: TokenCounter,
+) -> usize {
+    let mut first = end + 1;
+    while overlap > 0
+        && first > start + 1
+        && counter.count(&lines[first - 1..=end].join("\n")) <= overlap
+    {
+        first -= 1;
+    }
+    first
+}
+
+/// Natural end for a full parent spanning `start..=end`: the last blank line
+/// among its final six, else `end`. Never before `start`, since a blank line
+/// from the previous parent would close an empty parent and restart the scan
+/// at the same line forever.
+fn break_point(lines: &[&str], start: usize, end: usize) -> usize {
+    (end.saturating_sub(5).max(start)..=end)
+        .rev()
+        .find(|&j| lines[j].trim().is_empty())
+        .unwrap_or(end)
+}
+
+fn create_hierarchical_chunks(
+    content: &str,
+    sizes: ChunkSizes,
+    ids: &mut IdGenerator,
+) -> (Vec<ParentChunk>, Vec<ChildChunk>) {
     let mut parent_chunks = Vec::new();
     let mut child_chunks = Vec::new();
     let lines: Vec<&str> = content.lines().collect();
 
     let mut current_parent = String::new();
     let mut current_parent_start = 0;
-    let mut current_headers: Vec<String> = Vec::new();
-    let mut current_child_ids = Vec::new();
+    let mut headings = HeadingStack::default();
 
     let mut i = 0;
     while i < lines.len() {
@@ -111,53 +426,40 @@ fn create_hierarchical_chunks(content: &str) -> (Vec<ParentChunk>, Vec<ChildChun
         // Detect section boundaries (H1 and H2)
         if line.starts_with("##") && !line.starts_with("###") {
             // Save current parent if substantial
-            if current_parent.len() > MIN_PARENT_SIZE {
-                let parent_id = Uuid::new_v4().to_string();
-                let summary = create_summary(&current_parent, &current_headers);
-
-                parent_chunks.push(ParentChunk {
-                    id: parent_id.clone(),
-                    content: current_parent.clone(),
-                    start_line: current_parent_start,
-                    end_line: i - 1,
-                    headers: current_headers.clone(),
-                    child_ids: current_child_ids.clone(),
-                    summary,
-                });
-
+            if sizes.counter.count(&current_parent) > sizes.min_parent {
+                emit_parent(
+                    &mut parent_chunks,
+                    &mut child_chunks,
+                    &lines,
+                    (current_parent_start, i - 1),
+                    &headings.lines(),
+                    sizes,
+                    ids,
+                );
                 current_parent.clear();
-                current_child_ids.clear();
                 current_parent_start = i;
             }
 
-            // Update headers
-            current_headers = vec![line.to_string()];
+            headings.push_line(line);
         } else if line.starts_with("#") && !line.starts_with("##") {
             // H1 - major section
-            if !current_parent.is_empty() {
-                let parent_id = Uuid::new_v4().to_string();
-                let summary = create_summary(&current_parent, &current_headers);
-
-                parent_chunks.push(ParentChunk {
-                    id: parent_id.clone(),
-                    content: current_parent.clone(),
-                    start_line: current_parent_start,
-                    end_line: i - 1,
-                    headers: current_headers.clone(),
-                    child_ids: current_child_ids.clone(),
-                    summary,
-                });
-
+            if !current_parent.trim().is_empty() {
+                emit_parent(
+                    &mut parent_chunks,
+                    &mut child_chunks,
+                    &lines,
+                    (current_parent_start, i - 1),
+                    &headings.lines(),
+                    sizes,
+                    ids,
+                );
                 current_parent.clear();
-                current_child_ids.clear();
                 current_parent_start = i;
             }
-            current_headers = vec![line.to_string()];
+            headings.push_line(line);
         } else if line.starts_with("###") {
-            // H3 - subsection, add to headers
-            if current_headers.len() < 3 {
-                current_headers.push(line.to_string());
-            }
+            // H3 and deeper - subsection, add to headers
+            headings.push_line(line);
         }
 
         // Add line to parent
@@ -165,41 +467,32 @@ fn create_hierarchical_chunks(content: &str) -> (Vec<ParentChunk>, Vec<ChildChun
         current_parent.push('\n');
 
         // Check if we should create a parent chunk
-        if current_parent.len() >= PARENT_TARGET_SIZE {
-            // Look for natural break point
-            let mut break_point = i;
-            for j in (i.saturating_sub(5)..=i).rev() {
-                if j < lines.len() && lines[j].trim().is_empty() {
-                    break_point = j;
-                    break;
-                }
-            }
+        if sizes.counter.count(&current_parent) >= sizes.parent {
+            let break_point = break_point(&lines, current_parent_start, i);
 
             // Create parent and its children
-            let parent_id = Uuid::new_v4().to_string();
-            let parent_content = lines[current_parent_start..=break_point].join("\n");
-            let children = create_child_chunks(&parent_content, &parent_id, current_parent_start);
-
-            for child in &children {
-                current_child_ids.push(child.id.clone());
-            }
-            child_chunks.extend(children);
-
-            let summary = create_summary(&parent_content, &current_headers);
-            parent_chunks.push(ParentChunk {
-                id: parent_id.clone(),
-                content: parent_content,
-                start_line: current_parent_start,
-                end_line: break_point,
-                headers: current_headers.clone(),
-                child_ids: current_child_ids.clone(),
-                summary,
-            });
+            emit_parent(
+                &mut parent_chunks,
+                &mut child_chunks,
+                &lines,
+                (current_parent_start, break_point),
+                &headings.lines(),
+                sizes,
+                ids,
+            );
 
-            // Reset for next parent
+            // Start the next parent, repeating the tail of this one
+            current_parent_start = overlap_start(
+                &lines,
+                (current_parent_start, break_point),
+                sizes.parent_overlap,
+                sizes.counter,
+            );
             current_parent.clear();
-            current_child_ids.clear();
-            current_parent_start = break_point + 1;
+            for line in &lines[current_parent_start..=break_point] {
+                current_parent.push_str(line);
+                current_parent.push('\n');
+            }
             i = break_point;
         }
 
@@ -208,67 +501,100 @@ fn create_hierarchical_chunks(content: &str) -> (Vec<ParentChunk>, Vec<ChildChun
 
     // Handle remaining content
     if !current_parent.trim().is_empty() {
-        let parent_id = Uuid::new_v4().to_string();
-        let children = create_child_chunks(&current_parent, &parent_id, current_parent_start);
-
-        for child in &children {
-            current_child_ids.push(child.id.clone());
-        }
-        child_chunks.extend(children);
-
-        let summary = create_summary(&current_parent, &current_headers);
-        parent_chunks.push(ParentChunk {
-            id: parent_id,
-            content: current_parent,
-            start_line: current_parent_start,
-            end_line: lines.len() - 1,
-            headers: current_headers,
-            child_ids: current_child_ids,
-            summary,
-        });
+        emit_parent(
+            &mut parent_chunks,
+            &mut child_chunks,
+            &lines,
+            (current_parent_start, lines.len() - 1),
+            &headings.lines(),
+            sizes,
+            ids,
+        );
     }
 
     (parent_chunks, child_chunks)
 }
 
+/// Check that parent `child_ids` and child `parent_id` links agree with each other
+fn validate_links(parents: &[ParentChunk], children: &[ChildChunk]) -> Result<()> {
+    let parent_ids: HashSet<&str> = parents.iter().map(|p| p.id.as_str()).collect();
+    let child_parents: HashMap<&str, &str> = children
+        .iter()
+        .map(|c| (c.id.as_str(), c.parent_id.as_str()))
+        .collect();
+
+    for child in children {
+        if !parent_ids.contains(child.parent_id.as_str()) {
+            anyhow::bail!(
+                "Child {} points to missing parent {}",
+                child.id,
+                child.parent_id
+            );
+        }
+    }
+
+    for parent in parents {
+        for child_id in &parent.child_ids {
+            match child_parents.get(child_id.as_str()) {
+                None => anyhow::bail!("Parent {} lists missing child {}", parent.id, child_id),
+                Some(owner) if *owner != parent.id => anyhow::bail!(
+                    "Parent {} lists child {} that belongs to parent {}",
+                    parent.id,
+                    child_id,
+                    owner
+                ),
+                Some(_) => {}
+            }
+        }
+    }
+
+    Ok(())
+}
+
 fn create_child_chunks(
-    parent_content: &str,
+    lines: &[&str],
+    first_line: usize,
     parent_id: &str,
-    parent_start_line: usize,
+    sizes: ChunkSizes,
+    ids: &mut IdGenerator,
 ) -> Vec<ChildChunk> {
     let mut children = Vec::new();
-    let lines: Vec<&str> = parent_content.lines().collect();
+    let last_line = first_line + lines.len().saturating_sub(1);
 
     let mut current_chunk = String::new();
-    let mut chunk_start = 0;
+    let mut chunk_start = first_line;
     let mut in_code_block = false;
     let mut chunk_type = ChunkType::Text;
     let mut has_code = false;
+    let mut code_lang: Option<String> = None;
 
-    for (i, line) in lines.iter().enumerate() {
+    // `n` is the line number in the whole document, not within the parent
+    for (i, (n, line)) in (first_line..).zip(lines).enumerate() {
         // Track code blocks
         if line.trim().starts_with("```") {
             if !in_code_block {
                 // Starting code block - save current chunk if exists
-                if current_chunk.len() > 300 {
+                if sizes.counter.count(&current_chunk) > MIN_CHILD_BEFORE_CODE {
                     children.push(ChildChunk {
-                        id: Uuid::new_v4().to_string(),
+                        id: ids.next_id(),
                         parent_id: parent_id.to_string(),
                         content: current_chunk.clone(),
-                        start_line: parent_start_line + chunk_start,
-                        end_line: parent_start_line + i - 1,
+                        start_line: chunk_start,
+                        end_line: n - 1,
                         chunk_type: if has_code {
                             ChunkType::Mixed
                         } else {
                             chunk_type.clone()
                         },
+                        code_lang: code_lang.take(),
                         index_in_parent: children.len(),
                     });
                     current_chunk.clear();
-                    chunk_start = i;
+                    chunk_start = n;
                     has_code = false;
                 }
                 in_code_block = true;
+                code_lang.get_or_insert_with(|| fence_lang(line).to_string());
                 chunk_type = ChunkType::Code;
             } else {
                 // Ending code block
@@ -295,24 +621,25 @@ fn create_child_chunks(
         current_chunk.push('\n');
 
         // Create child chunk at target size (but not in middle of code)
-        if !in_code_block && current_chunk.len() >= CHILD_TARGET_SIZE {
+        if !in_code_block && sizes.counter.count(&current_chunk) >= sizes.child {
             // Find natural break
             if line.trim().is_empty() || (i + 1 < lines.len() && lines[i + 1].starts_with('#')) {
                 children.push(ChildChunk {
-                    id: Uuid::new_v4().to_string(),
+                    id: ids.next_id(),
                     parent_id: parent_id.to_string(),
                     content: current_chunk.clone(),
-                    start_line: parent_start_line + chunk_start,
-                    end_line: parent_start_line + i,
+                    start_line: chunk_start,
+                    end_line: n,
                     chunk_type: if has_code {
                         ChunkType::Mixed
                     } else {
                         chunk_type.clone()
                     },
+                    code_lang: code_lang.take(),
                     index_in_parent: children.len(),
                 });
                 current_chunk.clear();
-                chunk_start = i + 1;
+                chunk_start = n + 1;
                 chunk_type = ChunkType::Text;
                 has_code = false;
             }
@@ -322,35 +649,97 @@ fn create_child_chunks(
     // Add remaining content
     if !current_chunk.trim().is_empty() {
         children.push(ChildChunk {
-            id: Uuid::new_v4().to_string(),
+            id: ids.next_id(),
             parent_id: parent_id.to_string(),
             content: current_chunk,
-            start_line: parent_start_line + chunk_start,
-            end_line: parent_start_line + lines.len() - 1,
+            start_line: chunk_start,
+            end_line: last_line,
             chunk_type: if has_code {
                 ChunkType::Mixed
             } else {
                 chunk_type
             },
+            code_lang,
             index_in_parent: children.len(),
         });
     }
 
-    children
+    // Code blocks are never split above, so the hard limit is enforced last
+    split_oversized_children(children, sizes, ids)
 }
 
-/// Safely truncate a string at a character boundary
-fn safe_truncate(s: &str, max_chars: usize) -> &str {
-    if s.chars().count() <= max_chars {
-        s
-    } else {
-        // Find the byte position of the nth character
-        let byte_pos = s
-            .char_indices()
-            .nth(max_chars)
-            .map(|(pos, _)| pos)
-            .unwrap_or(s.len());
-        &s[..byte_pos]
+/// Split any child over `sizes.max_tokens` into several children of the same parent
+fn split_oversized_children(
+    children: Vec<ChildChunk>,
+    sizes: ChunkSizes,
+    ids: &mut IdGenerator,
+) -> Vec<ChildChunk> {
+    let mut result: Vec<ChildChunk> = Vec::with_capacity(children.len());
+    for child in children {
+        let pieces = sizes.counter.split(&child.content, sizes.max_tokens);
+        // Pieces are contiguous, so each starts on the line its first byte is on
+        let mut start_line = child.start_line;
+        for (i, piece) in pieces.into_iter().enumerate() {
+            let end_line = start_line + piece.trim_end_matches('\n').matches('\n').count();
+            let next_start = start_line + piece.matches('\n').count();
+            result.push(ChildChunk {
+                id: if i == 0 {
+                    child.id.clone()
+                } else {
+                    ids.next_id()
+                },
+                content: piece,
+                start_line,
+                end_line: end_line.min(child.end_line),
+                index_in_parent: result.len(),
+                ..child.clone()
+            });
+            start_line = next_start;
+        }
+    }
+    result
+}
+
+/// Replace the random IDs with UUIDv5s of `source` and each chunk's type and
+/// line range, rewriting the parents' `child_ids` and the children's
+/// `parent_id` to match. Chunks of one type on the same lines, such as the
+/// pieces of a split line, are told apart by their order.
+fn assign_deterministic_ids(
+    source: &str,
+    parents: &mut [ParentChunk],
+    children: &mut [ChildChunk],
+) {
+    let mut used = HashSet::new();
+    let mut stable_id = |chunk_type: &str, start_line: usize, end_line: usize| {
+        let key = format!("{source}\n{chunk_type}\n{start_line}\n{end_line}");
+        let mut name = key.clone();
+        let mut repeat = 1;
+        while !used.insert(name.clone()) {
+            name = format!("{key}\n{repeat}");
+            repeat += 1;
+        }
+        Uuid::new_v5(&Uuid::NAMESPACE_URL, name.as_bytes()).to_string()
+    };
+
+    let mut renamed: HashMap<String, String> = HashMap::new();
+    for parent in parents.iter_mut() {
+        let id = stable_id("parent", parent.start_line, parent.end_line);
+        renamed.insert(std::mem::replace(&mut parent.id, id.clone()), id);
+    }
+    for child in children.iter_mut() {
+        let chunk_type = format!("child_{:?}", child.chunk_type).to_lowercase();
+        let id = stable_id(&chunk_type, child.start_line, child.end_line);
+        renamed.insert(std::mem::replace(&mut child.id, id.clone()), id);
+        if let Some(parent_id) = renamed.get(&child.parent_id) {
+            child.parent_id = parent_id.clone();
+        }
+    }
+    for parent in parents.iter_mut() {
+        for child_id in &mut parent.child_ids {
+            if let Some(id) = renamed.get(child_id) {
+                *child_id = id.clone();
+            }
+        }
     }
 }
 
@@ -374,6 +763,42 @@ fn create_summary(content: &str, headers: &[String]) -> String {
     summary
 }
 
+/// Parent context prepended to a child's text before embedding
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ChildEmbedContext {
+    /// Parent headers joined with " > "
+    Headers,
+    /// Parent summary (headers plus the first substantial paragraph)
+    ParentSummary,
+}
+
+impl ChildEmbedContext {
+    /// Value stored in the child payload so searches can tell the strategies apart
+    fn label(self) -> &'static str {
+        match self {
+            ChildEmbedContext::Headers => "headers",
+            ChildEmbedContext::ParentSummary => "parent_summary",
+        }
+    }
+}
+
+/// Text embedded for a child: parent context followed by the child content
+fn child_embedding_text(
+    child: &ChildChunk,
+    parent: Option<&ParentChunk>,
+    context: ChildEmbedContext,
+) -> String {
+    match (parent, context) {
+        (Some(p), ChildEmbedContext::Headers) => {
+            format!("{}\n\n{}", p.headers.join(" > "), child.content)
+        }
+        (Some(p), ChildEmbedContext::ParentSummary) => {
+            format!("{}\n\n{}", p.summary, child.content)
+        }
+        (None, _) => child.content.clone(),
+    }
+}
+
 /// Maximum characters to send to embedding model (nomic-embed-text crashes around 2500)
 const MAX_EMBEDDING_CHARS: usize = 2000;
 
@@ -446,82 +871,207 @@ fn sanitize_for_embedding(text: &str) -> String {
         .collect()
 }
 
-/// Maximum retries for embedding requests
-const MAX_RETRIES: u32 = 5;
-/// Delay between retries in milliseconds (increases with each retry)
-const BASE_RETRY_DELAY_MS: u64 = 500;
-
-fn get_embedding(client: &Client, ollama_url: &str, model: &str, text: &str) -> Result<Vec<f32>> {
-    // Sanitize and truncate text for embedding model
+/// Sanitize and truncate text so it stays within what the embedding model accepts
+fn prepare_for_embedding(text: &str, sizes: &ChunkSizes) -> String {
     let sanitized = sanitize_for_embedding(text);
-    let prompt = safe_truncate(&sanitized, MAX_EMBEDDING_CHARS).to_string();
+    let truncated = safe_truncate(&sanitized, MAX_EMBEDDING_CHARS);
+    sizes
+        .counter
+        .truncate(truncated, sizes.max_tokens)
+        .to_string()
+}
 
-    let request = EmbeddingRequest {
-        model: model.to_string(),
-        prompt,
-    };
+/// Per-run settings that are written into every point's payload
+struct PayloadOptions<'a> {
+    /// Name cited in search results (the `--source-label`, or the file path)
+    source: &'a str,
+    /// Path of the ingested file as given on the command line
+    source_path: &'a str,
+    /// Payload key that stores the chunk text
+    text_field: &'a str,
+    /// Parent context children were embedded with
+    child_context: ChildEmbedContext,
+}
 
-    // Retry logic with exponential backoff for transient failures
-    let mut last_error = None;
-    for attempt in 0..MAX_RETRIES {
-        if attempt > 0 {
-            // Exponential backoff: 500ms, 1000ms, 2000ms, 4000ms
-            let delay = BASE_RETRY_DELAY_MS * (1 << (attempt - 1));
-            std::thread::sleep(std::time::Duration::from_millis(delay));
-
-            // On retry, try to "wake up" the model by sending a tiny test request
-            let _ = client
-                .post(format!("{}/api/embeddings", ollama_url))
-                .json(&EmbeddingRequest {
-                    model: model.to_string(),
-                    prompt: "test".to_string(),
-                })
-                .send();
-            std::thread::sleep(std::time::Duration::from_millis(100));
-        }
-
-        let response = client
-            .post(format!("{}/api/embeddings", ollama_url))
-            .json(&request)
-            .send();
-
-        match response {
-            Ok(resp) => {
-                if resp.status().is_success() {
-                    match resp.json::<EmbeddingResponse>() {
-                        Ok(embedding) => return Ok(embedding.embedding),
-                        Err(e) => {
-                            last_error = Some(format!("Failed to parse response: {e}"));
-                        }
-                    }
-                } else {
-                    last_error = Some(format!("Ollama returned error: {}", resp.status()));
-                }
-            }
-            Err(e) => {
-                last_error = Some(format!("Request failed: {e}"));
-            }
+/// Build the Qdrant payload for a parent chunk
+fn parent_payload(parent: &ParentChunk, opts: &PayloadOptions) -> serde_json::Value {
+    let mut payload = json!({
+        "source": opts.source,
+        "source_path": opts.source_path,
+        "chunk_type": "parent",
+        "lang": detect_lang(&parent.content),
+        "summary": parent.summary,
+        "headers": parent.headers,
+        "breadcrumb": breadcrumb(&parent.headers),
+        "child_ids": parent.child_ids,
+        "start_line": parent.start_line,
+        "end_line": parent.end_line,
+        "char_count": parent.content.len(),
+    });
+    payload[opts.text_field] = json!(parent.content);
+    payload
+}
+
+/// Build the Qdrant payload for a child chunk
+fn child_payload(
+    child: &ChildChunk,
+    parent: Option<&ParentChunk>,
+    opts: &PayloadOptions,
+) -> serde_json::Value {
+    let mut payload = json!({
+        "source": opts.source,
+        "source_path": opts.source_path,
+        "chunk_type": format!("child_{:?}", child.chunk_type).to_lowercase(),
+        "lang": detect_lang(&child.content),
+        "parent_id": child.parent_id,
+        "parent_summary": parent.map(|p| &p.summary),
+        "breadcrumb": parent.map(|p| breadcrumb(&p.headers)),
+        "index_in_parent": child.index_in_parent,
+        "start_line": child.start_line,
+        "end_line": child.end_line,
+        "char_count": child.content.len(),
+        "embed_context": opts.child_context.label(),
+    });
+    if let Some(lang) = &child.code_lang {
+        payload["code_lang"] = json!(lang);
+    }
+    payload[opts.text_field] = json!(child.content);
+    payload
+}
+
+/// Chunk metadata listed by `--dry-run`: each parent followed by its children
+fn chunk_plan(
+    parents: &[ParentChunk],
+    children: &[ChildChunk],
+    counter: TokenCounter,
+) -> Vec<ChunkPlan> {
+    let mut plan = Vec::with_capacity(parents.len() + children.len());
+    for parent in parents {
+        let header_context = breadcrumb(&parent.headers);
+        let own_children = children.iter().filter(|c| c.parent_id == parent.id);
+        let rows = std::iter::once((
+            "parent".to_string(),
+            &parent.content,
+            parent.start_line,
+            parent.end_line,
+        ))
+        .chain(own_children.map(|c| {
+            (
+                format!("child_{:?}", c.chunk_type).to_lowercase(),
+                &c.content,
+                c.start_line,
+                c.end_line,
+            )
+        }));
+        for (chunk_type, content, start_line, end_line) in rows {
+            plan.push(ChunkPlan {
+                index: plan.len(),
+                chunk_type,
+                chars: content.chars().count(),
+                tokens: counter.count(content),
+                header_context: header_context.clone(),
+                start_line: Some(start_line),
+                end_line: Some(end_line),
+            });
         }
     }
+    plan
+}
 
-    anyhow::bail!(
-        "Failed after {MAX_RETRIES} attempts: {}",
-        last_error.unwrap_or_else(|| "Unknown error".to_string())
-    );
+/// Dimensions of the nomic-embed-text vectors new collections are created for
+const VECTOR_SIZE: usize = 768;
+
+/// Config for a new collection: named dense vectors for each chunk's text and
+/// its parent's summary, so either can be searched, plus the keyword vector
+fn collection_config(distance: Distance) -> serde_json::Value {
+    let dense = json!({
+        "size": VECTOR_SIZE,
+        "distance": distance.qdrant_name()
+    });
+    json!({
+        "vectors": {
+            CONTENT_VECTOR_NAME: dense,
+            SUMMARY_VECTOR_NAME: dense
+        },
+        "sparse_vectors": {
+            // Term counts are uploaded per point; Qdrant applies IDF at query time
+            SPARSE_VECTOR_NAME: { "modifier": "idf" }
+        }
+    })
 }
 
 fn main() -> Result<()> {
-    let args = Args::parse();
-    let client = Client::new();
+    let mut args: Args = parse_args()?;
+    args.collection = namespaced_collection(
+        &args.collection_prefix,
+        &args.collection,
+        &args.collection_suffix,
+    );
+    let sizes = ChunkSizes {
+        child: args.child_size,
+        parent: args.parent_size,
+        min_parent: args.min_parent_size,
+        parent_overlap: args.parent_overlap,
+        max_tokens: args.max_tokens,
+        counter: TokenCounter::load(args.tokenizer),
+    };
+    sizes.validate()?;
 
     // Read markdown file
-    println!("📄 Reading Markdown: {}", args.md_path);
+    if !args.dry_run_json {
+        println!("📄 Reading Markdown: {}", args.md_path);
+    }
     let content = fs::read_to_string(&args.md_path).context("Failed to read Markdown file")?;
 
     // Create hierarchical chunks
-    println!("🎯 Creating hierarchical parent-child chunks...");
-    println!("   Research-based sizes: ~400 tokens for children, 1000-2000 tokens for parents");
-    let (parent_chunks, child_chunks) = create_hierarchical_chunks(&content);
+    if !args.dry_run_json {
+        println!("🎯 Creating hierarchical parent-child chunks...");
+        println!(
+            "   Sizes: {} tokens for children, {}-{} tokens for parents ({:?} tokenizer)",
+            sizes.child,
+            sizes.min_parent,
+            sizes.parent,
+            sizes.counter.tokenizer()
+        );
+    }
+    let mut ids = IdGenerator::new(args.seed);
+    let (mut parent_chunks, mut child_chunks) =
+        create_hierarchical_chunks(&content, sizes, &mut ids);
+    limit_chunks(&mut parent_chunks, args.max_chunks)?;
+    let kept: HashSet<&str> = parent_chunks.iter().map(|p| p.id.as_str()).collect();
+    child_chunks.retain(|c| kept.contains(c.parent_id.as_str()));
+    if args.deterministic_ids {
+        let source = args.source_label.as_deref().unwrap_or(&args.md_path);
+        assign_deterministic_ids(source, &mut parent_chunks, &mut child_chunks);
+    }
+    validate_links(&parent_chunks, &child_chunks).context("Inconsistent parent/child links")?;
+    if args.dry_run || args.dry_run_json {
+        let plan = chunk_plan(&parent_chunks, &child_chunks, sizes.counter);
+        return print_chunk_plan(&plan, args.dry_run_json);
+    }
+
+    if !args.skip_checks {
+        preflight(
+            &args.qdrant_url,
+            &args.ollama_url,
+            &args.model,
+            args.embed_api,
+        )?;
+    }
+    let embedder = Embedder::new(
+        &args.ollama_url,
+        args.embed_api,
+        &args.model,
+        RetryPolicy {
+            retries: args.embed_retries,
+            base_delay_ms: args.embed_retry_delay_ms,
+        },
+    )
+    .wake_on_retry(true);
+    let qdrant = QdrantClient::new(&args.qdrant_url)
+        .api_key(args.qdrant_api_key.as_ref())
+        .consistency(args.consistency)
+        .ingest_model(&args.model);
 
     println!("📦 Created chunks:");
     println!(
@@ -550,74 +1100,81 @@ fn main() -> Result<()> {
     // Ensure collection exists with proper configuration
     println!("🔧 Checking Qdrant collection...");
 
-    // Check if collection exists
-    let check_response = client
-        .get(format!(
-            "{}/collections/{}",
-            args.qdrant_url, args.collection
-        ))
-        .send();
-
-    if check_response.is_err() || !check_response.unwrap().status().is_success() {
+    // Collections created before summaries had their own vector keep a single
+    // dense vector embedding "summary + content"
+    let summary_vectors = if !qdrant.collection_exists(&args.collection) {
         // Collection doesn't exist, create it
         println!("   Creating new collection...");
-        let collection_config = json!({
-            "vectors": {
-                "size": 768,
-                "distance": "Cosine"
-            },
-            "sparse_vectors": {
-                "text": {}  // For keyword/BM25 search
-            }
-        });
+        let mut collection_config = collection_config(args.distance);
 
-        let response = client
-            .put(format!(
-                "{}/collections/{}",
-                args.qdrant_url, args.collection
-            ))
-            .json(&collection_config)
-            .send()
-            .context("Failed to create collection")?;
-
-        if !response.status().is_success() {
-            println!(
-                "Warning: Collection creation returned: {}",
-                response.status()
-            );
+        args.quantization.apply(&mut collection_config);
+
+        if let Err(e) = qdrant.create_collection(&args.collection, &collection_config) {
+            println!("Warning: {e:#}");
         }
+        true
     } else {
         println!("   Using existing collection");
-    }
+        qdrant.warn_existing_settings(&args.collection, args.distance, args.quantization);
+        let named = qdrant
+            .collection_info(&args.collection)?
+            .has_named_vector(SUMMARY_VECTOR_NAME);
+        if !named {
+            eprintln!(
+                "⚠️  '{}' has a single dense vector, so summaries are embedded with the content; ingest into a new collection to store separate '{}' and '{}' vectors",
+                args.collection, CONTENT_VECTOR_NAME, SUMMARY_VECTOR_NAME
+            );
+        }
+        named
+    };
+
+    let payload_opts = PayloadOptions {
+        source: args.source_label.as_deref().unwrap_or(&args.md_path),
+        source_path: &args.md_path,
+        text_field: &args.text_field,
+        child_context: if args.child_embed_parent_summary {
+            ChildEmbedContext::ParentSummary
+        } else {
+            ChildEmbedContext::Headers
+        },
+    };
 
     // Generate embeddings for parents
     println!("🧮 Generating embeddings for parent chunks...");
     let mut parent_points = Vec::new();
-
-    for (i, parent) in parent_chunks.iter().enumerate() {
-        print!("  Processing parent {}/{}...\r", i + 1, parent_chunks.len());
-
-        // Embed summary + headers for better retrieval
-        let embedding_text = format!("{}\n\n{}", parent.summary, parent.content);
-        let embedding = get_embedding(&client, &args.ollama_url, &args.model, &embedding_text)?;
+    // Children carry their parent's summary vector, so a summary search
+    // still lands on the precise chunks
+    let mut summary_embeddings: HashMap<&str, Vec<f32>> = HashMap::new();
+
+    let bar = progress_bar(parent_chunks.len(), "Embedding parents", args.quiet);
+    for parent in &parent_chunks {
+        let (embedding, summary_vector) = if summary_vectors {
+            let content = embedder.embed(&prepare_for_embedding(&parent.content, &sizes))?;
+            let summary = if parent.summary.trim().is_empty() {
+                content.clone()
+            } else {
+                embedder.embed(&prepare_for_embedding(&parent.summary, &sizes))?
+            };
+            summary_embeddings.insert(&parent.id, summary.clone());
+            (content, Some(summary))
+        } else {
+            // Embed summary + headers for better retrieval
+            let embedding_text = format!("{}\n\n{}", parent.summary, parent.content);
+            let embedding = embedder.embed(&prepare_for_embedding(&embedding_text, &sizes))?;
+            (embedding, None)
+        };
 
         parent_points.push(QdrantPoint {
             id: parent.id.clone(),
             vector: embedding,
-            payload: json!({
-                "text": parent.content,
-                "source": args.md_path,
-                "chunk_type": "parent",
-                "summary": parent.summary,
-                "headers": parent.headers,
-                "child_ids": parent.child_ids,
-                "start_line": parent.start_line,
-                "end_line": parent.end_line,
-                "char_count": parent.content.len(),
-            }),
+            sparse: Some(SparseVector::from_text(&parent.content)),
+            summary_vector,
+            payload: parent_payload(parent, &payload_opts),
         });
+        bar.inc(1);
     }
-    println!("\n✅ Generated parent embeddings");
+    bar.finish_and_clear();
+    println!("✅ Generated parent embeddings");
 
     // Generate embeddings for children
     println!("🧮 Generating embeddings for child chunks...");
@@ -627,79 +1184,52 @@ fn main() -> Result<()> {
     let parent_map: HashMap<String, &ParentChunk> =
         parent_chunks.iter().map(|p| (p.id.clone(), p)).collect();
 
-    for (i, child) in child_chunks.iter().enumerate() {
-        print!("  Processing child {}/{}...\r", i + 1, child_chunks.len());
-
+    let bar = progress_bar(child_chunks.len(), "Embedding children", args.quiet);
+    for child in &child_chunks {
         // Include parent context in child embedding for better retrieval
         let parent = parent_map.get(&child.parent_id);
-        let embedding_text = if let Some(p) = parent {
-            format!("{}\n\n{}", p.headers.join(" > "), child.content)
-        } else {
-            child.content.clone()
-        };
-
-        let embedding = get_embedding(&client, &args.ollama_url, &args.model, &embedding_text)?;
+        let embedding_text =
+            child_embedding_text(child, parent.copied(), payload_opts.child_context);
+
+        let embedding = embedder.embed(&prepare_for_embedding(&embedding_text, &sizes))?;
+        let summary_vector = summary_vectors.then(|| {
+            summary_embeddings
+                .get(child.parent_id.as_str())
+                .cloned()
+                .unwrap_or_else(|| embedding.clone())
+        });
 
         child_points.push(QdrantPoint {
             id: child.id.clone(),
             vector: embedding,
-            payload: json!({
-                "text": child.content,
-                "source": args.md_path,
-                "chunk_type": format!("child_{:?}", child.chunk_type).to_lowercase(),
-                "parent_id": child.parent_id,
-                "parent_summary": parent.map(|p| &p.summary),
-                "index_in_parent": child.index_in_parent,
-                "start_line": child.start_line,
-                "end_line": child.end_line,
-                "char_count": child.content.len(),
-            }),
+            sparse: Some(SparseVector::from_text(&child.content)),
+            summary_vector,
+            payload: child_payload(child, parent.copied(), &payload_opts),
         });
+        bar.inc(1);
     }
-    println!("\n✅ Generated child embeddings");
+    bar.finish_and_clear();
+    println!("✅ Generated child embeddings");
 
     // Upload all points
     println!("📤 Uploading to Qdrant...");
     let all_points: Vec<QdrantPoint> = parent_points.into_iter().chain(child_points).collect();
 
     let batch_size = 100;
-    for (i, batch) in all_points.chunks(batch_size).enumerate() {
-        print!(
-            "  Uploading batch {}/{}...\r",
-            i + 1,
-            all_points.len().div_ceil(batch_size)
-        );
-
-        let response = client
-            .put(format!(
-                "{}/collections/{}/points?wait=true",
-                args.qdrant_url, args.collection
-            ))
-            .json(&json!({
-                "points": batch
-            }))
-            .send()
-            .context("Failed to upload to Qdrant")?;
-
-        // Check HTTP status first
-        let status = response.status();
-        if !status.is_success() {
-            let error_text = response
-                .text()
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            anyhow::bail!("Qdrant upload failed (HTTP {}): {}", status, error_text);
-        }
-
-        // Parse response body and check for Qdrant-level errors
-        // Qdrant returns HTTP 200 even for some errors, with error in body
-        let body: serde_json::Value = response.json().context("Failed to parse Qdrant response")?;
-
-        if let Some(error) = body.get("status").and_then(|s| s.get("error")) {
-            anyhow::bail!("Qdrant upload failed: {}", error);
-        }
+    let bar = progress_bar(
+        all_points.len().div_ceil(batch_size),
+        "Uploading batches",
+        args.quiet,
+    );
+    for batch in all_points.chunks(batch_size) {
+        qdrant.upsert_points(&args.collection, batch)?;
+        bar.inc(1);
     }
-    println!();
+    bar.finish_and_clear();
 
+    if let Err(e) = qdrant.create_payload_indexes(&args.collection, &args.index_fields) {
+        eprintln!("⚠️  Could not index payload fields: {e:#}");
+    }
     println!("✅ Successfully ingested with hierarchical chunking!");
     println!("\n📊 Summary:");
     println!("   Total vectors: {}", all_points.len());
@@ -715,6 +1245,11 @@ fn main() -> Result<()> {
     println!("   1. Search returns matching child chunks");
     println!("   2. System retrieves parent for full context");
     println!("   3. Both child (precise) and parent (context) provided to LLM");
+    if summary_vectors {
+        println!(
+            "   search-hierarchical --vector-name {SUMMARY_VECTOR_NAME} matches section summaries instead"
+        );
+    }
 
     Ok(())
 }
@@ -724,27 +1259,30 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_safe_truncate_ascii() {
-        assert_eq!(safe_truncate("hello", 10), "hello");
-        assert_eq!(safe_truncate("hello world", 5), "hello");
-        assert_eq!(safe_truncate("", 5), "");
+    fn test_upserts_wait_unless_eventual_is_asked_for() {
+        let args = Args::try_parse_from(["ingest-hierarchical", "book.md"]).unwrap();
+        assert_eq!(args.consistency, Consistency::Strong);
+
+        let args = Args::try_parse_from([
+            "ingest-hierarchical",
+            "book.md",
+            "--consistency",
+            "eventual",
+        ])
+        .unwrap();
+        assert_eq!(args.consistency, Consistency::Eventual);
     }
 
     #[test]
-    fn test_safe_truncate_unicode() {
-        // Test with multi-byte UTF-8 characters
-        let s = "═══════════"; // Each ═ is 3 bytes
-        assert_eq!(safe_truncate(s, 3).chars().count(), 3);
-
-        // Mix of ASCII and multi-byte
-        let mixed = "Hello ═══ World";
-        let truncated = safe_truncate(mixed, 8);
-        assert_eq!(truncated, "Hello ══");
-        assert!(truncated.is_char_boundary(truncated.len()));
+    fn test_collection_config_names_content_and_summary_vectors() {
+        let config = collection_config(Distance::Dot);
 
-        // Emoji (4 bytes each)
-        let emoji = "🔍📦🎯";
-        assert_eq!(safe_truncate(emoji, 2), "🔍📦");
+        for name in [CONTENT_VECTOR_NAME, SUMMARY_VECTOR_NAME] {
+            assert_eq!(config["vectors"][name]["size"], 768);
+            assert_eq!(config["vectors"][name]["distance"], "Dot");
+        }
+        assert!(config["vectors"].get("size").is_none());
+        assert_eq!(config["sparse_vectors"]["text"]["modifier"], "idf");
     }
 
     #[test]
@@ -819,7 +1357,11 @@ mod tests {
     fn test_hierarchical_chunks_simple_text() {
         let content = "# Title\n\n".to_string() + &"Some content. ".repeat(200);
 
-        let (parent_chunks, child_chunks) = create_hierarchical_chunks(&content);
+        let (parent_chunks, child_chunks) = create_hierarchical_chunks(
+            &content,
+            ChunkSizes::default(),
+            &mut IdGenerator::new(None),
+        );
 
         assert!(
             !parent_chunks.is_empty(),
@@ -858,7 +1400,11 @@ More text after code.
         .to_string()
             + &"Additional content. ".repeat(100);
 
-        let (parent_chunks, child_chunks) = create_hierarchical_chunks(&content);
+        let (parent_chunks, child_chunks) = create_hierarchical_chunks(
+            &content,
+            ChunkSizes::default(),
+            &mut IdGenerator::new(None),
+        );
 
         assert!(!parent_chunks.is_empty());
         assert!(!child_chunks.is_empty());
@@ -877,7 +1423,11 @@ More text after code.
             "Content for subsection. ".repeat(150)
         );
 
-        let (parent_chunks, _child_chunks) = create_hierarchical_chunks(&content);
+        let (parent_chunks, _child_chunks) = create_hierarchical_chunks(
+            &content,
+            ChunkSizes::default(),
+            &mut IdGenerator::new(None),
+        );
 
         // Multiple sections should create multiple parents
         assert!(
@@ -892,6 +1442,279 @@ More text after code.
         );
     }
 
+    #[test]
+    fn test_parent_child_links_are_consistent() {
+        let content = format!(
+            "# Part 1\n\n{}\n\n## Chapter\n\n{}\n\n# Part 2\n\n{}",
+            "First part text. ".repeat(200),
+            "Chapter text. ".repeat(80),
+            "Second part text. ".repeat(200)
+        );
+
+        let (parents, children) = create_hierarchical_chunks(
+            &content,
+            ChunkSizes::default(),
+            &mut IdGenerator::new(None),
+        );
+
+        validate_links(&parents, &children).unwrap();
+
+        // Every child is listed by exactly the parent it points to
+        for child in &children {
+            let owners: Vec<_> = parents
+                .iter()
+                .filter(|p| p.child_ids.contains(&child.id))
+                .collect();
+            assert_eq!(owners.len(), 1);
+            assert_eq!(owners[0].id, child.parent_id);
+        }
+    }
+
+    #[test]
+    fn test_parents_ending_on_headings_have_children() {
+        // Each section is below the size threshold, so only headings end parents
+        let content = format!(
+            "# Intro\n\n{}\n# Setup\n\n{}\n## Details\n\n{}\n# Wrap-up\n\n{}",
+            "Intro text. ".repeat(40),
+            "Setup text. ".repeat(90),
+            "Detail text. ".repeat(40),
+            "Closing text. ".repeat(20)
+        );
+
+        let (parents, children) = create_hierarchical_chunks(
+            &content,
+            ChunkSizes::default(),
+            &mut IdGenerator::new(None),
+        );
+
+        assert!(parents.len() >= 3);
+        for parent in &parents {
+            assert!(
+                !parent.child_ids.is_empty(),
+                "Parent {:?} should have children",
+                parent.headers
+            );
+        }
+        validate_links(&parents, &children).unwrap();
+    }
+
+    #[test]
+    fn test_custom_sizes_change_boundaries() {
+        let content = "# Title\n\n".to_string() + &"A sentence of body text.\n\n".repeat(300);
+
+        let (default_parents, default_children) = create_hierarchical_chunks(
+            &content,
+            ChunkSizes::default(),
+            &mut IdGenerator::new(None),
+        );
+        let small = ChunkSizes {
+            child: 75,
+            parent: 225,
+            min_parent: 100,
+            ..ChunkSizes::default()
+        };
+        let (small_parents, small_children) =
+            create_hierarchical_chunks(&content, small, &mut IdGenerator::new(None));
+
+        assert!(small_parents.len() > default_parents.len());
+        assert!(small_children.len() > default_children.len());
+        assert!(small_parents.iter().all(|p| p.content.len() <= 900 + 30));
+    }
+
+    #[test]
+    fn test_chunk_sizes_validation() {
+        assert!(ChunkSizes::default().validate().is_ok());
+
+        let inverted = ChunkSizes {
+            min_parent: 2000,
+            ..ChunkSizes::default()
+        };
+        assert!(
+            inverted
+                .validate()
+                .unwrap_err()
+                .to_string()
+                .contains("--min-parent-size")
+        );
+
+        let oversized_child = ChunkSizes {
+            child: 5000,
+            ..ChunkSizes::default()
+        };
+        assert!(oversized_child.validate().is_err());
+
+        let zero = ChunkSizes {
+            child: 0,
+            ..ChunkSizes::default()
+        };
+        assert!(zero.validate().is_err());
+
+        let overlap_too_large = ChunkSizes {
+            parent_overlap: PARENT_TARGET_SIZE,
+            ..ChunkSizes::default()
+        };
+        assert!(overlap_too_large.validate().is_err());
+    }
+
+    #[test]
+    fn test_break_point_stays_inside_current_parent() {
+        let lines = ["Intro.", "", "First long line.", "Second long line."];
+
+        assert_eq!(break_point(&lines, 0, 3), 1);
+        assert_eq!(break_point(&lines, 2, 3), 3);
+    }
+
+    #[test]
+    fn test_long_lines_after_blank_line_split_into_parents() {
+        let long = "word ".repeat(120);
+        let content = format!("Intro.\n\n{long}\n{long}\n{long}\n{long}");
+        let sizes = ChunkSizes {
+            child: 75,
+            parent: 225,
+            min_parent: 100,
+            ..ChunkSizes::default()
+        };
+
+        let (parents, children) =
+            create_hierarchical_chunks(&content, sizes, &mut IdGenerator::new(None));
+
+        assert!(parents.len() >= 2);
+        assert!(parents.iter().all(|p| !p.content.trim().is_empty()));
+        assert_eq!(parents.last().unwrap().end_line, 5);
+        validate_links(&parents, &children).unwrap();
+    }
+
+    #[test]
+    fn test_parent_overlap_repeats_tail_lines() {
+        let content = (0..120)
+            .map(|n| format!("Line {n} of a long section without headings."))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let sizes = ChunkSizes {
+            parent_overlap: 40,
+            ..ChunkSizes::default()
+        };
+
+        let (parents, children) =
+            create_hierarchical_chunks(&content, sizes, &mut IdGenerator::new(None));
+
+        assert!(parents.len() >= 2);
+        for pair in parents.windows(2) {
+            assert!(pair[1].start_line <= pair[0].end_line);
+            assert!(pair[1].start_line > pair[0].start_line);
+        }
+        let lines: Vec<&str> = content.lines().collect();
+        for parent in &parents {
+            assert!(parent.content.starts_with(lines[parent.start_line]));
+        }
+        for child in &children {
+            let first = child.content.lines().next().unwrap();
+            assert_eq!(lines[child.start_line], first);
+        }
+        validate_links(&parents, &children).unwrap();
+    }
+
+    #[test]
+    fn test_child_line_numbers_point_at_source_text() {
+        let code = "    let value = compute(input, &mut state);\n".repeat(60);
+        let content = format!(
+            "# Guide\n\n{}\n\n## Build\n\n```rust\n{code}```\n\n## Deploy\n\n{}\n\nShip it with `cargo install`.\n",
+            "Some introduction text.\n".repeat(80),
+            "Deployment notes go here.\n".repeat(80)
+        );
+        let sizes = ChunkSizes {
+            max_tokens: 200,
+            parent_overlap: 30,
+            ..ChunkSizes::default()
+        };
+
+        let (_, children) =
+            create_hierarchical_chunks(&content, sizes, &mut IdGenerator::new(None));
+
+        let lines: Vec<&str> = content.lines().collect();
+        for child in &children {
+            assert_eq!(
+                child.content.trim_end(),
+                lines[child.start_line..=child.end_line]
+                    .join("\n")
+                    .trim_end(),
+                "child {} has wrong line numbers",
+                child.index_in_parent
+            );
+        }
+        let ship = children
+            .iter()
+            .find(|c| c.content.contains("Ship it"))
+            .unwrap();
+        let ship_line = lines.iter().position(|l| l.starts_with("Ship it")).unwrap();
+        assert!((ship.start_line..=ship.end_line).contains(&ship_line));
+    }
+
+    #[test]
+    fn test_chunk_plan_lists_children_after_their_parent() {
+        let content = format!(
+            "# Guide\n\n{}\n# Usage\n\n{}",
+            "Intro text. ".repeat(60),
+            "Usage text. ".repeat(60)
+        );
+        let (parents, children) = create_hierarchical_chunks(
+            &content,
+            ChunkSizes::default(),
+            &mut IdGenerator::new(None),
+        );
+
+        let plan = chunk_plan(&parents, &children, TokenCounter::estimate());
+
+        assert_eq!(plan.len(), parents.len() + children.len());
+        assert_eq!(plan[0].chunk_type, "parent");
+        assert_eq!(plan[0].header_context, "Guide");
+        assert!(plan[1].chunk_type.starts_with("child_"));
+        let second = plan.iter().rposition(|c| c.chunk_type == "parent").unwrap();
+        assert_eq!(plan[second].header_context, "Usage");
+        assert_eq!(plan[second].start_line, Some(parents[1].start_line));
+        assert!(plan.iter().enumerate().all(|(i, c)| c.index == i));
+    }
+
+    #[test]
+    fn test_validate_links_rejects_stale_child_id() {
+        let content = "# Title\n\n".to_string() + &"Some content. ".repeat(200);
+        let (mut parents, children) = create_hierarchical_chunks(
+            &content,
+            ChunkSizes::default(),
+            &mut IdGenerator::new(None),
+        );
+
+        parents[0].child_ids.push("stale-child".to_string());
+
+        let err = validate_links(&parents, &children).unwrap_err();
+        assert!(err.to_string().contains("stale-child"));
+    }
+
+    #[test]
+    fn test_oversized_code_block_is_split_under_max_tokens() {
+        let code = "    let value = compute(input, &mut state);\n".repeat(120);
+        let content = format!("# Listing\n\n```rust\n{code}```\n");
+        let sizes = ChunkSizes {
+            child: 100,
+            parent: 400,
+            min_parent: 100,
+            max_tokens: 150,
+            counter: TokenCounter::load(Tokenizer::Cl100k),
+            ..ChunkSizes::default()
+        };
+
+        let (parents, children) =
+            create_hierarchical_chunks(&content, sizes, &mut IdGenerator::new(Some(7)));
+
+        assert!(children.len() > 1);
+        assert!(
+            children
+                .iter()
+                .all(|c| sizes.counter.count(&c.content) <= 150)
+        );
+        validate_links(&parents, &children).unwrap();
+    }
+
     #[test]
     fn test_child_chunks_respects_code_boundaries() {
         let parent_content = r#"Some intro text.
@@ -908,7 +1731,13 @@ More text after the code block.
 "#;
         let parent_id = "test-parent-id";
 
-        let children = create_child_chunks(parent_content, parent_id, 0);
+        let children = create_child_chunks(
+            &parent_content.lines().collect::<Vec<_>>(),
+            0,
+            parent_id,
+            ChunkSizes::default(),
+            &mut IdGenerator::new(None),
+        );
 
         assert!(!children.is_empty());
 
@@ -922,6 +1751,11 @@ More text after the code block.
             .iter()
             .any(|c| c.chunk_type == ChunkType::Code || c.chunk_type == ChunkType::Mixed);
         assert!(has_code_or_mixed, "Should detect code chunk types");
+        assert!(
+            children
+                .iter()
+                .any(|c| c.code_lang.as_deref() == Some("rust"))
+        );
     }
 
     #[test]
@@ -939,7 +1773,13 @@ More text.
 "#;
         let parent_id = "test-parent-id";
 
-        let children = create_child_chunks(parent_content, parent_id, 0);
+        let children = create_child_chunks(
+            &parent_content.lines().collect::<Vec<_>>(),
+            0,
+            parent_id,
+            ChunkSizes::default(),
+            &mut IdGenerator::new(None),
+        );
 
         assert!(!children.is_empty());
 
@@ -953,7 +1793,13 @@ More text.
         let parent_content = "Short content.";
         let parent_id = "test-parent-id";
 
-        let children = create_child_chunks(parent_content, parent_id, 0);
+        let children = create_child_chunks(
+            &parent_content.lines().collect::<Vec<_>>(),
+            0,
+            parent_id,
+            ChunkSizes::default(),
+            &mut IdGenerator::new(None),
+        );
 
         // Even short content should create at least one child
         assert_eq!(children.len(), 1, "Should create at least one child chunk");
@@ -964,7 +1810,8 @@ More text.
     fn test_hierarchical_chunks_preserves_line_numbers() {
         let content = "Line 1\nLine 2\nLine 3\nLine 4\nLine 5";
 
-        let (parent_chunks, _child_chunks) = create_hierarchical_chunks(content);
+        let (parent_chunks, _child_chunks) =
+            create_hierarchical_chunks(content, ChunkSizes::default(), &mut IdGenerator::new(None));
 
         for parent in &parent_chunks {
             assert!(
@@ -995,7 +1842,11 @@ More text.
         // Content smaller than MIN_PARENT_SIZE
         let small_content = "# Small\n\nJust a bit of text.";
 
-        let (parent_chunks, _child_chunks) = create_hierarchical_chunks(small_content);
+        let (parent_chunks, _child_chunks) = create_hierarchical_chunks(
+            small_content,
+            ChunkSizes::default(),
+            &mut IdGenerator::new(None),
+        );
 
         // Should still create a parent even if small (handled in final block)
         assert!(
@@ -1004,12 +1855,229 @@ More text.
         );
     }
 
+    #[test]
+    fn test_tiny_document_has_retrievable_child() {
+        let body = "Tiny notes on ownership and borrowing. ".repeat(4);
+        let content = format!("# Notes\n\n## Ownership\n\n{body}");
+        let content = &content[..150];
+
+        let (parents, children) =
+            create_hierarchical_chunks(content, ChunkSizes::default(), &mut IdGenerator::new(None));
+
+        assert_eq!(parents.len(), 1);
+        assert_eq!(children.len(), 1, "Tiny document should still get a child");
+        assert_eq!(parents[0].child_ids, vec![children[0].id.clone()]);
+        assert!(children[0].content.contains("ownership and borrowing"));
+        assert!(
+            !prepare_for_embedding(&children[0].content, &ChunkSizes::default())
+                .trim()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_payload_uses_custom_text_field() {
+        let (parents, children) = create_hierarchical_chunks(
+            "# Title\n\nSome body text.",
+            ChunkSizes::default(),
+            &mut IdGenerator::new(None),
+        );
+        let parent = &parents[0];
+
+        let opts = PayloadOptions {
+            source: "doc.md",
+            source_path: "doc.md",
+            text_field: "content",
+            child_context: ChildEmbedContext::Headers,
+        };
+
+        let payload = parent_payload(parent, &opts);
+        assert_eq!(payload["content"], parent.content.as_str());
+        assert!(payload.get("text").is_none());
+
+        let payload = child_payload(&children[0], Some(parent), &opts);
+        assert_eq!(payload["content"], children[0].content.as_str());
+        assert!(payload.get("text").is_none());
+    }
+
+    #[test]
+    fn test_nested_headings_produce_full_breadcrumb() {
+        let content = format!(
+            "# Guide\n\n{}\n## Install\n\n{}\n### Linux\n\n{}",
+            "Intro text. ".repeat(40),
+            "Install text. ".repeat(20),
+            "Use the package manager. ".repeat(20)
+        );
+        let (parents, children) = create_hierarchical_chunks(
+            &content,
+            ChunkSizes::default(),
+            &mut IdGenerator::new(None),
+        );
+        let parent = parents.last().unwrap();
+        assert_eq!(parent.headers, vec!["# Guide", "## Install", "### Linux"]);
+
+        let opts = PayloadOptions {
+            source: "doc.md",
+            source_path: "doc.md",
+            text_field: "text",
+            child_context: ChildEmbedContext::Headers,
+        };
+        assert_eq!(
+            parent_payload(parent, &opts)["breadcrumb"],
+            "Guide > Install > Linux"
+        );
+        let linux = children
+            .iter()
+            .find(|c| c.content.contains("package manager"))
+            .unwrap();
+        assert_eq!(
+            child_payload(linux, Some(parent), &opts)["breadcrumb"],
+            "Guide > Install > Linux"
+        );
+    }
+
+    #[test]
+    fn test_payload_source_label_keeps_path() {
+        let (parents, children) = create_hierarchical_chunks(
+            "# Title\n\nSome body text.",
+            ChunkSizes::default(),
+            &mut IdGenerator::new(None),
+        );
+        let opts = PayloadOptions {
+            source: "The Rust Programming Language",
+            source_path: "/tmp/upload123.md",
+            text_field: "text",
+            child_context: ChildEmbedContext::Headers,
+        };
+
+        for payload in [
+            parent_payload(&parents[0], &opts),
+            child_payload(&children[0], Some(&parents[0]), &opts),
+        ] {
+            assert_eq!(payload["source"], "The Rust Programming Language");
+            assert_eq!(payload["source_path"], "/tmp/upload123.md");
+        }
+    }
+
+    #[test]
+    fn test_child_embedding_text_uses_chosen_parent_context() {
+        let content = format!(
+            "# Ownership\n\n{}\n\nBorrowing lets code use a value without moving it.",
+            "Every value in Rust has exactly one owner at any point in time."
+        );
+        let (parents, children) = create_hierarchical_chunks(
+            &content,
+            ChunkSizes::default(),
+            &mut IdGenerator::new(None),
+        );
+        let (parent, child) = (&parents[0], &children[0]);
+        assert!(parent.summary.contains("exactly one owner"));
+
+        let with_headers = child_embedding_text(child, Some(parent), ChildEmbedContext::Headers);
+        assert!(with_headers.starts_with("# Ownership\n\n"));
+
+        let with_summary =
+            child_embedding_text(child, Some(parent), ChildEmbedContext::ParentSummary);
+        assert!(with_summary.starts_with(&format!("{}\n\n", parent.summary)));
+        assert!(with_summary.ends_with(&child.content));
+
+        let opts = PayloadOptions {
+            source: "doc.md",
+            source_path: "doc.md",
+            text_field: "text",
+            child_context: ChildEmbedContext::ParentSummary,
+        };
+        let payload = child_payload(child, Some(parent), &opts);
+        assert_eq!(payload["embed_context"], "parent_summary");
+    }
+
+    #[test]
+    fn test_same_seed_produces_same_ids() {
+        let content = format!(
+            "# Section 1\n\n{}\n\n## Section 2\n\n{}",
+            "Content for section 1. ".repeat(100),
+            "Content for section 2. ".repeat(100)
+        );
+
+        let (parents_a, children_a) = create_hierarchical_chunks(
+            &content,
+            ChunkSizes::default(),
+            &mut IdGenerator::new(Some(42)),
+        );
+        let (parents_b, children_b) = create_hierarchical_chunks(
+            &content,
+            ChunkSizes::default(),
+            &mut IdGenerator::new(Some(42)),
+        );
+
+        let ids = |parents: &[ParentChunk], children: &[ChildChunk]| -> Vec<String> {
+            parents
+                .iter()
+                .map(|p| p.id.clone())
+                .chain(children.iter().map(|c| c.id.clone()))
+                .collect()
+        };
+        assert_eq!(ids(&parents_a, &children_a), ids(&parents_b, &children_b));
+
+        let (parents_c, _) = create_hierarchical_chunks(
+            &content,
+            ChunkSizes::default(),
+            &mut IdGenerator::new(Some(7)),
+        );
+        assert_ne!(parents_a[0].id, parents_c[0].id);
+    }
+
+    #[test]
+    fn test_deterministic_ids_repeat_across_runs() {
+        let content = format!(
+            "# Section 1\n\n{}\n\n## Section 2\n\n```rust\nfn main() {{}}\n```\n\n{}",
+            "Content for section 1. ".repeat(100),
+            "Content for section 2. ".repeat(100)
+        );
+        let run = |seed, source| {
+            let (mut parents, mut children) = create_hierarchical_chunks(
+                &content,
+                ChunkSizes::default(),
+                &mut IdGenerator::new(Some(seed)),
+            );
+            assign_deterministic_ids(source, &mut parents, &mut children);
+            (parents, children)
+        };
+
+        let (parents_a, children_a) = run(1, "guide.md");
+        let (parents_b, children_b) = run(2, "guide.md");
+        let (parents_c, _) = run(1, "other.md");
+
+        let ids = |parents: &[ParentChunk], children: &[ChildChunk]| -> Vec<String> {
+            parents
+                .iter()
+                .map(|p| p.id.clone())
+                .chain(children.iter().map(|c| c.id.clone()))
+                .collect()
+        };
+        let first = ids(&parents_a, &children_a);
+        assert_eq!(first, ids(&parents_b, &children_b));
+        assert_eq!(
+            first.iter().collect::<HashSet<_>>().len(),
+            first.len(),
+            "IDs are unique"
+        );
+        assert_ne!(parents_a[0].id, parents_c[0].id);
+        validate_links(&parents_a, &children_a).unwrap();
+    }
+
     #[test]
     fn test_child_chunk_indexing() {
         let parent_content = "Content. ".repeat(300);
         let parent_id = "test-parent-id";
 
-        let children = create_child_chunks(&parent_content, parent_id, 0);
+        let children = create_child_chunks(
+            &parent_content.lines().collect::<Vec<_>>(),
+            0,
+            parent_id,
+            ChunkSizes::default(),
+            &mut IdGenerator::new(None),
+        );
 
         // Verify index_in_parent is sequential
         for (i, child) in children.iter().enumerate() {
@@ -0,0 +1,472 @@
+// Copyright (c) 2025 Michael A. Wright
+// Licensed under the MIT License
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use rag_demo::config::parse_args;
+use rag_demo::rag::{
+    ApiKey, Consistency, EmbedApi, Embedder, IdGenerator, MarkdownChunk, MarkdownChunkType,
+    OnOversize, OversizePolicy, QdrantClient, QdrantPoint, RetryPolicy, SmallChunkStats,
+    TokenCounter, Tokenizer, detect_lang, enforce_max_tokens, html_to_markdown, limit_chunks,
+    markdown_chunk_plan, merge_small_chunks, namespaced_collection, preflight, print_chunk_plan,
+    progress_bar, smart_chunk_markdown,
+};
+use scraper::{Html, Selector};
+use serde_json::json;
+use std::fs;
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Ingest HTML pages with the markdown chunker, keeping headings and code blocks",
+    long_about = None
+)]
+struct Args {
+    #[arg(required = true, help = "Paths to HTML files")]
+    html_paths: Vec<String>,
+
+    #[arg(
+        short,
+        long,
+        default_value = "375",
+        help = "Tokens per text chunk before breaking at the next paragraph"
+    )]
+    chunk_size: usize,
+
+    #[arg(
+        long,
+        default_value = "50",
+        help = "Tokens under which a text chunk, such as a lone heading or a stray line, is merged into its neighbours (0 keeps every chunk)"
+    )]
+    min_chunk_size: usize,
+
+    #[arg(
+        long,
+        default_value = "documents",
+        env = "RAG_COLLECTION",
+        help = "Qdrant collection name"
+    )]
+    collection: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_PREFIX",
+        help = "Prepended to the collection name, e.g. acme_ to keep one tenant's collections apart"
+    )]
+    collection_prefix: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_SUFFIX",
+        help = "Appended to the collection name"
+    )]
+    collection_suffix: String,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:6333",
+        env = "QDRANT_URL",
+        help = "Qdrant URL"
+    )]
+    qdrant_url: String,
+
+    #[arg(
+        long,
+        env = "QDRANT_API_KEY",
+        hide_env_values = true,
+        help = "Qdrant API key, sent as the api-key header (Qdrant Cloud)"
+    )]
+    qdrant_api_key: Option<ApiKey>,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:11434",
+        env = "OLLAMA_URL",
+        help = "Ollama URL"
+    )]
+    ollama_url: String,
+
+    #[arg(
+        short,
+        long,
+        default_value = "nomic-embed-text",
+        env = "RAG_MODEL",
+        help = "Embedding model"
+    )]
+    model: String,
+
+    #[arg(
+        long,
+        default_value = "text",
+        help = "Payload field that stores the chunk text"
+    )]
+    text_field: String,
+
+    #[arg(
+        long,
+        help = "Source stored in the payload of a single page (default: its canonical URL, else the file path)"
+    )]
+    source_label: Option<String>,
+
+    #[arg(long, help = "Seed for reproducible point IDs (default: OS entropy)")]
+    seed: Option<u64>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "ollama",
+        help = "Embedding API: ollama (/api/embeddings), ollama-embed (/api/embed), openai (/v1/embeddings)"
+    )]
+    embed_api: EmbedApi,
+
+    #[arg(
+        long,
+        help = "Skip checking that Qdrant, Ollama and the embedding model are available"
+    )]
+    skip_checks: bool,
+
+    #[arg(
+        long,
+        default_value = "4",
+        help = "Times to retry a failed embedding request (5xx, 429, connection errors)"
+    )]
+    embed_retries: u32,
+
+    #[arg(
+        long,
+        default_value = "500",
+        help = "Delay before the first embedding retry in ms, doubled on each retry"
+    )]
+    embed_retry_delay_ms: u64,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "strong",
+        help = "strong waits for each upsert to be applied, so points are searchable and counted when ingestion ends; eventual returns as soon as Qdrant accepts them"
+    )]
+    consistency: Consistency,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "cl100k",
+        help = "Tokenizer used to measure chunk sizes (chars estimates 4 characters per token)"
+    )]
+    tokenizer: Tokenizer,
+
+    #[arg(
+        long,
+        default_value = "2048",
+        help = "Hard limit in tokens for every chunk, splitting code blocks if needed"
+    )]
+    max_tokens: usize,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "split",
+        help = "Text over --max-tokens at embedding time: split embeds pieces and averages them, truncate keeps the start, error fails"
+    )]
+    on_oversize: OnOversize,
+
+    #[arg(
+        long,
+        default_value = "32",
+        help = "Chunks embedded per request to Ollama's /api/embed"
+    )]
+    embed_batch_size: usize,
+
+    #[arg(
+        long,
+        help = "Embed one chunk per request with --embed-api instead of batching through /api/embed"
+    )]
+    legacy_embed: bool,
+
+    #[arg(
+        long,
+        help = "Print each chunk's type, size and header context, then exit without embedding or uploading"
+    )]
+    dry_run: bool,
+
+    #[arg(long, help = "Like --dry-run, but print the chunks as JSON")]
+    dry_run_json: bool,
+
+    #[arg(
+        long,
+        help = "Only embed and upload the first N chunks, for quick trial runs on big files"
+    )]
+    max_chunks: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Hide progress bars (also hidden when stdout is not a terminal)"
+    )]
+    quiet: bool,
+}
+
+/// A parsed page: its text as markdown and the source to store for it
+struct HtmlDocument {
+    path: String,
+    source: String,
+    markdown: String,
+}
+
+/// URL the page says it was published at, from `<link rel="canonical">` or `og:url`
+fn canonical_url(html: &Html) -> Option<String> {
+    let selector = Selector::parse(r#"link[rel="canonical"], meta[property="og:url"]"#)
+        .expect("selector is valid");
+    html.select(&selector)
+        .find_map(|element| element.attr("href").or_else(|| element.attr("content")))
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(str::to_string)
+}
+
+fn read_document(path: &str, source_label: Option<&str>) -> Result<HtmlDocument> {
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read {path}"))?;
+    let html = Html::parse_document(&content);
+    let source = source_label
+        .map(str::to_string)
+        .or_else(|| canonical_url(&html))
+        .unwrap_or_else(|| path.to_string());
+    Ok(HtmlDocument {
+        path: path.to_string(),
+        source,
+        markdown: html_to_markdown(&html),
+    })
+}
+
+fn main() -> Result<()> {
+    let mut args: Args = parse_args()?;
+    args.collection = namespaced_collection(
+        &args.collection_prefix,
+        &args.collection,
+        &args.collection_suffix,
+    );
+    let counter = TokenCounter::load(args.tokenizer);
+    if args.max_tokens == 0 {
+        anyhow::bail!("--max-tokens must be greater than zero");
+    }
+    if args.embed_batch_size == 0 {
+        anyhow::bail!("--embed-batch-size must be greater than zero");
+    }
+    if args.source_label.is_some() && args.html_paths.len() > 1 {
+        anyhow::bail!("--source-label names a single page; pass one HTML file with it");
+    }
+
+    // Convert each page to markdown and chunk it like ingest-markdown does
+    let mut documents = Vec::with_capacity(args.html_paths.len());
+    let mut chunks: Vec<(usize, MarkdownChunk)> = Vec::new();
+    let mut small = SmallChunkStats::default();
+    for path in &args.html_paths {
+        if !args.dry_run_json {
+            println!("📄 Reading HTML: {path}");
+        }
+        let document = read_document(path, args.source_label.as_deref())?;
+        let (doc_chunks, stats) = merge_small_chunks(
+            smart_chunk_markdown(&document.markdown, args.chunk_size, counter),
+            args.min_chunk_size,
+            counter,
+        );
+        small += stats;
+        let doc_chunks = enforce_max_tokens(doc_chunks, args.max_tokens, counter);
+        chunks.extend(doc_chunks.into_iter().map(|c| (documents.len(), c)));
+        documents.push(document);
+    }
+    if !args.dry_run_json {
+        small.print();
+    }
+    limit_chunks(&mut chunks, args.max_chunks)?;
+    if args.dry_run || args.dry_run_json {
+        let plain: Vec<MarkdownChunk> = chunks.iter().map(|(_, c)| c.clone()).collect();
+        return print_chunk_plan(&markdown_chunk_plan(&plain, counter), args.dry_run_json);
+    }
+
+    if !args.skip_checks {
+        preflight(
+            &args.qdrant_url,
+            &args.ollama_url,
+            &args.model,
+            args.embed_api,
+        )?;
+    }
+    let embedder = Embedder::new(
+        &args.ollama_url,
+        args.embed_api,
+        &args.model,
+        RetryPolicy {
+            retries: args.embed_retries,
+            base_delay_ms: args.embed_retry_delay_ms,
+        },
+    )
+    .oversize(OversizePolicy {
+        on_oversize: args.on_oversize,
+        max_tokens: args.max_tokens,
+        counter,
+    })
+    .legacy_embed(args.legacy_embed);
+    let qdrant = QdrantClient::new(&args.qdrant_url)
+        .api_key(args.qdrant_api_key.as_ref())
+        .consistency(args.consistency)
+        .ingest_model(&args.model);
+    let mut ids = IdGenerator::new(args.seed);
+
+    let code_chunks = chunks
+        .iter()
+        .filter(|(_, c)| matches!(c.chunk_type, MarkdownChunkType::CodeBlock))
+        .count();
+    println!(
+        "📦 Created {} chunks from {} pages ({code_chunks} code blocks)",
+        chunks.len(),
+        documents.len()
+    );
+
+    println!("🧮 Generating embeddings with model: {}", args.model);
+    let embedding_texts: Vec<String> = chunks.iter().map(|(_, c)| c.embedding_text()).collect();
+    let mut embeddings = Vec::new();
+    let bar = progress_bar(chunks.len(), "Embedding", args.quiet);
+    for texts in embedding_texts.chunks(args.embed_batch_size) {
+        embeddings.extend(embedder.embed_batch(texts)?);
+        bar.inc(texts.len() as u64);
+    }
+    bar.finish_and_clear();
+
+    let mut totals = vec![0; documents.len()];
+    for (doc, _) in &chunks {
+        totals[*doc] += 1;
+    }
+    let mut points = Vec::with_capacity(chunks.len());
+    for ((doc, chunk), embedding) in chunks.iter().zip(embeddings) {
+        let document = &documents[*doc];
+        let mut payload = json!({
+            "source": document.source,
+            "source_path": document.path,
+            "chunk_index": chunk.index,
+            "total_chunks": totals[*doc],
+            "chunk_type": chunk.chunk_type,
+            "lang": detect_lang(&chunk.content),
+            "header_context": chunk.header_context,
+            "breadcrumb": chunk.breadcrumb,
+            "is_code": matches!(chunk.chunk_type, MarkdownChunkType::CodeBlock),
+        });
+        if let Some(lang) = &chunk.code_lang {
+            payload["code_lang"] = json!(lang);
+        }
+        payload[args.text_field.as_str()] = json!(chunk.content);
+
+        points.push(QdrantPoint {
+            id: ids.next_id(),
+            vector: embedding,
+            sparse: None,
+            summary_vector: None,
+            payload,
+        });
+    }
+    println!("✅ Generated embeddings for all chunks");
+
+    // Upload to Qdrant in batches
+    println!("📤 Uploading to Qdrant collection: {}", args.collection);
+    let batch_size = 100;
+    let total_batches = points.len().div_ceil(batch_size);
+
+    let bar = progress_bar(total_batches, "Uploading batches", args.quiet);
+    for (i, batch) in points.chunks(batch_size).enumerate() {
+        qdrant
+            .upsert_points(&args.collection, batch)
+            .with_context(|| format!("Qdrant returned error in batch {}", i + 1))?;
+        bar.inc(1);
+    }
+    bar.finish_and_clear();
+
+    println!("✅ Successfully ingested HTML into Qdrant!");
+    for document in &documents {
+        println!("   Source: {}", document.source);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+  <title>Ownership</title>
+  <link rel="canonical" href="https://doc.rust-lang.org/book/ch04-01.html">
+  <style>body { color: red; }</style>
+</head>
+<body>
+  <nav><a href="/">Home</a> &raquo; <a href="/book">Book</a></nav>
+  <main>
+    <h1>Understanding   Ownership</h1>
+    <p>Ownership is Rust&rsquo;s most
+       unique feature &amp; it has deep implications.</p>
+    <h2>The Stack and the Heap</h2>
+    <p>Call <code>String::from</code> to allocate.</p>
+    <pre><code class="language-rust">let s = String::from("hello");
+
+println!("{s}");
+</code></pre>
+    <ul><li>Each value has an owner.</li><li>There can only be one owner.</li></ul>
+    <script>trackPageView();</script>
+    <h3>#include &lt;stdio.h&gt;</h3>
+    <p># not a heading</p>
+  </main>
+  <footer>Copyright</footer>
+</body>
+</html>"#;
+
+    #[test]
+    fn test_html_becomes_markdown_without_boilerplate() {
+        let markdown = html_to_markdown(&Html::parse_document(PAGE));
+
+        assert_eq!(
+            markdown,
+            "# Understanding Ownership\n\n\
+             Ownership is Rust\u{2019}s most unique feature & it has deep implications.\n\n\
+             ## The Stack and the Heap\n\n\
+             Call `String::from` to allocate.\n\n\
+             ```rust\nlet s = String::from(\"hello\");\n\nprintln!(\"{s}\");\n```\n\n\
+             - Each value has an owner.\n\
+             - There can only be one owner.\n\n\
+             ### #include <stdio.h>\n\n\
+             \\# not a heading\n"
+        );
+    }
+
+    #[test]
+    fn test_headings_and_code_chunk_like_markdown() {
+        let markdown = html_to_markdown(&Html::parse_document(PAGE));
+
+        let chunks = smart_chunk_markdown(&markdown, 375, TokenCounter::estimate());
+
+        let code = chunks
+            .iter()
+            .find(|c| matches!(c.chunk_type, MarkdownChunkType::CodeBlock))
+            .unwrap();
+        assert_eq!(code.code_lang.as_deref(), Some("rust"));
+        assert_eq!(
+            code.breadcrumb,
+            "Understanding Ownership > The Stack and the Heap"
+        );
+        assert!(code.content.contains("\n\nprintln!"));
+    }
+
+    #[test]
+    fn test_source_prefers_canonical_url() {
+        let html = Html::parse_document(PAGE);
+        assert_eq!(
+            canonical_url(&html).as_deref(),
+            Some("https://doc.rust-lang.org/book/ch04-01.html")
+        );
+        assert_eq!(canonical_url(&Html::parse_document("<p>No head</p>")), None);
+    }
+}
@@ -0,0 +1,495 @@
+// Copyright (c) 2025 Michael A. Wright
+// Licensed under the MIT License
+
+//! Embed and upload text that is already chunked, one JSON object per line:
+//! `{"text": "...", "payload": {...}}`, bypassing the chunkers.
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use rag_demo::config::parse_args;
+use rag_demo::rag::{
+    ApiKey, ChunkPlan, Consistency, EMBED_MODEL_FIELD, EmbedApi, Embedder, IdGenerator, OnOversize,
+    OversizePolicy, QdrantClient, QdrantPoint, RetryPolicy, TokenCounter, Tokenizer, detect_lang,
+    limit_chunks, namespaced_collection, preflight, print_chunk_plan, progress_bar,
+};
+use serde_json::{Map, Value, json};
+use std::collections::HashSet;
+use std::io::BufRead;
+use uuid::Uuid;
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Ingest pre-chunked JSON Lines, one point per {\"text\", \"payload\"} line",
+    long_about = None
+)]
+struct Args {
+    #[arg(help = "Path to a JSON Lines file")]
+    jsonl_path: String,
+
+    #[arg(
+        long,
+        help = "Record or payload field holding each point's ID, for re-ingesting without duplicates (default: random IDs)"
+    )]
+    id_field: Option<String>,
+
+    #[arg(
+        long,
+        conflicts_with = "id_field",
+        help = "Seed for reproducible random point IDs (default: OS entropy)"
+    )]
+    seed: Option<u64>,
+
+    #[arg(
+        long,
+        default_value = "documents",
+        env = "RAG_COLLECTION",
+        help = "Qdrant collection name"
+    )]
+    collection: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_PREFIX",
+        help = "Prepended to the collection name, e.g. acme_ to keep one tenant's collections apart"
+    )]
+    collection_prefix: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_SUFFIX",
+        help = "Appended to the collection name"
+    )]
+    collection_suffix: String,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:6333",
+        env = "QDRANT_URL",
+        help = "Qdrant URL"
+    )]
+    qdrant_url: String,
+
+    #[arg(
+        long,
+        env = "QDRANT_API_KEY",
+        hide_env_values = true,
+        help = "Qdrant API key, sent as the api-key header (Qdrant Cloud)"
+    )]
+    qdrant_api_key: Option<ApiKey>,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:11434",
+        env = "OLLAMA_URL",
+        help = "Ollama URL"
+    )]
+    ollama_url: String,
+
+    #[arg(
+        short,
+        long,
+        default_value = "nomic-embed-text",
+        env = "RAG_MODEL",
+        help = "Embedding model"
+    )]
+    model: String,
+
+    #[arg(
+        long,
+        default_value = "text",
+        help = "Payload field that stores the chunk text"
+    )]
+    text_field: String,
+
+    #[arg(
+        long,
+        help = "Source stored on records whose payload has none (default: file path)"
+    )]
+    source_label: Option<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "ollama",
+        help = "Embedding API: ollama (/api/embeddings), ollama-embed (/api/embed), openai (/v1/embeddings)"
+    )]
+    embed_api: EmbedApi,
+
+    #[arg(
+        long,
+        help = "Skip checking that Qdrant, Ollama and the embedding model are available"
+    )]
+    skip_checks: bool,
+
+    #[arg(
+        long,
+        default_value = "4",
+        help = "Times to retry a failed embedding request (5xx, 429, connection errors)"
+    )]
+    embed_retries: u32,
+
+    #[arg(
+        long,
+        default_value = "500",
+        help = "Delay before the first embedding retry in ms, doubled on each retry"
+    )]
+    embed_retry_delay_ms: u64,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "strong",
+        help = "strong waits for each upsert to be applied, so points are searchable and counted when ingestion ends; eventual returns as soon as Qdrant accepts them"
+    )]
+    consistency: Consistency,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "cl100k",
+        help = "Tokenizer used to measure record sizes (chars estimates 4 characters per token)"
+    )]
+    tokenizer: Tokenizer,
+
+    #[arg(
+        long,
+        default_value = "2048",
+        help = "Token limit for a record's text at embedding time"
+    )]
+    max_tokens: usize,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "split",
+        help = "Text over --max-tokens at embedding time: split embeds pieces and averages them, truncate keeps the start, error fails"
+    )]
+    on_oversize: OnOversize,
+
+    #[arg(
+        long,
+        default_value = "32",
+        help = "Records embedded per request to Ollama's /api/embed"
+    )]
+    embed_batch_size: usize,
+
+    #[arg(
+        long,
+        help = "Embed one record per request with --embed-api instead of batching through /api/embed"
+    )]
+    legacy_embed: bool,
+
+    #[arg(
+        long,
+        help = "Print each record's size and line, then exit without embedding or uploading"
+    )]
+    dry_run: bool,
+
+    #[arg(long, help = "Like --dry-run, but print the records as JSON")]
+    dry_run_json: bool,
+
+    #[arg(
+        long,
+        help = "Only embed and upload the first N records, for quick trial runs on big files"
+    )]
+    max_chunks: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Hide progress bars (also hidden when stdout is not a terminal)"
+    )]
+    quiet: bool,
+}
+
+/// One input line: the text to embed, the payload to store with it, and
+/// its ID from `--id-field`
+#[derive(Debug, PartialEq)]
+struct Record {
+    /// Line of the file the record is on (1-based)
+    line: usize,
+    text: String,
+    payload: Map<String, Value>,
+    id: Option<String>,
+}
+
+/// A Qdrant point ID for an `--id-field` value: UUIDs as they are, anything
+/// else as the UUIDv5 of its text, so the same value is always the same point
+fn point_id(value: &Value) -> Option<String> {
+    let text = match value {
+        Value::String(s) if !s.is_empty() => s.clone(),
+        Value::Number(n) => n.to_string(),
+        _ => return None,
+    };
+    if let Ok(uuid) = Uuid::parse_str(&text) {
+        return Some(uuid.to_string());
+    }
+    Some(Uuid::new_v5(&Uuid::NAMESPACE_URL, text.as_bytes()).to_string())
+}
+
+/// Parse every non-blank line of `data`. A record's ID is read from
+/// `id_field` at its top level, else from its payload.
+fn read_records(data: impl BufRead, id_field: Option<&str>) -> Result<Vec<Record>> {
+    let mut records = Vec::new();
+    for (index, line) in data.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.with_context(|| format!("Failed to read line {line_number}"))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value: Value = serde_json::from_str(&line)
+            .with_context(|| format!("Line {line_number} is not valid JSON"))?;
+        let Some(text) = value.get("text").and_then(Value::as_str) else {
+            anyhow::bail!("Line {line_number} has no \"text\" string");
+        };
+        let payload = match value.get("payload") {
+            None | Some(Value::Null) => Map::new(),
+            Some(Value::Object(payload)) => payload.clone(),
+            Some(_) => anyhow::bail!("Line {line_number}: \"payload\" must be an object"),
+        };
+        let id = match id_field {
+            Some(field) => {
+                let value = value.get(field).or_else(|| payload.get(field));
+                let id = value.and_then(point_id).with_context(|| {
+                    format!("Line {line_number} has no string or number '{field}' for --id-field")
+                })?;
+                Some(id)
+            }
+            None => None,
+        };
+        records.push(Record {
+            line: line_number,
+            text: text.trim().to_string(),
+            payload,
+            id,
+        });
+    }
+    Ok(records)
+}
+
+/// The stored payload: defaults the tool computes, overridden by the
+/// record's own payload, with the text always the tool's. A record's
+/// embedding model is dropped so the client stamps the one actually used.
+fn record_payload(record: Record, defaults: Value, text_field: &str) -> Value {
+    let mut payload = defaults;
+    let fields = payload.as_object_mut().expect("defaults are an object");
+    fields.extend(record.payload);
+    fields.insert(text_field.to_string(), json!(record.text));
+    fields.remove(EMBED_MODEL_FIELD);
+    payload
+}
+
+fn main() -> Result<()> {
+    let mut args: Args = parse_args()?;
+    args.collection = namespaced_collection(
+        &args.collection_prefix,
+        &args.collection,
+        &args.collection_suffix,
+    );
+    let counter = TokenCounter::load(args.tokenizer);
+    if args.max_tokens == 0 {
+        anyhow::bail!("--max-tokens must be greater than zero");
+    }
+    if args.embed_batch_size == 0 {
+        anyhow::bail!("--embed-batch-size must be greater than zero");
+    }
+
+    if !args.dry_run_json {
+        println!("📄 Reading JSON Lines: {}", args.jsonl_path);
+    }
+    let file = std::fs::File::open(&args.jsonl_path)
+        .with_context(|| format!("Failed to open {}", args.jsonl_path))?;
+    let records = read_records(std::io::BufReader::new(file), args.id_field.as_deref())?;
+    let total_records = records.len();
+    let (mut records, empty): (Vec<Record>, Vec<Record>) = records
+        .into_iter()
+        .partition(|record| !record.text.is_empty());
+    if !empty.is_empty() {
+        eprintln!("⚠️  Skipping {} records with empty text", empty.len());
+    }
+    let mut seen = HashSet::new();
+    let repeated = records
+        .iter()
+        .filter_map(|record| record.id.as_ref())
+        .filter(|id| !seen.insert(*id))
+        .count();
+    if repeated > 0 {
+        eprintln!(
+            "⚠️  {repeated} records repeat an earlier --id-field value and will overwrite that point"
+        );
+    }
+    limit_chunks(&mut records, args.max_chunks)?;
+    if args.dry_run || args.dry_run_json {
+        let plan: Vec<ChunkPlan> = records
+            .iter()
+            .enumerate()
+            .map(|(index, record)| ChunkPlan {
+                index,
+                chunk_type: "record".to_string(),
+                chars: record.text.chars().count(),
+                tokens: counter.count(&record.text),
+                header_context: String::new(),
+                start_line: Some(record.line),
+                end_line: Some(record.line),
+            })
+            .collect();
+        return print_chunk_plan(&plan, args.dry_run_json);
+    }
+
+    if !args.skip_checks {
+        preflight(
+            &args.qdrant_url,
+            &args.ollama_url,
+            &args.model,
+            args.embed_api,
+        )?;
+    }
+    let embedder = Embedder::new(
+        &args.ollama_url,
+        args.embed_api,
+        &args.model,
+        RetryPolicy {
+            retries: args.embed_retries,
+            base_delay_ms: args.embed_retry_delay_ms,
+        },
+    )
+    .oversize(OversizePolicy {
+        on_oversize: args.on_oversize,
+        max_tokens: args.max_tokens,
+        counter,
+    })
+    .legacy_embed(args.legacy_embed);
+    let qdrant = QdrantClient::new(&args.qdrant_url)
+        .api_key(args.qdrant_api_key.as_ref())
+        .consistency(args.consistency)
+        .ingest_model(&args.model);
+    let mut ids = IdGenerator::new(args.seed);
+    let source = args.source_label.as_deref().unwrap_or(&args.jsonl_path);
+
+    println!("📦 {} records", records.len());
+    println!("🧮 Generating embeddings with model: {}", args.model);
+    let texts: Vec<String> = records.iter().map(|record| record.text.clone()).collect();
+    let mut embeddings = Vec::new();
+    let bar = progress_bar(records.len(), "Embedding", args.quiet);
+    for batch in texts.chunks(args.embed_batch_size) {
+        embeddings.extend(embedder.embed_batch(batch)?);
+        bar.inc(batch.len() as u64);
+    }
+    bar.finish_and_clear();
+
+    let mut points = Vec::with_capacity(records.len());
+    for (index, (mut record, embedding)) in records.into_iter().zip(embeddings).enumerate() {
+        let id = record.id.take().unwrap_or_else(|| ids.next_id());
+        let defaults = json!({
+            "source": source,
+            "source_path": args.jsonl_path,
+            "line": record.line,
+            "chunk_index": index,
+            "total_chunks": total_records,
+            "chunk_type": "record",
+            "lang": detect_lang(&record.text),
+            "char_count": record.text.chars().count(),
+        });
+        points.push(QdrantPoint {
+            id,
+            vector: embedding,
+            sparse: None,
+            summary_vector: None,
+            payload: record_payload(record, defaults, &args.text_field),
+        });
+    }
+    println!("✅ Generated embeddings for all records");
+
+    // Upload to Qdrant in batches
+    println!("📤 Uploading to Qdrant collection: {}", args.collection);
+    let batch_size = 100;
+    let total_batches = points.len().div_ceil(batch_size);
+
+    let bar = progress_bar(total_batches, "Uploading batches", args.quiet);
+    for (i, batch) in points.chunks(batch_size).enumerate() {
+        qdrant
+            .upsert_points(&args.collection, batch)
+            .with_context(|| format!("Qdrant returned error in batch {}", i + 1))?;
+        bar.inc(1);
+    }
+    bar.finish_and_clear();
+
+    println!("✅ Successfully ingested JSON Lines into Qdrant!");
+    println!("📊 Summary:");
+    println!("   Records: {}", points.len());
+    println!("   Source: {source}");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JSONL: &str = r#"{"text": " Ownership moves values. ", "payload": {"source": "book.md", "chapter": 4}}
+
+{"id": 7, "text": "Borrowing lends them."}
+{"text": "", "payload": {"doc_id": "a"}}
+"#;
+
+    #[test]
+    fn test_read_records_skips_blank_lines_and_keeps_payload() {
+        let records = read_records(JSONL.as_bytes(), None).unwrap();
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].text, "Ownership moves values.");
+        assert_eq!(records[0].payload["chapter"], json!(4));
+        assert_eq!(records[1].line, 3);
+        assert!(records[1].payload.is_empty());
+        assert_eq!(records[1].id, None);
+
+        let error = read_records("{\"payload\": {}}\n".as_bytes(), None).unwrap_err();
+        assert!(error.to_string().contains("Line 1"), "{error}");
+        assert!(read_records("{\"text\": \"x\", \"payload\": [1]}".as_bytes(), None).is_err());
+        assert!(read_records("not json".as_bytes(), None).is_err());
+    }
+
+    #[test]
+    fn test_id_field_gives_stable_point_ids() {
+        let jsonl = "{\"id\": 7, \"text\": \"a\"}\n\
+            {\"text\": \"b\", \"payload\": {\"id\": \"67e55044-10b1-426f-9247-bb680e5fe0c8\"}}\n";
+
+        let records = read_records(jsonl.as_bytes(), Some("id")).unwrap();
+        let again = read_records(jsonl.as_bytes(), Some("id")).unwrap();
+
+        assert_eq!(records[0].id, again[0].id);
+        assert!(Uuid::parse_str(records[0].id.as_ref().unwrap()).is_ok());
+        assert_eq!(
+            records[1].id.as_deref(),
+            Some("67e55044-10b1-426f-9247-bb680e5fe0c8")
+        );
+        let error = read_records(JSONL.as_bytes(), Some("id")).unwrap_err();
+        assert!(error.to_string().contains("Line 1"), "{error}");
+    }
+
+    #[test]
+    fn test_record_payload_overrides_defaults_but_not_text_or_model() {
+        let record = Record {
+            line: 1,
+            text: "Ownership".to_string(),
+            payload: json!({"source": "book.md", "content": "stale", "embed_model": "old"})
+                .as_object()
+                .unwrap()
+                .clone(),
+            id: None,
+        };
+        let defaults = json!({"source": "chunks.jsonl", "line": 1});
+
+        let payload = record_payload(record, defaults, "content");
+
+        assert_eq!(payload["source"], "book.md");
+        assert_eq!(payload["line"], 1);
+        assert_eq!(payload["content"], "Ownership");
+        assert!(payload.get("embed_model").is_none());
+    }
+}
@@ -3,312 +3,883 @@
 
 use anyhow::{Context, Result};
 use clap::Parser;
-use reqwest::blocking::Client;
-use serde::{Deserialize, Serialize};
+use rag_demo::config::parse_args;
+use rag_demo::rag::{
+    ApiKey, ChunkPlan, Consistency, EmbedApi, Embedder, IdGenerator, MarkdownChunk,
+    MarkdownChunkType, OnOversize, OversizePolicy, QdrantClient, QdrantPoint, RetryPolicy,
+    TokenCounter, Tokenizer, detect_lang, enforce_max_tokens, limit_chunks, markdown_chunk_plan,
+    merge_small_chunks, namespaced_collection, overlap_text_chunks, preflight, print_chunk_plan,
+    progress_bar, smart_chunk_markdown,
+};
+use rag_demo::vecmath::cosine;
 use serde_json::json;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use uuid::Uuid;
+use std::io::Read;
+use std::ops::Range;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Ingest Markdown with smart chunking", long_about = None)]
 struct Args {
-    #[arg(help = "Path to Markdown file")]
-    md_path: String,
+    #[arg(
+        required_unless_present = "globs",
+        help = "Markdown files to ingest, or - to read one from stdin"
+    )]
+    md_paths: Vec<String>,
+
+    #[arg(
+        long = "glob",
+        help = "Also ingest the files matching this pattern, e.g. 'docs/**/*.md' (repeatable)"
+    )]
+    globs: Vec<String>,
 
-    #[arg(short, long, default_value = "1500", help = "Max characters per chunk")]
+    #[arg(
+        long,
+        help = "Stop at the first file that fails instead of ingesting the rest"
+    )]
+    fail_fast: bool,
+
+    #[arg(
+        short,
+        long,
+        default_value = "375",
+        help = "Tokens per text chunk before breaking at the next paragraph"
+    )]
     chunk_size: usize,
 
-    #[arg(long, default_value = "documents", help = "Qdrant collection name")]
+    #[arg(
+        long,
+        default_value = "50",
+        help = "Tokens under which a text chunk, such as a lone heading or a stray line, is merged into its neighbours (0 keeps every chunk)"
+    )]
+    min_chunk_size: usize,
+
+    #[arg(
+        long,
+        default_value = "0",
+        help = "Tokens from the end of a text chunk repeated at the start of the next one in the same section (code blocks get none)"
+    )]
+    overlap: usize,
+
+    #[arg(
+        long,
+        default_value = "documents",
+        env = "RAG_COLLECTION",
+        help = "Qdrant collection name"
+    )]
     collection: String,
 
-    #[arg(long, default_value = "http://localhost:6333", help = "Qdrant URL")]
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_PREFIX",
+        help = "Prepended to the collection name, e.g. acme_ to keep one tenant's collections apart"
+    )]
+    collection_prefix: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_SUFFIX",
+        help = "Appended to the collection name"
+    )]
+    collection_suffix: String,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:6333",
+        env = "QDRANT_URL",
+        help = "Qdrant URL"
+    )]
     qdrant_url: String,
 
-    #[arg(long, default_value = "http://localhost:11434", help = "Ollama URL")]
+    #[arg(
+        long,
+        env = "QDRANT_API_KEY",
+        hide_env_values = true,
+        help = "Qdrant API key, sent as the api-key header (Qdrant Cloud)"
+    )]
+    qdrant_api_key: Option<ApiKey>,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:11434",
+        env = "OLLAMA_URL",
+        help = "Ollama URL"
+    )]
     ollama_url: String,
 
     #[arg(
         short,
         long,
         default_value = "nomic-embed-text",
+        env = "RAG_MODEL",
         help = "Embedding model"
     )]
     model: String,
-}
 
-#[derive(Debug, Serialize, Deserialize)]
-struct EmbeddingRequest {
-    model: String,
-    prompt: String,
+    #[arg(
+        long,
+        default_value = "text",
+        help = "Payload field that stores the chunk text"
+    )]
+    text_field: String,
+
+    #[arg(
+        long,
+        visible_alias = "source-name",
+        help = "Human-friendly name stored as the payload source of a single file (default: file path, or \"stdin\" for -)"
+    )]
+    source_label: Option<String>,
+
+    #[arg(long, help = "Seed for reproducible point IDs (default: OS entropy)")]
+    seed: Option<u64>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "ollama",
+        help = "Embedding API: ollama (/api/embeddings), ollama-embed (/api/embed), openai (/v1/embeddings)"
+    )]
+    embed_api: EmbedApi,
+
+    #[arg(
+        long,
+        help = "Skip checking that Qdrant, Ollama and the embedding model are available"
+    )]
+    skip_checks: bool,
+
+    #[arg(
+        long,
+        default_value = "4",
+        help = "Times to retry a failed embedding request (5xx, 429, connection errors)"
+    )]
+    embed_retries: u32,
+
+    #[arg(
+        long,
+        default_value = "500",
+        help = "Delay before the first embedding retry in ms, doubled on each retry"
+    )]
+    embed_retry_delay_ms: u64,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "strong",
+        help = "strong waits for each upsert to be applied, so points are searchable and counted when ingestion ends; eventual returns as soon as Qdrant accepts them"
+    )]
+    consistency: Consistency,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "cl100k",
+        help = "Tokenizer used to measure chunk sizes (chars estimates 4 characters per token)"
+    )]
+    tokenizer: Tokenizer,
+
+    #[arg(
+        long,
+        default_value = "2048",
+        help = "Hard limit in tokens for every chunk, splitting code blocks if needed"
+    )]
+    max_tokens: usize,
+
+    #[arg(
+        long,
+        help = "Split text where the topic shifts: embed each sentence and start a new chunk where neighbours are dissimilar (embeds the text twice)"
+    )]
+    semantic: bool,
+
+    #[arg(
+        long,
+        default_value = "0.6",
+        help = "With --semantic, start a new chunk where the cosine similarity of adjacent sentences is below this"
+    )]
+    semantic_threshold: f32,
+
+    #[arg(
+        long,
+        default_value = "512",
+        help = "With --semantic, tokens a chunk may grow to before it is split regardless of similarity"
+    )]
+    max_chunk_size: usize,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "split",
+        help = "Text over --max-tokens at embedding time: split embeds pieces and averages them, truncate keeps the start, error fails"
+    )]
+    on_oversize: OnOversize,
+
+    #[arg(
+        long,
+        default_value = "32",
+        help = "Chunks embedded per request to Ollama's /api/embed"
+    )]
+    embed_batch_size: usize,
+
+    #[arg(
+        long,
+        help = "Embed one chunk per request with --embed-api instead of batching through /api/embed"
+    )]
+    legacy_embed: bool,
+
+    #[arg(
+        long,
+        help = "Print each chunk's type, size and header context, then exit without embedding or uploading"
+    )]
+    dry_run: bool,
+
+    #[arg(long, help = "Like --dry-run, but print the chunks as JSON")]
+    dry_run_json: bool,
+
+    #[arg(
+        long,
+        help = "Only embed and upload the first N chunks of each file, for quick trial runs on big files"
+    )]
+    max_chunks: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Hide progress bars (also hidden when stdout is not a terminal)"
+    )]
+    quiet: bool,
 }
 
-#[derive(Debug, Deserialize)]
-struct EmbeddingResponse {
-    embedding: Vec<f32>,
+fn main() -> Result<()> {
+    let mut args: Args = parse_args()?;
+    args.collection = namespaced_collection(
+        &args.collection_prefix,
+        &args.collection,
+        &args.collection_suffix,
+    );
+    let counter = TokenCounter::load(args.tokenizer);
+    if args.max_tokens == 0 {
+        anyhow::bail!("--max-tokens must be greater than zero");
+    }
+    if args.embed_batch_size == 0 {
+        anyhow::bail!("--embed-batch-size must be greater than zero");
+    }
+    if args.semantic && args.max_chunk_size == 0 {
+        anyhow::bail!("--max-chunk-size must be greater than zero");
+    }
+    let chunk_size = if args.semantic {
+        args.max_chunk_size
+    } else {
+        args.chunk_size
+    };
+    if args.overlap >= chunk_size {
+        anyhow::bail!(
+            "--overlap ({}) must be less than the chunk size ({chunk_size})",
+            args.overlap
+        );
+    }
+    let paths = markdown_paths(&args.md_paths, &args.globs)?;
+    if paths.len() > 1 {
+        if paths.iter().any(|path| path == STDIN_PATH) {
+            anyhow::bail!("- reads one document from stdin; pass it without other files");
+        }
+        if args.source_label.is_some() {
+            anyhow::bail!("--source-label names a single document; pass one Markdown file with it");
+        }
+    }
+
+    // Semantic chunking embeds sentences, so it needs the servers even for a dry run
+    let dry_run = args.dry_run || args.dry_run_json;
+    if !args.skip_checks && (args.semantic || !dry_run) {
+        preflight(
+            &args.qdrant_url,
+            &args.ollama_url,
+            &args.model,
+            args.embed_api,
+        )?;
+    }
+    let embedder = Embedder::new(
+        &args.ollama_url,
+        args.embed_api,
+        &args.model,
+        RetryPolicy {
+            retries: args.embed_retries,
+            base_delay_ms: args.embed_retry_delay_ms,
+        },
+    )
+    .oversize(OversizePolicy {
+        on_oversize: args.on_oversize,
+        max_tokens: args.max_tokens,
+        counter,
+    })
+    .legacy_embed(args.legacy_embed);
+    let qdrant = QdrantClient::new(&args.qdrant_url)
+        .api_key(args.qdrant_api_key.as_ref())
+        .consistency(args.consistency)
+        .ingest_model(&args.model);
+    let mut ingester = Ingester {
+        args: &args,
+        counter,
+        embedder,
+        qdrant,
+        ids: IdGenerator::new(args.seed),
+        sentence_embeddings: HashMap::new(),
+    };
+
+    // A lone file fails the run as before; a batch carries on unless --fail-fast
+    let fail_fast = args.fail_fast || paths.len() == 1;
+    let mut ingested: Vec<MarkdownChunk> = Vec::new();
+    let mut plans: Vec<(&str, Vec<ChunkPlan>)> = Vec::new();
+    let mut failed: Vec<&str> = Vec::new();
+    for path in &paths {
+        let result = ingester.chunk_file(path).and_then(|chunks| {
+            if !dry_run {
+                ingester.upload(path, &chunks)?;
+            }
+            Ok(chunks)
+        });
+        match result {
+            Ok(chunks) => {
+                if dry_run {
+                    plans.push((path, markdown_chunk_plan(&chunks, counter)));
+                }
+                ingested.extend(chunks);
+            }
+            Err(e) if fail_fast => {
+                return Err(e.context(format!("Failed to ingest {path}")));
+            }
+            Err(e) => {
+                eprintln!("⚠️  Failed to ingest {path}: {e:#}");
+                failed.push(path);
+            }
+        }
+    }
+
+    if dry_run {
+        print_plans(&plans, args.dry_run_json)?;
+    } else if let [path] = paths.as_slice() {
+        let source = args.source(path);
+        println!("✅ Successfully ingested Markdown into Qdrant!");
+        println!("📊 Summary:");
+        println!("   Total chunks: {}", ingested.len());
+        println!("   Code blocks preserved: {}", code_chunks(&ingested));
+        println!(
+            "   Average chunk size: {:.0} tokens",
+            average_tokens(&ingested, counter)
+        );
+        println!("   Source: {}", source);
+    } else {
+        println!("📊 Summary:");
+        println!(
+            "   Files: {} ingested, {} failed",
+            paths.len() - failed.len(),
+            failed.len()
+        );
+        println!("   Total chunks: {}", ingested.len());
+        println!("   Code blocks preserved: {}", code_chunks(&ingested));
+        println!(
+            "   Average chunk size: {:.0} tokens",
+            average_tokens(&ingested, counter)
+        );
+        for path in &failed {
+            println!("   ❌ {path}");
+        }
+    }
+    if !failed.is_empty() {
+        anyhow::bail!("{} of {} files failed", failed.len(), paths.len());
+    }
+
+    Ok(())
 }
 
-#[derive(Debug, Serialize)]
-struct QdrantPoint {
-    id: String,
-    vector: Vec<f32>,
-    payload: serde_json::Value,
+/// The files named on the command line followed by those `--glob` matches,
+/// each once, in that order
+fn markdown_paths(paths: &[String], globs: &[String]) -> Result<Vec<String>> {
+    let mut all: Vec<String> = paths.to_vec();
+    for pattern in globs {
+        let matches = glob::glob(pattern).with_context(|| format!("Invalid --glob '{pattern}'"))?;
+        let before = all.len();
+        for entry in matches {
+            let path = entry.with_context(|| format!("Failed to read a match of '{pattern}'"))?;
+            if path.is_file() {
+                all.push(path.to_string_lossy().into_owned());
+            }
+        }
+        if all.len() == before {
+            eprintln!("⚠️  --glob '{pattern}' matched no files");
+        }
+    }
+    let mut seen = HashSet::new();
+    all.retain(|path| seen.insert(path.clone()));
+    if all.is_empty() {
+        anyhow::bail!("No Markdown files to ingest");
+    }
+    Ok(all)
 }
 
-#[derive(Debug, Clone)]
-struct MarkdownChunk {
-    content: String,
-    chunk_type: ChunkType,
-    header_context: String,
-    #[allow(dead_code)]
-    index: usize,
+/// Dry-run plans: one file's as before, several files' each under its path
+/// (in JSON, an array of `{path, chunks}`)
+fn print_plans(plans: &[(&str, Vec<ChunkPlan>)], json: bool) -> Result<()> {
+    if let [(_, plan)] = plans {
+        return print_chunk_plan(plan, json);
+    }
+    if json {
+        let files: Vec<serde_json::Value> = plans
+            .iter()
+            .map(|(path, plan)| json!({ "path": path, "chunks": plan }))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&files)?);
+        return Ok(());
+    }
+    for (path, plan) in plans {
+        println!("\n📄 {path}");
+        print_chunk_plan(plan, false)?;
+    }
+    Ok(())
 }
 
-#[derive(Debug, Clone, Serialize)]
-enum ChunkType {
-    #[allow(dead_code)]
-    Header,
-    CodeBlock,
-    Text,
-    #[allow(dead_code)]
-    List,
-    #[allow(dead_code)]
-    Table,
+impl Args {
+    /// The payload `source` of the file at `path`
+    fn source<'a>(&'a self, path: &'a str) -> &'a str {
+        match &self.source_label {
+            Some(label) => label,
+            None if path == STDIN_PATH => "stdin",
+            None => path,
+        }
+    }
 }
 
-fn smart_chunk_markdown(content: &str, max_chunk_size: usize) -> Vec<MarkdownChunk> {
-    let mut chunks = Vec::new();
-    let lines: Vec<&str> = content.lines().collect();
-    let mut current_chunk = String::new();
-    let mut current_header = String::new();
-    let mut in_code_block = false;
-    let mut code_block = String::new();
-    let mut chunk_index = 0;
-
-    for line in lines {
-        // Detect headers
-        if line.starts_with('#') && !in_code_block {
-            // Save previous chunk if exists
-            if !current_chunk.is_empty() {
-                chunks.push(MarkdownChunk {
-                    content: current_chunk.clone(),
-                    chunk_type: ChunkType::Text,
-                    header_context: current_header.clone(),
-                    index: chunk_index,
-                });
-                chunk_index += 1;
-                current_chunk.clear();
-            }
+/// What ingesting each file shares: settings, clients, and the point IDs
+/// and sentence embeddings handed out so far
+struct Ingester<'a> {
+    args: &'a Args,
+    counter: TokenCounter,
+    embedder: Embedder,
+    qdrant: QdrantClient,
+    ids: IdGenerator,
+    /// Sentence embeddings by text, reused for chunks that are a single sentence
+    sentence_embeddings: HashMap<String, Vec<f32>>,
+}
 
-            // Update header context
-            let level = line.chars().take_while(|c| *c == '#').count();
-            if level <= 2 {
-                // Only track h1 and h2
-                current_header = line.to_string();
+impl Ingester<'_> {
+    /// Read `path` and cut it into the chunks that will be embedded
+    fn chunk_file(&mut self, path: &str) -> Result<Vec<MarkdownChunk>> {
+        let args = self.args;
+        let counter = self.counter;
+        if !args.dry_run_json {
+            if path == STDIN_PATH {
+                println!("📄 Reading Markdown from stdin");
+            } else {
+                println!("📄 Reading Markdown: {path}");
             }
-
-            current_chunk.push_str(line);
-            current_chunk.push('\n');
         }
-        // Detect code blocks
-        else if line.trim().starts_with("```") {
-            if in_code_block {
-                // End of code block
-                code_block.push_str(line);
-                code_block.push('\n');
-
-                // Save code block as single chunk (don't split code)
-                chunks.push(MarkdownChunk {
-                    content: code_block.clone(),
-                    chunk_type: ChunkType::CodeBlock,
-                    header_context: current_header.clone(),
-                    index: chunk_index,
-                });
-                chunk_index += 1;
-
-                code_block.clear();
-                in_code_block = false;
-            } else {
-                // Start of code block
-                // Save current chunk if exists
-                if !current_chunk.is_empty() {
-                    chunks.push(MarkdownChunk {
-                        content: current_chunk.clone(),
-                        chunk_type: ChunkType::Text,
-                        header_context: current_header.clone(),
-                        index: chunk_index,
-                    });
-                    chunk_index += 1;
-                    current_chunk.clear();
-                }
+        let content = read_markdown(path, std::io::stdin().lock())?;
 
-                in_code_block = true;
-                code_block.push_str(line);
-                code_block.push('\n');
+        // Smart chunking
+        if !args.dry_run_json {
+            println!("✂️  Smart chunking (preserving code blocks and structure)...");
+        }
+        let chunks = if args.semantic {
+            // Sections are cut at --max-chunk-size first, then split at topic shifts
+            let sections = smart_chunk_markdown(&content, args.max_chunk_size, counter);
+            let (chunks, stats) = semantic_chunks(
+                sections,
+                args,
+                &self.embedder,
+                counter,
+                &mut self.sentence_embeddings,
+            )?;
+            if !args.dry_run_json {
+                println!(
+                    "🧠 Semantic chunking: {} split points below similarity {}, {} at --max-chunk-size; average chunk {:.0} tokens",
+                    stats.semantic_splits,
+                    args.semantic_threshold,
+                    stats.size_splits,
+                    average_tokens(&chunks, counter)
+                );
             }
-        } else if in_code_block {
-            // Inside code block
-            code_block.push_str(line);
-            code_block.push('\n');
+            chunks
         } else {
-            // Regular text
-            current_chunk.push_str(line);
-            current_chunk.push('\n');
-
-            // Check if chunk is getting too large
-            if current_chunk.len() > max_chunk_size {
-                // Try to break at paragraph boundary
-                if line.trim().is_empty() {
-                    chunks.push(MarkdownChunk {
-                        content: current_chunk.clone(),
-                        chunk_type: ChunkType::Text,
-                        header_context: current_header.clone(),
-                        index: chunk_index,
-                    });
-                    chunk_index += 1;
-                    current_chunk.clear();
-                }
-            }
+            smart_chunk_markdown(&content, args.chunk_size, counter)
+        };
+        let (chunks, small) = merge_small_chunks(chunks, args.min_chunk_size, counter);
+        if !args.dry_run_json {
+            small.print();
         }
+        let chunks = overlap_text_chunks(chunks, args.overlap, counter);
+        let mut chunks = enforce_max_tokens(chunks, args.max_tokens, counter);
+        limit_chunks(&mut chunks, args.max_chunks)?;
+        Ok(chunks)
     }
 
-    // Save any remaining content
-    if !current_chunk.is_empty() {
-        chunks.push(MarkdownChunk {
-            content: current_chunk,
-            chunk_type: ChunkType::Text,
-            header_context: current_header.clone(),
-            index: chunk_index,
-        });
-    }
+    /// Embed the chunks of the file at `path` and upload them
+    fn upload(&mut self, path: &str, chunks: &[MarkdownChunk]) -> Result<()> {
+        let args = self.args;
+        let source = args.source(path);
+        // Piped content has no path; "-" would match nothing in delete-source
+        let source_path = if path == STDIN_PATH { source } else { path };
+
+        println!("📦 Created {} chunks:", chunks.len());
+        let code_chunks = code_chunks(chunks);
+        let text_chunks = chunks
+            .iter()
+            .filter(|c| matches!(c.chunk_type, MarkdownChunkType::Text))
+            .count();
+        println!("   Code blocks: {}", code_chunks);
+        println!("   Text sections: {}", text_chunks);
+
+        // Generate embeddings and prepare points
+        println!("🧮 Generating embeddings with model: {}", args.model);
+        let embedding_texts: Vec<String> =
+            chunks.iter().map(MarkdownChunk::embedding_text).collect();
+        let mut embeddings: Vec<Option<Vec<f32>>> = embedding_texts
+            .iter()
+            .map(|text| self.sentence_embeddings.get(text).cloned())
+            .collect();
+        let reused = embeddings.iter().filter(|e| e.is_some()).count();
+        if reused > 0 {
+            println!("   Reusing {reused} sentence embeddings");
+        }
+        let missing: Vec<usize> = (0..chunks.len())
+            .filter(|&i| embeddings[i].is_none())
+            .collect();
+        let bar = progress_bar(missing.len(), "Embedding", args.quiet);
+        for batch in missing.chunks(args.embed_batch_size) {
+            let texts: Vec<String> = batch.iter().map(|&i| embedding_texts[i].clone()).collect();
+            for (&i, embedding) in batch.iter().zip(self.embedder.embed_batch(&texts)?) {
+                embeddings[i] = Some(embedding);
+            }
+            bar.inc(batch.len() as u64);
+        }
+        bar.finish_and_clear();
+        let embeddings = embeddings.into_iter().flatten();
+        let mut points = Vec::new();
+
+        for ((i, chunk), embedding) in chunks.iter().enumerate().zip(embeddings) {
+            let mut payload = json!({
+                "source": source,
+                "source_path": source_path,
+                "chunk_index": i,
+                "total_chunks": chunks.len(),
+                "chunk_type": chunk.chunk_type,
+                "lang": detect_lang(&chunk.content),
+                "char_count": chunk.content.chars().count(),
+                "header_context": chunk.header_context,
+                "breadcrumb": chunk.breadcrumb,
+                "is_code": matches!(chunk.chunk_type, MarkdownChunkType::CodeBlock),
+            });
+            if let Some(lang) = &chunk.code_lang {
+                payload["code_lang"] = json!(lang);
+            }
+            payload[args.text_field.as_str()] = json!(chunk.content);
 
-    if !code_block.is_empty() {
-        chunks.push(MarkdownChunk {
-            content: code_block,
-            chunk_type: ChunkType::CodeBlock,
-            header_context: current_header,
-            index: chunk_index,
-        });
+            let point = QdrantPoint {
+                id: self.ids.next_id(),
+                vector: embedding,
+                sparse: None,
+                summary_vector: None,
+                payload,
+            };
+
+            points.push(point);
+        }
+        println!("✅ Generated embeddings for all chunks");
+
+        // Upload to Qdrant in batches
+        println!("📤 Uploading to Qdrant collection: {}", args.collection);
+        let batch_size = 100;
+        let total_batches = points.len().div_ceil(batch_size);
+
+        let bar = progress_bar(total_batches, "Uploading batches", args.quiet);
+        for (i, batch) in points.chunks(batch_size).enumerate() {
+            self.qdrant
+                .upsert_points(&args.collection, batch)
+                .with_context(|| format!("Qdrant returned error in batch {}", i + 1))?;
+            bar.inc(1);
+        }
+        bar.finish_and_clear();
+        Ok(())
     }
+}
 
+fn code_chunks(chunks: &[MarkdownChunk]) -> usize {
     chunks
+        .iter()
+        .filter(|c| matches!(c.chunk_type, MarkdownChunkType::CodeBlock))
+        .count()
 }
 
-fn get_embedding(client: &Client, ollama_url: &str, model: &str, text: &str) -> Result<Vec<f32>> {
-    let request = EmbeddingRequest {
-        model: model.to_string(),
-        prompt: text.to_string(),
-    };
+/// Where `semantic_chunks` split text chunks
+#[derive(Debug, Default, PartialEq)]
+struct SplitStats {
+    /// Splits where adjacent sentences fell below `--semantic-threshold`
+    semantic_splits: usize,
+    /// Splits forced by `--max-chunk-size`
+    size_splits: usize,
+}
 
-    let response = client
-        .post(format!("{}/api/embeddings", ollama_url))
-        .json(&request)
-        .send()
-        .context("Failed to get embedding from Ollama")?;
+/// Byte ranges of the sentences in `text`, each ending at `.`, `!` or `?`
+/// followed by whitespace, at a blank line, or at the end of the text
+fn split_sentences(text: &str) -> Vec<Range<usize>> {
+    let mut sentences = Vec::new();
+    let mut start = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if start.is_none() && !c.is_whitespace() {
+            start = Some(i);
+        }
+        let next = chars.peek().map(|&(_, next)| next);
+        let ends = match c {
+            '.' | '!' | '?' => next.is_none_or(char::is_whitespace),
+            '\n' => next == Some('\n'),
+            _ => false,
+        };
+        if ends && let Some(s) = start.take() {
+            sentences.push(s..(i + c.len_utf8()));
+        }
+    }
+    if let Some(s) = start {
+        sentences.push(s..text.trim_end().len());
+    }
+    sentences
+}
 
-    if !response.status().is_success() {
-        anyhow::bail!("Ollama returned error: {}", response.status());
+/// Group consecutive sentences, given the similarity of each sentence to the
+/// next and each sentence's token count: a group ends where the similarity is
+/// below `threshold` or where the next sentence would take it past `max_tokens`
+fn group_sentences(
+    similarities: &[f32],
+    tokens: &[usize],
+    threshold: f32,
+    max_tokens: usize,
+) -> (Vec<Range<usize>>, SplitStats) {
+    let mut groups = Vec::new();
+    let mut stats = SplitStats::default();
+    let mut start = 0;
+    let mut size = 0;
+    for (i, &count) in tokens.iter().enumerate() {
+        if i > start {
+            if similarities[i - 1] < threshold {
+                stats.semantic_splits += 1;
+            } else if size + count > max_tokens {
+                stats.size_splits += 1;
+            } else {
+                size += count;
+                continue;
+            }
+            groups.push(start..i);
+            start = i;
+        }
+        size = count;
+    }
+    if start < tokens.len() {
+        groups.push(start..tokens.len());
     }
+    (groups, stats)
+}
 
-    let embedding: EmbeddingResponse = response
-        .json()
-        .context("Failed to parse embedding response")?;
+/// Split each text chunk at topic shifts between its sentences, keeping its
+/// headings and narrowing its line range; other chunks pass through as they are.
+/// Sentence embeddings are stored in `embeddings` by sentence text.
+fn semantic_chunks(
+    sections: Vec<MarkdownChunk>,
+    args: &Args,
+    embedder: &Embedder,
+    counter: TokenCounter,
+    embeddings: &mut HashMap<String, Vec<f32>>,
+) -> Result<(Vec<MarkdownChunk>, SplitStats)> {
+    let mut sentences = Vec::with_capacity(sections.len());
+    for section in &sections {
+        let ranges = if matches!(section.chunk_type, MarkdownChunkType::Text) {
+            split_sentences(&section.content)
+        } else {
+            Vec::new()
+        };
+        sentences.push(ranges);
+    }
 
-    Ok(embedding.embedding)
+    let texts: Vec<String> = sections
+        .iter()
+        .zip(&sentences)
+        .filter(|(_, ranges)| ranges.len() > 1)
+        .flat_map(|(section, ranges)| {
+            ranges
+                .iter()
+                .map(|r| section.content[r.clone()].to_string())
+        })
+        .filter(|text| !embeddings.contains_key(text))
+        .collect();
+    let bar = progress_bar(texts.len(), "Embedding sentences", args.quiet);
+    for batch in texts.chunks(args.embed_batch_size) {
+        for (text, embedding) in batch.iter().zip(embedder.embed_batch(batch)?) {
+            embeddings.insert(text.clone(), embedding);
+        }
+        bar.inc(batch.len() as u64);
+    }
+    bar.finish_and_clear();
+
+    let mut chunks = Vec::new();
+    let mut stats = SplitStats::default();
+    for (section, ranges) in sections.into_iter().zip(sentences) {
+        if ranges.len() < 2 {
+            chunks.push(section);
+            continue;
+        }
+        let text = |r: &Range<usize>| &section.content[r.clone()];
+        let similarities: Vec<f32> = ranges
+            .windows(2)
+            .map(|pair| cosine(&embeddings[text(&pair[0])], &embeddings[text(&pair[1])]))
+            .collect();
+        let tokens: Vec<usize> = ranges.iter().map(|r| counter.count(text(r))).collect();
+        let (groups, split) = group_sentences(
+            &similarities,
+            &tokens,
+            args.semantic_threshold,
+            args.max_chunk_size,
+        );
+        stats.semantic_splits += split.semantic_splits;
+        stats.size_splits += split.size_splits;
+
+        let line_of =
+            |byte: usize| section.start_line + section.content[..byte].matches('\n').count();
+        for group in groups {
+            let bytes = ranges[group.start].start..ranges[group.end - 1].end;
+            chunks.push(MarkdownChunk {
+                content: section.content[bytes.clone()].to_string(),
+                start_line: line_of(bytes.start),
+                end_line: line_of(bytes.end),
+                ..section.clone()
+            });
+        }
+    }
+    for (index, chunk) in chunks.iter_mut().enumerate() {
+        chunk.index = index;
+    }
+    Ok((chunks, stats))
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
-    let client = Client::new();
+/// The `md_path` that reads the document from stdin
+const STDIN_PATH: &str = "-";
 
-    // Read markdown file
-    println!("📄 Reading Markdown: {}", args.md_path);
-    let content = fs::read_to_string(&args.md_path).context("Failed to read Markdown file")?;
+/// The Markdown at `path`, or everything on `stdin` when the path is `-`.
+/// Empty input on stdin is an error, as it is usually a broken pipe upstream.
+fn read_markdown(path: &str, mut stdin: impl Read) -> Result<String> {
+    if path != STDIN_PATH {
+        return fs::read_to_string(path).context("Failed to read Markdown file");
+    }
+    let mut content = String::new();
+    stdin
+        .read_to_string(&mut content)
+        .context("Failed to read Markdown from stdin")?;
+    if content.trim().is_empty() {
+        anyhow::bail!(
+            "No Markdown on stdin; pipe a document in, e.g. cat notes.md | ingest-markdown - --source-name notes.md"
+        );
+    }
+    Ok(content)
+}
 
-    // Smart chunking
-    println!("✂️  Smart chunking (preserving code blocks and structure)...");
-    let chunks = smart_chunk_markdown(&content, args.chunk_size);
+fn average_tokens(chunks: &[MarkdownChunk], counter: TokenCounter) -> f64 {
+    let total: usize = chunks.iter().map(|c| counter.count(&c.content)).sum();
+    total as f64 / chunks.len().max(1) as f64
+}
 
-    println!("📦 Created {} chunks:", chunks.len());
-    let code_chunks = chunks
-        .iter()
-        .filter(|c| matches!(c.chunk_type, ChunkType::CodeBlock))
-        .count();
-    let text_chunks = chunks
-        .iter()
-        .filter(|c| matches!(c.chunk_type, ChunkType::Text))
-        .count();
-    println!("   Code blocks: {}", code_chunks);
-    println!("   Text sections: {}", text_chunks);
-
-    // Generate embeddings and prepare points
-    println!("🧮 Generating embeddings with model: {}", args.model);
-    let mut points = Vec::new();
-
-    for (i, chunk) in chunks.iter().enumerate() {
-        print!("  Processing chunk {}/{}...\r", i + 1, chunks.len());
-
-        // For code blocks, include the header context in the embedding
-        let embedding_text = if matches!(chunk.chunk_type, ChunkType::CodeBlock)
-            && !chunk.header_context.is_empty()
-        {
-            format!("{}\n\n{}", chunk.header_context, chunk.content)
-        } else {
-            chunk.content.clone()
-        };
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_paths_expands_globs_once_each() {
+        let root = std::env::temp_dir().join(format!("rag-md-glob-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("guide/async")).unwrap();
+        for file in [
+            "intro.md",
+            "guide/traits.md",
+            "guide/async/pin.md",
+            "notes.txt",
+        ] {
+            fs::write(root.join(file), "# Title\n").unwrap();
+        }
+        let root_str = root.to_string_lossy();
+        let intro = format!("{root_str}/intro.md");
+
+        let paths = markdown_paths(
+            std::slice::from_ref(&intro),
+            &[format!("{root_str}/**/*.md")],
+        )
+        .unwrap();
+
+        let relative: Vec<&str> = paths
+            .iter()
+            .map(|p| p.strip_prefix(&*root_str).unwrap())
+            .collect();
+        assert_eq!(
+            relative,
+            vec!["/intro.md", "/guide/async/pin.md", "/guide/traits.md"]
+        );
+        assert!(markdown_paths(&[], &[format!("{root_str}/*.rst")]).is_err());
+        assert!(markdown_paths(&[], &["[".to_string()]).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
 
-        let embedding = get_embedding(&client, &args.ollama_url, &args.model, &embedding_text)?;
+    #[test]
+    fn test_read_markdown_from_stdin_rejects_empty_input() {
+        let content = read_markdown("-", "# Notes\n\nPiped in.\n".as_bytes()).unwrap();
+        assert_eq!(content, "# Notes\n\nPiped in.\n");
 
-        let point = QdrantPoint {
-            id: Uuid::new_v4().to_string(),
-            vector: embedding,
-            payload: json!({
-                "text": chunk.content,
-                "source": args.md_path,
-                "chunk_index": i,
-                "total_chunks": chunks.len(),
-                "chunk_type": chunk.chunk_type,
-                "header_context": chunk.header_context,
-                "is_code": matches!(chunk.chunk_type, ChunkType::CodeBlock),
-            }),
-        };
+        let error = read_markdown("-", " \n\n".as_bytes()).unwrap_err();
+        assert!(
+            error.to_string().contains("No Markdown on stdin"),
+            "{error}"
+        );
 
-        points.push(point);
+        let args =
+            Args::try_parse_from(["ingest-markdown", "-", "--source-name", "notes.md"]).unwrap();
+        assert_eq!(args.source_label.as_deref(), Some("notes.md"));
     }
-    println!("\n✅ Generated embeddings for all chunks");
-
-    // Upload to Qdrant in batches
-    println!("📤 Uploading to Qdrant collection: {}", args.collection);
-    let batch_size = 100;
-    let total_batches = points.len().div_ceil(batch_size);
-
-    for (i, batch) in points.chunks(batch_size).enumerate() {
-        print!("  Uploading batch {}/{}...\r", i + 1, total_batches);
-
-        let response = client
-            .put(format!(
-                "{}/collections/{}/points",
-                args.qdrant_url, args.collection
-            ))
-            .json(&json!({
-                "points": batch
-            }))
-            .send()
-            .context("Failed to upload to Qdrant")?;
-
-        if !response.status().is_success() {
-            let error_text = response
-                .text()
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            anyhow::bail!("Qdrant returned error in batch {}: {}", i + 1, error_text);
-        }
+
+    #[test]
+    fn test_split_sentences_at_terminators_and_blank_lines() {
+        let text =
+            "Ownership moves values. Borrowing lends them!\nIs 3.5 a float?\n\n- a list item\n";
+        let sentences: Vec<&str> = split_sentences(text)
+            .into_iter()
+            .map(|r| &text[r])
+            .collect();
+
+        assert_eq!(
+            sentences,
+            vec![
+                "Ownership moves values.",
+                "Borrowing lends them!",
+                "Is 3.5 a float?",
+                "- a list item"
+            ]
+        );
     }
-    println!();
 
-    println!("✅ Successfully ingested Markdown into Qdrant!");
-    println!("📊 Summary:");
-    println!("   Total chunks: {}", chunks.len());
-    println!("   Code blocks preserved: {}", code_chunks);
-    println!("   Source: {}", args.md_path);
+    #[test]
+    fn test_group_sentences_splits_at_topic_shifts_and_size() {
+        // Sentences 0-1 and 3-4 are on one topic each; 2-3 is a size split
+        let similarities = [0.9, 0.2, 0.8, 0.85];
+        let tokens = [10, 10, 30, 30, 5];
 
-    Ok(())
+        let (groups, stats) = group_sentences(&similarities, &tokens, 0.5, 40);
+
+        assert_eq!(groups, vec![0..2, 2..3, 3..5]);
+        assert_eq!(
+            stats,
+            SplitStats {
+                semantic_splits: 1,
+                size_splits: 1
+            }
+        );
+        assert_eq!(
+            group_sentences(&[], &[500], 0.5, 40).0,
+            vec![0..1],
+            "a sentence over the cap stays whole"
+        );
+    }
 }
@@ -3,11 +3,17 @@
 
 use anyhow::{Context, Result};
 use clap::Parser;
-use reqwest::blocking::Client;
-use serde::{Deserialize, Serialize};
+use rag_demo::config::parse_args;
+use rag_demo::rag::{
+    ApiKey, Consistency, DEFAULT_INDEX_FIELDS, Distance, EmbedApi, Embedder, HeadingStack,
+    IdGenerator, IndexFields, OnOversize, OversizePolicy, QdrantClient, QdrantPoint, Quantization,
+    RetryPolicy, TokenCounter, Tokenizer, breadcrumb, detect_lang, limit_chunks,
+    namespaced_collection, preflight,
+};
+use serde::Serialize;
 use serde_json::json;
+use std::collections::HashMap;
 use std::fs;
-use uuid::Uuid;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Multi-scale Markdown ingestion", long_about = None)]
@@ -15,43 +21,183 @@ struct Args {
     #[arg(help = "Path to Markdown file")]
     md_path: String,
 
-    #[arg(long, default_value = "documents", help = "Base collection name")]
+    #[arg(
+        long,
+        default_value = "documents",
+        env = "RAG_COLLECTION",
+        help = "Base collection name"
+    )]
     collection: String,
 
-    #[arg(long, default_value = "http://localhost:6333", help = "Qdrant URL")]
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_PREFIX",
+        help = "Prepended to the collection name, e.g. acme_ to keep one tenant's collections apart"
+    )]
+    collection_prefix: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_SUFFIX",
+        help = "Appended to the collection name"
+    )]
+    collection_suffix: String,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:6333",
+        env = "QDRANT_URL",
+        help = "Qdrant URL"
+    )]
     qdrant_url: String,
 
-    #[arg(long, default_value = "http://localhost:11434", help = "Ollama URL")]
+    #[arg(
+        long,
+        env = "QDRANT_API_KEY",
+        hide_env_values = true,
+        help = "Qdrant API key, sent as the api-key header (Qdrant Cloud)"
+    )]
+    qdrant_api_key: Option<ApiKey>,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:11434",
+        env = "OLLAMA_URL",
+        help = "Ollama URL"
+    )]
     ollama_url: String,
 
     #[arg(
         short,
         long,
         default_value = "nomic-embed-text",
+        env = "RAG_MODEL",
         help = "Embedding model"
     )]
     model: String,
 
     #[arg(long, help = "Enable multi-scale ingestion")]
     multi_scale: bool,
-}
 
-#[derive(Debug, Serialize, Deserialize)]
-struct EmbeddingRequest {
-    model: String,
-    prompt: String,
-}
+    #[arg(
+        long,
+        default_value = "text",
+        help = "Payload field that stores the chunk text"
+    )]
+    text_field: String,
 
-#[derive(Debug, Deserialize)]
-struct EmbeddingResponse {
-    embedding: Vec<f32>,
-}
+    #[arg(
+        long,
+        help = "Human-friendly name stored as the payload source (default: file path)"
+    )]
+    source_label: Option<String>,
+
+    #[arg(
+        long,
+        help = "Upload the _small/_medium/_large collections concurrently"
+    )]
+    parallel_collections: bool,
+
+    #[arg(long, help = "Seed for reproducible point IDs (default: OS entropy)")]
+    seed: Option<u64>,
 
-#[derive(Debug, Serialize)]
-struct QdrantPoint {
-    id: String,
-    vector: Vec<f32>,
-    payload: serde_json::Value,
+    #[arg(
+        long,
+        value_enum,
+        default_value = "ollama",
+        help = "Embedding API: ollama (/api/embeddings), ollama-embed (/api/embed), openai (/v1/embeddings)"
+    )]
+    embed_api: EmbedApi,
+
+    #[arg(
+        long,
+        help = "Skip checking that Qdrant, Ollama and the embedding model are available"
+    )]
+    skip_checks: bool,
+
+    #[arg(
+        long,
+        default_value = "4",
+        help = "Times to retry a failed embedding request (5xx, 429, connection errors)"
+    )]
+    embed_retries: u32,
+
+    #[arg(
+        long,
+        default_value = "500",
+        help = "Delay before the first embedding retry in ms, doubled on each retry"
+    )]
+    embed_retry_delay_ms: u64,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "strong",
+        help = "strong waits for each upsert to be applied, so points are searchable and counted when ingestion ends; eventual returns as soon as Qdrant accepts them"
+    )]
+    consistency: Consistency,
+
+    #[arg(
+        long,
+        help = "Report stored size and redundancy per chunk size, then exit without ingesting"
+    )]
+    report_overlap_stats: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "cl100k",
+        help = "Tokenizer used to measure chunk sizes (chars estimates 4 characters per token)"
+    )]
+    tokenizer: Tokenizer,
+
+    #[arg(
+        long,
+        default_value = "2048",
+        help = "Hard limit in tokens for every chunk, splitting code blocks if needed"
+    )]
+    max_tokens: usize,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "split",
+        help = "Text over --max-tokens at embedding time: split embeds pieces and averages them, truncate keeps the start, error fails"
+    )]
+    on_oversize: OnOversize,
+
+    #[arg(
+        long,
+        help = "Only embed and upload the first N chunks, for quick trial runs on big files"
+    )]
+    max_chunks: Option<usize>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "cosine",
+        help = "Vector distance for newly created collections; existing collections keep theirs"
+    )]
+    distance: Distance,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "none",
+        help = "Quantization for newly created collections: scalar keeps int8 vectors in RAM (~4x less memory, slightly lower recall)"
+    )]
+    quantization: Quantization,
+
+    #[arg(
+        long,
+        default_value = DEFAULT_INDEX_FIELDS,
+        help = "Payload fields to index after ingesting, comma-separated as name or name:schema (keyword, integer, float, bool, text); \"\" indexes none"
+    )]
+    index_fields: IndexFields,
 }
 
 #[derive(Debug, Clone)]
@@ -66,40 +212,99 @@ struct Chunk {
 
 #[derive(Debug, Clone, Serialize)]
 enum ChunkSize {
-    Small,  // ~500-1000 chars
-    Medium, // ~2000-3000 chars
-    Large,  // ~4000-6000 chars
+    Small,  // ~125-250 tokens
+    Medium, // ~500-750 tokens
+    Large,  // ~1000-1500 tokens
+}
+
+/// Chunk count and stored characters for one chunk size
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct SizeStats {
+    chunks: usize,
+    chars: usize,
+}
+
+/// Storage cost of a chunking run compared with the source document
+#[derive(Debug, Default, PartialEq)]
+struct OverlapStats {
+    source_chars: usize,
+    small: SizeStats,
+    medium: SizeStats,
+    large: SizeStats,
+}
+
+impl OverlapStats {
+    fn compute(source: &str, chunks: &[Chunk]) -> Self {
+        let mut stats = OverlapStats {
+            source_chars: source.len(),
+            ..Default::default()
+        };
+        for chunk in chunks {
+            let size = match chunk.chunk_size {
+                ChunkSize::Small => &mut stats.small,
+                ChunkSize::Medium => &mut stats.medium,
+                ChunkSize::Large => &mut stats.large,
+            };
+            size.chunks += 1;
+            size.chars += chunk.content.len();
+        }
+        stats
+    }
+
+    fn stored_chars(&self) -> usize {
+        self.small.chars + self.medium.chars + self.large.chars
+    }
+
+    /// Stored characters per source character (1.0 means no duplication)
+    fn redundancy_factor(&self) -> f64 {
+        if self.source_chars == 0 {
+            return 0.0;
+        }
+        self.stored_chars() as f64 / self.source_chars as f64
+    }
+
+    fn print(&self) {
+        println!("📊 Overlap statistics:");
+        println!("   Source document: {} chars", self.source_chars);
+        for (name, size) in [
+            ("Small", self.small),
+            ("Medium", self.medium),
+            ("Large", self.large),
+        ] {
+            if size.chunks > 0 {
+                println!(
+                    "   {name}: {} chunks, {} chars stored",
+                    size.chunks, size.chars
+                );
+            }
+        }
+        println!("   Total stored: {} chars", self.stored_chars());
+        println!("   Redundancy factor: {:.2}x", self.redundancy_factor());
+    }
 }
 
-fn create_multi_scale_chunks(content: &str) -> Vec<Chunk> {
+fn create_multi_scale_chunks(content: &str, counter: TokenCounter) -> Vec<Chunk> {
     let mut chunks = Vec::new();
     let lines: Vec<&str> = content.lines().collect();
 
     // Chunk sizes with overlap
     let configs = vec![
-        (ChunkSize::Small, 1000, 200),  // 1000 chars, 200 overlap
-        (ChunkSize::Medium, 3000, 500), // 3000 chars, 500 overlap
-        (ChunkSize::Large, 6000, 1000), // 6000 chars, 1000 overlap
+        (ChunkSize::Small, 250, 50),   // 250 tokens, 50 overlap
+        (ChunkSize::Medium, 750, 125), // 750 tokens, 125 overlap
+        (ChunkSize::Large, 1500, 250), // 1500 tokens, 250 overlap
     ];
 
     for (size_type, target_size, overlap) in configs {
         let mut current_chunk = String::new();
         let mut start_line = 0;
-        let mut current_headers = Vec::new();
+        let mut headings = HeadingStack::default();
         let mut has_code = false;
         let mut in_code_block = false;
 
         for (i, line) in lines.iter().enumerate() {
             // Track headers for context
-            if line.starts_with('#') && !in_code_block {
-                let level = line.chars().take_while(|c| *c == '#').count();
-                if level <= 2 {
-                    current_headers.clear();
-                    current_headers.push(line.to_string());
-                } else if level == 3 {
-                    current_headers.truncate(1);
-                    current_headers.push(line.to_string());
-                }
+            if !in_code_block {
+                headings.push_line(line);
             }
 
             // Track code blocks
@@ -113,16 +318,14 @@ fn create_multi_scale_chunks(content: &str) -> Vec<Chunk> {
             current_chunk.push_str(line);
             current_chunk.push('\n');
 
-            // Check if we should create a chunk
-            let should_chunk = current_chunk.len() >= target_size && {
-                // Try to break at natural boundaries
-                !in_code_block
-                    && (
-                        line.trim().is_empty() ||          // Paragraph break
+            // Check if we should create a chunk at a natural boundary (tokens are only counted there)
+            let should_chunk = !in_code_block
+                && (
+                    line.trim().is_empty() ||          // Paragraph break
                     lines.get(i + 1).is_none_or(|next| next.starts_with('#'))
-                        // Before header
-                    )
-            };
+                    // Before header
+                )
+                && counter.count(&current_chunk) >= target_size;
 
             if should_chunk {
                 chunks.push(Chunk {
@@ -131,12 +334,11 @@ fn create_multi_scale_chunks(content: &str) -> Vec<Chunk> {
                     end_line: i,
                     chunk_size: size_type.clone(),
                     has_code,
-                    headers: current_headers.clone(),
+                    headers: headings.lines(),
                 });
 
-                // Overlap: go back some characters
-                let overlap_start = current_chunk.len().saturating_sub(overlap);
-                current_chunk = current_chunk[overlap_start..].to_string();
+                // Overlap: carry the last few tokens into the next chunk
+                current_chunk = counter.tail(&current_chunk, overlap).to_string();
                 start_line = i.saturating_sub(10); // Rough line overlap
                 has_code = in_code_block;
             }
@@ -150,7 +352,7 @@ fn create_multi_scale_chunks(content: &str) -> Vec<Chunk> {
                 end_line: lines.len() - 1,
                 chunk_size: size_type,
                 has_code,
-                headers: current_headers,
+                headers: headings.lines(),
             });
         }
     }
@@ -158,12 +360,12 @@ fn create_multi_scale_chunks(content: &str) -> Vec<Chunk> {
     chunks
 }
 
-fn semantic_chunk_markdown(content: &str, target_size: usize) -> Vec<Chunk> {
+fn semantic_chunk_markdown(content: &str, target_size: usize, counter: TokenCounter) -> Vec<Chunk> {
     let mut chunks = Vec::new();
     let lines: Vec<&str> = content.lines().collect();
     let mut current_chunk = String::new();
     let mut start_line = 0;
-    let mut current_headers = Vec::new();
+    let mut headings = HeadingStack::default();
     let mut in_code_block = false;
     let mut has_code = false;
     let mut code_block_buffer = String::new();
@@ -174,42 +376,35 @@ fn semantic_chunk_markdown(content: &str, target_size: usize) -> Vec<Chunk> {
             let level = line.chars().take_while(|c| *c == '#').count();
 
             // Major section boundary - save current chunk if substantial
-            if level <= 2 && current_chunk.len() > 500 {
+            if level <= 2 && counter.count(&current_chunk) > 125 {
                 chunks.push(Chunk {
                     content: current_chunk.clone(),
                     start_line,
                     end_line: i - 1,
                     chunk_size: ChunkSize::Medium,
                     has_code,
-                    headers: current_headers.clone(),
+                    headers: headings.lines(),
                 });
                 current_chunk.clear();
                 start_line = i;
                 has_code = false;
             }
 
-            // Update header context
-            if level <= 2 {
-                current_headers.clear();
-                current_headers.push(line.to_string());
-            } else if level == 3 {
-                current_headers.truncate(1);
-                current_headers.push(line.to_string());
-            }
+            headings.push_line(line);
         }
 
         // Handle code blocks
         if line.trim().starts_with("```") {
             if !in_code_block {
                 // Starting code block - save any pending content first
-                if !current_chunk.is_empty() && current_chunk.len() > 300 {
+                if !current_chunk.is_empty() && counter.count(&current_chunk) > 75 {
                     chunks.push(Chunk {
                         content: current_chunk.clone(),
                         start_line,
                         end_line: i - 1,
                         chunk_size: ChunkSize::Medium,
                         has_code: false,
-                        headers: current_headers.clone(),
+                        headers: headings.lines(),
                     });
                     current_chunk.clear();
                     start_line = i;
@@ -239,7 +434,7 @@ fn semantic_chunk_markdown(content: &str, target_size: usize) -> Vec<Chunk> {
         }
 
         // Check if we should create a chunk (but not in middle of code)
-        if !in_code_block && current_chunk.len() >= target_size {
+        if !in_code_block && counter.count(&current_chunk) >= target_size {
             // Look for good break point
             if line.trim().is_empty() || lines.get(i + 1).is_none_or(|next| next.starts_with('#')) {
                 chunks.push(Chunk {
@@ -248,7 +443,7 @@ fn semantic_chunk_markdown(content: &str, target_size: usize) -> Vec<Chunk> {
                     end_line: i,
                     chunk_size: ChunkSize::Medium,
                     has_code,
-                    headers: current_headers.clone(),
+                    headers: headings.lines(),
                 });
                 current_chunk.clear();
                 start_line = i + 1;
@@ -266,52 +461,185 @@ fn semantic_chunk_markdown(content: &str, target_size: usize) -> Vec<Chunk> {
             end_line: lines.len() - 1,
             chunk_size: ChunkSize::Medium,
             has_code,
-            headers: current_headers,
+            headers: headings.lines(),
         });
     }
 
     chunks
 }
 
-fn get_embedding(client: &Client, ollama_url: &str, model: &str, text: &str) -> Result<Vec<f32>> {
-    let request = EmbeddingRequest {
-        model: model.to_string(),
-        prompt: text.to_string(),
-    };
+/// Split chunks over `max_tokens` (typically long code blocks) into several chunks
+fn enforce_max_tokens(chunks: Vec<Chunk>, max_tokens: usize, counter: TokenCounter) -> Vec<Chunk> {
+    chunks
+        .into_iter()
+        .flat_map(|chunk| {
+            counter
+                .split(&chunk.content, max_tokens)
+                .into_iter()
+                .map(move |piece| Chunk {
+                    content: piece,
+                    ..chunk.clone()
+                })
+        })
+        .collect()
+}
 
-    let response = client
-        .post(format!("{}/api/embeddings", ollama_url))
-        .json(&request)
-        .send()
-        .context("Failed to get embedding from Ollama")?;
+/// Create the collection if needed and upload its points in batches.
+/// The `\r` batch counter is only shown when `show_progress` is set, since
+/// concurrent uploads would overwrite each other's progress line.
+fn upload_collection(
+    qdrant: &QdrantClient,
+    collection_name: &str,
+    points: &[QdrantPoint],
+    (distance, quantization): (Distance, Quantization),
+    show_progress: bool,
+) -> Result<()> {
+    // Ensure collection exists
+    if qdrant.collection_exists(collection_name) {
+        qdrant.warn_existing_settings(collection_name, distance, quantization);
+    } else {
+        let mut config = json!({
+            "vectors": {
+                "size": 768,
+                "distance": distance.qdrant_name()
+            }
+        });
+        quantization.apply(&mut config);
+        let _ = qdrant.create_collection(collection_name, &config);
+    }
 
-    if !response.status().is_success() {
-        anyhow::bail!("Ollama returned error: {}", response.status());
+    // Upload in batches
+    let batch_size = 100;
+    for (i, batch) in points.chunks(batch_size).enumerate() {
+        if show_progress {
+            print!(
+                "  Uploading batch {}/{}...\r",
+                i + 1,
+                points.len().div_ceil(batch_size)
+            );
+        }
+
+        qdrant.upsert_points(collection_name, batch)?;
+    }
+    if show_progress {
+        println!();
     }
 
-    let embedding: EmbeddingResponse = response
-        .json()
-        .context("Failed to parse embedding response")?;
+    Ok(())
+}
 
-    Ok(embedding.embedding)
+/// Run `upload` for every collection, either one after another or all at once.
+/// In parallel mode every collection is attempted and all failures are reported together.
+fn upload_collections<F>(
+    collections: &HashMap<String, Vec<QdrantPoint>>,
+    parallel: bool,
+    upload: F,
+) -> Result<()>
+where
+    F: Fn(&str, &[QdrantPoint]) -> Result<()> + Sync,
+{
+    if !parallel {
+        for (collection_name, points) in collections {
+            upload(collection_name, points)?;
+        }
+        return Ok(());
+    }
+
+    let upload = &upload;
+    let results: Vec<(&String, Result<()>)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = collections
+            .iter()
+            .map(|(name, points)| (name, scope.spawn(move || upload(name, points))))
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|(name, handle)| {
+                let result = handle
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow::anyhow!("upload thread panicked")));
+                (name, result)
+            })
+            .collect()
+    });
+
+    let failures: Vec<String> = results
+        .into_iter()
+        .filter_map(|(name, result)| result.err().map(|e| format!("   {}: {:#}", name, e)))
+        .collect();
+
+    if !failures.is_empty() {
+        anyhow::bail!(
+            "Upload failed for {} collection(s):\n{}",
+            failures.len(),
+            failures.join("\n")
+        );
+    }
+
+    Ok(())
 }
 
 fn main() -> Result<()> {
-    let args = Args::parse();
-    let client = Client::new();
+    let mut args: Args = parse_args()?;
+    args.collection = namespaced_collection(
+        &args.collection_prefix,
+        &args.collection,
+        &args.collection_suffix,
+    );
+    let counter = TokenCounter::load(args.tokenizer);
+    if !args.skip_checks {
+        preflight(
+            &args.qdrant_url,
+            &args.ollama_url,
+            &args.model,
+            args.embed_api,
+        )?;
+    }
+    let embedder = Embedder::new(
+        &args.ollama_url,
+        args.embed_api,
+        &args.model,
+        RetryPolicy {
+            retries: args.embed_retries,
+            base_delay_ms: args.embed_retry_delay_ms,
+        },
+    )
+    .oversize(OversizePolicy {
+        on_oversize: args.on_oversize,
+        max_tokens: args.max_tokens,
+        counter,
+    });
+    let qdrant = QdrantClient::new(&args.qdrant_url)
+        .api_key(args.qdrant_api_key.as_ref())
+        .consistency(args.consistency)
+        .ingest_model(&args.model);
+    let mut ids = IdGenerator::new(args.seed);
+    let source = args.source_label.as_deref().unwrap_or(&args.md_path);
 
     // Read markdown file
     println!("📄 Reading Markdown: {}", args.md_path);
     let content = fs::read_to_string(&args.md_path).context("Failed to read Markdown file")?;
 
     // Create chunks
+    if args.max_tokens == 0 {
+        anyhow::bail!("--max-tokens must be greater than zero");
+    }
     let chunks = if args.multi_scale {
         println!("🎯 Multi-scale chunking...");
-        create_multi_scale_chunks(&content)
+        create_multi_scale_chunks(&content, counter)
     } else {
-        println!("📝 Semantic chunking (target ~3000 chars)...");
-        semantic_chunk_markdown(&content, 3000)
+        println!("📝 Semantic chunking (target ~750 tokens)...");
+        semantic_chunk_markdown(&content, 750, counter)
     };
+    let mut chunks = enforce_max_tokens(chunks, args.max_tokens, counter);
+    limit_chunks(&mut chunks, args.max_chunks)?;
+    if chunks.is_empty() {
+        eprintln!(
+            "⚠️  No chunks produced from {}; is the file empty? Nothing to ingest",
+            args.md_path
+        );
+        return Ok(());
+    }
 
     // Show statistics
     let small = chunks
@@ -340,6 +668,11 @@ fn main() -> Result<()> {
         chunks.iter().map(|c| c.content.len()).sum::<usize>() / chunks.len()
     );
 
+    if args.report_overlap_stats {
+        OverlapStats::compute(&content, &chunks).print();
+        return Ok(());
+    }
+
     // Generate embeddings and prepare points
     println!("🧮 Generating embeddings with model: {}", args.model);
     let mut points = Vec::new();
@@ -354,7 +687,7 @@ fn main() -> Result<()> {
             chunk.content.clone()
         };
 
-        let embedding = get_embedding(&client, &args.ollama_url, &args.model, &embedding_text)?;
+        let embedding = embedder.embed(&embedding_text)?;
 
         // Determine collection based on chunk size
         let collection_name = if args.multi_scale {
@@ -367,21 +700,28 @@ fn main() -> Result<()> {
             args.collection.clone()
         };
 
+        let mut payload = json!({
+            "source": source,
+            "source_path": args.md_path,
+            "chunk_index": i,
+            "total_chunks": chunks.len(),
+            "chunk_size": chunk.chunk_size,
+            "lang": detect_lang(&chunk.content),
+            "has_code": chunk.has_code,
+            "headers": chunk.headers,
+            "breadcrumb": breadcrumb(&chunk.headers),
+            "start_line": chunk.start_line,
+            "end_line": chunk.end_line,
+            "char_count": chunk.content.len(),
+        });
+        payload[args.text_field.as_str()] = json!(chunk.content);
+
         let point = QdrantPoint {
-            id: Uuid::new_v4().to_string(),
+            id: ids.next_id(),
             vector: embedding,
-            payload: json!({
-                "text": chunk.content,
-                "source": args.md_path,
-                "chunk_index": i,
-                "total_chunks": chunks.len(),
-                "chunk_size": chunk.chunk_size,
-                "has_code": chunk.has_code,
-                "headers": chunk.headers,
-                "start_line": chunk.start_line,
-                "end_line": chunk.end_line,
-                "char_count": chunk.content.len(),
-            }),
+            sparse: None,
+            summary_vector: None,
+            payload,
         };
 
         points.push((collection_name, point));
@@ -389,69 +729,193 @@ fn main() -> Result<()> {
     println!("\n✅ Generated embeddings for all chunks");
 
     // Group points by collection
-    let mut collections: std::collections::HashMap<String, Vec<QdrantPoint>> =
-        std::collections::HashMap::new();
+    let mut collections: HashMap<String, Vec<QdrantPoint>> = HashMap::new();
     for (collection, point) in points {
         collections.entry(collection).or_default().push(point);
     }
 
     // Upload to Qdrant
-    for (collection_name, points) in collections {
+    if args.parallel_collections {
+        println!("⚡ Uploading {} collections in parallel", collections.len());
+    }
+    upload_collections(
+        &collections,
+        args.parallel_collections,
+        |collection_name, points| {
+            println!(
+                "📤 Uploading {} chunks to collection: {}",
+                points.len(),
+                collection_name
+            );
+            upload_collection(
+                &qdrant,
+                collection_name,
+                points,
+                (args.distance, args.quantization),
+                !args.parallel_collections,
+            )?;
+            if let Err(e) = qdrant.create_payload_indexes(collection_name, &args.index_fields) {
+                eprintln!("⚠️  Could not index payload fields: {e:#}");
+            }
+            if args.parallel_collections {
+                println!(
+                    "   ✅ {}: {} points uploaded",
+                    collection_name,
+                    points.len()
+                );
+            }
+            Ok(())
+        },
+    )?;
+
+    println!("✅ Successfully ingested Markdown into Qdrant!");
+    if args.multi_scale {
+        println!("📊 Created 3 collections with different chunk sizes");
         println!(
-            "📤 Uploading {} chunks to collection: {}",
-            points.len(),
-            collection_name
+            "   Search all three with: search-multiscale --base-collection {} \"<query>\"",
+            args.collection
         );
+    }
 
-        // Ensure collection exists
-        let _ = client
-            .put(format!(
-                "{}/collections/{}",
-                args.qdrant_url, collection_name
-            ))
-            .json(&json!({
-                "vectors": {
-                    "size": 768,
-                    "distance": "Cosine"
-                }
-            }))
-            .send();
+    Ok(())
+}
 
-        // Upload in batches
-        let batch_size = 100;
-        for (i, batch) in points.chunks(batch_size).enumerate() {
-            print!(
-                "  Uploading batch {}/{}...\r",
-                i + 1,
-                points.len().div_ceil(batch_size)
-            );
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn points(n: usize) -> Vec<QdrantPoint> {
+        (0..n)
+            .map(|i| QdrantPoint {
+                id: format!("point-{i}"),
+                vector: vec![0.0; 4],
+                sparse: None,
+                summary_vector: None,
+                payload: json!({}),
+            })
+            .collect()
+    }
+
+    fn chunk(content: &str, chunk_size: ChunkSize) -> Chunk {
+        Chunk {
+            content: content.to_string(),
+            start_line: 0,
+            end_line: 0,
+            chunk_size,
+            has_code: false,
+            headers: Vec::new(),
+        }
+    }
 
-            let response = client
-                .put(format!(
-                    "{}/collections/{}/points",
-                    args.qdrant_url, collection_name
-                ))
-                .json(&json!({
-                    "points": batch
-                }))
-                .send()
-                .context("Failed to upload to Qdrant")?;
-
-            if !response.status().is_success() {
-                let error_text = response
-                    .text()
-                    .unwrap_or_else(|_| "Unknown error".to_string());
-                anyhow::bail!("Qdrant returned error: {}", error_text);
+    #[test]
+    fn test_overlap_stats_redundancy_factor() {
+        let source = "abcdefghij";
+        let chunks = vec![
+            chunk("abcdef", ChunkSize::Small),
+            chunk("efghij", ChunkSize::Small),
+            chunk("abcdefghij", ChunkSize::Medium),
+        ];
+
+        let stats = OverlapStats::compute(source, &chunks);
+
+        assert_eq!(stats.source_chars, 10);
+        assert_eq!(
+            stats.small,
+            SizeStats {
+                chunks: 2,
+                chars: 12
             }
+        );
+        assert_eq!(stats.medium.chunks, 1);
+        assert_eq!(stats.large, SizeStats::default());
+        assert_eq!(stats.stored_chars(), 22);
+        assert!((stats.redundancy_factor() - 2.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_empty_document_produces_no_chunks() {
+        for content in ["", "  \n\n\t\n"] {
+            assert!(create_multi_scale_chunks(content, TokenCounter::estimate()).is_empty());
+            assert!(semantic_chunk_markdown(content, 750, TokenCounter::estimate()).is_empty());
         }
-        println!();
     }
 
-    println!("✅ Successfully ingested Markdown into Qdrant!");
-    if args.multi_scale {
-        println!("📊 Created 3 collections with different chunk sizes");
-        println!("   Use hybrid search across all three for best results");
+    #[test]
+    fn test_multi_scale_stores_document_more_than_once() {
+        let content = "# Title\n\n".to_string() + &"Body text paragraph.\n\n".repeat(500);
+
+        let stats = OverlapStats::compute(
+            &content,
+            &create_multi_scale_chunks(&content, TokenCounter::estimate()),
+        );
+
+        assert!(stats.small.chunks > stats.large.chunks);
+        assert!(stats.redundancy_factor() > 3.0);
     }
 
-    Ok(())
+    #[test]
+    fn test_chunks_stay_under_max_tokens() {
+        let counter = TokenCounter::load(Tokenizer::Cl100k);
+        let code = "let crab = \"🦀\".repeat(n);\n".repeat(400);
+        let content = format!("# Crabs 🦀\n\nWhy crabs?\n\n```rust\n{code}```\n");
+
+        let chunks = enforce_max_tokens(create_multi_scale_chunks(&content, counter), 300, counter);
+
+        assert!(chunks.iter().all(|c| counter.count(&c.content) <= 300));
+        assert!(
+            chunks
+                .iter()
+                .any(|c| matches!(c.chunk_size, ChunkSize::Large))
+        );
+    }
+
+    #[test]
+    fn test_parallel_upload_reaches_every_collection() {
+        let collections: HashMap<String, Vec<QdrantPoint>> = [
+            ("docs_small".to_string(), points(5)),
+            ("docs_medium".to_string(), points(3)),
+            ("docs_large".to_string(), points(1)),
+        ]
+        .into_iter()
+        .collect();
+
+        let received = Mutex::new(HashMap::new());
+        upload_collections(&collections, true, |name, points| {
+            received
+                .lock()
+                .unwrap()
+                .insert(name.to_string(), points.len());
+            Ok(())
+        })
+        .unwrap();
+
+        let received = received.into_inner().unwrap();
+        assert_eq!(received.len(), 3);
+        assert_eq!(received["docs_small"], 5);
+        assert_eq!(received["docs_medium"], 3);
+        assert_eq!(received["docs_large"], 1);
+    }
+
+    #[test]
+    fn test_parallel_upload_reports_failed_collection() {
+        let collections: HashMap<String, Vec<QdrantPoint>> = [
+            ("docs_small".to_string(), points(1)),
+            ("docs_large".to_string(), points(1)),
+        ]
+        .into_iter()
+        .collect();
+
+        let err = upload_collections(&collections, true, |name, _| {
+            if name == "docs_large" {
+                anyhow::bail!("connection reset");
+            }
+            Ok(())
+        })
+        .unwrap_err();
+
+        let message = err.to_string();
+        assert!(message.contains("docs_large"));
+        assert!(!message.contains("docs_small"));
+    }
 }
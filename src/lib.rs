@@ -1,5 +1,8 @@
 // Copyright (c) 2025 Michael A. Wright
 // Licensed under the MIT License
 
-// Shared library code for RAG demo
-// Currently empty as binaries are self-contained
+//! Shared library code for the RAG demo binaries.
+
+pub mod config;
+pub mod rag;
+pub mod vecmath;
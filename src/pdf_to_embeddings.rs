@@ -3,167 +3,895 @@
 
 use anyhow::{Context, Result};
 use clap::Parser;
-use pdf_extract::extract_text;
-use reqwest::blocking::Client;
+use pdf_extract::{extract_text, extract_text_by_pages};
+use rag_demo::config::parse_args;
+use rag_demo::rag::{
+    ApiKey, ChunkPlan, Consistency, EmbedApi, Embedder, IdGenerator, OnOversize, OversizePolicy,
+    QdrantClient, QdrantPoint, RetryPolicy, TokenCounter, Tokenizer, detect_lang, limit_chunks,
+    namespaced_collection, preflight, print_chunk_plan, progress_bar,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::path::Path;
-use uuid::Uuid;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser, Debug)]
-#[command(author, version, about = "Extract text from PDF and store in Qdrant", long_about = None)]
+#[command(author, version, about = "Extract text from a PDF (or read a .txt file) and store it in Qdrant", long_about = None)]
 struct Args {
-    #[arg(help = "Path to PDF file")]
+    #[arg(help = "Path to PDF or plain .txt file")]
     pdf_path: String,
 
-    #[arg(short, long, default_value = "1000", help = "Characters per chunk")]
+    #[arg(short, long, default_value = "250", help = "Tokens per chunk")]
     chunk_size: usize,
 
-    #[arg(short, long, default_value = "200", help = "Overlap between chunks")]
+    #[arg(
+        short,
+        long,
+        default_value = "50",
+        help = "Tokens from the end of a chunk repeated at the start of the next one"
+    )]
     overlap: usize,
 
-    #[arg(long, default_value = "documents", help = "Qdrant collection name")]
+    #[arg(
+        long,
+        help = "Cut chunks at exactly chunk-size tokens instead of at the nearest sentence end"
+    )]
+    raw_chunks: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "cl100k",
+        help = "Tokenizer used to measure chunk sizes (chars estimates 4 characters per token)"
+    )]
+    tokenizer: Tokenizer,
+
+    #[arg(
+        long,
+        help = "Extract the PDF as one text without page boundaries and store no page numbers"
+    )]
+    flatten: bool,
+
+    #[arg(
+        long,
+        default_value = "documents",
+        env = "RAG_COLLECTION",
+        help = "Qdrant collection name"
+    )]
     collection: String,
 
-    #[arg(long, default_value = "http://localhost:6333", help = "Qdrant URL")]
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_PREFIX",
+        help = "Prepended to the collection name, e.g. acme_ to keep one tenant's collections apart"
+    )]
+    collection_prefix: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_SUFFIX",
+        help = "Appended to the collection name"
+    )]
+    collection_suffix: String,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:6333",
+        env = "QDRANT_URL",
+        help = "Qdrant URL"
+    )]
     qdrant_url: String,
 
-    #[arg(long, default_value = "http://localhost:11434", help = "Ollama URL")]
+    #[arg(
+        long,
+        env = "QDRANT_API_KEY",
+        hide_env_values = true,
+        help = "Qdrant API key, sent as the api-key header (Qdrant Cloud)"
+    )]
+    qdrant_api_key: Option<ApiKey>,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:11434",
+        env = "OLLAMA_URL",
+        help = "Ollama URL"
+    )]
     ollama_url: String,
 
     #[arg(
         short,
         long,
         default_value = "nomic-embed-text",
+        env = "RAG_MODEL",
         help = "Embedding model"
     )]
     model: String,
+
+    #[arg(
+        long,
+        default_value = "text",
+        help = "Payload field that stores the chunk text"
+    )]
+    text_field: String,
+
+    #[arg(
+        long,
+        help = "Human-friendly name stored as the payload source (default: file path)"
+    )]
+    source_label: Option<String>,
+
+    #[arg(long, help = "Seed for reproducible point IDs (default: OS entropy)")]
+    seed: Option<u64>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "ollama",
+        help = "Embedding API: ollama (/api/embeddings), ollama-embed (/api/embed), openai (/v1/embeddings)"
+    )]
+    embed_api: EmbedApi,
+
+    #[arg(
+        long,
+        help = "Skip checking that Qdrant, Ollama and the embedding model are available"
+    )]
+    skip_checks: bool,
+
+    #[arg(
+        long,
+        default_value = "4",
+        help = "Times to retry a failed embedding request (5xx, 429, connection errors)"
+    )]
+    embed_retries: u32,
+
+    #[arg(
+        long,
+        default_value = "500",
+        help = "Delay before the first embedding retry in ms, doubled on each retry"
+    )]
+    embed_retry_delay_ms: u64,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "strong",
+        help = "strong waits for each upsert to be applied, so points are searchable and counted when ingestion ends; eventual returns as soon as Qdrant accepts them"
+    )]
+    consistency: Consistency,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "split",
+        help = "Text over the embedding token limit: split embeds pieces and averages them, truncate keeps the start, error fails"
+    )]
+    on_oversize: OnOversize,
+
+    #[arg(
+        long,
+        default_value = "2048",
+        help = "Embedding model context in tokens, measured with --tokenizer"
+    )]
+    embed_max_tokens: usize,
+
+    #[arg(
+        long,
+        help = "Ignore an existing checkpoint and ingest every chunk again"
+    )]
+    restart: bool,
+
+    #[arg(
+        long,
+        default_value = "32",
+        help = "Chunks embedded per request to Ollama's /api/embed"
+    )]
+    embed_batch_size: usize,
+
+    #[arg(
+        long,
+        help = "Embed one chunk per request with --embed-api instead of batching through /api/embed"
+    )]
+    legacy_embed: bool,
+
+    #[arg(
+        long,
+        help = "Print each chunk's size, then exit without embedding or uploading"
+    )]
+    dry_run: bool,
+
+    #[arg(long, help = "Like --dry-run, but print the chunks as JSON")]
+    dry_run_json: bool,
+
+    #[arg(
+        long,
+        help = "Only embed and upload the first N chunks, for quick trial runs on big files"
+    )]
+    max_chunks: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Hide progress bars (also hidden when stdout is not a terminal)"
+    )]
+    quiet: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct EmbeddingRequest {
-    model: String,
-    prompt: String,
+/// Chunks embedded and uploaded per batch; the checkpoint is written after each
+const UPLOAD_BATCH_SIZE: usize = 100;
+
+/// Progress of an interrupted ingest, saved next to the source file
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Checkpoint {
+    collection: String,
+    total_chunks: usize,
+    /// Hash of every chunk's text, so a changed file or chunk settings start over
+    chunks_sha256: String,
+    last_completed_chunk: usize,
 }
 
-#[derive(Debug, Deserialize)]
-struct EmbeddingResponse {
-    embedding: Vec<f32>,
+impl Checkpoint {
+    fn path(source: &str) -> PathBuf {
+        PathBuf::from(format!("{source}.ingest-checkpoint"))
+    }
+
+    fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read checkpoint {}", path.display()))?;
+        let checkpoint = serde_json::from_str(&json).with_context(|| {
+            format!(
+                "Failed to parse checkpoint {}; pass --restart to ignore it",
+                path.display()
+            )
+        })?;
+        Ok(Some(checkpoint))
+    }
+
+    /// Write through a temporary file so a crash never leaves it half written
+    fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("ingest-checkpoint.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write checkpoint {}", tmp.display()))?;
+        fs::rename(&tmp, path)
+            .with_context(|| format!("Failed to write checkpoint {}", path.display()))
+    }
+
+    /// Index of the first chunk still to ingest, or 0 unless this checkpoint
+    /// was written for the same chunks going into the same collection
+    fn resume_index(&self, collection: &str, chunks: &[String], chunks_sha256: &str) -> usize {
+        if self.collection == collection
+            && self.total_chunks == chunks.len()
+            && self.chunks_sha256 == chunks_sha256
+        {
+            (self.last_completed_chunk + 1).min(chunks.len())
+        } else {
+            0
+        }
+    }
 }
 
-#[derive(Debug, Serialize)]
-struct QdrantPoint {
-    id: String,
-    vector: Vec<f32>,
-    payload: serde_json::Value,
+/// ID generator positioned at chunk `start`: the IDs of the chunks already
+/// uploaded are drawn first, so a resumed `--seed` run gives every chunk the
+/// same ID as an uninterrupted one instead of overwriting earlier chunks
+fn resumed_ids(seed: Option<u64>, start: usize) -> IdGenerator {
+    let mut ids = IdGenerator::new(seed);
+    for _ in 0..start {
+        ids.next_id();
+    }
+    ids
 }
 
-fn chunk_text(text: &str, chunk_size: usize, overlap: usize) -> Vec<String> {
+fn chunks_sha256(chunks: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    for chunk in chunks {
+        hasher.update(chunk.as_bytes());
+        hasher.update([0]);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Chunk and overlap sizes in tokens, with the counter that measures them
+#[derive(Clone, Copy)]
+struct ChunkSizes {
+    chunk: usize,
+    overlap: usize,
+    counter: TokenCounter,
+}
+
+impl ChunkSizes {
+    fn tokens(&self, chars: &[char]) -> usize {
+        self.counter.count(&chars.iter().collect::<String>())
+    }
+
+    /// End of the longest run of characters from `start` within `chunk`
+    /// tokens; at least one character, so a chunk always moves forward
+    fn cutoff(&self, chars: &[char], start: usize) -> usize {
+        let fits = |end: usize| self.tokens(&chars[start..end]) <= self.chunk;
+        // Double the range until it is over the limit, then bisect it
+        let mut hi = (start + self.chunk).min(chars.len());
+        while hi < chars.len() && fits(hi) {
+            hi = (start + (hi - start) * 2).min(chars.len());
+        }
+        if fits(hi) {
+            return hi;
+        }
+        let mut lo = start + 1;
+        while lo + 1 < hi {
+            let mid = (lo + hi) / 2;
+            if fits(mid) {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        lo
+    }
+
+    /// Start of the longest tail of `from..end` within `overlap` tokens
+    fn overlap_start(&self, chars: &[char], from: usize, end: usize) -> usize {
+        let fits = |start: usize| self.tokens(&chars[start..end]) <= self.overlap;
+        if fits(from) {
+            return from;
+        }
+        let (mut lo, mut hi) = (from, end);
+        while lo + 1 < hi {
+            let mid = (lo + hi) / 2;
+            if fits(mid) {
+                hi = mid;
+            } else {
+                lo = mid;
+            }
+        }
+        hi
+    }
+}
+
+/// Character ranges of `sizes.chunk`-token chunks, each overlapping the last
+/// by up to `sizes.overlap` tokens
+fn chunk_text(chars: &[char], sizes: ChunkSizes) -> Vec<Range<usize>> {
     let mut chunks = Vec::new();
-    let chars: Vec<char> = text.chars().collect();
     let mut start = 0;
 
     while start < chars.len() {
-        let end = std::cmp::min(start + chunk_size, chars.len());
-        let chunk: String = chars[start..end].iter().collect();
-        chunks.push(chunk);
+        let end = sizes.cutoff(chars, start);
+        chunks.push(start..end);
 
         if end >= chars.len() {
             break;
         }
 
-        start += chunk_size - overlap;
+        start = sizes.overlap_start(chars, start + 1, end);
     }
 
     chunks
 }
 
-fn get_embedding(client: &Client, ollama_url: &str, model: &str, text: &str) -> Result<Vec<f32>> {
-    let request = EmbeddingRequest {
-        model: model.to_string(),
-        prompt: text.to_string(),
+/// Fraction of a chunk, counted back from the cutoff, searched for a sentence end
+const SENTENCE_WINDOW_DIVISOR: usize = 4;
+
+/// `.`, `!` or `?` followed by whitespace or the end of the text
+fn is_sentence_end(chars: &[char], i: usize) -> bool {
+    matches!(chars[i], '.' | '!' | '?') && chars.get(i + 1).is_none_or(|c| c.is_whitespace())
+}
+
+/// First character of a sentence that follows a sentence end
+fn starts_sentence(chars: &[char], i: usize) -> bool {
+    !chars[i].is_whitespace()
+        && chars[..i]
+            .iter()
+            .rposition(|c| !c.is_whitespace())
+            .is_some_and(|p| is_sentence_end(chars, p))
+}
+
+/// Like `chunk_text`, but ends each chunk at the last sentence end within the
+/// window before the cutoff, and overlaps by whole trailing sentences that fit
+/// in `sizes.overlap` tokens. Without a sentence end in the window it cuts hard.
+fn chunk_sentences(chars: &[char], sizes: ChunkSizes) -> Vec<Range<usize>> {
+    let mut chunks = Vec::new();
+    let skip_whitespace = |mut i: usize| {
+        while i < chars.len() && chars[i].is_whitespace() {
+            i += 1;
+        }
+        i
     };
+    let mut start = skip_whitespace(0);
+
+    while start < chars.len() {
+        let cutoff = sizes.cutoff(chars, start);
+        if cutoff == chars.len() {
+            chunks.push(start..chars.len());
+            break;
+        }
+
+        let window = ((cutoff - start) / SENTENCE_WINDOW_DIVISOR).max(1);
+        let floor = cutoff.saturating_sub(window).max(start + 1);
+        let next = match (floor..=cutoff)
+            .rev()
+            .find(|&end| is_sentence_end(chars, end - 1))
+        {
+            Some(end) => {
+                chunks.push(start..end);
+                (sizes.overlap_start(chars, start + 1, end)..end)
+                    .find(|&i| starts_sentence(chars, i))
+                    .unwrap_or(end)
+            }
+            None => {
+                chunks.push(start..cutoff);
+                sizes.overlap_start(chars, start + 1, cutoff)
+            }
+        };
+        start = skip_whitespace(next);
+    }
+
+    chunks
+}
+
+fn is_text_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("txt"))
+}
+
+/// Text of a PDF page by page, or a plain text file (or a `--flatten`ed PDF)
+/// as a single page
+fn read_source_pages(path: &Path, flatten: bool) -> Result<Vec<String>> {
+    if is_text_file(path) {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        return Ok(vec![text]);
+    }
+    if flatten {
+        return Ok(vec![
+            extract_text(path).context("Failed to extract text from PDF")?,
+        ]);
+    }
+    extract_text_by_pages(path).context("Failed to extract text from PDF")
+}
+
+/// Pages joined into one text to chunk, remembering where each page starts
+struct PagedText {
+    chars: Vec<char>,
+    page_starts: Vec<usize>,
+}
 
-    let response = client
-        .post(format!("{}/api/embeddings", ollama_url))
-        .json(&request)
-        .send()
-        .context("Failed to get embedding from Ollama")?;
+impl PagedText {
+    fn new(pages: &[String]) -> Self {
+        let mut chars = Vec::new();
+        let mut page_starts = Vec::with_capacity(pages.len());
+        for page in pages {
+            if !chars.is_empty() {
+                chars.extend(PAGE_SEPARATOR.chars());
+            }
+            page_starts.push(chars.len());
+            chars.extend(page.chars());
+        }
+        Self { chars, page_starts }
+    }
 
-    if !response.status().is_success() {
-        anyhow::bail!("Ollama returned error: {}", response.status());
+    fn text(&self, range: &Range<usize>) -> String {
+        self.chars[range.clone()].iter().collect()
     }
 
-    let embedding: EmbeddingResponse = response
-        .json()
-        .context("Failed to parse embedding response")?;
+    /// First and last page (1-based) a chunk's characters come from
+    fn pages(&self, range: &Range<usize>) -> (usize, usize) {
+        let page_of = |i: usize| self.page_starts.partition_point(|&start| start <= i).max(1);
+        (page_of(range.start), page_of(range.end.saturating_sub(1)))
+    }
+}
+
+/// Inserted between pages so a sentence never runs across a page break unseen
+const PAGE_SEPARATOR: &str = "\n\n";
+
+/// "p. 4" or "pp. 4-5", for dry-run listings
+fn page_label((first, last): (usize, usize)) -> String {
+    if first == last {
+        format!("p. {first}")
+    } else {
+        format!("pp. {first}-{last}")
+    }
+}
 
-    Ok(embedding.embedding)
+/// Chunk metadata listed by `--dry-run`, with each chunk's pages as its context
+fn chunk_plan(
+    chunks: &[String],
+    pages: &[Option<(usize, usize)>],
+    counter: TokenCounter,
+) -> Vec<ChunkPlan> {
+    chunks
+        .iter()
+        .zip(pages)
+        .enumerate()
+        .map(|(index, (chunk, pages))| ChunkPlan {
+            index,
+            chunk_type: "text".to_string(),
+            chars: chunk.chars().count(),
+            tokens: counter.count(chunk),
+            header_context: pages.map(page_label).unwrap_or_default(),
+            start_line: None,
+            end_line: None,
+        })
+        .collect()
 }
 
 fn main() -> Result<()> {
-    let args = Args::parse();
-    let client = Client::new();
+    let mut args: Args = parse_args()?;
+    args.collection = namespaced_collection(
+        &args.collection_prefix,
+        &args.collection,
+        &args.collection_suffix,
+    );
+    if args.chunk_size == 0 {
+        anyhow::bail!("--chunk-size must be greater than zero");
+    }
+    if args.overlap >= args.chunk_size {
+        anyhow::bail!("--overlap must be smaller than --chunk-size");
+    }
+    let sizes = ChunkSizes {
+        chunk: args.chunk_size,
+        overlap: args.overlap,
+        counter: TokenCounter::load(args.tokenizer),
+    };
+    if args.embed_batch_size == 0 {
+        anyhow::bail!("--embed-batch-size must be greater than zero");
+    }
 
-    // Extract text from PDF
-    println!("📄 Extracting text from PDF: {}", args.pdf_path);
     let path = Path::new(&args.pdf_path);
-    let text = extract_text(path).context("Failed to extract text from PDF")?;
+    if !args.dry_run_json {
+        if is_text_file(path) {
+            println!("📄 Reading text file: {}", path.display());
+        } else {
+            println!("📄 Extracting text from PDF: {}", path.display());
+        }
+    }
+    let pages = read_source_pages(path, args.flatten)?;
+    // Plain text and flattened PDFs have no page numbers to record
+    let paged = !args.flatten && !is_text_file(path);
+    let text = PagedText::new(&pages);
 
     // Create chunks
-    println!(
-        "✂️  Creating chunks (size: {}, overlap: {})",
-        args.chunk_size, args.overlap
-    );
-    let chunks = chunk_text(&text, args.chunk_size, args.overlap);
+    if !args.dry_run_json {
+        println!(
+            "✂️  Creating chunks (size: {} tokens, overlap: {}, {:?} tokenizer)",
+            args.chunk_size,
+            args.overlap,
+            sizes.counter.tokenizer()
+        );
+    }
+    let mut ranges = if args.raw_chunks {
+        chunk_text(&text.chars, sizes)
+    } else {
+        chunk_sentences(&text.chars, sizes)
+    };
+    limit_chunks(&mut ranges, args.max_chunks)?;
+    let chunks: Vec<String> = ranges.iter().map(|range| text.text(range)).collect();
+    let chunk_pages: Vec<Option<(usize, usize)>> = ranges
+        .iter()
+        .map(|range| paged.then(|| text.pages(range)))
+        .collect();
+    if args.dry_run || args.dry_run_json {
+        return print_chunk_plan(
+            &chunk_plan(&chunks, &chunk_pages, sizes.counter),
+            args.dry_run_json,
+        );
+    }
     println!("📦 Created {} chunks", chunks.len());
+    if paged {
+        let spanning = chunk_pages
+            .iter()
+            .flatten()
+            .filter(|(first, last)| first != last)
+            .count();
+        println!(
+            "   {} pages; {spanning} chunks cross a page break (stored as page..page_end)",
+            pages.len()
+        );
+    }
 
-    // Generate embeddings and prepare points
-    println!("🧮 Generating embeddings with model: {}", args.model);
-    let mut points = Vec::new();
-
-    for (i, chunk) in chunks.iter().enumerate() {
-        print!("  Processing chunk {}/{}...\r", i + 1, chunks.len());
+    if !args.skip_checks {
+        preflight(
+            &args.qdrant_url,
+            &args.ollama_url,
+            &args.model,
+            args.embed_api,
+        )?;
+    }
+    let embedder = Embedder::new(
+        &args.ollama_url,
+        args.embed_api,
+        &args.model,
+        RetryPolicy {
+            retries: args.embed_retries,
+            base_delay_ms: args.embed_retry_delay_ms,
+        },
+    )
+    .oversize(OversizePolicy {
+        on_oversize: args.on_oversize,
+        max_tokens: args.embed_max_tokens,
+        counter: sizes.counter,
+    })
+    .legacy_embed(args.legacy_embed);
+    let qdrant = QdrantClient::new(&args.qdrant_url)
+        .api_key(args.qdrant_api_key.as_ref())
+        .consistency(args.consistency)
+        .ingest_model(&args.model);
+    let source = args.source_label.as_deref().unwrap_or(&args.pdf_path);
+
+    let checkpoint_path = Checkpoint::path(&args.pdf_path);
+    let chunks_sha256 = chunks_sha256(&chunks);
+    let checkpoint = if args.restart {
+        None
+    } else {
+        Checkpoint::load(&checkpoint_path)?
+    };
+    let start = match &checkpoint {
+        Some(checkpoint) => {
+            let start = checkpoint.resume_index(&args.collection, &chunks, &chunks_sha256);
+            if start > 0 {
+                println!(
+                    "↻ Resuming from {} ({start}/{} chunks already uploaded)",
+                    checkpoint_path.display(),
+                    chunks.len()
+                );
+            } else {
+                println!(
+                    "⚠️  Ignoring {}: it was written for different chunks or another collection",
+                    checkpoint_path.display()
+                );
+            }
+            start
+        }
+        None => 0,
+    };
+    let mut ids = resumed_ids(args.seed, start);
 
-        let embedding = get_embedding(&client, &args.ollama_url, &args.model, chunk)?;
+    // Embed and upload in batches, checkpointing after each one
+    println!("🧮 Generating embeddings with model: {}", args.model);
+    println!("📤 Uploading to Qdrant collection: {}", args.collection);
 
-        let point = QdrantPoint {
-            id: Uuid::new_v4().to_string(),
-            vector: embedding,
-            payload: json!({
-                "text": chunk,
-                "source": args.pdf_path,
+    let bar = progress_bar(chunks.len() - start, "Embedding", args.quiet);
+    for batch_start in (start..chunks.len()).step_by(UPLOAD_BATCH_SIZE) {
+        let batch_end = (batch_start + UPLOAD_BATCH_SIZE).min(chunks.len());
+        let mut embeddings = Vec::new();
+        bar.set_message("Embedding");
+        for texts in chunks[batch_start..batch_end].chunks(args.embed_batch_size) {
+            embeddings.extend(embedder.embed_batch(texts)?);
+            bar.inc(texts.len() as u64);
+        }
+        let mut points = Vec::new();
+
+        for ((i, chunk), embedding) in (batch_start..batch_end)
+            .zip(&chunks[batch_start..batch_end])
+            .zip(embeddings)
+        {
+            let mut payload = json!({
+                "source": source,
+                "source_path": args.pdf_path,
                 "chunk_index": i,
                 "total_chunks": chunks.len(),
-            }),
-        };
+                "lang": detect_lang(chunk),
+            });
+            if let Some((first, last)) = chunk_pages[i] {
+                payload["page"] = json!(first);
+                payload["page_end"] = json!(last);
+            }
+            payload[args.text_field.as_str()] = json!(chunk);
+
+            let point = QdrantPoint {
+                id: ids.next_id(),
+                vector: embedding,
+                sparse: None,
+                summary_vector: None,
+                payload,
+            };
+
+            points.push(point);
+        }
 
-        points.push(point);
+        bar.set_message("Uploading");
+        qdrant.upsert_points(&args.collection, &points)?;
+        Checkpoint {
+            collection: args.collection.clone(),
+            total_chunks: chunks.len(),
+            chunks_sha256: chunks_sha256.clone(),
+            last_completed_chunk: batch_end - 1,
+        }
+        .save(&checkpoint_path)?;
     }
-    println!("\n✅ Generated embeddings for all chunks");
+    bar.finish_and_clear();
+    println!("✅ Generated and uploaded embeddings for all chunks");
 
-    // Upload to Qdrant
-    println!("📤 Uploading to Qdrant collection: {}", args.collection);
-    let response = client
-        .put(format!(
-            "{}/collections/{}/points",
-            args.qdrant_url, args.collection
-        ))
-        .json(&json!({
-            "points": points
-        }))
-        .send()
-        .context("Failed to upload to Qdrant")?;
-
-    if !response.status().is_success() {
-        let error_text = response
-            .text()
-            .unwrap_or_else(|_| "Unknown error".to_string());
-        anyhow::bail!("Qdrant returned error: {}", error_text);
-    }
-
-    println!("✅ Successfully ingested PDF into Qdrant!");
+    if checkpoint_path.exists() {
+        fs::remove_file(&checkpoint_path).with_context(|| {
+            format!("Failed to remove checkpoint {}", checkpoint_path.display())
+        })?;
+    }
+
+    println!("✅ Successfully ingested {} into Qdrant!", args.pdf_path);
     println!("📊 Stored {} chunks from {}", chunks.len(), args.pdf_path);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sizes measured with the 4-characters-per-token estimate
+    fn estimated(chunk: usize, overlap: usize) -> ChunkSizes {
+        ChunkSizes {
+            chunk,
+            overlap,
+            counter: TokenCounter::estimate(),
+        }
+    }
+
+    /// Chunk `text` with `chunker` and return the chunk strings
+    fn chunk_strings(
+        chunker: fn(&[char], ChunkSizes) -> Vec<Range<usize>>,
+        text: &str,
+        sizes: ChunkSizes,
+    ) -> Vec<String> {
+        let text = PagedText::new(&[text.to_string()]);
+        chunker(&text.chars, sizes)
+            .iter()
+            .map(|range| text.text(range))
+            .collect()
+    }
+
+    #[test]
+    fn test_checkpoint_resumes_only_for_the_same_chunks() {
+        let chunks: Vec<String> = (0..250).map(|i| format!("chunk {i}")).collect();
+        let hash = chunks_sha256(&chunks);
+        let path = std::env::temp_dir().join(format!(
+            "rag-book-{}.pdf.ingest-checkpoint",
+            std::process::id()
+        ));
+        Checkpoint {
+            collection: "rust-books".to_string(),
+            total_chunks: chunks.len(),
+            chunks_sha256: hash.clone(),
+            last_completed_chunk: 199,
+        }
+        .save(&path)
+        .unwrap();
+
+        let checkpoint = Checkpoint::load(&path).unwrap().unwrap();
+
+        assert_eq!(checkpoint.resume_index("rust-books", &chunks, &hash), 200);
+        assert_eq!(checkpoint.resume_index("lisp-books", &chunks, &hash), 0);
+        let mut edited = chunks.clone();
+        edited[10].push('!');
+        assert_eq!(
+            checkpoint.resume_index("rust-books", &edited, &chunks_sha256(&edited)),
+            0
+        );
+        assert_eq!(
+            checkpoint.resume_index("rust-books", &chunks[..200], &chunks_sha256(&chunks[..200])),
+            0
+        );
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(Checkpoint::load(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn test_seeded_resume_continues_the_id_sequence() {
+        let mut full = IdGenerator::new(Some(42));
+        let all: Vec<String> = (0..5).map(|_| full.next_id()).collect();
+
+        let mut resumed = resumed_ids(Some(42), 3);
+
+        assert_eq!(resumed.next_id(), all[3]);
+        assert_eq!(resumed.next_id(), all[4]);
+        assert_eq!(resumed_ids(Some(42), 0).next_id(), all[0]);
+    }
+
+    #[test]
+    fn test_sentence_chunks_end_at_sentence_boundaries() {
+        let text = "One two three. Four five six! Seven eight nine? Ten eleven twelve.";
+
+        let chunks = chunk_strings(chunk_sentences, text, estimated(9, 0));
+
+        assert_eq!(
+            chunks,
+            vec![
+                "One two three. Four five six!",
+                "Seven eight nine? Ten eleven twelve."
+            ]
+        );
+        // Raw mode still cuts mid-word at the exact offset
+        assert_eq!(
+            chunk_strings(chunk_text, text, estimated(10, 0))[0],
+            "One two three. Four five six! Seven eigh"
+        );
+    }
+
+    #[test]
+    fn test_sentence_overlap_carries_whole_trailing_sentences() {
+        let text = "Alpha beta gamma. Delta epsilon. Zeta eta theta iota kappa lambda.";
+
+        let chunks = chunk_strings(chunk_sentences, text, estimated(9, 4));
+
+        assert_eq!(chunks[0], "Alpha beta gamma. Delta epsilon.");
+        assert!(chunks[1].starts_with("Delta epsilon. Zeta"));
+        // A version number is not a sentence end
+        assert!(!is_sentence_end(
+            &"v1.5 is out".chars().collect::<Vec<_>>(),
+            2
+        ));
+    }
+
+    #[test]
+    fn test_sentence_chunks_never_split_multibyte_characters() {
+        let text = "Größe ändert sich. ".repeat(20) + &"日本語のテキスト".repeat(30);
+
+        let chunks = chunk_strings(chunk_sentences, &text, estimated(12, 2));
+
+        assert!(chunks.iter().all(|c| c.chars().count() <= 48));
+        assert!(chunks.iter().any(|c| c.ends_with("sich.")));
+        assert!(chunks.last().unwrap().ends_with("テキスト"));
+    }
+
+    #[test]
+    fn test_chunks_are_sized_with_the_tokenizer() {
+        let text = PagedText::new(&["Tokenizers count words, not characters. ".repeat(100)]);
+        let sizes = ChunkSizes {
+            chunk: 50,
+            overlap: 10,
+            counter: TokenCounter::load(Tokenizer::Cl100k),
+        };
+
+        for chunker in [chunk_text, chunk_sentences] {
+            let ranges = chunker(&text.chars, sizes);
+            let tokens = |range: Range<usize>| sizes.tokens(&text.chars[range]);
+
+            assert!(ranges.len() > 1);
+            assert!(ranges.iter().all(|r| tokens(r.clone()) <= 50));
+            // Far more than 4 characters per token for plain English
+            assert!(ranges[0].len() > 50 * 4);
+            let overlap = ranges[1].start..ranges[0].end;
+            assert!(!overlap.is_empty() && tokens(overlap) <= 10);
+        }
+    }
+
+    #[test]
+    fn test_chunks_record_the_pages_they_span() {
+        let pages = vec![
+            "Page one talks about ownership. It is short.".to_string(),
+            "Page two covers borrowing. References must not outlive data.".to_string(),
+            "Page three explains lifetimes.".to_string(),
+        ];
+        let text = PagedText::new(&pages);
+
+        let ranges = chunk_sentences(&text.chars, estimated(11, 0));
+        let spans: Vec<(usize, usize)> = ranges.iter().map(|r| text.pages(r)).collect();
+
+        assert_eq!(
+            text.text(&ranges[0]),
+            "Page one talks about ownership. It is short."
+        );
+        assert_eq!(spans[0], (1, 1));
+        assert_eq!(*spans.last().unwrap(), (3, 3));
+        assert!(
+            ranges
+                .iter()
+                .zip(&spans)
+                .all(|(r, &(first, last))| first == last || text.text(r).contains("\n\n"))
+        );
+        assert_eq!(text.pages(&(40..50)), (1, 2));
+        assert_eq!(text.pages(&(0..text.chars.len())), (1, 3));
+        assert_eq!(page_label((4, 4)), "p. 4");
+        assert_eq!(page_label((4, 5)), "pp. 4-5");
+    }
+}
@@ -0,0 +1,5157 @@
+// Copyright (c) 2025 Michael A. Wright
+// Licensed under the MIT License
+
+//! Embedding and Qdrant plumbing shared by the ingestion and search binaries.
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use indicatif::{ProgressBar, ProgressStyle};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use reqwest::StatusCode;
+use reqwest::blocking::Client;
+use scraper::{ElementRef, Html, Node, Selector};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+use tiktoken_rs::CoreBPE;
+use uuid::Builder;
+use whatlang::Lang;
+
+/// Embedding API flavour, which decides the endpoint and request/response shape
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum EmbedApi {
+    /// Ollama `/api/embeddings` (sends `prompt`)
+    Ollama,
+    /// Ollama `/api/embed` (sends `input`)
+    OllamaEmbed,
+    /// OpenAI-compatible `/v1/embeddings` (sends `input`)
+    Openai,
+}
+
+impl EmbedApi {
+    pub fn path(self) -> &'static str {
+        match self {
+            EmbedApi::Ollama => "/api/embeddings",
+            EmbedApi::OllamaEmbed => "/api/embed",
+            EmbedApi::Openai => "/v1/embeddings",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmbeddingRequest {
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input: Option<String>,
+    /// Ollama `/api/embed` only: truncate over-long input instead of failing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncate: Option<bool>,
+}
+
+impl EmbeddingRequest {
+    /// Build a request using the text field name the endpoint expects
+    pub fn new(api: EmbedApi, model: &str, text: &str) -> Self {
+        let (prompt, input) = match api {
+            EmbedApi::Ollama => (Some(text.to_string()), None),
+            EmbedApi::OllamaEmbed | EmbedApi::Openai => (None, Some(text.to_string())),
+        };
+        Self {
+            model: model.to_string(),
+            prompt,
+            input,
+            truncate: None,
+        }
+    }
+}
+
+/// Request for Ollama `/api/embed` with several inputs, answered in order
+#[derive(Debug, Serialize)]
+struct EmbedBatchRequest<'a> {
+    model: &'a str,
+    input: Vec<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    truncate: Option<bool>,
+}
+
+/// Response from any of the supported endpoints; only one field is populated
+#[derive(Debug, Deserialize)]
+pub struct EmbeddingResponse {
+    #[serde(default)]
+    pub embedding: Vec<f32>,
+    #[serde(default)]
+    pub embeddings: Vec<Vec<f32>>,
+    #[serde(default)]
+    pub data: Vec<OpenAiEmbedding>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenAiEmbedding {
+    pub embedding: Vec<f32>,
+}
+
+impl EmbeddingResponse {
+    /// Extract the vector, failing loudly instead of returning an empty embedding
+    pub fn into_vector(self, api: EmbedApi) -> Result<Vec<f32>> {
+        let vector = match api {
+            EmbedApi::Ollama => self.embedding,
+            EmbedApi::OllamaEmbed => self.embeddings.into_iter().next().unwrap_or_default(),
+            EmbedApi::Openai => self
+                .data
+                .into_iter()
+                .next()
+                .map(|d| d.embedding)
+                .unwrap_or_default(),
+        };
+        if vector.is_empty() {
+            anyhow::bail!(
+                "Embedding response from {} was empty; check that --embed-api matches the server",
+                api.path()
+            );
+        }
+        Ok(vector)
+    }
+}
+
+/// Retry settings for transient embedding failures
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub base_delay_ms: u64,
+}
+
+impl RetryPolicy {
+    /// Backoff before the given retry (1-based): base, 2x base, 4x base, ...
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 1u64 << retry.saturating_sub(1).min(16);
+        Duration::from_millis(self.base_delay_ms.saturating_mul(factor))
+    }
+}
+
+/// Server errors and rate limiting are worth retrying; other failures are not
+pub fn is_retryable_status(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// What to do with text longer than the embedding model accepts
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum OnOversize {
+    /// Keep the start of the text (Ollama's `truncate` option on /api/embed)
+    Truncate,
+    /// Fail with an error giving the token count
+    Error,
+    /// Embed the text in pieces and average their vectors
+    #[default]
+    Split,
+}
+
+/// Context length of nomic-embed-text as served by Ollama
+pub const DEFAULT_EMBED_MAX_TOKENS: usize = 2048;
+
+/// Token limit for embedding input and how to handle text over it
+#[derive(Debug, Clone, Copy)]
+pub struct OversizePolicy {
+    pub on_oversize: OnOversize,
+    pub max_tokens: usize,
+    pub counter: TokenCounter,
+}
+
+impl Default for OversizePolicy {
+    fn default() -> Self {
+        Self {
+            on_oversize: OnOversize::default(),
+            max_tokens: DEFAULT_EMBED_MAX_TOKENS,
+            counter: TokenCounter::estimate(),
+        }
+    }
+}
+
+/// On-disk cache of embeddings, one JSON file per (model, SHA-256 of the text)
+#[derive(Debug, Clone)]
+pub struct EmbeddingCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl EmbeddingCache {
+    /// Entries older than this are ignored and evicted
+    pub const DEFAULT_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+    /// Oldest entries beyond this many are evicted when the cache is opened
+    pub const DEFAULT_MAX_ENTRIES: usize = 10_000;
+
+    /// `$XDG_CACHE_HOME/rag-demo`, or `~/.cache/rag-demo`
+    pub fn default_dir() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+        Some(base.join("rag-demo"))
+    }
+
+    /// Open the cache in `dir`, creating it, and evict expired entries and the
+    /// oldest ones beyond `max_entries`
+    pub fn open(dir: &Path, ttl: Duration, max_entries: usize) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create cache directory {}", dir.display()))?;
+        let cache = Self {
+            dir: dir.to_path_buf(),
+            ttl,
+        };
+        cache.evict(max_entries)?;
+        Ok(cache)
+    }
+
+    /// The cache for a binary's `--cache-dir`/`--no-cache` flags; problems
+    /// opening it only disable caching
+    pub fn from_flags(dir: Option<&Path>, no_cache: bool) -> Option<Self> {
+        if no_cache {
+            return None;
+        }
+        let dir = dir.map(Path::to_path_buf).or_else(Self::default_dir)?;
+        match Self::open(&dir, Self::DEFAULT_TTL, Self::DEFAULT_MAX_ENTRIES) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                eprintln!("⚠️  Embedding cache disabled: {e:#}");
+                None
+            }
+        }
+    }
+
+    fn entry_path(&self, model: &str, text: &str) -> PathBuf {
+        let model: String = model
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let hash: String = Sha256::digest(text.as_bytes())
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        self.dir.join(format!("{model}-{hash}.json"))
+    }
+
+    fn is_expired(&self, modified: SystemTime) -> bool {
+        modified.elapsed().is_ok_and(|age| age > self.ttl)
+    }
+
+    pub fn get(&self, model: &str, text: &str) -> Option<Vec<f32>> {
+        let path = self.entry_path(model, text);
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+        if self.is_expired(modified) {
+            return None;
+        }
+        serde_json::from_slice(&fs::read(&path).ok()?).ok()
+    }
+
+    pub fn put(&self, model: &str, text: &str, vector: &[f32]) -> Result<()> {
+        let path = self.entry_path(model, text);
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(vector)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &path).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Remove expired entries, then the oldest until at most `max_entries` remain
+    fn evict(&self, max_entries: usize) -> Result<()> {
+        let mut entries: Vec<(SystemTime, PathBuf)> = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let modified = fs::metadata(&path)?.modified()?;
+            if self.is_expired(modified) {
+                fs::remove_file(&path)?;
+            } else {
+                entries.push((modified, path));
+            }
+        }
+
+        entries.sort();
+        let excess = entries.len().saturating_sub(max_entries);
+        for (_, path) in &entries[..excess] {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Client for an embedding endpoint with a fixed model, API flavour and retry policy
+pub struct Embedder {
+    client: Client,
+    url: String,
+    /// Ollama `/api/embed`, used by [`Embedder::embed_batch`]; `None` embeds one text per request
+    batch_url: Option<String>,
+    /// Set once the batch endpoint answers 404, so later batches go straight to one-by-one
+    batch_unsupported: AtomicBool,
+    api: EmbedApi,
+    model: String,
+    retry: RetryPolicy,
+    wake_on_retry: bool,
+    oversize: OversizePolicy,
+    cache: Option<EmbeddingCache>,
+}
+
+impl Embedder {
+    pub fn new(base_url: &str, api: EmbedApi, model: &str, retry: RetryPolicy) -> Self {
+        let batch_url = match api {
+            EmbedApi::Ollama | EmbedApi::OllamaEmbed => {
+                Some(format!("{}{}", base_url, EmbedApi::OllamaEmbed.path()))
+            }
+            EmbedApi::Openai => None,
+        };
+        Self {
+            client: http_client(),
+            url: format!("{}{}", base_url, api.path()),
+            batch_url,
+            batch_unsupported: AtomicBool::new(false),
+            api,
+            model: model.to_string(),
+            retry,
+            wake_on_retry: false,
+            oversize: OversizePolicy::default(),
+            cache: None,
+        }
+    }
+
+    /// Reuse embeddings of text seen before instead of asking the server again
+    pub fn cache(mut self, cache: Option<EmbeddingCache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Never batch: [`Embedder::embed_batch`] sends one request per text to the configured API
+    pub fn legacy_embed(mut self, legacy: bool) -> Self {
+        if legacy {
+            self.batch_url = None;
+        }
+        self
+    }
+
+    pub fn oversize(mut self, oversize: OversizePolicy) -> Self {
+        self.oversize = oversize;
+        self
+    }
+
+    /// Send a tiny request before each retry to get a stalled model loaded again
+    pub fn wake_on_retry(mut self, enabled: bool) -> Self {
+        self.wake_on_retry = enabled;
+        self
+    }
+
+    /// Embed `text` as one vector; split text is the mean of its pieces' vectors
+    pub fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let mut pieces = self.embed_pieces(text)?;
+        if pieces.len() == 1 {
+            return Ok(pieces.remove(0));
+        }
+        Ok(mean_vector(&pieces))
+    }
+
+    /// Embed every text, one vector per text in the same order. Uncached texts
+    /// within the token limit go to Ollama's `/api/embed` in a single request;
+    /// the rest, or all of them when that endpoint is missing, go one at a time.
+    pub fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let mut vectors: Vec<Option<Vec<f32>>> = texts
+            .iter()
+            .map(|text| self.cache.as_ref()?.get(&self.model, text))
+            .collect();
+
+        if let Some(batch_url) = &self.batch_url
+            && !self.batch_unsupported.load(Ordering::Relaxed)
+        {
+            let pending: Vec<usize> = (0..texts.len())
+                .filter(|&i| vectors[i].is_none() && self.fits(&texts[i]))
+                .collect();
+            if !pending.is_empty() {
+                let inputs = pending.iter().map(|&i| texts[i].as_str()).collect();
+                match self.request_batch(batch_url, inputs)? {
+                    Some(batch) => {
+                        for (i, vector) in pending.into_iter().zip(batch) {
+                            if let Some(cache) = &self.cache
+                                && let Err(e) = cache.put(&self.model, &texts[i], &vector)
+                            {
+                                eprintln!("⚠️  Could not cache embedding: {e:#}");
+                            }
+                            vectors[i] = Some(vector);
+                        }
+                    }
+                    None => {
+                        eprintln!(
+                            "⚠️  {batch_url} not found; embedding one text per request (upgrade Ollama for batching)"
+                        );
+                        self.batch_unsupported.store(true, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+
+        texts
+            .iter()
+            .zip(vectors)
+            .map(|(text, vector)| vector.map_or_else(|| self.embed(text), Ok))
+            .collect()
+    }
+
+    /// Whether `text` can be embedded as is under the oversize policy
+    fn fits(&self, text: &str) -> bool {
+        let OversizePolicy {
+            on_oversize,
+            max_tokens,
+            counter,
+        } = self.oversize;
+        let server_truncates =
+            on_oversize == OnOversize::Truncate && self.api == EmbedApi::OllamaEmbed;
+        server_truncates || counter.count(text) <= max_tokens
+    }
+
+    /// Embed `text` under the oversize policy, one vector per piece it was split into
+    pub fn embed_pieces(&self, text: &str) -> Result<Vec<Vec<f32>>> {
+        let OversizePolicy {
+            on_oversize,
+            max_tokens,
+            counter,
+        } = self.oversize;
+        if self.fits(text) {
+            return Ok(vec![self.embed_one(text)?]);
+        }
+        let tokens = counter.count(text);
+
+        match on_oversize {
+            OnOversize::Truncate => Ok(vec![self.embed_one(counter.truncate(text, max_tokens))?]),
+            OnOversize::Error => anyhow::bail!(
+                "Text is {tokens} tokens, over the embedding limit of {max_tokens}; \
+                 use --on-oversize split or truncate to embed it anyway"
+            ),
+            OnOversize::Split => counter
+                .split(text, max_tokens)
+                .iter()
+                .map(|piece| self.embed_one(piece))
+                .collect(),
+        }
+    }
+
+    fn embed_one(&self, text: &str) -> Result<Vec<f32>> {
+        let Some(cache) = &self.cache else {
+            return self.request_embedding(text);
+        };
+        if let Some(vector) = cache.get(&self.model, text) {
+            return Ok(vector);
+        }
+        let vector = self.request_embedding(text)?;
+        if let Err(e) = cache.put(&self.model, text, &vector) {
+            eprintln!("⚠️  Could not cache embedding: {e:#}");
+        }
+        Ok(vector)
+    }
+
+    fn request_embedding(&self, text: &str) -> Result<Vec<f32>> {
+        let mut request = EmbeddingRequest::new(self.api, &self.model, text);
+        if self.api == EmbedApi::OllamaEmbed {
+            request.truncate = Some(self.oversize.on_oversize == OnOversize::Truncate);
+        }
+
+        match self.post_with_retry(&self.url, &request)? {
+            Ok(embedding) => embedding.into_vector(self.api),
+            Err(status) => anyhow::bail!("Ollama returned error: {status}"),
+        }
+    }
+
+    /// Embed `inputs` with one `/api/embed` request, or `None` if the endpoint doesn't exist
+    fn request_batch(&self, batch_url: &str, inputs: Vec<&str>) -> Result<Option<Vec<Vec<f32>>>> {
+        let expected = inputs.len();
+        let request = EmbedBatchRequest {
+            model: &self.model,
+            input: inputs,
+            truncate: Some(self.oversize.on_oversize == OnOversize::Truncate),
+        };
+
+        match self.post_with_retry(batch_url, &request)? {
+            Ok(response) if response.embeddings.len() == expected => Ok(Some(response.embeddings)),
+            Ok(response) => anyhow::bail!(
+                "{batch_url} returned {} embeddings for {expected} inputs",
+                response.embeddings.len()
+            ),
+            Err(StatusCode::NOT_FOUND) => Ok(None),
+            Err(status) => anyhow::bail!("Ollama returned error: {status}"),
+        }
+    }
+
+    /// POST `request`, retrying transient failures. A non-retryable status is
+    /// returned as the inner `Err` for the caller to report or handle.
+    fn post_with_retry(
+        &self,
+        url: &str,
+        request: &impl Serialize,
+    ) -> Result<std::result::Result<EmbeddingResponse, StatusCode>> {
+        let mut attempt = 0;
+        loop {
+            let error = match self.client.post(url).json(request).send() {
+                Ok(response) if response.status().is_success() => {
+                    match response.json::<EmbeddingResponse>() {
+                        Ok(embedding) => return Ok(Ok(embedding)),
+                        Err(e) => format!("Failed to parse embedding response: {e}"),
+                    }
+                }
+                Ok(response) if !is_retryable_status(response.status()) => {
+                    return Ok(Err(response.status()));
+                }
+                Ok(response) => format!("Ollama returned error: {}", response.status()),
+                Err(e) => format!("Failed to get embedding from Ollama: {}", request_error(&e)),
+            };
+
+            if attempt >= self.retry.retries {
+                anyhow::bail!("{error} (gave up after {} attempts)", attempt + 1);
+            }
+            attempt += 1;
+            let delay = self.retry.delay(attempt);
+            eprintln!(
+                "⚠️  {error}; retry {attempt}/{} in {}ms",
+                self.retry.retries,
+                delay.as_millis()
+            );
+            std::thread::sleep(delay);
+
+            if self.wake_on_retry {
+                let _ = self
+                    .client
+                    .post(&self.url)
+                    .json(&EmbeddingRequest::new(self.api, &self.model, "test"))
+                    .send();
+                std::thread::sleep(Duration::from_millis(100));
+            }
+        }
+    }
+}
+
+/// Component-wise mean of equally sized vectors
+fn mean_vector(vectors: &[Vec<f32>]) -> Vec<f32> {
+    let mut mean = vec![0.0; vectors.first().map_or(0, Vec::len)];
+    for vector in vectors {
+        for (m, v) in mean.iter_mut().zip(vector) {
+            *m += v;
+        }
+    }
+    for m in &mut mean {
+        *m /= vectors.len() as f32;
+    }
+    mean
+}
+
+/// Encoding used to measure chunk sizes in tokens
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Tokenizer {
+    /// OpenAI cl100k_base BPE
+    Cl100k,
+    /// OpenAI o200k_base BPE
+    O200k,
+    /// No tokenizer: estimate one token per four characters
+    Chars,
+}
+
+static CL100K: LazyLock<Option<CoreBPE>> = LazyLock::new(|| tiktoken_rs::cl100k_base().ok());
+static O200K: LazyLock<Option<CoreBPE>> = LazyLock::new(|| tiktoken_rs::o200k_base().ok());
+
+/// Counts tokens with a BPE encoding, or estimates them from characters
+#[derive(Clone, Copy)]
+pub struct TokenCounter {
+    tokenizer: Tokenizer,
+    bpe: Option<&'static CoreBPE>,
+}
+
+impl std::fmt::Debug for TokenCounter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("TokenCounter")
+            .field(&self.tokenizer)
+            .finish()
+    }
+}
+
+impl TokenCounter {
+    /// Load `tokenizer`, falling back to the character estimate if it cannot be loaded
+    pub fn load(tokenizer: Tokenizer) -> Self {
+        let bpe = match tokenizer {
+            Tokenizer::Cl100k => CL100K.as_ref(),
+            Tokenizer::O200k => O200K.as_ref(),
+            Tokenizer::Chars => return Self::estimate(),
+        };
+        match bpe {
+            Some(bpe) => Self {
+                tokenizer,
+                bpe: Some(bpe),
+            },
+            None => {
+                eprintln!(
+                    "⚠️  Could not load the {tokenizer:?} tokenizer, estimating tokens from characters"
+                );
+                Self::estimate()
+            }
+        }
+    }
+
+    /// Character-based estimate of one token per four characters
+    pub fn estimate() -> Self {
+        Self {
+            tokenizer: Tokenizer::Chars,
+            bpe: None,
+        }
+    }
+
+    pub fn tokenizer(&self) -> Tokenizer {
+        self.tokenizer
+    }
+
+    pub fn count(&self, text: &str) -> usize {
+        match self.bpe {
+            Some(bpe) => bpe.encode_ordinary(text).len(),
+            None => text.chars().count().div_ceil(4),
+        }
+    }
+
+    /// Longest prefix of `text` within `max_tokens`, never splitting a character
+    pub fn truncate<'t>(&self, text: &'t str, max_tokens: usize) -> &'t str {
+        let ends: Vec<usize> = text.char_indices().map(|(i, c)| i + c.len_utf8()).collect();
+        let fits = ends.partition_point(|&end| self.count(&text[..end]) <= max_tokens);
+        if fits == 0 {
+            ""
+        } else {
+            &text[..ends[fits - 1]]
+        }
+    }
+
+    /// Longest suffix of `text` within `max_tokens`, never splitting a character
+    pub fn tail<'t>(&self, text: &'t str, max_tokens: usize) -> &'t str {
+        let starts: Vec<usize> = text.char_indices().map(|(i, _)| i).rev().collect();
+        let fits = starts.partition_point(|&start| self.count(&text[start..]) <= max_tokens);
+        if fits == 0 {
+            ""
+        } else {
+            &text[starts[fits - 1]..]
+        }
+    }
+
+    /// Split `text` into pieces of at most `max_tokens`, preferring line breaks,
+    /// then whitespace, and cutting inside a word only when it alone is too long
+    pub fn split(&self, text: &str, max_tokens: usize) -> Vec<String> {
+        let mut pieces = Vec::new();
+        let mut rest = text;
+
+        while self.count(rest) > max_tokens {
+            let prefix = self.truncate(rest, max_tokens);
+            let break_after = |wanted: fn(char) -> bool| {
+                prefix
+                    .char_indices()
+                    .rev()
+                    .find(|&(_, c)| wanted(c))
+                    .map(|(i, c)| i + c.len_utf8())
+            };
+            let cut = break_after(|c| c == '\n')
+                .or_else(|| break_after(char::is_whitespace))
+                .unwrap_or(prefix.len());
+            // A single character can exceed a tiny budget; take it anyway to make progress
+            let cut = if cut == 0 {
+                rest.chars().next().map_or(rest.len(), char::len_utf8)
+            } else {
+                cut
+            };
+
+            pieces.push(rest[..cut].to_string());
+            rest = &rest[cut..];
+        }
+
+        if !rest.trim().is_empty() || pieces.is_empty() {
+            pieces.push(rest.to_string());
+        }
+        pieces
+    }
+}
+
+/// Name of the sparse (keyword) vector declared on hierarchical collections
+pub const SPARSE_VECTOR_NAME: &str = "text";
+
+/// Dense vector of a chunk's own text in hierarchical collections created
+/// with named vectors
+pub const CONTENT_VECTOR_NAME: &str = "content";
+
+/// Dense vector of the parent section's summary in hierarchical collections
+/// created with named vectors
+pub const SUMMARY_VECTOR_NAME: &str = "summary";
+
+/// Term-frequency sparse vector over a hashed vocabulary, so ingest and search
+/// map the same token to the same index without sharing a vocabulary file
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SparseVector {
+    pub indices: Vec<u32>,
+    pub values: Vec<f32>,
+}
+
+impl SparseVector {
+    /// Count lowercase alphanumeric tokens longer than two characters
+    pub fn from_text(text: &str) -> Self {
+        let mut counts: BTreeMap<u32, f32> = BTreeMap::new();
+        for token in text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|t| t.chars().count() > 2)
+        {
+            *counts
+                .entry(token_index(&token.to_lowercase()))
+                .or_default() += 1.0;
+        }
+
+        Self {
+            indices: counts.keys().copied().collect(),
+            values: counts.values().copied().collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+}
+
+/// FNV-1a hash of a token, used as its sparse vector index
+fn token_index(token: &str) -> u32 {
+    token.bytes().fold(0x811c_9dc5, |hash: u32, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+#[derive(Debug)]
+pub struct QdrantPoint {
+    pub id: String,
+    pub vector: Vec<f32>,
+    /// Keyword vector uploaded under [`SPARSE_VECTOR_NAME`] next to the dense vector
+    pub sparse: Option<SparseVector>,
+    /// Summary vector uploaded under [`SUMMARY_VECTOR_NAME`]; with it the
+    /// dense vector is uploaded under [`CONTENT_VECTOR_NAME`]
+    pub summary_vector: Option<Vec<f32>>,
+    pub payload: Value,
+}
+
+impl Serialize for QdrantPoint {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        // With only a sparse vector the dense one is addressed by the default (empty) name
+        let vector = match (&self.sparse, &self.summary_vector) {
+            (None, None) => json!(self.vector),
+            (sparse, summary) => {
+                let mut named = serde_json::Map::new();
+                match summary {
+                    Some(summary) => {
+                        named.insert(CONTENT_VECTOR_NAME.to_string(), json!(self.vector));
+                        named.insert(SUMMARY_VECTOR_NAME.to_string(), json!(summary));
+                    }
+                    None => {
+                        named.insert(String::new(), json!(self.vector));
+                    }
+                }
+                if let Some(sparse) = sparse {
+                    named.insert(SPARSE_VECTOR_NAME.to_string(), json!(sparse));
+                }
+                Value::Object(named)
+            }
+        };
+        json!({
+            "id": self.id,
+            "vector": vector,
+            "payload": self.payload,
+        })
+        .serialize(serializer)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SearchResult {
+    pub id: String,
+    pub score: f32,
+    pub payload: Value,
+}
+
+/// Rank constant of Reciprocal Rank Fusion; damps the lead of the top ranks
+pub const RRF_K: f32 = 60.0;
+
+/// A hit fused from several rankings; its score is the summed RRF score
+#[derive(Debug, Clone)]
+pub struct FusedResult {
+    pub result: SearchResult,
+    /// Indexes of the rankings the hit appeared in
+    pub rankings: Vec<usize>,
+}
+
+/// Reciprocal Rank Fusion: score each hit by the sum of `1 / (RRF_K + rank)`
+/// over the rankings it appears in, treating hits with the same `key` as one.
+/// Sorted best first; a merged hit keeps the payload of its first appearance.
+pub fn rrf_fuse<K: PartialEq>(
+    rankings: Vec<Vec<SearchResult>>,
+    key: impl Fn(&SearchResult) -> K,
+) -> Vec<FusedResult> {
+    let mut fused: Vec<(K, FusedResult)> = Vec::new();
+    for (ranking_index, ranking) in rankings.into_iter().enumerate() {
+        for (rank, result) in ranking.into_iter().enumerate() {
+            let score = 1.0 / (RRF_K + rank as f32 + 1.0);
+            let k = key(&result);
+            match fused.iter_mut().find(|(existing, _)| *existing == k) {
+                Some((_, existing)) => {
+                    existing.result.score += score;
+                    if !existing.rankings.contains(&ranking_index) {
+                        existing.rankings.push(ranking_index);
+                    }
+                }
+                None => fused.push((
+                    k,
+                    FusedResult {
+                        result: SearchResult { score, ..result },
+                        rankings: vec![ranking_index],
+                    },
+                )),
+            }
+        }
+    }
+    let mut fused: Vec<FusedResult> = fused.into_iter().map(|(_, f)| f).collect();
+    // Stable, so ties keep the order the hits were first found in
+    fused.sort_by(|a, b| b.result.score.total_cmp(&a.result.score));
+    fused
+}
+
+/// Point fetched by ID; unlike a search hit it has no score
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct RetrievedPoint {
+    pub id: String,
+    pub payload: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct QdrantRetrieveResponse {
+    result: Vec<RetrievedPoint>,
+}
+
+/// Search hit as returned by Qdrant, with the stored vector when it was requested
+#[derive(Debug, Deserialize)]
+struct QdrantHit {
+    #[serde(flatten)]
+    result: SearchResult,
+    #[serde(default)]
+    vector: Option<Value>,
+}
+
+impl QdrantHit {
+    fn dense_vector(&self) -> Option<Vec<f32>> {
+        dense_vector(self.vector.as_ref()?)
+    }
+}
+
+/// The dense vector of a point's `vector` field, which is the unnamed one in
+/// collections that also have a sparse vector and [`CONTENT_VECTOR_NAME`] in
+/// those with named dense vectors
+fn dense_vector(vector: &Value) -> Option<Vec<f32>> {
+    let dense = vector
+        .get("")
+        .or_else(|| vector.get(CONTENT_VECTOR_NAME))
+        .unwrap_or(vector);
+    serde_json::from_value(dense.clone()).ok()
+}
+
+/// A point ID as Qdrant expects it: integer IDs, which scrolling hands out as
+/// decimal strings, go back as numbers and UUIDs as strings
+fn point_id(id: &str) -> Value {
+    id.parse::<u64>().map_or_else(|_| json!(id), |n| json!(n))
+}
+
+/// Filter leaving out the point `id`, so a search with its own vector finds
+/// its neighbours rather than itself
+pub fn exclude_point(id: &str) -> Value {
+    json!({"must_not": [{"has_id": [point_id(id)]}]})
+}
+
+#[derive(Debug, Deserialize)]
+struct QdrantSearchResponse {
+    result: Vec<QdrantHit>,
+}
+
+/// Collection details from `GET /collections/{name}`
+#[derive(Debug, Deserialize)]
+pub struct CollectionResult {
+    #[serde(default)]
+    pub status: Option<String>,
+    pub vectors_count: Option<usize>,
+    pub indexed_vectors_count: Option<usize>,
+    pub points_count: Option<usize>,
+    pub config: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct QdrantCollectionResponse {
+    result: CollectionResult,
+}
+
+impl CollectionResult {
+    /// Parameters of the dense vector, which is the unnamed one when the
+    /// collection also declares a sparse vector, or [`CONTENT_VECTOR_NAME`]
+    /// when its dense vectors are named
+    fn vector_params(&self) -> &Value {
+        let vectors = &self.config["params"]["vectors"];
+        if vectors.get("size").is_some() {
+            vectors
+        } else {
+            vectors.get("").unwrap_or(&vectors[CONTENT_VECTOR_NAME])
+        }
+    }
+
+    /// The dense vector searches use when none is asked for:
+    /// [`CONTENT_VECTOR_NAME`] if the collection's dense vectors are named,
+    /// else the unnamed one
+    pub fn default_vector_name(&self) -> Option<&'static str> {
+        self.has_named_vector(CONTENT_VECTOR_NAME)
+            .then_some(CONTENT_VECTOR_NAME)
+    }
+
+    /// Whether the collection declares a dense vector called `name`
+    pub fn has_named_vector(&self, name: &str) -> bool {
+        self.config["params"]["vectors"]
+            .get(name)
+            .is_some_and(|params| params.get("size").is_some())
+    }
+
+    pub fn vector_size(&self) -> Option<u64> {
+        self.vector_params()["size"].as_u64()
+    }
+
+    pub fn distance(&self) -> Option<&str> {
+        self.vector_params()["distance"].as_str()
+    }
+
+    /// Metric of the default vector, Cosine if the config doesn't say
+    pub fn distance_metric(&self) -> Distance {
+        self.distance()
+            .and_then(Distance::from_qdrant_name)
+            .unwrap_or_default()
+    }
+}
+
+/// A collection snapshot stored on the Qdrant server, from `GET /collections/{name}/snapshots`
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SnapshotInfo {
+    pub name: String,
+    #[serde(default)]
+    pub creation_time: Option<String>,
+    #[serde(default)]
+    pub size: u64,
+    /// SHA-256 of the snapshot file, hex encoded
+    #[serde(default)]
+    pub checksum: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct QdrantSnapshotResponse<T> {
+    result: T,
+}
+
+/// Creating, downloading or restoring a large collection's snapshot takes far
+/// longer than `--http-timeout-secs` allows for ordinary requests
+pub const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Vector distance metric, fixed when a collection is created
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum Distance {
+    #[default]
+    Cosine,
+    Dot,
+    Euclid,
+}
+
+impl Distance {
+    /// Name of the metric in Qdrant's collection config
+    pub fn qdrant_name(self) -> &'static str {
+        match self {
+            Distance::Cosine => "Cosine",
+            Distance::Dot => "Dot",
+            Distance::Euclid => "Euclid",
+        }
+    }
+
+    /// Metric with this name in Qdrant's collection config
+    pub fn from_qdrant_name(name: &str) -> Option<Self> {
+        [Distance::Cosine, Distance::Dot, Distance::Euclid]
+            .into_iter()
+            .find(|distance| distance.qdrant_name() == name)
+    }
+
+    /// Whether a higher search score is a closer match. Qdrant scores Euclid
+    /// searches with the distance itself, where lower is closer.
+    pub fn higher_is_better(self) -> bool {
+        self != Distance::Euclid
+    }
+
+    /// Whether `score` is at least as close a match as `threshold`
+    pub fn meets(self, score: f32, threshold: f32) -> bool {
+        if self.higher_is_better() {
+            score >= threshold
+        } else {
+            score <= threshold
+        }
+    }
+
+    /// Order of two scores, closest match first
+    pub fn rank(self, a: f32, b: f32) -> std::cmp::Ordering {
+        if self.higher_is_better() {
+            b.total_cmp(&a)
+        } else {
+            a.total_cmp(&b)
+        }
+    }
+
+    /// `score` as a similarity where higher is closer: Euclid distances map
+    /// to 1 / (1 + distance), other scores are returned as they are
+    pub fn similarity(self, score: f32) -> f32 {
+        if self.higher_is_better() {
+            score
+        } else {
+            1.0 / (1.0 + score.max(0.0))
+        }
+    }
+
+    /// Warning to show when an existing collection uses another metric, since
+    /// `--distance` only applies when a collection is created
+    pub fn mismatch(self, collection: &str, info: &CollectionResult) -> Option<String> {
+        let existing = info.distance()?;
+        (existing != self.qdrant_name()).then(|| {
+            format!(
+                "Collection {collection} already uses {existing} distance; --distance {} only applies to new collections",
+                self.qdrant_name().to_lowercase()
+            )
+        })
+    }
+}
+
+/// Vector quantization, fixed when a collection is created
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum Quantization {
+    /// Keep float32 vectors only
+    #[default]
+    None,
+    /// Also keep int8 copies in RAM, about 4x smaller, for a little recall
+    Scalar,
+}
+
+impl Quantization {
+    /// Add the quantization config to a collection-create request
+    pub fn apply(self, config: &mut Value) {
+        if self == Quantization::Scalar {
+            eprintln!(
+                "⚠️  Scalar (int8) quantization cuts vector memory about 4x but trades away a little recall"
+            );
+            config["quantization_config"] = json!({
+                "scalar": {"type": "int8", "always_ram": true}
+            });
+        }
+    }
+
+    /// Note to show when an existing collection lacks the requested
+    /// quantization, since `--quantization` only applies to new collections
+    pub fn mismatch(self, collection: &str, info: &CollectionResult) -> Option<String> {
+        let quantized = !info.config["quantization_config"].is_null();
+        (self == Quantization::Scalar && !quantized).then(|| {
+            format!(
+                "Collection {collection} already exists without quantization; --quantization scalar only applies to new collections, so recreate it to apply"
+            )
+        })
+    }
+}
+
+/// How soon ingested points must be visible to searches. Ingest tools
+/// default to `Strong`, so a finished run is fully searchable; search tools
+/// default to `Eventual`
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum Consistency {
+    /// Return as soon as Qdrant accepts an upsert; search the HNSW index
+    #[default]
+    Eventual,
+    /// Wait for upserts to be applied and search exhaustively, so fresh points are always found
+    Strong,
+}
+
+/// Default for `--http-timeout-secs`
+pub const DEFAULT_HTTP_TIMEOUT: Duration = Duration::from_secs(120);
+/// Default for `--connect-timeout-secs`
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Settings shared by every HTTP client: TLS (`--ca-cert`,
+/// `--insecure-skip-tls-verify`) and timeouts (`--http-timeout-secs`,
+/// `--connect-timeout-secs`). The default is strict verification against the
+/// system roots and the default timeouts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpOptions {
+    /// PEM file of extra root certificates to trust, e.g. an internal CA
+    pub ca_cert: Option<PathBuf>,
+    /// Accept any certificate, including self-signed and expired ones
+    pub insecure: bool,
+    /// Limit on a whole request, from connecting to reading the last byte
+    pub timeout: Duration,
+    /// Limit on establishing the connection
+    pub connect_timeout: Duration,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            ca_cert: None,
+            insecure: false,
+            timeout: DEFAULT_HTTP_TIMEOUT,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+        }
+    }
+}
+
+impl HttpOptions {
+    /// Pass these settings on to a child tool through its environment
+    pub fn forward(&self, command: &mut std::process::Command) {
+        if let Some(path) = &self.ca_cert {
+            command.env("RAG_CA_CERT", path);
+        }
+        if self.insecure {
+            command.env("RAG_INSECURE_SKIP_TLS_VERIFY", "true");
+        }
+        command.env("RAG_HTTP_TIMEOUT_SECS", self.timeout.as_secs().to_string());
+        command.env(
+            "RAG_CONNECT_TIMEOUT_SECS",
+            self.connect_timeout.as_secs().to_string(),
+        );
+    }
+}
+
+/// HTTP settings with the CA certificates already read
+struct Http {
+    options: HttpOptions,
+    roots: Vec<reqwest::Certificate>,
+}
+
+impl Http {
+    fn client_builder(&self) -> reqwest::blocking::ClientBuilder {
+        let mut builder = Client::builder()
+            .timeout(self.options.timeout)
+            .connect_timeout(self.options.connect_timeout);
+        for root in &self.roots {
+            builder = builder.add_root_certificate(root.clone());
+        }
+        builder.danger_accept_invalid_certs(self.options.insecure)
+    }
+}
+
+static HTTP: std::sync::OnceLock<Http> = std::sync::OnceLock::new();
+
+fn load_http(options: HttpOptions) -> Result<Http> {
+    let roots = match &options.ca_cert {
+        Some(path) => {
+            let pem = fs::read(path)
+                .with_context(|| format!("Failed to read --ca-cert {}", path.display()))?;
+            let roots = reqwest::Certificate::from_pem_bundle(&pem)
+                .with_context(|| format!("Invalid PEM in --ca-cert {}", path.display()))?;
+            if roots.is_empty() {
+                anyhow::bail!("No certificates found in --ca-cert {}", path.display());
+            }
+            roots
+        }
+        None => Vec::new(),
+    };
+    Ok(Http { options, roots })
+}
+
+/// Use `options` for every HTTP client this process builds from now on. Set
+/// once at startup, before any client is created; later calls are ignored.
+pub fn set_http_options(options: HttpOptions) -> Result<()> {
+    if options.insecure {
+        eprintln!("⚠️  TLS certificate verification is disabled (--insecure-skip-tls-verify)");
+    }
+    let _ = HTTP.set(load_http(options)?);
+    Ok(())
+}
+
+/// The HTTP settings in effect
+pub fn http_options() -> HttpOptions {
+    HTTP.get()
+        .map(|http| http.options.clone())
+        .unwrap_or_default()
+}
+
+/// Client builder with the process's TLS settings and timeouts, for Qdrant
+/// and Ollama alike
+pub fn http_client_builder() -> reqwest::blocking::ClientBuilder {
+    match HTTP.get() {
+        Some(http) => http.client_builder(),
+        None => Http {
+            options: HttpOptions::default(),
+            roots: Vec::new(),
+        }
+        .client_builder(),
+    }
+}
+
+/// Describe a failed request, naming the timeout when that is what fired so
+/// a hung server reads differently from a refused connection
+pub fn request_error(e: &reqwest::Error) -> String {
+    if !e.is_timeout() {
+        return e.to_string();
+    }
+    let options = http_options();
+    if e.is_connect() {
+        format!(
+            "could not connect within {}s (--connect-timeout-secs) to {}",
+            options.connect_timeout.as_secs(),
+            e.url().map(|url| url.as_str()).unwrap_or("the server")
+        )
+    } else {
+        format!(
+            "no response within {}s (--http-timeout-secs) from {}",
+            options.timeout.as_secs(),
+            e.url().map(|url| url.as_str()).unwrap_or("the server")
+        )
+    }
+}
+
+/// `http_client_builder()` with the default settings, in place of `Client::new()`
+pub fn http_client() -> Client {
+    http_client_builder()
+        .build()
+        .expect("Failed to build HTTP client")
+}
+
+/// Qdrant API key (`--qdrant-api-key`); `Debug` prints it redacted so it
+/// can't leak into logs
+#[derive(Clone, PartialEq)]
+pub struct ApiKey(String);
+
+impl ApiKey {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::str::FromStr for ApiKey {
+    type Err = String;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        reqwest::header::HeaderValue::from_str(key)
+            .map(|_| ApiKey(key.to_string()))
+            .map_err(|_| "API key contains characters not allowed in an HTTP header".to_string())
+    }
+}
+
+impl std::fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ApiKey(<redacted>)")
+    }
+}
+
+/// Default for `--index-fields`: the payload fields the search tools filter on
+pub const DEFAULT_INDEX_FIELDS: &str = "source,chunk_type,has_code,parent_id";
+
+/// Type of a payload index, as Qdrant names it in `field_schema`
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum PayloadSchema {
+    /// Exact-match strings such as `source` or `chunk_type`
+    Keyword,
+    Integer,
+    Float,
+    Bool,
+    /// Full-text token index
+    Text,
+}
+
+impl PayloadSchema {
+    pub fn qdrant_name(self) -> &'static str {
+        match self {
+            PayloadSchema::Keyword => "keyword",
+            PayloadSchema::Integer => "integer",
+            PayloadSchema::Float => "float",
+            PayloadSchema::Bool => "bool",
+            PayloadSchema::Text => "text",
+        }
+    }
+
+    /// Schema for a field written by the ingesters, keyword if unknown
+    fn for_field(name: &str) -> Self {
+        match name {
+            "has_code" => PayloadSchema::Bool,
+            "page" | "page_end" | "chunk_index" | "chapter_index" | "start_line" | "end_line"
+            | "index_in_parent" | "char_count" => PayloadSchema::Integer,
+            _ => PayloadSchema::Keyword,
+        }
+    }
+}
+
+/// Payload fields to index (`--index-fields`): comma-separated `name` or
+/// `name:schema`; a bare name gets the schema the ingesters store it with.
+/// An empty list indexes nothing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexFields(pub Vec<(String, PayloadSchema)>);
+
+impl std::str::FromStr for IndexFields {
+    type Err = String;
+
+    fn from_str(list: &str) -> Result<Self, Self::Err> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .map(|field| match field.split_once(':') {
+                Some((name, schema)) => PayloadSchema::from_str(schema.trim(), true)
+                    .map(|schema| (name.trim().to_string(), schema))
+                    .map_err(|_| {
+                        format!(
+                            "unknown index schema '{schema}' for {name}; use keyword, integer, float, bool or text"
+                        )
+                    }),
+                None => Ok((field.to_string(), PayloadSchema::for_field(field))),
+            })
+            .collect::<Result<_, _>>()
+            .map(IndexFields)
+    }
+}
+
+/// HTTP client for Qdrant's REST API, sending `api_key` (if any) as the
+/// `api-key` header on every request
+pub fn qdrant_http_client(api_key: Option<&ApiKey>) -> Client {
+    let mut headers = reqwest::header::HeaderMap::new();
+    if let Some(key) = api_key
+        && let Ok(mut value) = reqwest::header::HeaderValue::from_str(key.as_str())
+    {
+        // Sensitive values print as "Sensitive" in reqwest's Debug output
+        value.set_sensitive(true);
+        headers.insert("api-key", value);
+    }
+    http_client_builder()
+        .default_headers(headers)
+        .build()
+        .expect("Failed to build HTTP client")
+}
+
+/// Minimal Qdrant REST client covering collection setup, upserts and search
+pub struct QdrantClient {
+    client: Client,
+    url: String,
+    consistency: Consistency,
+    vector_name: Option<String>,
+    /// RFC 3339 time the client was created, stamped on upserted points
+    ingested_at: String,
+    /// Embedding model stamped on upserted points as [`EMBED_MODEL_FIELD`]
+    embed_model: Option<String>,
+}
+
+impl QdrantClient {
+    pub fn new(url: &str) -> Self {
+        Self {
+            client: http_client(),
+            url: url.to_string(),
+            consistency: Consistency::default(),
+            vector_name: None,
+            ingested_at: chrono::Utc::now().to_rfc3339(),
+            embed_model: None,
+        }
+    }
+
+    /// Record `model` on every upserted point, where the search tools check
+    /// it against the model they embed queries with
+    pub fn ingest_model(mut self, model: &str) -> Self {
+        self.embed_model = Some(model.to_string());
+        self
+    }
+
+    pub fn consistency(mut self, consistency: Consistency) -> Self {
+        self.consistency = consistency;
+        self
+    }
+
+    /// Search the named dense vector `name`, as in collections created with
+    /// [`CONTENT_VECTOR_NAME`] and [`SUMMARY_VECTOR_NAME`]; `None` searches
+    /// the unnamed one
+    pub fn vector_name(mut self, name: Option<&str>) -> Self {
+        self.vector_name = name.map(str::to_string);
+        self
+    }
+
+    /// A dense query vector, addressed by name when [`QdrantClient::vector_name`] is set
+    fn dense_query(&self, vector: &[f32]) -> Value {
+        match &self.vector_name {
+            Some(name) => json!({ "name": name, "vector": vector }),
+            None => json!(vector),
+        }
+    }
+
+    /// Authenticate with `api_key` (Qdrant Cloud, or a server with
+    /// `service.api_key` set); `None` sends no key
+    pub fn api_key(mut self, api_key: Option<&ApiKey>) -> Self {
+        self.client = qdrant_http_client(api_key);
+        self
+    }
+
+    pub fn collection_exists(&self, collection: &str) -> bool {
+        self.client
+            .get(format!("{}/collections/{}", self.url, collection))
+            .send()
+            .map(|r| r.status().is_success())
+            .unwrap_or(false)
+    }
+
+    pub fn collection_info(&self, collection: &str) -> Result<CollectionResult> {
+        let response = self
+            .client
+            .get(format!("{}/collections/{}", self.url, collection))
+            .send()
+            .context("Failed to get collection info")?;
+
+        if !response.status().is_success() {
+            anyhow::bail!(
+                "Collection info for {} returned: {}",
+                collection,
+                response.status()
+            );
+        }
+
+        let info: QdrantCollectionResponse =
+            response.json().context("Failed to parse collection info")?;
+        Ok(info.result)
+    }
+
+    /// Delete `collection` and every point in it
+    pub fn delete_collection(&self, collection: &str) -> Result<()> {
+        let response = self
+            .client
+            .delete(format!("{}/collections/{}", self.url, collection))
+            .send()
+            .with_context(|| format!("Failed to delete collection {collection}"))?;
+
+        if !response.status().is_success() {
+            anyhow::bail!(
+                "Deleting collection {} returned: {}",
+                collection,
+                response.status()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Ask Qdrant to run its optimizers on `collection` now; an empty
+    /// `optimizers_config` update does this without changing any setting
+    pub fn trigger_optimizers(&self, collection: &str) -> Result<()> {
+        let response = self
+            .client
+            .patch(format!("{}/collections/{}", self.url, collection))
+            .json(&json!({ "optimizers_config": {} }))
+            .send()
+            .with_context(|| format!("Failed to trigger optimizers for {collection}"))?;
+
+        if !response.status().is_success() {
+            anyhow::bail!(
+                "Triggering optimizers for {} returned: {}",
+                collection,
+                response.status()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Poll `collection` every `poll` until its status is green, passing each
+    /// snapshot to `progress`; fails once `timeout` has passed
+    pub fn wait_for_green(
+        &self,
+        collection: &str,
+        timeout: Duration,
+        poll: Duration,
+        mut progress: impl FnMut(&CollectionResult),
+    ) -> Result<CollectionResult> {
+        let started = std::time::Instant::now();
+        loop {
+            let info = self.collection_info(collection)?;
+            progress(&info);
+            if info.status.as_deref() == Some("green") {
+                return Ok(info);
+            }
+            if started.elapsed() >= timeout {
+                anyhow::bail!(
+                    "Collection {} still {} after {}s ({}/{} vectors indexed)",
+                    collection,
+                    info.status.as_deref().unwrap_or("unknown"),
+                    timeout.as_secs(),
+                    info.indexed_vectors_count.unwrap_or(0),
+                    info.points_count.unwrap_or(0)
+                );
+            }
+            std::thread::sleep(poll);
+        }
+    }
+
+    /// Have Qdrant write a snapshot of `collection` on the server, waiting until it is done
+    pub fn create_snapshot(&self, collection: &str) -> Result<SnapshotInfo> {
+        let response = self
+            .client
+            .post(format!(
+                "{}/collections/{}/snapshots?wait=true",
+                self.url, collection
+            ))
+            .timeout(SNAPSHOT_TIMEOUT)
+            .send()
+            .with_context(|| format!("Failed to create a snapshot of {collection}"))?;
+
+        if !response.status().is_success() {
+            anyhow::bail!(
+                "Creating a snapshot of {} returned: {}",
+                collection,
+                response.status()
+            );
+        }
+
+        let body: QdrantSnapshotResponse<SnapshotInfo> = response
+            .json()
+            .context("Failed to parse snapshot response")?;
+        Ok(body.result)
+    }
+
+    /// Snapshots of `collection` stored on the server, in Qdrant's order
+    pub fn list_snapshots(&self, collection: &str) -> Result<Vec<SnapshotInfo>> {
+        let response = self
+            .client
+            .get(format!("{}/collections/{}/snapshots", self.url, collection))
+            .send()
+            .with_context(|| format!("Failed to list snapshots of {collection}"))?;
+
+        if !response.status().is_success() {
+            anyhow::bail!(
+                "Listing snapshots of {} returned: {}",
+                collection,
+                response.status()
+            );
+        }
+
+        let body: QdrantSnapshotResponse<Vec<SnapshotInfo>> =
+            response.json().context("Failed to parse snapshot list")?;
+        Ok(body.result)
+    }
+
+    /// Stream the snapshot file `name` of `collection` into `out`, returning
+    /// the number of bytes written
+    pub fn download_snapshot(
+        &self,
+        collection: &str,
+        name: &str,
+        out: &mut impl std::io::Write,
+    ) -> Result<u64> {
+        let mut response = self
+            .client
+            .get(format!(
+                "{}/collections/{}/snapshots/{}",
+                self.url, collection, name
+            ))
+            .timeout(SNAPSHOT_TIMEOUT)
+            .send()
+            .with_context(|| format!("Failed to download snapshot {name} of {collection}"))?;
+
+        if !response.status().is_success() {
+            anyhow::bail!(
+                "Downloading snapshot {} of {} returned: {}",
+                name,
+                collection,
+                response.status()
+            );
+        }
+
+        response
+            .copy_to(out)
+            .with_context(|| format!("Failed to download snapshot {name} of {collection}"))
+    }
+
+    /// Upload the snapshot file at `path` and recover `collection` from it,
+    /// creating the collection or replacing its points, vectors and config
+    pub fn restore_snapshot(&self, collection: &str, path: &Path) -> Result<()> {
+        let form = reqwest::blocking::multipart::Form::new()
+            .file("snapshot", path)
+            .with_context(|| format!("Failed to read snapshot {}", path.display()))?;
+        let response = self
+            .client
+            .post(format!(
+                "{}/collections/{}/snapshots/upload?wait=true&priority=snapshot",
+                self.url, collection
+            ))
+            .multipart(form)
+            .timeout(SNAPSHOT_TIMEOUT)
+            .send()
+            .with_context(|| format!("Failed to upload snapshot to {collection}"))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let detail = response.text().unwrap_or_default();
+            anyhow::bail!(
+                "Restoring {} from {} returned: {} {}",
+                collection,
+                path.display(),
+                status,
+                detail.trim()
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn list_collections(&self) -> Result<Vec<String>> {
+        let body: Value = self
+            .client
+            .get(format!("{}/collections", self.url))
+            .send()
+            .context("Failed to list Qdrant collections")?
+            .json()
+            .context("Failed to parse collection list")?;
+
+        Ok(body["result"]["collections"]
+            .as_array()
+            .map(|collections| {
+                collections
+                    .iter()
+                    .filter_map(|c| c["name"].as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// Fail with a "not found" error naming similar collections if `collection` is missing
+    pub fn require_collection(&self, collection: &str) -> Result<()> {
+        if self.collection_exists(collection) {
+            return Ok(());
+        }
+        let available = self.list_collections()?;
+        if available.iter().any(|name| name == collection) {
+            return Ok(());
+        }
+        Err(missing_collection_error(collection, &available))
+    }
+
+    /// The embedding model recorded on a point of `collection`, or `None`
+    /// when no point records one, as in collections ingested before the
+    /// payload had it
+    pub fn embed_model(&self, collection: &str) -> Result<Option<String>> {
+        let filter = json!({"must_not": [{"is_empty": {"key": EMBED_MODEL_FIELD}}]});
+        let points = self.scroll_points(collection, &filter, 1)?;
+        Ok(points
+            .first()
+            .and_then(|point| point.payload.get(EMBED_MODEL_FIELD))
+            .and_then(Value::as_str)
+            .map(str::to_string))
+    }
+
+    /// Warn, or with `strict` fail, when `collection` was embedded with a
+    /// model other than `model`: its vectors and the query's are not
+    /// comparable, so every result would be noise. Collections that don't
+    /// record a model are not checked.
+    pub fn check_embed_model(&self, collection: &str, model: &str, strict: bool) -> Result<()> {
+        let Ok(Some(stored)) = self.embed_model(collection) else {
+            return Ok(());
+        };
+        if same_model(&stored, model) {
+            return Ok(());
+        }
+        let message = format!(
+            "Collection '{collection}' was embedded with '{stored}', but --model is '{model}'; results will be meaningless unless you search with --model {stored}"
+        );
+        if strict {
+            anyhow::bail!(message);
+        }
+        eprintln!("⚠️  {message}");
+        Ok(())
+    }
+
+    /// Print a warning for each creation-time setting that existing
+    /// `collection` does not have
+    pub fn warn_existing_settings(
+        &self,
+        collection: &str,
+        distance: Distance,
+        quantization: Quantization,
+    ) {
+        let Ok(info) = self.collection_info(collection) else {
+            return;
+        };
+        let warnings = [
+            distance.mismatch(collection, &info),
+            quantization.mismatch(collection, &info),
+        ];
+        for warning in warnings.into_iter().flatten() {
+            eprintln!("⚠️  {warning}");
+        }
+    }
+
+    pub fn create_collection(&self, collection: &str, config: &Value) -> Result<()> {
+        let response = self
+            .client
+            .put(format!("{}/collections/{}", self.url, collection))
+            .json(config)
+            .send()
+            .context("Failed to create collection")?;
+
+        if !response.status().is_success() {
+            anyhow::bail!(
+                "Collection creation for {} returned: {}",
+                collection,
+                response.status()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Create a payload index on each of `fields`, so filters on them don't
+    /// scan every point. Qdrant accepts an index that already exists, so
+    /// this is safe to repeat after every ingest.
+    pub fn create_payload_indexes(&self, collection: &str, fields: &IndexFields) -> Result<()> {
+        for (name, schema) in &fields.0 {
+            let response = self
+                .client
+                .put(format!(
+                    "{}/collections/{}/index?wait=true",
+                    self.url, collection
+                ))
+                .json(&json!({
+                    "field_name": name,
+                    "field_schema": schema.qdrant_name(),
+                }))
+                .send()
+                .with_context(|| format!("Failed to index payload field {name}"))?;
+
+            if !response.status().is_success() {
+                let error_text = response
+                    .text()
+                    .unwrap_or_else(|_| "Unknown error".to_string());
+                anyhow::bail!("Qdrant payload index on {name} failed: {error_text}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Upsert points, waiting for Qdrant to apply them under strong consistency.
+    /// Payloads without their own `ingested_at` are stamped with the client's
+    /// creation time, so export-collection --since can pick out this run's points,
+    /// and likewise with the client's embedding model, if it has one
+    pub fn upsert_points(&self, collection: &str, points: &[QdrantPoint]) -> Result<()> {
+        let wait = self.consistency == Consistency::Strong;
+        let mut points = serde_json::to_value(points).context("Failed to serialize points")?;
+        for point in points.as_array_mut().into_iter().flatten() {
+            if let Some(payload) = point["payload"].as_object_mut() {
+                payload
+                    .entry("ingested_at")
+                    .or_insert_with(|| json!(self.ingested_at));
+                if let Some(model) = &self.embed_model {
+                    payload
+                        .entry(EMBED_MODEL_FIELD)
+                        .or_insert_with(|| json!(model));
+                }
+            }
+        }
+        let response = self
+            .client
+            .put(format!(
+                "{}/collections/{}/points?wait={}",
+                self.url, collection, wait
+            ))
+            .json(&json!({
+                "points": points
+            }))
+            .send()
+            .with_context(|| format!("Failed to upload to Qdrant collection {collection}"))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response
+                .text()
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            anyhow::bail!(
+                "Qdrant upload to {} failed (HTTP {}): {}",
+                collection,
+                status,
+                error_text
+            );
+        }
+
+        // Qdrant returns HTTP 200 even for some errors, with error in body
+        let body: Value = response.json().context("Failed to parse Qdrant response")?;
+        if let Some(error) = body.get("status").and_then(|s| s.get("error")) {
+            anyhow::bail!("Qdrant upload to {} failed: {}", collection, error);
+        }
+
+        Ok(())
+    }
+
+    /// Number of points matching `filter`. Without `exact` Qdrant may
+    /// estimate it from its indexes, which is cheaper on large collections.
+    pub fn count_points(&self, collection: &str, filter: &Value, exact: bool) -> Result<usize> {
+        let response = self
+            .client
+            .post(format!(
+                "{}/collections/{}/points/count",
+                self.url, collection
+            ))
+            .json(&json!({ "filter": filter, "exact": exact }))
+            .send()
+            .with_context(|| format!("Failed to count points in {collection}"))?;
+
+        if !response.status().is_success() {
+            let error_text = response
+                .text()
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            anyhow::bail!("Qdrant count failed: {}", error_text);
+        }
+
+        let body: Value = response.json().context("Failed to parse count response")?;
+        body["result"]["count"]
+            .as_u64()
+            .map(|count| count as usize)
+            .context("Count response had no result.count")
+    }
+
+    /// Delete every point matching `filter`, waiting until Qdrant has applied it
+    pub fn delete_points(&self, collection: &str, filter: &Value) -> Result<()> {
+        self.delete_with(collection, json!({ "filter": filter }))
+    }
+
+    /// Delete points by ID, waiting until Qdrant has applied it
+    pub fn delete_point_ids(&self, collection: &str, ids: &[String]) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let ids: Vec<Value> = ids.iter().map(|id| point_id(id)).collect();
+        self.delete_with(collection, json!({ "points": ids }))
+    }
+
+    fn delete_with(&self, collection: &str, selector: Value) -> Result<()> {
+        let response = self
+            .client
+            .post(format!(
+                "{}/collections/{}/points/delete?wait=true",
+                self.url, collection
+            ))
+            .json(&selector)
+            .send()
+            .with_context(|| format!("Failed to delete points from {collection}"))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response
+                .text()
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            anyhow::bail!(
+                "Deleting points from {} failed (HTTP {}): {}",
+                collection,
+                status,
+                error_text
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn search(
+        &self,
+        collection: &str,
+        vector: &[f32],
+        limit: usize,
+        filter: Option<&Value>,
+    ) -> Result<Vec<SearchResult>> {
+        let hits = self.search_with(collection, self.dense_query(vector), limit, filter, false)?;
+        Ok(hits.into_iter().map(|hit| hit.result).collect())
+    }
+
+    /// Fetch points with their payloads by ID in one request; unknown IDs are left out
+    pub fn get_points_by_id(&self, collection: &str, ids: &[&str]) -> Result<Vec<RetrievedPoint>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let response = self
+            .client
+            .post(format!("{}/collections/{}/points", self.url, collection))
+            .json(&json!({
+                "ids": ids,
+                "with_payload": true,
+                "with_vector": false,
+            }))
+            .send()
+            .with_context(|| format!("Failed to fetch points from {collection}"))?;
+
+        if !response.status().is_success() {
+            let error_text = response
+                .text()
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            anyhow::bail!("Qdrant point lookup failed: {}", error_text);
+        }
+
+        let retrieved: QdrantRetrieveResponse = response
+            .json()
+            .context("Failed to parse point lookup response")?;
+        Ok(retrieved.result)
+    }
+
+    /// One point with its payload and dense vector, to search for points like it
+    pub fn get_point_with_vector(
+        &self,
+        collection: &str,
+        id: &str,
+    ) -> Result<(RetrievedPoint, Vec<f32>)> {
+        let response = self
+            .client
+            .post(format!("{}/collections/{}/points", self.url, collection))
+            .json(&json!({
+                "ids": [point_id(id)],
+                "with_payload": true,
+                "with_vector": true,
+            }))
+            .send()
+            .with_context(|| format!("Failed to fetch point {id} from {collection}"))?;
+
+        if !response.status().is_success() {
+            let error_text = response
+                .text()
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            anyhow::bail!("Qdrant point lookup failed: {}", error_text);
+        }
+
+        let mut body: Value = response
+            .json()
+            .context("Failed to parse point lookup response")?;
+        let Some(point) = body["result"]
+            .as_array_mut()
+            .and_then(|points| points.first_mut())
+        else {
+            anyhow::bail!("No point {id} in collection '{collection}'");
+        };
+        let vector = dense_vector(&point["vector"])
+            .with_context(|| format!("Qdrant returned no vector for point {id}"))?;
+        let point = RetrievedPoint {
+            id: id.to_string(),
+            payload: point["payload"].take(),
+        };
+        Ok((point, vector))
+    }
+
+    /// First `limit` points matching `filter`, in ID order rather than by relevance
+    pub fn scroll_points(
+        &self,
+        collection: &str,
+        filter: &Value,
+        limit: usize,
+    ) -> Result<Vec<RetrievedPoint>> {
+        let response = self
+            .client
+            .post(format!(
+                "{}/collections/{}/points/scroll",
+                self.url, collection
+            ))
+            .json(&json!({
+                "filter": filter,
+                "limit": limit,
+                "with_payload": true,
+                "with_vector": false,
+            }))
+            .send()
+            .with_context(|| format!("Failed to scroll points in {collection}"))?;
+
+        if !response.status().is_success() {
+            let error_text = response
+                .text()
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            anyhow::bail!("Qdrant scroll failed: {}", error_text);
+        }
+
+        let body: Value = response.json().context("Failed to parse scroll response")?;
+        serde_json::from_value(body["result"]["points"].clone())
+            .context("Scroll response had no result.points")
+    }
+
+    /// Payload of every point in `collection`, restricted to `fields` and fetched
+    /// `batch_size` points per page without vectors
+    pub fn scroll_payloads(
+        &self,
+        collection: &str,
+        fields: &[&str],
+        batch_size: usize,
+    ) -> Result<Vec<Value>> {
+        let mut payloads = Vec::new();
+        self.scroll_pages(collection, fields, batch_size, |points| {
+            payloads.extend(points.into_iter().map(|point| point.payload));
+            Ok(())
+        })?;
+        Ok(payloads)
+    }
+
+    /// Scroll through every point of `collection` without vectors, handing each
+    /// page of `batch_size` points (payload restricted to `fields`) to `page`
+    pub fn scroll_pages(
+        &self,
+        collection: &str,
+        fields: &[&str],
+        batch_size: usize,
+        mut page: impl FnMut(Vec<RetrievedPoint>) -> Result<()>,
+    ) -> Result<()> {
+        self.scroll_with(collection, fields, batch_size, false, |points| {
+            page(points.into_iter().map(|(point, _)| point).collect())
+        })
+    }
+
+    /// Like [`QdrantClient::scroll_pages`], but with each point's dense vector
+    pub fn scroll_vectors(
+        &self,
+        collection: &str,
+        fields: &[&str],
+        batch_size: usize,
+        mut page: impl FnMut(Vec<(RetrievedPoint, Vec<f32>)>) -> Result<()>,
+    ) -> Result<()> {
+        self.scroll_with(collection, fields, batch_size, true, |points| {
+            let points = points
+                .into_iter()
+                .map(|(point, vector)| {
+                    let dense = dense_vector(&vector).with_context(|| {
+                        format!("Qdrant returned no vector for point {}", point.id)
+                    })?;
+                    Ok((point, dense))
+                })
+                .collect::<Result<_>>()?;
+            page(points)
+        })
+    }
+
+    fn scroll_with(
+        &self,
+        collection: &str,
+        fields: &[&str],
+        batch_size: usize,
+        with_vector: bool,
+        mut page: impl FnMut(Vec<(RetrievedPoint, Value)>) -> Result<()>,
+    ) -> Result<()> {
+        let mut offset = Value::Null;
+        loop {
+            let mut request = json!({
+                "limit": batch_size,
+                "with_payload": { "include": fields },
+                "with_vector": with_vector,
+            });
+            if !offset.is_null() {
+                request["offset"] = offset;
+            }
+
+            let response = self
+                .client
+                .post(format!(
+                    "{}/collections/{}/points/scroll",
+                    self.url, collection
+                ))
+                .json(&request)
+                .send()
+                .with_context(|| format!("Failed to scroll points in {collection}"))?;
+            if !response.status().is_success() {
+                let error_text = response
+                    .text()
+                    .unwrap_or_else(|_| "Unknown error".to_string());
+                anyhow::bail!("Qdrant scroll failed: {}", error_text);
+            }
+
+            let mut body: Value = response.json().context("Failed to parse scroll response")?;
+            let Some(points) = body["result"]["points"].as_array_mut() else {
+                anyhow::bail!("Scroll response had no result.points");
+            };
+            // Integer IDs are carried as their decimal string, like UUIDs
+            let points = points
+                .iter_mut()
+                .map(|point| {
+                    let retrieved = RetrievedPoint {
+                        id: match &point["id"] {
+                            Value::String(id) => id.clone(),
+                            id => id.to_string(),
+                        },
+                        payload: point["payload"].take(),
+                    };
+                    (retrieved, point["vector"].take())
+                })
+                .collect();
+            page(points)?;
+
+            offset = body["result"]["next_page_offset"].take();
+            if offset.is_null() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Merge each payload into its point's stored payload, leaving vectors and
+    /// other fields alone, in one batch request applied before returning
+    pub fn set_payloads(&self, collection: &str, updates: &[(String, Value)]) -> Result<()> {
+        if updates.is_empty() {
+            return Ok(());
+        }
+        let operations: Vec<Value> = updates
+            .iter()
+            .map(|(id, payload)| {
+                json!({ "set_payload": { "payload": payload, "points": [point_id(id)] } })
+            })
+            .collect();
+        let response = self
+            .client
+            .post(format!(
+                "{}/collections/{}/points/batch?wait=true",
+                self.url, collection
+            ))
+            .json(&json!({ "operations": operations }))
+            .send()
+            .with_context(|| format!("Failed to update payloads in {collection}"))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response
+                .text()
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            anyhow::bail!(
+                "Updating payloads in {} failed (HTTP {}): {}",
+                collection,
+                status,
+                error_text
+            );
+        }
+        Ok(())
+    }
+
+    /// Like [`QdrantClient::search`], but also return each hit's stored dense vector
+    pub fn search_with_vectors(
+        &self,
+        collection: &str,
+        vector: &[f32],
+        limit: usize,
+        filter: Option<&Value>,
+    ) -> Result<Vec<(SearchResult, Vec<f32>)>> {
+        self.search_with(collection, self.dense_query(vector), limit, filter, true)?
+            .into_iter()
+            .map(|hit| {
+                let dense = hit.dense_vector().with_context(|| {
+                    format!("Qdrant returned no vector for point {}", hit.result.id)
+                })?;
+                Ok((hit.result, dense))
+            })
+            .collect()
+    }
+
+    /// Search the [`SPARSE_VECTOR_NAME`] keyword index instead of the dense vectors
+    pub fn search_sparse(
+        &self,
+        collection: &str,
+        vector: &SparseVector,
+        limit: usize,
+        filter: Option<&Value>,
+    ) -> Result<Vec<SearchResult>> {
+        let named = json!({
+            "name": SPARSE_VECTOR_NAME,
+            "vector": vector,
+        });
+        let hits = self
+            .search_with(collection, named, limit, filter, false)
+            .context(
+                "Sparse search failed; was the collection ingested with ingest-hierarchical?",
+            )?;
+        Ok(hits.into_iter().map(|hit| hit.result).collect())
+    }
+
+    fn search_with(
+        &self,
+        collection: &str,
+        vector: Value,
+        limit: usize,
+        filter: Option<&Value>,
+        with_vector: bool,
+    ) -> Result<Vec<QdrantHit>> {
+        let mut request = json!({
+            "vector": vector,
+            "limit": limit,
+            "with_payload": true,
+            "with_vector": with_vector,
+        });
+        if let Some(f) = filter {
+            request["filter"] = f.clone();
+        }
+        if self.consistency == Consistency::Strong {
+            request["params"] = json!({ "exact": true });
+        }
+
+        let response = self
+            .client
+            .post(format!(
+                "{}/collections/{}/points/search",
+                self.url, collection
+            ))
+            .json(&request)
+            .send()
+            .context("Failed to search Qdrant")?;
+
+        if !response.status().is_success() {
+            let error_text = response
+                .text()
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            anyhow::bail!("Qdrant search failed: {}", error_text);
+        }
+
+        let search_response: QdrantSearchResponse =
+            response.json().context("Failed to parse search response")?;
+
+        Ok(search_response.result)
+    }
+}
+
+/// A Qdrant filter requiring every `--filter` condition (`must`) and none of
+/// the `--exclude` conditions (`must_not`), see [`build_condition`]
+pub fn build_filter(filter_args: &[String], exclude_args: &[String]) -> Result<Value> {
+    let conditions = |args: &[String]| {
+        args.iter()
+            .map(|filter_str| build_condition(filter_str))
+            .collect::<Result<Vec<_>>>()
+    };
+    let must_conditions = conditions(filter_args)?;
+    let must_not_conditions = conditions(exclude_args)?;
+
+    let mut filter = json!({
+        "must": must_conditions
+    });
+    if !must_not_conditions.is_empty() {
+        filter["must_not"] = json!(must_not_conditions);
+    }
+    Ok(filter)
+}
+
+/// One Qdrant condition from `key=value`, `key=a|b|c`, or a `key>=N` style comparison
+pub fn build_condition(filter_str: &str) -> Result<Value> {
+    let Some(op_start) = filter_str.find(['=', '<', '>']) else {
+        anyhow::bail!(
+            "Invalid filter format: '{}'. Use key=value, key=a|b, or key>=N",
+            filter_str
+        );
+    };
+    let key = &filter_str[..op_start];
+    let rest = &filter_str[op_start..];
+    let (op, value) = ["<=", ">=", "<", ">", "="]
+        .into_iter()
+        .find_map(|op| rest.strip_prefix(op).map(|value| (op, value)))
+        .expect("rest starts with an operator character");
+    if key.is_empty() {
+        anyhow::bail!("Invalid filter format: '{}'. Missing key", filter_str);
+    }
+
+    if op != "=" {
+        let bound: f64 = value
+            .parse()
+            .with_context(|| format!("Invalid filter '{filter_str}': {op} needs a number"))?;
+        let range_key = match op {
+            ">=" => "gte",
+            "<=" => "lte",
+            ">" => "gt",
+            _ => "lt",
+        };
+        return Ok(json!({
+            "key": key,
+            "range": { range_key: bound }
+        }));
+    }
+
+    if value.contains('|') {
+        let values: Vec<&str> = value.split('|').filter(|v| !v.is_empty()).collect();
+        return Ok(json!({
+            "key": key,
+            "match": {"any": values}
+        }));
+    }
+
+    // Try to parse as bool
+    let filter_value = if value == "true" || value == "false" {
+        json!({
+            "key": key,
+            "match": {"value": value == "true"}
+        })
+    } else {
+        // Treat as string
+        json!({
+            "key": key,
+            "match": {"value": value}
+        })
+    };
+    Ok(filter_value)
+}
+
+/// Payload field naming the embedding model a point's vector came from
+pub const EMBED_MODEL_FIELD: &str = "embed_model";
+
+/// Whether two Ollama model names are the same model; a name without a tag
+/// means `:latest`
+fn same_model(a: &str, b: &str) -> bool {
+    let tagged = |name: &str| {
+        if name.contains(':') {
+            name.to_string()
+        } else {
+            format!("{name}:latest")
+        }
+    };
+    tagged(a) == tagged(b)
+}
+
+/// Safely truncate a string at a character boundary
+pub fn safe_truncate(s: &str, max_chars: usize) -> &str {
+    if s.chars().count() <= max_chars {
+        s
+    } else {
+        // Find the byte position of the nth character
+        let byte_pos = s
+            .char_indices()
+            .nth(max_chars)
+            .map(|(pos, _)| pos)
+            .unwrap_or(s.len());
+        &s[..byte_pos]
+    }
+}
+
+/// First `max_chars` characters of `text` for display, with "…" if anything was cut
+pub fn preview(text: &str, max_chars: usize) -> String {
+    let truncated = safe_truncate(text, max_chars);
+    if truncated.len() < text.len() {
+        format!("{truncated}…")
+    } else {
+        text.to_string()
+    }
+}
+
+/// How the search tools print their results (`--format`)
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable, with emoji headings
+    Text,
+    Json,
+    /// A numbered list of blockquotes, for pasting into issues and docs
+    Markdown,
+}
+
+/// Search result `number` as a markdown list item: `text` as a blockquote,
+/// then a `**Source:**` line ending with `score`
+pub fn markdown_result(number: usize, text: &str, source: &str, score: &str) -> String {
+    let marker = format!("{number}. ");
+    let indent = " ".repeat(marker.len());
+    let mut item = String::new();
+    for (i, line) in text.trim().lines().enumerate() {
+        item.push_str(if i == 0 { &marker } else { &indent });
+        item.push('>');
+        if !line.trim().is_empty() {
+            item.push(' ');
+            item.push_str(line.trim_end());
+        }
+        item.push('\n');
+    }
+    if item.is_empty() {
+        item.push_str(&marker);
+        item.push_str("> _(no text)_\n");
+    }
+    item.push('\n');
+    item.push_str(&format!(
+        "{indent}**Source:** {source} · **Score:** {score}\n"
+    ));
+    item
+}
+
+/// Characters shown either side of the best match in a result snippet
+pub const SNIPPET_RADIUS: usize = 120;
+
+/// Lowercased query words longer than two characters, the terms that keyword
+/// scoring and snippets look for
+pub fn query_terms(query: &str) -> Vec<String> {
+    query
+        .to_lowercase()
+        .split_whitespace()
+        .filter(|t| t.len() > 2) // Ignore very short words
+        .map(str::to_string)
+        .collect()
+}
+
+/// How `snippet` marks the query terms it finds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Highlight {
+    /// `**term**`
+    Markdown,
+    /// ANSI bold, for a terminal
+    Ansi,
+}
+
+impl Highlight {
+    /// ANSI bold when stdout is a terminal, else markdown
+    pub fn for_stdout() -> Self {
+        if std::io::stdout().is_terminal() {
+            Highlight::Ansi
+        } else {
+            Highlight::Markdown
+        }
+    }
+
+    fn mark(self, term: &str) -> String {
+        match self {
+            Highlight::Markdown => format!("**{term}**"),
+            Highlight::Ansi => colored::Colorize::bold(term).to_string(),
+        }
+    }
+}
+
+/// Char ranges of case-insensitive, non-overlapping matches of `terms` in
+/// `chars`, preferring the longest term at each position
+fn term_matches(chars: &[char], terms: &[String]) -> Vec<std::ops::Range<usize>> {
+    // Lowercase char by char so positions still line up with `chars`
+    let lower: Vec<char> = chars
+        .iter()
+        .map(|&c| {
+            let mut lower = c.to_lowercase();
+            if lower.len() == 1 {
+                lower.next().unwrap_or(c)
+            } else {
+                c
+            }
+        })
+        .collect();
+    let terms: Vec<Vec<char>> = terms.iter().map(|t| t.chars().collect()).collect();
+    let mut matches = Vec::new();
+    let mut i = 0;
+    while i < lower.len() {
+        let longest = terms
+            .iter()
+            .filter(|t| !t.is_empty() && lower[i..].starts_with(t))
+            .map(|t| t.len())
+            .max();
+        match longest {
+            Some(len) => {
+                matches.push(i..i + len);
+                i += len;
+            }
+            None => i += 1,
+        }
+    }
+    matches
+}
+
+/// About `2 * radius` characters of `text` around where `terms` (from
+/// `query_terms`) occur most densely, with each match marked by `highlight`
+/// and "…" where text was cut. Falls back to the head of the text, as
+/// `preview` shows it, when no term occurs.
+pub fn snippet(text: &str, terms: &[String], radius: usize, highlight: Highlight) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let matches = term_matches(&chars, terms);
+    if matches.is_empty() {
+        return preview(text, 2 * radius);
+    }
+
+    // The run of matches that fits in the widest window and holds the most
+    let width = 2 * radius;
+    let (mut first, mut count) = (0, 1);
+    for (i, m) in matches.iter().enumerate() {
+        let fits = matches[i..]
+            .iter()
+            .take_while(|other| other.end <= m.start + width)
+            .count();
+        if fits > count {
+            (first, count) = (i, fits);
+        }
+    }
+    let last = first + count - 1;
+
+    // Center the window on that run, then trim it to whole words
+    let (span_start, span_end) = (matches[first].start, matches[last].end);
+    let mid = (span_start + span_end) / 2;
+    let mut end = (mid.saturating_sub(radius) + width).min(chars.len());
+    let mut start = end.saturating_sub(width);
+    if start > 0
+        && !chars[start - 1].is_whitespace()
+        && let Some(space) = chars[start..span_start]
+            .iter()
+            .position(|c| c.is_whitespace())
+    {
+        start += space + 1;
+    }
+    if end < chars.len()
+        && !chars[end].is_whitespace()
+        && let Some(space) = chars[span_end..end].iter().rposition(|c| c.is_whitespace())
+    {
+        end = span_end + space;
+    }
+
+    let mut out = String::new();
+    if start > 0 {
+        out.push('…');
+    }
+    let mut pos = start;
+    for m in matches.iter().filter(|m| m.start >= start && m.end <= end) {
+        out.extend(&chars[pos..m.start]);
+        out.push_str(&highlight.mark(&chars[m.clone()].iter().collect::<String>()));
+        pos = m.end;
+    }
+    out.extend(&chars[pos..end]);
+    if end < chars.len() {
+        out.push('…');
+    }
+    out
+}
+
+/// Stored as `lang` when a chunk's language can't be told reliably (ISO 639 "undetermined")
+pub const UNDETERMINED_LANG: &str = "und";
+
+/// Fewer letters of prose than this are too little to detect a language from
+const MIN_LANG_LETTERS: usize = 40;
+
+/// ISO 639-1 code of the natural language of `text`, ignoring fenced code, or
+/// [`UNDETERMINED_LANG`] when there is too little prose or detection is unsure
+pub fn detect_lang(text: &str) -> &'static str {
+    let mut in_code_block = false;
+    let mut prose = String::new();
+    for line in text.lines() {
+        if line.trim().starts_with("```") {
+            in_code_block = !in_code_block;
+        } else if !in_code_block {
+            prose.push_str(line);
+            prose.push('\n');
+        }
+    }
+
+    if prose.chars().filter(|c| c.is_alphabetic()).count() < MIN_LANG_LETTERS {
+        return UNDETERMINED_LANG;
+    }
+    match whatlang::detect(&prose) {
+        Some(info) if info.is_reliable() => iso_639_1(info.lang()),
+        _ => UNDETERMINED_LANG,
+    }
+}
+
+fn iso_639_1(lang: Lang) -> &'static str {
+    match lang {
+        Lang::Epo => "eo",
+        Lang::Eng => "en",
+        Lang::Rus => "ru",
+        Lang::Cmn => "zh",
+        Lang::Spa => "es",
+        Lang::Por => "pt",
+        Lang::Ita => "it",
+        Lang::Ben => "bn",
+        Lang::Fra => "fr",
+        Lang::Deu => "de",
+        Lang::Ukr => "uk",
+        Lang::Kat => "ka",
+        Lang::Ara => "ar",
+        Lang::Hin => "hi",
+        Lang::Jpn => "ja",
+        Lang::Heb => "he",
+        Lang::Yid => "yi",
+        Lang::Pol => "pl",
+        Lang::Amh => "am",
+        Lang::Jav => "jv",
+        Lang::Kor => "ko",
+        Lang::Nob => "nb",
+        Lang::Dan => "da",
+        Lang::Swe => "sv",
+        Lang::Fin => "fi",
+        Lang::Tur => "tr",
+        Lang::Nld => "nl",
+        Lang::Hun => "hu",
+        Lang::Ces => "cs",
+        Lang::Ell => "el",
+        Lang::Bul => "bg",
+        Lang::Bel => "be",
+        Lang::Mar => "mr",
+        Lang::Kan => "kn",
+        Lang::Ron => "ro",
+        Lang::Slv => "sl",
+        Lang::Hrv => "hr",
+        Lang::Srp => "sr",
+        Lang::Mkd => "mk",
+        Lang::Lit => "lt",
+        Lang::Lav => "lv",
+        Lang::Est => "et",
+        Lang::Tam => "ta",
+        Lang::Vie => "vi",
+        Lang::Urd => "ur",
+        Lang::Tha => "th",
+        Lang::Guj => "gu",
+        Lang::Uzb => "uz",
+        Lang::Pan => "pa",
+        Lang::Aze => "az",
+        Lang::Ind => "id",
+        Lang::Tel => "te",
+        Lang::Pes => "fa",
+        Lang::Mal => "ml",
+        Lang::Ori => "or",
+        Lang::Mya => "my",
+        Lang::Nep => "ne",
+        Lang::Sin => "si",
+        Lang::Khm => "km",
+        Lang::Tuk => "tk",
+        Lang::Aka => "ak",
+        Lang::Zul => "zu",
+        Lang::Sna => "sn",
+        Lang::Afr => "af",
+        Lang::Lat => "la",
+        Lang::Slk => "sk",
+        Lang::Cat => "ca",
+        Lang::Tgl => "tl",
+        Lang::Hye => "hy",
+        Lang::Cym => "cy",
+    }
+}
+
+/// Language tag of an opening code fence: "```rust,ignore" gives "rust", and a
+/// fence without an info string gives "unknown"
+pub fn fence_lang(fence: &str) -> &str {
+    fence
+        .trim()
+        .trim_start_matches(['`', '~'])
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .next()
+        .map(|lang| lang.trim_matches(['{', '}', '.']))
+        .filter(|lang| !lang.is_empty())
+        .unwrap_or("unknown")
+}
+
+/// Level of an ATX heading line ("## Setup" is 2), or None for any other line
+pub fn heading_level(line: &str) -> Option<usize> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let rest = &line[level..];
+    ((1..=6).contains(&level) && (rest.is_empty() || rest.starts_with([' ', '\t'])))
+        .then_some(level)
+}
+
+/// Join heading lines into a breadcrumb such as "Guide > Install > Linux"
+pub fn breadcrumb(heading_lines: &[String]) -> String {
+    heading_lines
+        .iter()
+        .map(|line| line.trim().trim_start_matches('#').trim())
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join(" > ")
+}
+
+/// Headings enclosing the current line of a markdown document, outermost
+/// first. A new heading closes every open heading at its level or deeper.
+#[derive(Debug, Clone, Default)]
+pub struct HeadingStack {
+    headings: Vec<(usize, String)>,
+}
+
+impl HeadingStack {
+    /// Record `line` if it is a heading, returning its level
+    pub fn push_line(&mut self, line: &str) -> Option<usize> {
+        let level = heading_level(line)?;
+        self.headings.retain(|(open, _)| *open < level);
+        self.headings.push((level, line.trim_end().to_string()));
+        Some(level)
+    }
+
+    /// Open heading lines as written, e.g. `["# Guide", "## Install"]`
+    pub fn lines(&self) -> Vec<String> {
+        self.headings.iter().map(|(_, line)| line.clone()).collect()
+    }
+
+    /// Open headings joined as a breadcrumb, e.g. "Guide > Install"
+    pub fn breadcrumb(&self) -> String {
+        breadcrumb(&self.lines())
+    }
+}
+
+/// A markdown chunk: a code block kept whole, or text up to a paragraph break
+#[derive(Debug, Clone)]
+pub struct MarkdownChunk {
+    pub content: String,
+    pub chunk_type: MarkdownChunkType,
+    /// Enclosing heading lines, prepended to code blocks when embedding
+    pub header_context: String,
+    /// Enclosing heading texts, e.g. "Guide > Install > Linux"
+    pub breadcrumb: String,
+    /// Fence language of a code block chunk ("unknown" if untagged)
+    pub code_lang: Option<String>,
+    pub index: usize,
+    /// Source lines the chunk came from (0-based, inclusive); the pieces of
+    /// a chunk split by `enforce_max_tokens` keep the whole chunk's range
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+impl MarkdownChunk {
+    /// Text to embed for the chunk; code blocks include their header context
+    pub fn embedding_text(&self) -> String {
+        if matches!(self.chunk_type, MarkdownChunkType::CodeBlock)
+            && !self.header_context.is_empty()
+        {
+            format!("{}\n\n{}", self.header_context, self.content)
+        } else {
+            self.content.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub enum MarkdownChunkType {
+    Header,
+    CodeBlock,
+    Text,
+    List,
+    Table,
+}
+
+/// Chunk markdown at headings and code fences, breaking text at the first
+/// blank line after `chunk_size` tokens
+pub fn smart_chunk_markdown(
+    content: &str,
+    chunk_size: usize,
+    counter: TokenCounter,
+) -> Vec<MarkdownChunk> {
+    let mut chunks = Vec::new();
+    let lines: Vec<&str> = content.lines().collect();
+    let mut current_chunk = String::new();
+    let mut headings = HeadingStack::default();
+    let mut in_code_block = false;
+    let mut code_block = String::new();
+    let mut code_lang = String::new();
+    let mut chunk_index = 0;
+    // First line of the text and code block being collected
+    let mut text_start = 0;
+    let mut code_start = 0;
+    let last_line = lines.len().saturating_sub(1);
+
+    for (line_no, line) in lines.into_iter().enumerate() {
+        // Detect headers
+        if line.starts_with('#') && !in_code_block {
+            // Save previous chunk if exists
+            if !current_chunk.is_empty() {
+                chunks.push(MarkdownChunk {
+                    content: current_chunk.clone(),
+                    chunk_type: MarkdownChunkType::Text,
+                    header_context: headings.lines().join("\n"),
+                    breadcrumb: headings.breadcrumb(),
+                    code_lang: None,
+                    index: chunk_index,
+                    start_line: text_start,
+                    end_line: line_no - 1,
+                });
+                chunk_index += 1;
+                current_chunk.clear();
+            }
+
+            headings.push_line(line);
+            text_start = line_no;
+
+            current_chunk.push_str(line);
+            current_chunk.push('\n');
+        }
+        // Detect code blocks
+        else if line.trim().starts_with("```") {
+            if in_code_block {
+                // End of code block
+                code_block.push_str(line);
+                code_block.push('\n');
+
+                // Save code block as single chunk (don't split code)
+                chunks.push(MarkdownChunk {
+                    content: code_block.clone(),
+                    chunk_type: MarkdownChunkType::CodeBlock,
+                    header_context: headings.lines().join("\n"),
+                    breadcrumb: headings.breadcrumb(),
+                    code_lang: Some(code_lang.clone()),
+                    index: chunk_index,
+                    start_line: code_start,
+                    end_line: line_no,
+                });
+                chunk_index += 1;
+
+                code_block.clear();
+                in_code_block = false;
+            } else {
+                // Start of code block
+                // Save current chunk if exists
+                if !current_chunk.is_empty() {
+                    chunks.push(MarkdownChunk {
+                        content: current_chunk.clone(),
+                        chunk_type: MarkdownChunkType::Text,
+                        header_context: headings.lines().join("\n"),
+                        breadcrumb: headings.breadcrumb(),
+                        code_lang: None,
+                        index: chunk_index,
+                        start_line: text_start,
+                        end_line: line_no - 1,
+                    });
+                    chunk_index += 1;
+                    current_chunk.clear();
+                }
+
+                in_code_block = true;
+                code_start = line_no;
+                code_lang = fence_lang(line).to_string();
+                code_block.push_str(line);
+                code_block.push('\n');
+            }
+        } else if in_code_block {
+            // Inside code block
+            code_block.push_str(line);
+            code_block.push('\n');
+        } else {
+            // Regular text
+            if current_chunk.is_empty() {
+                text_start = line_no;
+            }
+            current_chunk.push_str(line);
+            current_chunk.push('\n');
+
+            // Check if chunk is getting too large
+            if counter.count(&current_chunk) > chunk_size {
+                // Try to break at paragraph boundary
+                if line.trim().is_empty() {
+                    chunks.push(MarkdownChunk {
+                        content: current_chunk.clone(),
+                        chunk_type: MarkdownChunkType::Text,
+                        header_context: headings.lines().join("\n"),
+                        breadcrumb: headings.breadcrumb(),
+                        code_lang: None,
+                        index: chunk_index,
+                        start_line: text_start,
+                        end_line: line_no,
+                    });
+                    chunk_index += 1;
+                    current_chunk.clear();
+                }
+            }
+        }
+    }
+
+    // Save any remaining content
+    if !current_chunk.is_empty() {
+        chunks.push(MarkdownChunk {
+            content: current_chunk,
+            chunk_type: MarkdownChunkType::Text,
+            header_context: headings.lines().join("\n"),
+            breadcrumb: headings.breadcrumb(),
+            code_lang: None,
+            index: chunk_index,
+            start_line: text_start,
+            end_line: last_line,
+        });
+    }
+
+    if !code_block.is_empty() {
+        chunks.push(MarkdownChunk {
+            content: code_block,
+            chunk_type: MarkdownChunkType::CodeBlock,
+            header_context: headings.lines().join("\n"),
+            breadcrumb: headings.breadcrumb(),
+            code_lang: Some(code_lang),
+            index: chunk_index,
+            start_line: code_start,
+            end_line: last_line,
+        });
+    }
+
+    chunks
+}
+
+/// Split chunks over `max_tokens` (typically long code blocks) and renumber them
+pub fn enforce_max_tokens(
+    chunks: Vec<MarkdownChunk>,
+    max_tokens: usize,
+    counter: TokenCounter,
+) -> Vec<MarkdownChunk> {
+    let mut result = Vec::with_capacity(chunks.len());
+    for chunk in chunks {
+        for piece in counter.split(&chunk.content, max_tokens) {
+            result.push(MarkdownChunk {
+                content: piece,
+                index: result.len(),
+                ..chunk.clone()
+            });
+        }
+    }
+    result
+}
+
+/// Prepend to each text chunk the last `overlap` tokens of the text chunk
+/// before it in the same section, so a passage cut at a paragraph break is
+/// whole in at least one chunk. Code blocks neither get nor give overlap, and
+/// a chunk that opens a new heading starts clean. The carried text begins at
+/// a word boundary; line ranges are left as they were.
+pub fn overlap_text_chunks(
+    mut chunks: Vec<MarkdownChunk>,
+    overlap: usize,
+    counter: TokenCounter,
+) -> Vec<MarkdownChunk> {
+    if overlap == 0 {
+        return chunks;
+    }
+    // Walk backwards so each tail comes from the previous chunk's own text
+    for i in (1..chunks.len()).rev() {
+        let (before, after) = chunks.split_at_mut(i);
+        let (previous, chunk) = (&before[i - 1], &mut after[0]);
+        let is_text = |c: &MarkdownChunk| matches!(c.chunk_type, MarkdownChunkType::Text);
+        if !is_text(previous)
+            || !is_text(chunk)
+            || previous.breadcrumb != chunk.breadcrumb
+            || chunk.content.starts_with('#')
+        {
+            continue;
+        }
+        let mut tail = counter.tail(&previous.content, overlap);
+        if tail.len() < previous.content.len()
+            && !previous.content[..previous.content.len() - tail.len()]
+                .ends_with(char::is_whitespace)
+        {
+            // Drop the partial word the tail starts with
+            tail = tail
+                .find(char::is_whitespace)
+                .map_or("", |space| &tail[space..]);
+        }
+        let tail = tail.trim_start();
+        if !tail.is_empty() {
+            chunk.content = format!("{tail}{}", chunk.content);
+        }
+    }
+    chunks
+}
+
+/// What `merge_small_chunks` did with the text chunks under the minimum
+#[derive(Debug, Default, PartialEq)]
+pub struct SmallChunkStats {
+    /// Merged into a neighbouring text chunk
+    pub merged: usize,
+    /// Headings with no text of their own, dropped
+    pub dropped: usize,
+}
+
+impl std::ops::AddAssign for SmallChunkStats {
+    fn add_assign(&mut self, other: Self) {
+        self.merged += other.merged;
+        self.dropped += other.dropped;
+    }
+}
+
+impl SmallChunkStats {
+    /// Report what was merged or dropped, if anything
+    pub fn print(&self) {
+        if *self != Self::default() {
+            println!(
+                "🧹 Small chunks: {} merged into their neighbours, {} empty headings dropped",
+                self.merged, self.dropped
+            );
+        }
+    }
+}
+
+fn only_headings(text: &str) -> bool {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .all(|line| heading_level(line).is_some())
+}
+
+/// Fold text chunks under `min_tokens` (a lone heading, a stray short line)
+/// into their neighbours and renumber. A chunk opening with a heading is
+/// merged forward into the text after it, so the heading stays with its
+/// content; other small chunks join the text before them in the same
+/// section, else the text after them. Headings with nothing but a code block
+/// (whose header context names them) or the end of the document after them
+/// are dropped. Code blocks, and small text with no text chunk to join, are
+/// kept as they are.
+pub fn merge_small_chunks(
+    chunks: Vec<MarkdownChunk>,
+    min_tokens: usize,
+    counter: TokenCounter,
+) -> (Vec<MarkdownChunk>, SmallChunkStats) {
+    let mut stats = SmallChunkStats::default();
+    if min_tokens == 0 {
+        return (chunks, stats);
+    }
+    let is_text = |c: &MarkdownChunk| matches!(c.chunk_type, MarkdownChunkType::Text);
+    let mut result: Vec<MarkdownChunk> = Vec::with_capacity(chunks.len());
+    // A small chunk waiting to be prepended to the next text chunk
+    let mut carry: Option<MarkdownChunk> = None;
+
+    for mut chunk in chunks {
+        if let Some(small) = carry.take() {
+            let joins = is_text(&chunk)
+                && (small.content.starts_with('#') || small.breadcrumb == chunk.breadcrumb);
+            if joins {
+                chunk.content = format!("{}{}", small.content, chunk.content);
+                chunk.start_line = small.start_line;
+                stats.merged += 1;
+            } else if only_headings(&small.content) {
+                stats.dropped += 1;
+            } else {
+                result.push(small);
+            }
+        }
+        if !is_text(&chunk) || counter.count(chunk.content.trim()) >= min_tokens {
+            result.push(chunk);
+            continue;
+        }
+        if chunk.content.starts_with('#') {
+            carry = Some(chunk);
+            continue;
+        }
+        match result.last_mut() {
+            Some(previous) if is_text(previous) && previous.breadcrumb == chunk.breadcrumb => {
+                previous.content.push_str(&chunk.content);
+                previous.end_line = chunk.end_line;
+                stats.merged += 1;
+            }
+            _ => carry = Some(chunk),
+        }
+    }
+    if let Some(small) = carry {
+        if only_headings(&small.content) {
+            stats.dropped += 1;
+        } else {
+            result.push(small);
+        }
+    }
+
+    for (index, chunk) in result.iter_mut().enumerate() {
+        chunk.index = index;
+    }
+    (result, stats)
+}
+
+/// Chunk metadata listed by `--dry-run`
+pub fn markdown_chunk_plan(chunks: &[MarkdownChunk], counter: TokenCounter) -> Vec<ChunkPlan> {
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| ChunkPlan {
+            index,
+            chunk_type: format!("{:?}", chunk.chunk_type).to_lowercase(),
+            chars: chunk.content.chars().count(),
+            tokens: counter.count(&chunk.content),
+            header_context: chunk.breadcrumb.clone(),
+            start_line: Some(chunk.start_line),
+            end_line: Some(chunk.end_line),
+        })
+        .collect()
+}
+
+/// Elements that hold scripts, styling or site chrome rather than page content
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "head", "script", "style", "noscript", "template", "nav", "footer", "aside", "svg", "iframe",
+    "form", "button",
+];
+
+/// Elements that start a new paragraph
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "div",
+    "section",
+    "article",
+    "main",
+    "header",
+    "blockquote",
+    "ul",
+    "ol",
+    "dl",
+    "dt",
+    "dd",
+    "table",
+    "tr",
+    "figure",
+    "figcaption",
+    "details",
+    "summary",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+];
+
+/// Builds markdown from an HTML tree: collapsed inline text, `#` headings
+/// for `<h1>`-`<h3>`, and fenced code blocks for `<pre>`
+#[derive(Default)]
+struct MarkdownWriter {
+    out: String,
+    inline: String,
+    /// Marker for the paragraph being collected, e.g. "- " inside `<li>`
+    prefix: &'static str,
+}
+
+impl MarkdownWriter {
+    /// End the current paragraph, collapsing its whitespace
+    fn flush(&mut self, separator: &str) {
+        let text = collapse_whitespace(&self.inline);
+        self.inline.clear();
+        let prefix = std::mem::take(&mut self.prefix);
+        if text.is_empty() {
+            return;
+        }
+        if prefix.is_empty() {
+            self.end_list();
+        }
+        // A paragraph must not read as a heading or a fence to the markdown chunker
+        if text.starts_with('#') || text.starts_with("```") {
+            self.out.push('\\');
+        }
+        self.out.push_str(prefix);
+        self.out.push_str(&text);
+        self.out.push_str(separator);
+    }
+
+    /// Leave a blank line after list items, which are written one per line
+    fn end_list(&mut self) {
+        if self.out.ends_with('\n') && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+
+    fn element(&mut self, element: ElementRef) {
+        let name = element.value().name();
+        if SKIPPED_ELEMENTS.contains(&name) {
+            return;
+        }
+        match name {
+            "h1" | "h2" | "h3" => {
+                self.flush("\n\n");
+                self.end_list();
+                let text = collapse_whitespace(&element.text().collect::<String>());
+                if !text.is_empty() {
+                    let level = usize::from(name.as_bytes()[1] - b'0');
+                    self.out
+                        .push_str(&format!("{} {text}\n\n", "#".repeat(level)));
+                }
+            }
+            "pre" => {
+                self.flush("\n\n");
+                self.end_list();
+                let code: String = element.text().collect();
+                let code = code.trim_matches('\n');
+                if !code.trim().is_empty() {
+                    self.out
+                        .push_str(&format!("```{}\n{code}\n```\n\n", code_lang(element)));
+                }
+            }
+            "code" | "kbd" | "samp" => {
+                self.inline.push('`');
+                self.inline
+                    .push_str(&collapse_whitespace(&element.text().collect::<String>()));
+                self.inline.push('`');
+            }
+            "br" => self.flush("\n\n"),
+            "li" => {
+                self.flush("\n\n");
+                self.prefix = "- ";
+                self.children(element);
+                self.flush("\n");
+            }
+            _ if BLOCK_ELEMENTS.contains(&name) => {
+                self.flush("\n\n");
+                self.children(element);
+                self.flush("\n\n");
+            }
+            // Table cells and inline elements run on within the paragraph
+            _ => {
+                self.children(element);
+                if matches!(name, "td" | "th") {
+                    self.inline.push(' ');
+                }
+            }
+        }
+    }
+
+    fn children(&mut self, element: ElementRef) {
+        for child in element.children() {
+            if let Some(child) = ElementRef::wrap(child) {
+                self.element(child);
+            } else if let Node::Text(text) = child.value() {
+                self.inline.push_str(text);
+            }
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.flush("\n\n");
+        self.out.trim_end().to_string() + "\n"
+    }
+}
+
+/// Replace every run of whitespace with a single space and trim the ends
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Language of a `<pre>` block from a `language-x` or `lang-x` class on it or
+/// on its `<code>`, as highlighters mark them
+fn code_lang(pre: ElementRef) -> String {
+    let code = Selector::parse("code").expect("selector is valid");
+    std::iter::once(pre)
+        .chain(pre.select(&code))
+        .flat_map(|element| element.value().classes())
+        .find_map(|class| {
+            class
+                .strip_prefix("language-")
+                .or_else(|| class.strip_prefix("lang-"))
+        })
+        .unwrap_or("")
+        .to_string()
+}
+
+/// The page's main content as markdown: `<main>`, or its only `<article>`,
+/// or else the whole `<body>`
+pub fn html_to_markdown(html: &Html) -> String {
+    let select = |selector: &str| {
+        let selector = Selector::parse(selector).expect("selector is valid");
+        html.select(&selector).collect::<Vec<_>>()
+    };
+    let root = select("main")
+        .first()
+        .copied()
+        .or_else(|| match select("article").as_slice() {
+            [article] => Some(*article),
+            _ => None,
+        })
+        .or_else(|| select("body").first().copied())
+        .unwrap_or_else(|| html.root_element());
+
+    let mut writer = MarkdownWriter::default();
+    writer.children(root);
+    writer.finish()
+}
+
+/// Progress bar with rate and ETA over `len` items, hidden under `--quiet`
+/// or when stdout is not a terminal so piped output stays clean
+pub fn progress_bar(len: usize, message: &str, quiet: bool) -> ProgressBar {
+    if quiet || !std::io::stdout().is_terminal() {
+        return ProgressBar::hidden();
+    }
+    let style =
+        ProgressStyle::with_template("  {msg} [{bar:30}] {pos}/{len} ({per_sec}, ETA {eta})")
+            .expect("progress template is valid")
+            .progress_chars("=> ");
+    ProgressBar::new(len as u64)
+        .with_style(style)
+        .with_message(message.to_string())
+}
+
+/// Keep only the first `max_chunks` chunks (`--max-chunks`), for quick trial
+/// runs on big documents, and say so on stderr
+pub fn limit_chunks<T>(chunks: &mut Vec<T>, max_chunks: Option<usize>) -> Result<()> {
+    match max_chunks {
+        Some(0) => anyhow::bail!("--max-chunks must be greater than zero"),
+        Some(max) if chunks.len() > max => {
+            eprintln!(
+                "✂️  Limiting to {max} of {} chunks (--max-chunks)",
+                chunks.len()
+            );
+            chunks.truncate(max);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// One chunk as listed by an ingest binary's `--dry-run`
+#[derive(Debug, Clone, Serialize)]
+pub struct ChunkPlan {
+    pub index: usize,
+    pub chunk_type: String,
+    pub chars: usize,
+    pub tokens: usize,
+    /// Enclosing headings, empty for text without structure
+    pub header_context: String,
+    /// Source line range, for chunkers that track lines
+    pub start_line: Option<usize>,
+    pub end_line: Option<usize>,
+}
+
+impl ChunkPlan {
+    fn row(&self) -> String {
+        let lines = match (self.start_line, self.end_line) {
+            (Some(start), Some(end)) => format!("{start}-{end}"),
+            _ => "-".to_string(),
+        };
+        format!(
+            "{:>5}  {:<12} {:>7} {:>7}  {:<11} {}",
+            self.index, self.chunk_type, self.chars, self.tokens, lines, self.header_context
+        )
+        .trim_end()
+        .to_string()
+    }
+}
+
+/// Print the chunks an ingest run would embed, as a table or a JSON array
+pub fn print_chunk_plan(plan: &[ChunkPlan], json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(plan)?);
+        return Ok(());
+    }
+    println!(
+        "{:>5}  {:<12} {:>7} {:>7}  {:<11} headers",
+        "index", "type", "chars", "tokens", "lines"
+    );
+    for chunk in plan {
+        println!("{}", chunk.row());
+    }
+    println!(
+        "🔍 Dry run: {} chunks, nothing embedded or uploaded",
+        plan.len()
+    );
+    Ok(())
+}
+
+/// Lowercase `name` and turn every run of characters other than ASCII letters
+/// and digits into a single `-`, so names derived from directories are valid
+/// and match what users type at query time
+pub fn normalize_collection_name(name: &str) -> String {
+    name.to_ascii_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// `collection` inside a tenant's `--collection-prefix` and
+/// `--collection-suffix`, e.g. "acme_" + "docs" = "acme_docs". The multi-scale
+/// tools append `_small`/`_medium`/`_large` to this name. When either is set
+/// the result is logged to stderr, so a run shows which collection it uses.
+pub fn namespaced_collection(prefix: &str, collection: &str, suffix: &str) -> String {
+    let name = format!("{prefix}{collection}{suffix}");
+    if !prefix.is_empty() || !suffix.is_empty() {
+        eprintln!("🏷️  Collection: {name}");
+    }
+    name
+}
+
+/// Error for a search against a collection that does not exist
+pub fn missing_collection_error(collection: &str, available: &[String]) -> anyhow::Error {
+    let similar = similar_names(collection, available);
+    let listed = if similar.is_empty() {
+        available.to_vec()
+    } else {
+        similar
+    };
+    anyhow::anyhow!(
+        "Collection '{}' not found, available: [{}]. Did you mean one of these, or do you need to ingest documents into it first?",
+        collection,
+        listed.join(", ")
+    )
+}
+
+/// Check that Qdrant and the embedding server are reachable and, for the Ollama
+/// APIs, that `model` has been pulled, so a missing service fails with a fix-it
+/// hint instead of a connection or JSON parse error halfway through a run
+pub fn preflight(qdrant_url: &str, ollama_url: &str, model: &str, api: EmbedApi) -> Result<()> {
+    let client = http_client_builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .context("Failed to build HTTP client")?;
+
+    // Qdrant answers /healthz without an API key
+    let qdrant_ok = client
+        .get(format!("{qdrant_url}/healthz"))
+        .send()
+        .is_ok_and(|response| response.status().is_success());
+    if !qdrant_ok {
+        anyhow::bail!(
+            "Qdrant is not reachable at {qdrant_url}. Please run: ./scripts/setup-qdrant.sh"
+        );
+    }
+
+    match api {
+        EmbedApi::Ollama | EmbedApi::OllamaEmbed => {
+            if !ollama_has_model(ollama_url, model)? {
+                anyhow::bail!(
+                    "Embedding model '{model}' is not available in Ollama at {ollama_url}; run: ollama pull {model}"
+                );
+            }
+        }
+        EmbedApi::Openai => {
+            client
+                .get(ollama_url)
+                .send()
+                .with_context(|| format!("Embedding server is not reachable at {ollama_url}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether Ollama's `/api/tags` lists `model`, where an untagged name matches `:latest`
+pub fn ollama_has_model(ollama_url: &str, model: &str) -> Result<bool> {
+    let response = http_client()
+        .get(format!("{ollama_url}/api/tags"))
+        .send()
+        .with_context(|| {
+            format!("Ollama is not running at {ollama_url}. Please start it with: ollama serve")
+        })?;
+    if !response.status().is_success() {
+        anyhow::bail!(
+            "Ollama at {ollama_url} returned {} for /api/tags; is this an Ollama server?",
+            response.status()
+        );
+    }
+
+    let tags: Value = response.json().with_context(|| {
+        format!("{ollama_url}/api/tags did not return a model list; is this an Ollama server?")
+    })?;
+    let latest = format!("{model}:latest");
+    Ok(tags["models"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry["name"].as_str())
+        .any(|name| name == model || name == latest))
+}
+
+/// Names within a small edit distance of `target`, or sharing it as a substring
+fn similar_names(target: &str, names: &[String]) -> Vec<String> {
+    let max_distance = (target.len() / 3).max(2);
+    let mut scored: Vec<(usize, &String)> = names
+        .iter()
+        .map(|name| (strsim::levenshtein(target, name), name))
+        .filter(|(distance, name)| {
+            *distance <= max_distance || name.contains(target) || target.contains(name.as_str())
+        })
+        .collect();
+    scored.sort();
+    scored.into_iter().map(|(_, name)| name.clone()).collect()
+}
+
+/// Source of point IDs: seeded for reproducible runs, OS entropy otherwise
+pub struct IdGenerator {
+    rng: StdRng,
+}
+
+impl IdGenerator {
+    pub fn new(seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+        Self { rng }
+    }
+
+    /// Next random (v4) UUID drawn from this generator
+    pub fn next_id(&mut self) -> String {
+        Builder::from_random_bytes(self.rng.random())
+            .into_uuid()
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_embedding_request_serialization() {
+        let request = EmbeddingRequest::new(EmbedApi::Ollama, "nomic-embed-text", "test query");
+
+        let json = serde_json::to_value(&request).unwrap();
+
+        assert_eq!(json["model"], "nomic-embed-text");
+        assert_eq!(json["prompt"], "test query");
+        assert!(json.get("input").is_none());
+    }
+
+    #[test]
+    fn test_embedding_request_uses_input_for_embed_apis() {
+        for api in [EmbedApi::OllamaEmbed, EmbedApi::Openai] {
+            let request = EmbeddingRequest::new(api, "nomic-embed-text", "test query");
+            let json = serde_json::to_value(&request).unwrap();
+
+            assert_eq!(json["input"], "test query");
+            assert!(json.get("prompt").is_none());
+        }
+    }
+
+    #[test]
+    fn test_embedding_response_shapes() {
+        let ollama: EmbeddingResponse =
+            serde_json::from_value(json!({"embeddings": [[0.5, 0.6]]})).unwrap();
+        assert_eq!(
+            ollama.into_vector(EmbedApi::OllamaEmbed).unwrap(),
+            vec![0.5, 0.6]
+        );
+
+        let openai: EmbeddingResponse =
+            serde_json::from_value(json!({"data": [{"embedding": [0.7, 0.8]}]})).unwrap();
+        assert_eq!(
+            openai.into_vector(EmbedApi::Openai).unwrap(),
+            vec![0.7, 0.8]
+        );
+    }
+
+    #[test]
+    fn test_embedding_response_mismatch_is_an_error() {
+        // An /api/embed style body read as the legacy API yields no vector
+        let response: EmbeddingResponse =
+            serde_json::from_value(json!({"embeddings": [[0.5, 0.6]]})).unwrap();
+
+        let err = response.into_vector(EmbedApi::Ollama).unwrap_err();
+        assert!(err.to_string().contains("--embed-api"));
+    }
+
+    #[test]
+    fn test_embedding_response_deserialization() {
+        let json_data = json!({
+            "embedding": [0.1, 0.2, 0.3, 0.4]
+        });
+
+        let response: EmbeddingResponse = serde_json::from_value(json_data).unwrap();
+
+        assert_eq!(response.embedding.len(), 4);
+        assert_eq!(response.embedding[0], 0.1);
+        assert_eq!(response.embedding[3], 0.4);
+    }
+
+    #[test]
+    fn test_qdrant_search_response_deserialization() {
+        let json_data = json!({
+            "result": [
+                {
+                    "id": "id-1",
+                    "score": 0.95,
+                    "payload": {"text": "Result 1"}
+                },
+                {
+                    "id": "id-2",
+                    "score": 0.90,
+                    "payload": {"text": "Result 2"}
+                }
+            ]
+        });
+
+        let response: QdrantSearchResponse = serde_json::from_value(json_data).unwrap();
+
+        assert_eq!(response.result.len(), 2);
+        assert_eq!(response.result[0].result.id, "id-1");
+        assert_eq!(response.result[1].result.score, 0.90);
+        assert!(response.result[0].dense_vector().is_none());
+    }
+
+    #[test]
+    fn test_search_hit_dense_vector_plain_and_named() {
+        let plain: QdrantHit = serde_json::from_value(json!({
+            "id": "id-1", "score": 0.9, "payload": {}, "vector": [0.1, 0.2]
+        }))
+        .unwrap();
+        let named: QdrantHit = serde_json::from_value(json!({
+            "id": "id-2", "score": 0.8, "payload": {},
+            "vector": {"": [0.3, 0.4], "text": {"indices": [1], "values": [1.0]}}
+        }))
+        .unwrap();
+
+        assert_eq!(plain.dense_vector(), Some(vec![0.1, 0.2]));
+        assert_eq!(named.dense_vector(), Some(vec![0.3, 0.4]));
+    }
+
+    #[test]
+    fn test_safe_truncate_ascii() {
+        assert_eq!(safe_truncate("hello", 10), "hello");
+        assert_eq!(safe_truncate("hello world", 5), "hello");
+        assert_eq!(safe_truncate("", 5), "");
+    }
+
+    #[test]
+    fn test_safe_truncate_unicode() {
+        // Test with multi-byte UTF-8 characters
+        let s = "═══════════"; // Each ═ is 3 bytes
+        assert_eq!(safe_truncate(s, 3).chars().count(), 3);
+
+        // Mix of ASCII and multi-byte
+        let mixed = "Hello ═══ World";
+        let truncated = safe_truncate(mixed, 8);
+        assert_eq!(truncated, "Hello ══");
+        assert!(truncated.is_char_boundary(truncated.len()));
+
+        // Emoji (4 bytes each)
+        let emoji = "🔍📦🎯";
+        assert_eq!(safe_truncate(emoji, 2), "🔍📦");
+    }
+
+    #[test]
+    fn test_ca_cert_must_hold_certificates() {
+        let dir = std::env::temp_dir().join(format!("rag-tls-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let not_pem = dir.join("not-a-cert.pem");
+        fs::write(&not_pem, "just some text\n").unwrap();
+        let load = |path: &Path| {
+            load_http(HttpOptions {
+                ca_cert: Some(path.to_path_buf()),
+                ..HttpOptions::default()
+            })
+        };
+
+        let missing = load(&dir.join("missing.pem")).err().unwrap();
+        assert!(missing.to_string().contains("Failed to read --ca-cert"));
+        assert!(load(&not_pem).is_err());
+        assert!(load_http(HttpOptions::default()).unwrap().roots.is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_api_key_is_sent_as_header_and_redacted() {
+        use std::io::{BufRead, BufReader};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut head = String::new();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            while reader.read_line(&mut head).unwrap() > 2 && !head.ends_with("\r\n\r\n") {}
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            )
+            .unwrap();
+            head
+        });
+        let key: ApiKey = "s3cret-key".parse().unwrap();
+
+        assert!(
+            QdrantClient::new(&url)
+                .api_key(Some(&key))
+                .collection_exists("docs")
+        );
+
+        let head = server.join().unwrap().to_ascii_lowercase();
+        assert!(head.contains("\r\napi-key: s3cret-key\r\n"), "{head}");
+        assert_eq!(format!("{key:?}"), "ApiKey(<redacted>)");
+        assert!(!format!("{:?}", qdrant_http_client(Some(&key))).contains("s3cret"));
+        assert!("bad\nkey".parse::<ApiKey>().is_err());
+    }
+
+    #[test]
+    fn test_preview_cuts_on_char_boundaries() {
+        // "é" is two bytes, so byte 200 falls inside the 200th character
+        let text = "a".repeat(199) + "éé and more";
+        assert!(!text.is_char_boundary(200));
+
+        let short = preview(&text, 200);
+
+        assert_eq!(short, "a".repeat(199) + "é…");
+        assert_eq!(preview("short — text", 200), "short — text");
+        assert_eq!(preview("🔍📦🎯", 2), "🔍📦…");
+    }
+
+    #[test]
+    fn test_markdown_result_quotes_every_line_under_the_number() {
+        let item = markdown_result(
+            12,
+            "Ownership rules.\n\nBorrowing too.\n",
+            "rust.pdf",
+            "0.912",
+        );
+
+        assert_eq!(
+            item,
+            "12. > Ownership rules.\n    >\n    > Borrowing too.\n\n    **Source:** rust.pdf · **Score:** 0.912\n"
+        );
+        assert!(markdown_result(1, "  ", "a.md", "0.5").starts_with("1. > _(no text)_\n"));
+    }
+
+    #[test]
+    fn test_snippet_centers_on_densest_matches() {
+        let text = format!(
+            "Ownership is mentioned once here. {} The Borrow checker enforces borrowing rules, \
+             and borrow scopes end early. {}",
+            "filler ".repeat(40),
+            "tail ".repeat(40)
+        );
+        let terms = query_terms("borrow ownership of");
+
+        let snip = snippet(&text, &terms, 60, Highlight::Markdown);
+
+        assert_eq!(terms, vec!["borrow", "ownership"]);
+        assert!(snip.starts_with('…') && snip.ends_with('…'), "{snip}");
+        assert!(
+            snip.contains("The **Borrow** checker enforces **borrow**ing rules"),
+            "{snip}"
+        );
+        assert!(!snip.contains("Ownership"), "{snip}");
+        assert!(snip.chars().count() <= 120 + 2 + 4 * 3, "{snip}");
+    }
+
+    #[test]
+    fn test_snippet_falls_back_to_head_and_keeps_chars_whole() {
+        let text = "Ärger über 🦀 Ünïcode ".repeat(20);
+
+        assert_eq!(
+            snippet(&text, &query_terms("lifetimes"), 10, Highlight::Markdown),
+            preview(&text, 20)
+        );
+        let snip = snippet(&text, &query_terms("ÜNÏCODE"), 10, Highlight::Markdown);
+        assert!(snip.contains("**Ünïcode**"), "{snip}");
+        assert_eq!(
+            snippet(
+                "short about crabs",
+                &query_terms("crabs"),
+                10,
+                Highlight::Markdown
+            ),
+            "short about **crabs**"
+        );
+    }
+
+    #[test]
+    fn test_detect_lang_of_english_and_german() {
+        assert_eq!(
+            detect_lang(
+                "The borrow checker makes sure that every reference is valid for as long as it is used, \
+                 so a program can never read memory that has already been freed."
+            ),
+            "en"
+        );
+        assert_eq!(
+            detect_lang(
+                "Der Borrow-Checker stellt sicher, dass jede Referenz so lange gültig ist, wie sie \
+                 benutzt wird, damit ein Programm niemals bereits freigegebenen Speicher liest."
+            ),
+            "de"
+        );
+    }
+
+    #[test]
+    fn test_detect_lang_is_undetermined_for_short_or_code_chunks() {
+        assert_eq!(detect_lang("Ownership"), UNDETERMINED_LANG);
+        assert_eq!(
+            detect_lang(
+                "```rust\nfn main() {\n    let values = vec![1, 2, 3];\n    println!(\"{values:?}\");\n}\n```\n"
+            ),
+            UNDETERMINED_LANG
+        );
+    }
+
+    #[test]
+    fn test_fence_lang_reads_info_string() {
+        assert_eq!(fence_lang("```rust"), "rust");
+        assert_eq!(fence_lang("  ```rust,ignore"), "rust");
+        assert_eq!(fence_lang("```python title=\"x.py\""), "python");
+        assert_eq!(fence_lang("```{.haskell}"), "haskell");
+        assert_eq!(fence_lang("```"), "unknown");
+        assert_eq!(fence_lang("```   "), "unknown");
+    }
+
+    #[test]
+    fn test_limit_chunks_keeps_first_n() {
+        let mut chunks = vec!["a", "b", "c"];
+        limit_chunks(&mut chunks, Some(2)).unwrap();
+        assert_eq!(chunks, vec!["a", "b"]);
+        limit_chunks(&mut chunks, Some(5)).unwrap();
+        limit_chunks(&mut chunks, None).unwrap();
+        assert_eq!(chunks, vec!["a", "b"]);
+        assert!(limit_chunks(&mut chunks, Some(0)).is_err());
+    }
+
+    #[test]
+    fn test_progress_bar_hidden_when_quiet() {
+        assert!(progress_bar(10, "Embedding", true).is_hidden());
+    }
+
+    #[test]
+    fn test_chunk_plan_row_shows_lines_when_known() {
+        let mut chunk = ChunkPlan {
+            index: 3,
+            chunk_type: "code".to_string(),
+            chars: 120,
+            tokens: 31,
+            header_context: "Guide > Install".to_string(),
+            start_line: Some(10),
+            end_line: Some(14),
+        };
+        assert_eq!(
+            chunk.row(),
+            "    3  code             120      31  10-14       Guide > Install"
+        );
+
+        chunk.start_line = None;
+        assert!(chunk.row().contains("  -  "));
+    }
+
+    #[test]
+    fn test_heading_stack_pops_to_new_level() {
+        let mut headings = HeadingStack::default();
+        for line in ["# Guide", "## Install", "### Linux", "### macOS"] {
+            headings.push_line(line);
+        }
+        assert_eq!(headings.breadcrumb(), "Guide > Install > macOS");
+
+        headings.push_line("## Usage");
+        assert_eq!(headings.lines(), vec!["# Guide", "## Usage"]);
+        assert_eq!(headings.push_line("#include <stdio.h>"), None);
+        assert_eq!(headings.breadcrumb(), "Guide > Usage");
+    }
+
+    #[test]
+    fn test_normalize_collection_name() {
+        assert_eq!(normalize_collection_name("rust-books"), "rust-books");
+        assert_eq!(
+            normalize_collection_name("Machine Learning-books"),
+            "machine-learning-books"
+        );
+        assert_eq!(
+            normalize_collection_name("  C++ & Rust!!-books"),
+            "c-rust-books"
+        );
+        assert_eq!(
+            normalize_collection_name("JavaScript_ES6-books"),
+            "javascript-es6-books"
+        );
+        assert_eq!(
+            normalize_collection_name("Café Ünïcode-books"),
+            "caf-n-code-books"
+        );
+        assert_eq!(normalize_collection_name("日本語-books"), "books");
+    }
+
+    #[test]
+    fn test_distance_mismatch_warns_only_on_differing_metric() {
+        let info: CollectionResult = serde_json::from_value(json!({
+            "config": {"params": {"vectors": {"size": 768, "distance": "Cosine"}}}
+        }))
+        .unwrap();
+
+        assert_eq!(Distance::Cosine.mismatch("docs", &info), None);
+        let warning = Distance::Dot.mismatch("docs", &info).unwrap();
+        assert!(warning.contains("already uses Cosine"));
+        assert!(warning.contains("--distance dot"));
+    }
+
+    #[test]
+    fn test_euclid_scores_rank_lower_first() {
+        let info: CollectionResult = serde_json::from_value(json!({
+            "config": {"params": {"vectors": {"size": 768, "distance": "Euclid"}}}
+        }))
+        .unwrap();
+        let euclid = info.distance_metric();
+
+        assert_eq!(euclid, Distance::Euclid);
+        assert!(euclid.meets(0.3, 0.5) && !euclid.meets(0.7, 0.5));
+        assert!(Distance::Cosine.meets(0.7, 0.5) && !Distance::Cosine.meets(0.3, 0.5));
+        assert_eq!(euclid.rank(0.3, 0.7), std::cmp::Ordering::Less);
+        assert_eq!(Distance::Dot.rank(0.3, 0.7), std::cmp::Ordering::Greater);
+        assert!(euclid.similarity(0.0) == 1.0 && euclid.similarity(1.0) == 0.5);
+        assert_eq!(Distance::Cosine.similarity(0.8), 0.8);
+    }
+
+    #[test]
+    fn test_scalar_quantization_config_and_existing_collection_note() {
+        let mut config = json!({"vectors": {"size": 768, "distance": "Cosine"}});
+        Quantization::None.apply(&mut config);
+        assert!(config.get("quantization_config").is_none());
+        Quantization::Scalar.apply(&mut config);
+        assert_eq!(
+            config["quantization_config"],
+            json!({"scalar": {"type": "int8", "always_ram": true}})
+        );
+
+        let plain: CollectionResult = serde_json::from_value(json!({
+            "config": {"params": {"vectors": {"size": 768, "distance": "Cosine"}}}
+        }))
+        .unwrap();
+        let quantized: CollectionResult = serde_json::from_value(json!({
+            "config": {"quantization_config": {"scalar": {"type": "int8"}}}
+        }))
+        .unwrap();
+        assert!(
+            Quantization::Scalar
+                .mismatch("docs", &plain)
+                .unwrap()
+                .contains("recreate")
+        );
+        assert_eq!(Quantization::Scalar.mismatch("docs", &quantized), None);
+        assert_eq!(Quantization::None.mismatch("docs", &plain), None);
+    }
+
+    #[test]
+    fn test_collection_result_reads_plain_and_named_vector_params() {
+        let plain: CollectionResult = serde_json::from_value(json!({
+            "status": "green",
+            "points_count": 12,
+            "indexed_vectors_count": 0,
+            "config": {"params": {"vectors": {"size": 768, "distance": "Cosine"}}}
+        }))
+        .unwrap();
+        let named: CollectionResult = serde_json::from_value(json!({
+            "points_count": 3,
+            "config": {"params": {"vectors": {"": {"size": 384, "distance": "Dot"}}}}
+        }))
+        .unwrap();
+
+        assert_eq!(plain.status.as_deref(), Some("green"));
+        assert_eq!(plain.vector_size(), Some(768));
+        assert_eq!(plain.distance(), Some("Cosine"));
+        assert_eq!(named.vector_size(), Some(384));
+        assert_eq!(named.distance(), Some("Dot"));
+        assert_eq!(named.vectors_count, None);
+    }
+
+    #[test]
+    fn test_missing_collection_lists_similar_names() {
+        let available = vec![
+            "rust-books".to_string(),
+            "python-books".to_string(),
+            "notes".to_string(),
+        ];
+
+        let err = missing_collection_error("rust-book", &available);
+
+        assert!(
+            err.to_string()
+                .starts_with("Collection 'rust-book' not found, available: [rust-books]")
+        );
+    }
+
+    #[test]
+    fn test_missing_collection_lists_everything_when_nothing_is_close() {
+        let available = vec!["rust-books".to_string(), "notes".to_string()];
+
+        let err = missing_collection_error("zzz", &available);
+
+        assert!(
+            err.to_string()
+                .contains("not found, available: [rust-books, notes]")
+        );
+    }
+
+    /// Answer each request on a local port with `handler(request_line, body)`,
+    /// returning the URL and request count
+    fn serve(
+        mut handler: impl FnMut(&str, &str) -> (&'static str, String) + Send + 'static,
+    ) -> (String, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+        use std::io::{BufRead, BufReader, Read};
+        use std::sync::atomic::Ordering;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let hits = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = hits.clone();
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(len) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        content_length = len.trim().parse().unwrap();
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut body = String::new();
+                reader
+                    .by_ref()
+                    .take(content_length)
+                    .read_to_string(&mut body)
+                    .unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                let (status, response) = handler(&request_line, &body);
+                write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}",
+                    response.len()
+                )
+                .unwrap();
+            }
+        });
+
+        (url, hits)
+    }
+
+    /// Serve `status` to every request on a local port, returning the URL and request count
+    fn stub_server(
+        status: &'static str,
+    ) -> (String, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+        serve(move |_, _| (status, String::new()))
+    }
+
+    /// Fake Qdrant that only makes upserted points searchable once applied,
+    /// which happens immediately for `wait=true` and never otherwise.
+    /// Returns the URL and whether every search asked for exact results.
+    fn fake_qdrant() -> (String, std::sync::Arc<std::sync::atomic::AtomicBool>) {
+        use std::sync::atomic::Ordering;
+
+        let exact = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let exact_seen = exact.clone();
+        let mut applied: Vec<Value> = Vec::new();
+
+        let (url, _) = serve(move |request_line, body| {
+            let body: Value = serde_json::from_str(body).unwrap_or_default();
+            if request_line.starts_with("PUT ") && request_line.contains("/points?") {
+                if request_line.contains("wait=true") {
+                    applied.extend(body["points"].as_array().cloned().unwrap_or_default());
+                }
+                return ("200 OK", json!({"status": "ok", "result": {}}).to_string());
+            }
+            if request_line.starts_with("POST ") && request_line.contains("/points/search") {
+                if body["params"]["exact"] != json!(true) {
+                    exact_seen.store(false, Ordering::SeqCst);
+                }
+                let result: Vec<Value> = applied
+                    .iter()
+                    .map(|p| json!({"id": p["id"], "score": 1.0, "payload": p["payload"]}))
+                    .collect();
+                return ("200 OK", json!({ "result": result }).to_string());
+            }
+            ("404 Not Found", String::new())
+        });
+
+        (url, exact)
+    }
+
+    fn sample_point() -> QdrantPoint {
+        QdrantPoint {
+            id: "00000000-0000-0000-0000-000000000001".to_string(),
+            vector: vec![0.1, 0.2, 0.3],
+            sparse: None,
+            summary_vector: None,
+            payload: json!({"text": "Freshly ingested content"}),
+        }
+    }
+
+    fn fake_services() -> String {
+        serve(|request_line, _| {
+            if request_line.starts_with("GET /healthz") {
+                ("200 OK", String::new())
+            } else if request_line.starts_with("GET /api/tags") {
+                (
+                    "200 OK",
+                    json!({"models": [{"name": "nomic-embed-text:latest"}, {"name": "llama3.2:3b"}]})
+                        .to_string(),
+                )
+            } else {
+                ("404 Not Found", String::new())
+            }
+        })
+        .0
+    }
+
+    #[test]
+    fn test_upsert_stamps_ingested_at_and_model_unless_payload_sets_them() {
+        let (url, _) = fake_qdrant();
+        let qdrant = QdrantClient::new(&url)
+            .consistency(Consistency::Strong)
+            .ingest_model("nomic-embed-text");
+        let mut imported = sample_point();
+        imported.id = "00000000-0000-0000-0000-000000000002".to_string();
+        imported.payload["ingested_at"] = json!("2025-06-01T12:00:00+00:00");
+        imported.payload[EMBED_MODEL_FIELD] = json!("mxbai-embed-large");
+
+        qdrant
+            .upsert_points("docs", &[sample_point(), imported])
+            .unwrap();
+        let results = qdrant.search("docs", &[0.1, 0.2, 0.3], 10, None).unwrap();
+
+        assert_eq!(results[0].payload["ingested_at"], json!(qdrant.ingested_at));
+        assert_eq!(
+            results[1].payload["ingested_at"],
+            "2025-06-01T12:00:00+00:00"
+        );
+        assert_eq!(results[0].payload[EMBED_MODEL_FIELD], "nomic-embed-text");
+        assert_eq!(results[1].payload[EMBED_MODEL_FIELD], "mxbai-embed-large");
+    }
+
+    #[test]
+    fn test_scroll_pages_follow_offsets_and_set_payloads_batches() {
+        let batches = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = batches.clone();
+        let (url, _) = serve(move |request_line, body| {
+            let body: Value = serde_json::from_str(body).unwrap_or_default();
+            if request_line.contains("/points/scroll") {
+                let (id, next) = if body["offset"].is_null() {
+                    ("a", json!("b"))
+                } else {
+                    ("b", Value::Null)
+                };
+                let points = json!([{"id": id, "payload": {"text": id}}]);
+                let result = json!({"points": points, "next_page_offset": next});
+                return ("200 OK", json!({ "result": result }).to_string());
+            }
+            if request_line.starts_with("POST ") && request_line.contains("/points/batch?wait=true")
+            {
+                seen.lock().unwrap().push(body["operations"].clone());
+                return ("200 OK", json!({"result": []}).to_string());
+            }
+            ("404 Not Found", String::new())
+        });
+        let qdrant = QdrantClient::new(&url);
+
+        let mut pages = Vec::new();
+        qdrant
+            .scroll_pages("docs", &["text"], 1, |points| {
+                pages.push(points.into_iter().map(|p| p.id).collect::<Vec<_>>());
+                Ok(())
+            })
+            .unwrap();
+        qdrant
+            .set_payloads("docs", &[("a".to_string(), json!({"lang": "en"}))])
+            .unwrap();
+        qdrant.set_payloads("docs", &[]).unwrap();
+
+        assert_eq!(pages, vec![vec!["a"], vec!["b"]]);
+        assert_eq!(
+            *batches.lock().unwrap(),
+            vec![json!([{"set_payload": {"payload": {"lang": "en"}, "points": ["a"]}}])]
+        );
+    }
+
+    #[test]
+    fn test_scroll_vectors_and_delete_point_ids() {
+        let deleted = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = deleted.clone();
+        let (url, _) = serve(move |request_line, body| {
+            let body: Value = serde_json::from_str(body).unwrap_or_default();
+            if request_line.contains("/points/scroll") {
+                assert_eq!(body["with_vector"], json!(true));
+                let points = json!([
+                    {"id": 7, "payload": {}, "vector": [1.0, 0.0]},
+                    {"id": "u-1", "payload": {}, "vector": {"": [0.0, 1.0], "text": {"indices": [], "values": []}}}
+                ]);
+                let result = json!({"points": points, "next_page_offset": null});
+                return ("200 OK", json!({ "result": result }).to_string());
+            }
+            if request_line.contains("/points/delete?wait=true") {
+                seen.lock().unwrap().push(body);
+                return ("200 OK", json!({"result": {}}).to_string());
+            }
+            ("404 Not Found", String::new())
+        });
+        let qdrant = QdrantClient::new(&url);
+
+        let mut points = Vec::new();
+        qdrant
+            .scroll_vectors("docs", &[], 10, |page| {
+                points.extend(page);
+                Ok(())
+            })
+            .unwrap();
+        qdrant
+            .delete_point_ids("docs", &["7".to_string(), "u-1".to_string()])
+            .unwrap();
+
+        let ids: Vec<&str> = points.iter().map(|(p, _)| p.id.as_str()).collect();
+        assert_eq!(ids, vec!["7", "u-1"]);
+        assert_eq!(
+            points[1].1,
+            vec![0.0, 1.0],
+            "named dense vector is unwrapped"
+        );
+        assert_eq!(
+            *deleted.lock().unwrap(),
+            vec![json!({"points": [7, "u-1"]})]
+        );
+    }
+
+    #[test]
+    fn test_preflight_matches_pulled_models() {
+        let url = fake_services();
+
+        assert!(ollama_has_model(&url, "nomic-embed-text").unwrap());
+        assert!(ollama_has_model(&url, "llama3.2:3b").unwrap());
+        assert!(!ollama_has_model(&url, "llama3.2").unwrap());
+        preflight(&url, &url, "nomic-embed-text", EmbedApi::Ollama).unwrap();
+
+        let err = preflight(&url, &url, "mxbai-embed-large", EmbedApi::OllamaEmbed).unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with("run: ollama pull mxbai-embed-large")
+        );
+    }
+
+    #[test]
+    fn test_preflight_reports_unreachable_services() {
+        let url = fake_services();
+        let (down, _) = stub_server("503 Service Unavailable");
+
+        let err = preflight(&down, &url, "nomic-embed-text", EmbedApi::Ollama).unwrap_err();
+        assert!(err.to_string().contains("setup-qdrant.sh"));
+
+        let err = preflight(&url, &down, "nomic-embed-text", EmbedApi::Ollama).unwrap_err();
+        assert!(err.to_string().contains("is this an Ollama server?"));
+    }
+
+    #[test]
+    fn test_retry_delay_doubles() {
+        let retry = RetryPolicy {
+            retries: 4,
+            base_delay_ms: 500,
+        };
+
+        let delays: Vec<u128> = (1..=4).map(|n| retry.delay(n).as_millis()).collect();
+        assert_eq!(delays, vec![500, 1000, 2000, 4000]);
+    }
+
+    #[test]
+    fn test_bad_request_fails_fast() {
+        let (url, hits) = stub_server("400 Bad Request");
+        let retry = RetryPolicy {
+            retries: 3,
+            base_delay_ms: 1,
+        };
+
+        let err = Embedder::new(&url, EmbedApi::Ollama, "bad-model", retry)
+            .embed("q")
+            .unwrap_err();
+
+        assert!(err.to_string().contains("400"));
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_server_error_is_retried_until_exhausted() {
+        let (url, hits) = stub_server("503 Service Unavailable");
+        let retry = RetryPolicy {
+            retries: 2,
+            base_delay_ms: 1,
+        };
+
+        let err = Embedder::new(&url, EmbedApi::Ollama, "model", retry)
+            .embed("q")
+            .unwrap_err();
+
+        assert!(err.to_string().contains("gave up after 3 attempts"));
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_hung_server_times_out_and_is_retried() {
+        // Connections queue in the backlog and are never answered
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let retry = RetryPolicy {
+            retries: 1,
+            base_delay_ms: 1,
+        };
+        let mut embedder = Embedder::new(&url, EmbedApi::Ollama, "model", retry);
+        embedder.client = Http {
+            options: HttpOptions {
+                timeout: Duration::from_millis(200),
+                ..HttpOptions::default()
+            },
+            roots: Vec::new(),
+        }
+        .client_builder()
+        .build()
+        .unwrap();
+
+        let err = embedder.embed("q").unwrap_err().to_string();
+
+        assert!(err.contains("(--http-timeout-secs)"), "{err}");
+        assert!(err.contains("gave up after 2 attempts"), "{err}");
+        drop(listener);
+    }
+
+    /// Embedding server for /api/embed that answers `[input length, 1.0]` and records request bodies
+    fn embed_server() -> (String, std::sync::Arc<std::sync::Mutex<Vec<Value>>>) {
+        let bodies = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = bodies.clone();
+        let (url, _) = serve(move |_, body| {
+            let body: Value = serde_json::from_str(body).unwrap_or_default();
+            let len = body["input"].as_str().unwrap_or_default().len();
+            seen.lock().unwrap().push(body);
+            ("200 OK", json!({"embeddings": [[len, 1.0]]}).to_string())
+        });
+        (url, bodies)
+    }
+
+    /// Ollama with (`batch`) or without `/api/embed`; every vector is `[text length, 1.0]`.
+    /// Returns the URL and the request lines received.
+    fn ollama_server(batch: bool) -> (String, std::sync::Arc<std::sync::Mutex<Vec<String>>>) {
+        let requests = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        let (url, _) = serve(move |request_line, body| {
+            seen.lock().unwrap().push(request_line.trim().to_string());
+            let body: Value = serde_json::from_str(body).unwrap_or_default();
+            let embed = |text: &Value| json!([text.as_str().unwrap().len(), 1.0]);
+            if request_line.starts_with("POST /api/embeddings ") {
+                (
+                    "200 OK",
+                    json!({"embedding": embed(&body["prompt"])}).to_string(),
+                )
+            } else if batch && request_line.starts_with("POST /api/embed ") {
+                let inputs = body["input"].as_array().unwrap();
+                let embeddings: Vec<Value> = inputs.iter().map(embed).collect();
+                ("200 OK", json!({"embeddings": embeddings}).to_string())
+            } else {
+                ("404 Not Found", String::new())
+            }
+        });
+        (url, requests)
+    }
+
+    fn texts(texts: &[&str]) -> Vec<String> {
+        texts.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn test_embed_batch_sends_one_request_in_order() {
+        let (url, requests) = ollama_server(true);
+        let retry = RetryPolicy {
+            retries: 0,
+            base_delay_ms: 1,
+        };
+        let embedder = Embedder::new(&url, EmbedApi::Ollama, "model", retry);
+
+        let vectors = embedder.embed_batch(&texts(&["a", "abc", "ab"])).unwrap();
+
+        assert_eq!(
+            vectors,
+            vec![vec![1.0, 1.0], vec![3.0, 1.0], vec![2.0, 1.0]]
+        );
+        assert_eq!(*requests.lock().unwrap(), vec!["POST /api/embed HTTP/1.1"]);
+        assert!(embedder.embed_batch(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_embed_batch_falls_back_when_missing_or_legacy() {
+        let retry = RetryPolicy {
+            retries: 0,
+            base_delay_ms: 1,
+        };
+        let input = texts(&["a", "abc"]);
+        let expected = vec![vec![1.0, 1.0], vec![3.0, 1.0]];
+
+        let (url, requests) = ollama_server(false);
+        let embedder = Embedder::new(&url, EmbedApi::Ollama, "model", retry);
+        assert_eq!(embedder.embed_batch(&input).unwrap(), expected);
+        assert_eq!(embedder.embed_batch(&input).unwrap(), expected);
+        // Only the first batch tries /api/embed
+        let lines = requests.lock().unwrap().clone();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "POST /api/embed HTTP/1.1");
+        assert!(
+            lines[1..]
+                .iter()
+                .all(|l| l == "POST /api/embeddings HTTP/1.1")
+        );
+
+        let (url, requests) = ollama_server(true);
+        let embedder = Embedder::new(&url, EmbedApi::Ollama, "model", retry).legacy_embed(true);
+        assert_eq!(embedder.embed_batch(&input).unwrap(), expected);
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    fn oversize(on_oversize: OnOversize) -> OversizePolicy {
+        OversizePolicy {
+            on_oversize,
+            max_tokens: 5,
+            counter: TokenCounter::estimate(),
+        }
+    }
+
+    #[test]
+    fn test_split_oversize_embeds_each_piece() {
+        let (url, bodies) = embed_server();
+        let retry = RetryPolicy {
+            retries: 0,
+            base_delay_ms: 1,
+        };
+        let embedder = Embedder::new(&url, EmbedApi::OllamaEmbed, "model", retry)
+            .oversize(oversize(OnOversize::Split));
+        let text = "alpha beta gamma delta epsilon zeta eta theta";
+
+        let pieces = embedder.embed_pieces(text).unwrap();
+
+        assert!(pieces.len() > 1);
+        let bodies = bodies.lock().unwrap().clone();
+        assert_eq!(bodies.len(), pieces.len());
+        assert!(bodies.iter().all(|b| b["truncate"] == json!(false)));
+        let inputs: String = bodies
+            .iter()
+            .map(|b| b["input"].as_str().unwrap())
+            .collect();
+        assert_eq!(inputs, text);
+        assert!(pieces.iter().all(|p| p[0] <= 20.0));
+
+        let mean = embedder.embed(text).unwrap();
+        let expected = pieces.iter().map(|p| p[0]).sum::<f32>() / pieces.len() as f32;
+        assert_eq!(mean, vec![expected, 1.0]);
+    }
+
+    #[test]
+    fn test_error_and_truncate_oversize() {
+        let (url, bodies) = embed_server();
+        let retry = RetryPolicy {
+            retries: 0,
+            base_delay_ms: 1,
+        };
+        let text = "alpha beta gamma delta epsilon zeta eta theta";
+
+        let err = Embedder::new(&url, EmbedApi::OllamaEmbed, "model", retry)
+            .oversize(oversize(OnOversize::Error))
+            .embed(text)
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("12 tokens, over the embedding limit of 5")
+        );
+        assert!(bodies.lock().unwrap().is_empty());
+
+        // /api/embed leaves truncation to Ollama
+        let vector = Embedder::new(&url, EmbedApi::OllamaEmbed, "model", retry)
+            .oversize(oversize(OnOversize::Truncate))
+            .embed(text)
+            .unwrap();
+        assert_eq!(vector[0], text.len() as f32);
+        assert_eq!(bodies.lock().unwrap()[0]["truncate"], json!(true));
+    }
+
+    fn temp_cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rag-cache-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_cached_embedding_skips_the_server() {
+        let dir = temp_cache_dir("hit");
+        let (url, hits) = serve(|_, _| ("200 OK", json!({"embedding": [0.25, 0.5]}).to_string()));
+        let retry = RetryPolicy {
+            retries: 0,
+            base_delay_ms: 1,
+        };
+        let cache = EmbeddingCache::open(&dir, EmbeddingCache::DEFAULT_TTL, 10).unwrap();
+        let embedder = Embedder::new(&url, EmbedApi::Ollama, "nomic-embed-text:latest", retry)
+            .cache(Some(cache.clone()));
+
+        assert_eq!(
+            embedder.embed("what is ownership").unwrap(),
+            vec![0.25, 0.5]
+        );
+        assert_eq!(
+            embedder.embed("what is ownership").unwrap(),
+            vec![0.25, 0.5]
+        );
+
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(
+            cache.get("nomic-embed-text:latest", "what is ownership"),
+            Some(vec![0.25, 0.5])
+        );
+        assert_eq!(cache.get("other-model", "what is ownership"), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cache_evicts_expired_and_oldest_entries() {
+        let dir = temp_cache_dir("evict");
+        let cache = EmbeddingCache::open(&dir, Duration::from_secs(3600), 10).unwrap();
+        let now = SystemTime::now();
+        for (i, age_secs) in [7200, 30, 20, 10].into_iter().enumerate() {
+            let text = format!("query {i}");
+            cache.put("model", &text, &[i as f32]).unwrap();
+            fs::File::options()
+                .write(true)
+                .open(cache.entry_path("model", &text))
+                .unwrap()
+                .set_modified(now - Duration::from_secs(age_secs))
+                .unwrap();
+        }
+        assert_eq!(cache.get("model", "query 0"), None);
+
+        let cache = EmbeddingCache::open(&dir, Duration::from_secs(3600), 2).unwrap();
+
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        assert_eq!(cache.get("model", "query 1"), None);
+        assert_eq!(cache.get("model", "query 3"), Some(vec![3.0]));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_strong_consistency_finds_just_ingested_points() {
+        let (url, exact) = fake_qdrant();
+        let qdrant = QdrantClient::new(&url).consistency(Consistency::Strong);
+
+        qdrant.upsert_points("docs", &[sample_point()]).unwrap();
+        let results = qdrant.search("docs", &[0.1, 0.2, 0.3], 5, None).unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].payload["text"], "Freshly ingested content");
+        assert!(exact.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_eventual_consistency_does_not_wait_or_search_exactly() {
+        let (url, exact) = fake_qdrant();
+        let qdrant = QdrantClient::new(&url);
+
+        qdrant.upsert_points("docs", &[sample_point()]).unwrap();
+        let results = qdrant.search("docs", &[0.1, 0.2, 0.3], 5, None).unwrap();
+
+        assert!(results.is_empty());
+        assert!(!exact.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_build_filter_single() {
+        let filters = vec!["is_code=true".to_string()];
+        let result = build_filter(&filters, &[]).unwrap();
+
+        assert!(result["must"].is_array());
+        assert_eq!(result["must"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_build_filter_multiple() {
+        let filters = vec!["is_code=true".to_string(), "source=test.pdf".to_string()];
+        let result = build_filter(&filters, &[]).unwrap();
+
+        assert_eq!(result["must"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_build_filter_invalid() {
+        let filters = vec!["invalid_format".to_string()];
+        let result = build_filter(&filters, &[]);
+
+        assert!(result.is_err());
+        assert!(build_filter(&["char_count>=many".to_string()], &[]).is_err());
+        assert!(build_filter(&["=rust".to_string()], &[]).is_err());
+    }
+
+    #[test]
+    fn test_build_filter_range_operators() {
+        for (filter, range) in [
+            ("char_count>=500", json!({"gte": 500.0})),
+            ("char_count<=2000", json!({"lte": 2000.0})),
+            ("char_count>500", json!({"gt": 500.0})),
+            ("score<0.5", json!({"lt": 0.5})),
+        ] {
+            let result = build_filter(&[filter.to_string()], &[]).unwrap();
+
+            assert_eq!(result["must"][0]["range"], range, "{filter}");
+            assert!(result["must"][0].get("match").is_none());
+        }
+    }
+
+    #[test]
+    fn test_build_filter_combines_must_and_must_not() {
+        let filters = vec!["lang=en".to_string()];
+        let excludes = vec![
+            "chunk_type=child_code|child_mixed".to_string(),
+            "source=draft.md".to_string(),
+        ];
+
+        let result = build_filter(&filters, &excludes).unwrap();
+
+        assert_eq!(
+            result,
+            json!({
+                "must": [{"key": "lang", "match": {"value": "en"}}],
+                "must_not": [
+                    {"key": "chunk_type", "match": {"any": ["child_code", "child_mixed"]}},
+                    {"key": "source", "match": {"value": "draft.md"}},
+                ]
+            })
+        );
+        let only_excludes = build_filter(&[], &excludes).unwrap();
+        assert_eq!(only_excludes["must"], json!([]));
+        assert_eq!(only_excludes["must_not"].as_array().unwrap().len(), 2);
+        assert!(
+            build_filter(&filters, &[])
+                .unwrap()
+                .get("must_not")
+                .is_none()
+        );
+        assert!(build_filter(&[], &["bad".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_build_filter_any_and_mixed_conditions() {
+        let filters = vec![
+            "chunk_type=child_code|child_mixed".to_string(),
+            "char_count>=500".to_string(),
+            "lang=en".to_string(),
+        ];
+
+        let result = build_filter(&filters, &[]).unwrap();
+
+        assert_eq!(
+            result["must"],
+            json!([
+                {"key": "chunk_type", "match": {"any": ["child_code", "child_mixed"]}},
+                {"key": "char_count", "range": {"gte": 500.0}},
+                {"key": "lang", "match": {"value": "en"}},
+            ])
+        );
+    }
+
+    #[test]
+    fn test_check_embed_model_warns_or_fails_on_another_model() {
+        let (url, _) = serve(|request_line, body| {
+            let body: Value = serde_json::from_str(body).unwrap_or_default();
+            if request_line.starts_with("POST /collections/docs/points/scroll ")
+                && body["filter"]["must_not"][0]["is_empty"]["key"] == "embed_model"
+            {
+                let points = json!([{"id": "a", "payload": {"embed_model": "nomic-embed-text"}}]);
+                return ("200 OK", json!({"result": {"points": points}}).to_string());
+            }
+            ("200 OK", json!({"result": {"points": []}}).to_string())
+        });
+        let qdrant = QdrantClient::new(&url);
+
+        assert_eq!(
+            qdrant.embed_model("docs").unwrap().as_deref(),
+            Some("nomic-embed-text")
+        );
+        qdrant
+            .check_embed_model("docs", "nomic-embed-text:latest", true)
+            .unwrap();
+        qdrant
+            .check_embed_model("docs", "mxbai-embed-large", false)
+            .unwrap();
+        let error = qdrant
+            .check_embed_model("docs", "mxbai-embed-large", true)
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("--model nomic-embed-text"),
+            "{error}"
+        );
+        assert_eq!(qdrant.embed_model("legacy").unwrap(), None);
+        qdrant
+            .check_embed_model("legacy", "mxbai-embed-large", true)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_count_points_sends_exact_filter() {
+        let (url, _) = serve(|request_line, body| {
+            let body: Value = serde_json::from_str(body).unwrap_or_default();
+            if request_line.starts_with("POST /collections/docs/points/count ")
+                && body["exact"] == json!(true)
+                && body["filter"]["must"][0]["key"] == "source"
+            {
+                return ("200 OK", json!({"result": {"count": 42}}).to_string());
+            }
+            (
+                "400 Bad Request",
+                json!({"status": {"error": "bad"}}).to_string(),
+            )
+        });
+
+        let filter = json!({"must": [{"key": "source", "match": {"value": "book.pdf"}}]});
+        let count = QdrantClient::new(&url)
+            .count_points("docs", &filter, true)
+            .unwrap();
+
+        assert_eq!(count, 42);
+    }
+
+    #[test]
+    fn test_snapshots_are_created_listed_and_downloaded() {
+        let (url, _) = serve(|request_line, _| {
+            let snapshot = json!({
+                "name": "docs-1.snapshot",
+                "creation_time": "2025-06-10T08:30:00",
+                "size": 11,
+                "checksum": "abc"
+            });
+            if request_line.starts_with("POST /collections/docs/snapshots?wait=true ") {
+                return ("200 OK", json!({"result": snapshot}).to_string());
+            }
+            if request_line.starts_with("GET /collections/docs/snapshots ") {
+                return ("200 OK", json!({"result": [snapshot]}).to_string());
+            }
+            if request_line.starts_with("GET /collections/docs/snapshots/docs-1.snapshot ") {
+                return ("200 OK", "snapshot!\n".to_string());
+            }
+            ("404 Not Found", String::new())
+        });
+        let qdrant = QdrantClient::new(&url);
+
+        let created = qdrant.create_snapshot("docs").unwrap();
+        let listed = qdrant.list_snapshots("docs").unwrap();
+        let mut file = Vec::new();
+        let bytes = qdrant
+            .download_snapshot("docs", &created.name, &mut file)
+            .unwrap();
+
+        assert_eq!(created.size, 11);
+        assert_eq!(created.checksum.as_deref(), Some("abc"));
+        assert_eq!(listed, vec![created]);
+        assert_eq!(file, b"snapshot!\n");
+        assert_eq!(bytes, 10);
+        assert!(qdrant.download_snapshot("docs", "gone", &mut file).is_err());
+    }
+
+    #[test]
+    fn test_scroll_points_returns_matching_payloads() {
+        let (url, _) = serve(|request_line, body| {
+            let body: Value = serde_json::from_str(body).unwrap_or_default();
+            if !request_line.starts_with("POST /collections/docs/points/scroll ") {
+                return ("404 Not Found", String::new());
+            }
+            let result = json!({
+                "points": [{"id": "p-1", "payload": {"text": "macros"}}],
+                "next_page_offset": null,
+                "limit_seen": body["limit"],
+            });
+            ("200 OK", json!({ "result": result }).to_string())
+        });
+
+        let filter = json!({"should": [{"key": "text", "match": {"text": "macros"}}]});
+        let points = QdrantClient::new(&url)
+            .scroll_points("docs", &filter, 20)
+            .unwrap();
+
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].payload["text"], "macros");
+    }
+
+    #[test]
+    fn test_scroll_payloads_follows_pages() {
+        let (url, requests) = serve(|_, body| {
+            let body: Value = serde_json::from_str(body).unwrap();
+            assert_eq!(body["with_payload"], json!({"include": ["source"]}));
+            assert_eq!(body["with_vector"], json!(false));
+            let page = if body["offset"].is_null() {
+                json!({"points": [{"id": 1, "payload": {"source": "a.pdf"}}], "next_page_offset": 2})
+            } else {
+                json!({"points": [{"id": 2, "payload": {"source": "b.pdf"}}], "next_page_offset": null})
+            };
+            ("200 OK", json!({ "result": page }).to_string())
+        });
+
+        let payloads = QdrantClient::new(&url)
+            .scroll_payloads("docs", &["source"], 1)
+            .unwrap();
+
+        assert_eq!(
+            payloads,
+            vec![json!({"source": "a.pdf"}), json!({"source": "b.pdf"})]
+        );
+        assert_eq!(requests.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_wait_for_green_polls_until_indexed() {
+        let mut polls = 0;
+        let (url, _) = serve(move |_, _| {
+            polls += 1;
+            let status = if polls < 3 { "yellow" } else { "green" };
+            let result = json!({"status": status, "points_count": 10, "indexed_vectors_count": polls * 3, "config": {}});
+            ("200 OK", json!({ "result": result }).to_string())
+        });
+        let mut seen = Vec::new();
+
+        let info = QdrantClient::new(&url)
+            .wait_for_green("docs", Duration::from_secs(5), Duration::ZERO, |info| {
+                seen.push(info.status.clone().unwrap())
+            })
+            .unwrap();
+
+        assert_eq!(seen, vec!["yellow", "yellow", "green"]);
+        assert_eq!(info.indexed_vectors_count, Some(9));
+    }
+
+    #[test]
+    fn test_wait_for_green_times_out() {
+        let (url, _) = serve(|_, _| {
+            let result = json!({"status": "yellow", "points_count": 10, "config": {}});
+            ("200 OK", json!({ "result": result }).to_string())
+        });
+
+        let err = QdrantClient::new(&url)
+            .wait_for_green("docs", Duration::ZERO, Duration::ZERO, |_| {})
+            .unwrap_err();
+
+        assert!(err.to_string().contains("still yellow"));
+    }
+
+    #[test]
+    fn test_index_fields_parse_with_default_schemas() {
+        let fields: IndexFields = DEFAULT_INDEX_FIELDS.parse().unwrap();
+        let custom: IndexFields = "lang, page ,title:text".parse().unwrap();
+
+        assert_eq!(
+            fields.0,
+            vec![
+                ("source".to_string(), PayloadSchema::Keyword),
+                ("chunk_type".to_string(), PayloadSchema::Keyword),
+                ("has_code".to_string(), PayloadSchema::Bool),
+                ("parent_id".to_string(), PayloadSchema::Keyword),
+            ]
+        );
+        assert_eq!(
+            custom.0,
+            vec![
+                ("lang".to_string(), PayloadSchema::Keyword),
+                ("page".to_string(), PayloadSchema::Integer),
+                ("title".to_string(), PayloadSchema::Text),
+            ]
+        );
+        assert!("".parse::<IndexFields>().unwrap().0.is_empty());
+        assert!("page:date".parse::<IndexFields>().is_err());
+    }
+
+    #[test]
+    fn test_payload_indexes_are_created_per_field() {
+        let bodies = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = bodies.clone();
+        let (url, _) = serve(move |request_line, body| {
+            assert!(request_line.starts_with("PUT /collections/docs/index?wait=true"));
+            seen.lock()
+                .unwrap()
+                .push(serde_json::from_str::<Value>(body).unwrap());
+            ("200 OK", json!({"status": "ok"}).to_string())
+        });
+
+        QdrantClient::new(&url)
+            .create_payload_indexes("docs", &"source,has_code".parse().unwrap())
+            .unwrap();
+
+        assert_eq!(
+            *bodies.lock().unwrap(),
+            vec![
+                json!({"field_name": "source", "field_schema": "keyword"}),
+                json!({"field_name": "has_code", "field_schema": "bool"}),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_points_by_id_fetches_in_one_request() {
+        let (url, hits) = serve(|request_line, body| {
+            let body: Value = serde_json::from_str(body).unwrap_or_default();
+            if !request_line.starts_with("POST /collections/docs/points ") {
+                return ("404 Not Found", String::new());
+            }
+            let result: Vec<Value> = body["ids"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter(|id| *id != "missing")
+                .map(|id| json!({"id": id, "payload": {"text": format!("parent {}", id.as_str().unwrap())}}))
+                .collect();
+            ("200 OK", json!({ "result": result }).to_string())
+        });
+        let qdrant = QdrantClient::new(&url);
+
+        let points = qdrant
+            .get_points_by_id("docs", &["p-1", "missing", "p-2"])
+            .unwrap();
+
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[1].id, "p-2");
+        assert_eq!(points[1].payload["text"], "parent p-2");
+        assert!(qdrant.get_points_by_id("docs", &[]).unwrap().is_empty());
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_get_point_with_vector_reads_named_or_plain_vector() {
+        let (url, _) = serve(|request_line, body| {
+            let body: Value = serde_json::from_str(body).unwrap_or_default();
+            if !request_line.starts_with("POST /collections/docs/points ")
+                || body["with_vector"] != json!(true)
+            {
+                return ("404 Not Found", String::new());
+            }
+            let result = match body["ids"][0].as_str() {
+                Some("p-1") => {
+                    json!([{"id": "p-1", "payload": {"text": "one"}, "vector": [0.5, 0.25]}])
+                }
+                Some("p-2") => {
+                    json!([{"id": "p-2", "payload": {}, "vector": {"": [1.0], "text": {"indices": [], "values": []}}}])
+                }
+                _ => json!([]),
+            };
+            ("200 OK", json!({ "result": result }).to_string())
+        });
+        let qdrant = QdrantClient::new(&url);
+
+        let (point, vector) = qdrant.get_point_with_vector("docs", "p-1").unwrap();
+        assert_eq!(point.payload["text"], "one");
+        assert_eq!(vector, vec![0.5, 0.25]);
+        let (_, vector) = qdrant.get_point_with_vector("docs", "p-2").unwrap();
+        assert_eq!(vector, vec![1.0]);
+        let error = qdrant.get_point_with_vector("docs", "gone").unwrap_err();
+        assert!(error.to_string().contains("No point gone"), "{error}");
+        assert_eq!(exclude_point("42")["must_not"][0]["has_id"], json!([42]));
+    }
+
+    #[test]
+    fn test_sparse_vector_counts_terms() {
+        let sparse = SparseVector::from_text("Borrow a borrow checker; an ox borrows.");
+
+        // "a", "an" and "ox" are too short; "Borrow" and "borrow" share a token
+        assert_eq!(sparse.indices.len(), 3);
+        let borrow = sparse
+            .indices
+            .iter()
+            .position(|&i| i == token_index("borrow"))
+            .unwrap();
+        assert_eq!(sparse.values[borrow], 2.0);
+        assert!(sparse.indices.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_sparse_vector_matches_between_ingest_and_query() {
+        let chunk = SparseVector::from_text("Rust macros expand at compile time");
+        let query = SparseVector::from_text("macros");
+
+        assert!(chunk.indices.contains(&query.indices[0]));
+        assert!(SparseVector::from_text("a b").is_empty());
+    }
+
+    #[test]
+    fn test_point_with_sparse_vector_uses_named_vectors() {
+        let mut point = sample_point();
+        point.sparse = Some(SparseVector::from_text("fresh content"));
+
+        let json = serde_json::to_value(&point).unwrap();
+
+        assert_eq!(json["vector"][""], json!([0.1f32, 0.2f32, 0.3f32]));
+        assert_eq!(
+            json["vector"]["text"]["indices"].as_array().unwrap().len(),
+            2
+        );
+
+        let json = serde_json::to_value(sample_point()).unwrap();
+        assert!(json["vector"].is_array());
+    }
+
+    #[test]
+    fn test_point_with_summary_vector_uses_content_and_summary_names() {
+        let mut point = sample_point();
+        point.summary_vector = Some(vec![0.4, 0.5, 0.6]);
+
+        let json = serde_json::to_value(&point).unwrap();
+
+        assert_eq!(json["vector"]["content"], json!([0.1f32, 0.2f32, 0.3f32]));
+        assert_eq!(json["vector"]["summary"], json!([0.4f32, 0.5f32, 0.6f32]));
+        assert!(json["vector"].get("").is_none());
+        assert!(json["vector"].get("text").is_none());
+
+        point.sparse = Some(SparseVector::from_text("fresh content"));
+        let json = serde_json::to_value(&point).unwrap();
+        assert_eq!(json["vector"].as_object().unwrap().len(), 3);
+        assert_eq!(
+            json["vector"]["text"]["indices"].as_array().unwrap().len(),
+            2
+        );
+        assert_eq!(dense_vector(&json["vector"]), Some(vec![0.1, 0.2, 0.3]));
+    }
+
+    #[test]
+    fn test_named_vector_search_sends_vector_name() {
+        let (url, _) = serve(|request_line, body| {
+            let body: Value = serde_json::from_str(body).unwrap_or_default();
+            if request_line.starts_with("POST /collections/docs/points/search ")
+                && body["vector"]["name"] == "summary"
+                && body["vector"]["vector"] == json!([1.0, 0.0])
+            {
+                let hit = json!({"id": "p-1", "score": 0.9, "payload": {}});
+                return ("200 OK", json!({"result": [hit]}).to_string());
+            }
+            ("400 Bad Request", String::new())
+        });
+
+        let named = QdrantClient::new(&url).vector_name(Some(SUMMARY_VECTOR_NAME));
+        let results = named.search("docs", &[1.0, 0.0], 3, None).unwrap();
+
+        assert_eq!(results[0].id, "p-1");
+        assert!(
+            QdrantClient::new(&url)
+                .search("docs", &[1.0, 0.0], 3, None)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_token_estimate_is_a_quarter_of_chars() {
+        let counter = TokenCounter::estimate();
+
+        assert_eq!(counter.count(""), 0);
+        assert_eq!(counter.count("abcd"), 1);
+        assert_eq!(counter.count("abcde"), 2);
+        assert_eq!(counter.count("日本語の"), 1);
+    }
+
+    #[test]
+    fn test_bpe_counts_real_tokens() {
+        let counter = TokenCounter::load(Tokenizer::Cl100k);
+
+        assert_eq!(counter.tokenizer(), Tokenizer::Cl100k);
+        assert_eq!(counter.count("hello world"), 2);
+        // Code is far denser in tokens than the four-characters estimate
+        let code = "fn main(){let x=vec![1,2,3];println!(\"{:?}\",x);}";
+        assert!(counter.count(code) > TokenCounter::estimate().count(code));
+    }
+
+    #[test]
+    fn test_truncate_and_tail_respect_budget_and_characters() {
+        let counter = TokenCounter::load(Tokenizer::Cl100k);
+        let text = "ownership 🦀 borrowing lifetimes ".repeat(10);
+
+        let head = counter.truncate(&text, 7);
+        let tail = counter.tail(&text, 7);
+
+        assert!(counter.count(head) <= 7 && !head.is_empty());
+        assert!(counter.count(tail) <= 7 && !tail.is_empty());
+        assert!(text.starts_with(head) && text.ends_with(tail));
+    }
+
+    #[test]
+    fn test_split_keeps_every_piece_under_max_tokens() {
+        let counter = TokenCounter::load(Tokenizer::Cl100k);
+        let text = format!(
+            "{}\n{}\n{}",
+            "Short line about borrowing.",
+            "word ".repeat(300),
+            "x".repeat(2000)
+        );
+
+        let pieces = counter.split(&text, 50);
+
+        assert!(pieces.len() > 3);
+        assert!(pieces.iter().all(|p| counter.count(p) <= 50));
+        assert_eq!(pieces.concat(), text);
+        assert!(pieces[0].ends_with('\n'));
+    }
+
+    #[test]
+    fn test_split_leaves_small_text_alone() {
+        let counter = TokenCounter::estimate();
+
+        assert_eq!(counter.split("tiny", 10), vec!["tiny"]);
+    }
+
+    #[test]
+    fn test_rrf_fuse_dedups_and_rewards_agreement() {
+        let hit = |id: &str, score: f32| SearchResult {
+            id: id.to_string(),
+            score,
+            payload: json!({"text": id}),
+        };
+        let rankings = vec![
+            vec![hit("a", 0.9), hit("b", 0.8), hit("c", 0.7)],
+            vec![hit("b", 0.95), hit("d", 0.6)],
+        ];
+
+        let fused = rrf_fuse(rankings, |r| r.id.clone());
+
+        let ids: Vec<&str> = fused.iter().map(|f| f.result.id.as_str()).collect();
+        assert_eq!(ids, vec!["b", "a", "d", "c"]);
+        assert!((fused[0].result.score - (1.0 / 62.0 + 1.0 / 61.0)).abs() < 1e-6);
+        assert_eq!(fused[0].rankings, vec![0, 1]);
+        assert!((fused[1].result.score - 1.0 / 61.0).abs() < 1e-6);
+        assert_eq!(fused[2].rankings, vec![1]);
+    }
+
+    #[test]
+    fn test_code_blocks_keep_their_fence_language() {
+        let content = "# Ownership\n\nMoves and borrows.\n\n```rust\nlet s = String::new();\n```\n\n```\nplain\n```\n";
+
+        let chunks = smart_chunk_markdown(content, 375, TokenCounter::estimate());
+
+        let langs: Vec<Option<&str>> = chunks.iter().map(|c| c.code_lang.as_deref()).collect();
+        assert_eq!(langs, vec![None, Some("rust"), None, Some("unknown")]);
+    }
+
+    #[test]
+    fn test_markdown_chunks_record_their_lines() {
+        let content = "# Title\nIntro line.\n\n```sh\nls\n```\nAfter.\n## Next\nMore.\n";
+
+        let chunks = smart_chunk_markdown(content, 375, TokenCounter::estimate());
+
+        let ranges: Vec<(usize, usize)> =
+            chunks.iter().map(|c| (c.start_line, c.end_line)).collect();
+        assert_eq!(ranges, vec![(0, 2), (3, 5), (6, 6), (7, 8)]);
+    }
+
+    #[test]
+    fn test_overlap_repeats_previous_text_at_start_of_next_chunk() {
+        let content = "# Guide\n\nFirst paragraph about lifetimes.\n\nSecond paragraph.\n\n```rust\nfn main() {}\n```\n\nAfter the code.\n\n## Next\n\nNew section.\n";
+        let counter = TokenCounter::estimate();
+        let chunks = smart_chunk_markdown(content, 5, counter);
+        let plain: Vec<String> = chunks.iter().map(|c| c.content.clone()).collect();
+
+        let chunks = overlap_text_chunks(chunks, 5, counter);
+
+        let second = chunks
+            .iter()
+            .find(|c| c.content.contains("Second paragraph"))
+            .unwrap();
+        assert!(
+            second
+                .content
+                .starts_with("about lifetimes.\n\nSecond paragraph."),
+            "{:?}",
+            second.content
+        );
+        let code = chunks.iter().find(|c| c.code_lang.is_some()).unwrap();
+        assert!(code.content.starts_with("```rust"));
+        let after = chunks
+            .iter()
+            .find(|c| c.content.contains("After the code"))
+            .unwrap();
+        assert!(
+            after.content.trim_start().starts_with("After the code"),
+            "no overlap from code"
+        );
+        assert_eq!(chunks.last().unwrap().content, *plain.last().unwrap());
+        assert!(chunks[0].content.starts_with("# Guide"));
+    }
+
+    #[test]
+    fn test_small_chunks_merge_headings_forward() {
+        let content = "# Guide\n## Install\nRun the installer and follow the prompts to finish.\n\n# API\n```rust\nfn main() {}\n```\nShort.\n## Trailing\n";
+        let counter = TokenCounter::estimate();
+        let chunks = smart_chunk_markdown(content, 375, counter);
+        assert_eq!(chunks.len(), 6);
+
+        let (chunks, stats) = merge_small_chunks(chunks, 8, counter);
+
+        let contents: Vec<&str> = chunks.iter().map(|c| c.content.as_str()).collect();
+        assert_eq!(
+            contents,
+            vec![
+                "# Guide\n## Install\nRun the installer and follow the prompts to finish.\n\n",
+                "```rust\nfn main() {}\n```\n",
+                "Short.\n",
+            ]
+        );
+        assert_eq!(
+            stats,
+            SmallChunkStats {
+                merged: 1,
+                dropped: 2
+            }
+        );
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (0, 3));
+        assert_eq!(chunks[0].breadcrumb, "Guide > Install");
+        assert_eq!(chunks[2].index, 2);
+        assert!(chunks[1].header_context.contains("# API"));
+    }
+
+    #[test]
+    fn test_chunks_carry_full_heading_breadcrumb() {
+        let content = "# Guide\n\nIntro.\n\n## Install\n\n### Linux\n\nUse apt.\n\n```sh\napt install foo\n```\n\n## Usage\n\nRun it.\n";
+
+        let chunks = smart_chunk_markdown(content, 375, TokenCounter::estimate());
+
+        let linux = chunks
+            .iter()
+            .find(|c| c.content.contains("Use apt"))
+            .unwrap();
+        assert_eq!(linux.breadcrumb, "Guide > Install > Linux");
+        let code = chunks.iter().find(|c| c.code_lang.is_some()).unwrap();
+        assert_eq!(code.header_context, "# Guide\n## Install\n### Linux");
+        let usage = chunks
+            .iter()
+            .find(|c| c.content.contains("Run it"))
+            .unwrap();
+        assert_eq!(usage.breadcrumb, "Guide > Usage");
+    }
+}
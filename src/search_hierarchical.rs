@@ -1,11 +1,18 @@
 // Copyright (c) 2025 Michael A. Wright
 // Licensed under the MIT License
 
-use anyhow::{Context, Result};
+use anyhow::Result;
 use clap::Parser;
-use reqwest::blocking::Client;
-use serde::{Deserialize, Serialize};
+use rag_demo::config::parse_args;
+use rag_demo::rag::{
+    ApiKey, CONTENT_VECTOR_NAME, CollectionResult, Consistency, EmbedApi, Embedder, EmbeddingCache,
+    QdrantClient, RetrievedPoint, RetryPolicy, SUMMARY_VECTOR_NAME, SearchResult,
+    namespaced_collection, preflight, preview,
+};
+use serde::Serialize;
 use serde_json::json;
+use std::collections::HashMap;
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Hierarchical search with parent-child context", long_about = None)]
@@ -16,19 +23,61 @@ struct Args {
     #[arg(short, long, default_value = "5", help = "Number of results")]
     limit: usize,
 
-    #[arg(long, default_value = "documents", help = "Collection name")]
+    #[arg(
+        long,
+        default_value = "documents",
+        env = "RAG_COLLECTION",
+        help = "Collection name"
+    )]
     collection: String,
 
-    #[arg(long, default_value = "http://localhost:6333", help = "Qdrant URL")]
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_PREFIX",
+        help = "Prepended to the collection name, e.g. acme_ to keep one tenant's collections apart"
+    )]
+    collection_prefix: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_SUFFIX",
+        help = "Appended to the collection name"
+    )]
+    collection_suffix: String,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:6333",
+        env = "QDRANT_URL",
+        help = "Qdrant URL"
+    )]
     qdrant_url: String,
 
-    #[arg(long, default_value = "http://localhost:11434", help = "Ollama URL")]
+    #[arg(
+        long,
+        env = "QDRANT_API_KEY",
+        hide_env_values = true,
+        help = "Qdrant API key, sent as the api-key header (Qdrant Cloud)"
+    )]
+    qdrant_api_key: Option<ApiKey>,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:11434",
+        env = "OLLAMA_URL",
+        help = "Ollama URL"
+    )]
     ollama_url: String,
 
     #[arg(
         short,
         long,
         default_value = "nomic-embed-text",
+        env = "RAG_MODEL",
         help = "Model for embeddings"
     )]
     model: String,
@@ -38,101 +87,212 @@ struct Args {
 
     #[arg(long, help = "Include parent context for child chunks")]
     with_parent: bool,
-}
 
-#[derive(Debug, Serialize, Deserialize)]
-struct EmbeddingRequest {
-    model: String,
-    prompt: String,
-}
+    #[arg(
+        long,
+        default_value = "text",
+        help = "Payload field that stores the chunk text"
+    )]
+    text_field: String,
 
-#[derive(Debug, Deserialize)]
-struct EmbeddingResponse {
-    embedding: Vec<f32>,
-}
+    #[arg(
+        long,
+        help = "Like --json, but compact and errors are reported as JSON too"
+    )]
+    strict_json: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "ollama",
+        help = "Embedding API: ollama (/api/embeddings), ollama-embed (/api/embed), openai (/v1/embeddings)"
+    )]
+    embed_api: EmbedApi,
 
-#[derive(Debug, Deserialize)]
-struct QdrantSearchResponse {
-    result: Vec<SearchResult>,
+    #[arg(
+        long,
+        help = "Skip checking that Qdrant, Ollama and the embedding model are available"
+    )]
+    skip_checks: bool,
+
+    #[arg(
+        long,
+        help = "Fail instead of warning when the collection was embedded with a model other than --model"
+    )]
+    strict: bool,
+
+    #[arg(
+        long,
+        default_value = "4",
+        help = "Times to retry a failed embedding request (5xx, 429, connection errors)"
+    )]
+    embed_retries: u32,
+
+    #[arg(
+        long,
+        default_value = "500",
+        help = "Delay before the first embedding retry in ms, doubled on each retry"
+    )]
+    embed_retry_delay_ms: u64,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "eventual",
+        help = "strong waits for upserts to be applied and searches exactly, so just-ingested points are always found"
+    )]
+    consistency: Consistency,
+
+    #[arg(
+        long,
+        help = "Directory for cached query embeddings (default: ~/.cache/rag-demo)"
+    )]
+    cache_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Always ask the embedding server instead of using the cache"
+    )]
+    no_cache: bool,
+
+    #[arg(
+        long,
+        help = "Dense vector to search in collections with named vectors: content (each chunk's text, the default) or summary (its section's summary)"
+    )]
+    vector_name: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
-struct SearchResult {
-    id: String,
-    score: f32,
-    payload: serde_json::Value,
+/// The named vector to search: `requested` if the collection has it, else
+/// the collection's default
+fn resolve_vector_name<'a>(
+    collection: &str,
+    info: &CollectionResult,
+    requested: Option<&'a str>,
+) -> Result<Option<&'a str>> {
+    match requested {
+        Some(name) if !info.has_named_vector(name) => anyhow::bail!(
+            "'{collection}' has no vector named '{name}'; only collections ingest-hierarchical created with named vectors have '{CONTENT_VECTOR_NAME}' and '{SUMMARY_VECTOR_NAME}' (re-ingest into a new collection to get them)"
+        ),
+        Some(name) => Ok(Some(name)),
+        None => Ok(info.default_vector_name()),
+    }
 }
 
 #[derive(Debug, Serialize)]
 struct HierarchicalResult {
     child: SearchResult,
-    parent: Option<SearchResult>,
+    parent: Option<RetrievedPoint>,
     combined_text: String,
 }
 
-fn get_embedding(client: &Client, ollama_url: &str, model: &str, text: &str) -> Result<Vec<f32>> {
-    let request = EmbeddingRequest {
-        model: model.to_string(),
-        prompt: text.to_string(),
-    };
-
-    let response = client
-        .post(format!("{}/api/embeddings", ollama_url))
-        .json(&request)
-        .send()
-        .context("Failed to get embedding from Ollama")?;
+fn parent_id(child: &SearchResult) -> Option<&str> {
+    child
+        .payload
+        .get("parent_id")
+        .and_then(|v| v.as_str())
+        .filter(|id| !id.is_empty())
+}
 
-    if !response.status().is_success() {
-        anyhow::bail!("Ollama returned error: {}", response.status());
+/// Parent and child text for the `combined_text` field. A child is normally
+/// cut from its parent's text; then only the parent is given, with the
+/// child's span marked `>>>` ... `<<<`, instead of repeating the child.
+fn combine_texts(parent: &str, child: &str) -> String {
+    let span = child.trim();
+    if let Some(start) = parent.find(span).filter(|_| !span.is_empty()) {
+        let end = start + span.len();
+        return format!(
+            "=== CONTEXT (Parent Chunk, precise match between >>> and <<<) ===\n{}>>>{}<<<{}",
+            &parent[..start],
+            span,
+            &parent[end..]
+        );
     }
-
-    let embedding: EmbeddingResponse = response
-        .json()
-        .context("Failed to parse embedding response")?;
-
-    Ok(embedding.embedding)
+    format!(
+        "=== CONTEXT (Parent Chunk) ===\n{parent}\n\n=== PRECISE MATCH (Child Chunk) ===\n{child}"
+    )
 }
 
-fn search_qdrant(
-    client: &Client,
-    qdrant_url: &str,
-    collection: &str,
-    embedding: Vec<f32>,
-    limit: usize,
-    filter: Option<serde_json::Value>,
-) -> Result<Vec<SearchResult>> {
-    let search_request = json!({
-        "vector": embedding,
-        "limit": limit,
-        "with_payload": true,
-        "filter": filter,
-    });
+/// Pair each child with its parent from `parents`, combining their texts
+fn attach_parents(
+    children: Vec<SearchResult>,
+    parents: Vec<RetrievedPoint>,
+    text_field: &str,
+) -> Vec<HierarchicalResult> {
+    let parents: HashMap<String, RetrievedPoint> =
+        parents.into_iter().map(|p| (p.id.clone(), p)).collect();
+
+    children
+        .into_iter()
+        .map(|child| {
+            let parent = parent_id(&child).and_then(|id| parents.get(id)).cloned();
+            let combined_text = if let Some(ref p) = parent {
+                combine_texts(
+                    payload_text(&p.payload, text_field),
+                    payload_text(&child.payload, text_field),
+                )
+            } else {
+                payload_text(&child.payload, text_field).to_string()
+            };
 
-    let response = client
-        .post(format!(
-            "{}/collections/{}/points/search",
-            qdrant_url, collection
-        ))
-        .json(&search_request)
-        .send()
-        .context("Failed to search Qdrant")?;
-
-    if !response.status().is_success() {
-        anyhow::bail!("Qdrant search failed: {}", response.status());
-    }
+            HierarchicalResult {
+                child,
+                parent,
+                combined_text,
+            }
+        })
+        .collect()
+}
 
-    let search_response: QdrantSearchResponse =
-        response.json().context("Failed to parse search response")?;
+/// Read the chunk text from a payload using the configured field name
+fn payload_text<'a>(payload: &'a serde_json::Value, text_field: &str) -> &'a str {
+    payload
+        .get(text_field)
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+}
 
-    Ok(search_response.result)
+/// Serialize output for `--json` (pretty) or `--strict-json` (single line)
+fn to_json_string<T: Serialize>(value: &T, strict: bool) -> Result<String> {
+    Ok(if strict {
+        serde_json::to_string(value)?
+    } else {
+        serde_json::to_string_pretty(value)?
+    })
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
-    let client = Client::new();
+fn run(args: &Args) -> Result<()> {
+    if !args.skip_checks {
+        preflight(
+            &args.qdrant_url,
+            &args.ollama_url,
+            &args.model,
+            args.embed_api,
+        )?;
+    }
+    let embedder = Embedder::new(
+        &args.ollama_url,
+        args.embed_api,
+        &args.model,
+        RetryPolicy {
+            retries: args.embed_retries,
+            base_delay_ms: args.embed_retry_delay_ms,
+        },
+    )
+    .cache(EmbeddingCache::from_flags(
+        args.cache_dir.as_deref(),
+        args.no_cache,
+    ));
+    let qdrant = QdrantClient::new(&args.qdrant_url)
+        .api_key(args.qdrant_api_key.as_ref())
+        .consistency(args.consistency);
+    qdrant.require_collection(&args.collection)?;
+    qdrant.check_embed_model(&args.collection, &args.model, args.strict)?;
+    let info = qdrant.collection_info(&args.collection)?;
+    let vector_name = resolve_vector_name(&args.collection, &info, args.vector_name.as_deref())?;
+    let qdrant = qdrant.vector_name(vector_name);
 
     // Get embedding for query
-    let query_embedding = get_embedding(&client, &args.ollama_url, &args.model, &args.query)?;
+    let query_embedding = embedder.embed(&args.query)?;
 
     // Search for child chunks first (more precise)
     let child_filter = json!({
@@ -144,82 +304,38 @@ fn main() -> Result<()> {
         }]
     });
 
-    let child_results = search_qdrant(
-        &client,
-        &args.qdrant_url,
+    let child_results = qdrant.search(
         &args.collection,
-        query_embedding.clone(),
+        &query_embedding,
         args.limit,
-        Some(child_filter),
+        Some(&child_filter),
     )?;
 
-    if args.json {
-        if args.with_parent {
-            // Fetch parent chunks for context
-            let mut hierarchical_results = Vec::new();
+    let json_mode = args.json || args.strict_json;
 
-            for child in child_results {
-                let parent_id = child
-                    .payload
-                    .get("parent_id")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("");
-
-                // Fetch parent by ID
-                let parent_filter = json!({
-                    "must": [{
-                        "key": "chunk_type",
-                        "match": { "value": "parent" }
-                    }]
-                });
-
-                // Search for parent chunk (using same embedding for now, could optimize)
-                let parent_results = search_qdrant(
-                    &client,
-                    &args.qdrant_url,
-                    &args.collection,
-                    query_embedding.clone(),
-                    20, // Search more to find the specific parent
-                    Some(parent_filter),
-                )?;
-
-                // Find matching parent
-                let parent = parent_results.iter().find(|p| p.id == parent_id).cloned();
-
-                let combined_text = if let Some(ref p) = parent {
-                    format!(
-                        "=== CONTEXT (Parent Chunk) ===\n{}\n\n=== PRECISE MATCH (Child Chunk) ===\n{}",
-                        p.payload.get("text").and_then(|v| v.as_str()).unwrap_or(""),
-                        child
-                            .payload
-                            .get("text")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("")
-                    )
-                } else {
-                    child
-                        .payload
-                        .get("text")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("")
-                        .to_string()
-                };
-
-                hierarchical_results.push(HierarchicalResult {
-                    child,
-                    parent,
-                    combined_text,
-                });
-            }
+    if json_mode {
+        if args.with_parent {
+            // Fetch the parents of all matched children in one request
+            let mut parent_ids: Vec<&str> = child_results.iter().filter_map(parent_id).collect();
+            parent_ids.sort_unstable();
+            parent_ids.dedup();
+            let parents = qdrant.get_points_by_id(&args.collection, &parent_ids)?;
+            let hierarchical_results = attach_parents(child_results, parents, &args.text_field);
 
-            println!("{}", serde_json::to_string_pretty(&hierarchical_results)?);
+            println!(
+                "{}",
+                to_json_string(&hierarchical_results, args.strict_json)?
+            );
         } else {
             println!(
                 "{}",
-                serde_json::to_string_pretty(&json!({
-                    "query": args.query,
-                    "results": child_results,
-                }))?
+                to_json_string(
+                    &json!({
+                        "query": args.query,
+                        "results": child_results,
+                    }),
+                    args.strict_json
+                )?
             );
         }
     } else {
@@ -240,11 +356,7 @@ fn main() -> Result<()> {
                 .and_then(|v| v.as_str())
                 .unwrap_or("none");
 
-            let text = result
-                .payload
-                .get("text")
-                .and_then(|v| v.as_str())
-                .unwrap_or("");
+            let text = payload_text(&result.payload, &args.text_field);
 
             println!("Type: {}", chunk_type);
 
@@ -262,12 +374,11 @@ fn main() -> Result<()> {
             }
 
             // Show text preview
-            let preview = if text.len() > 300 {
-                format!("{}...", &text[..300])
-            } else {
-                text.to_string()
-            };
-            println!("{}", preview);
+            println!("{}", preview(text, 300));
+
+            if let Some(source) = result.payload.get("source").and_then(|v| v.as_str()) {
+                println!("Source: {}", source);
+            }
 
             println!();
         }
@@ -282,11 +393,52 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+fn main() -> Result<()> {
+    let mut args: Args = parse_args()?;
+    args.collection = namespaced_collection(
+        &args.collection_prefix,
+        &args.collection,
+        &args.collection_suffix,
+    );
+    if !args.strict_json {
+        return run(&args);
+    }
+
+    // Strict mode: even failures are reported as JSON on stdout
+    if let Err(e) = run(&args) {
+        println!("{}", json!({ "error": format!("{e:#}") }));
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use serde_json::json;
 
+    #[test]
+    fn test_resolve_vector_name_defaults_to_content_when_named() {
+        let collection = |vectors: serde_json::Value| -> CollectionResult {
+            serde_json::from_value(json!({"config": {"params": {"vectors": vectors}}})).unwrap()
+        };
+        let dense = json!({"size": 768, "distance": "Cosine"});
+        let named = collection(json!({"content": dense, "summary": dense}));
+        let single = collection(dense.clone());
+
+        assert_eq!(
+            resolve_vector_name("docs", &named, None).unwrap(),
+            Some("content")
+        );
+        assert_eq!(
+            resolve_vector_name("docs", &named, Some("summary")).unwrap(),
+            Some("summary")
+        );
+        assert_eq!(resolve_vector_name("docs", &single, None).unwrap(), None);
+        assert!(resolve_vector_name("docs", &single, Some("summary")).is_err());
+        assert!(resolve_vector_name("docs", &named, Some("title")).is_err());
+    }
+
     #[test]
     fn test_search_result_deserialization() {
         let json_data = json!({
@@ -313,9 +465,8 @@ mod tests {
             payload: json!({"text": "Child text", "chunk_type": "Text"}),
         };
 
-        let parent = SearchResult {
+        let parent = RetrievedPoint {
             id: "parent-1".to_string(),
-            score: 0.85,
             payload: json!({"text": "Parent text", "chunk_type": "Text"}),
         };
 
@@ -330,6 +481,43 @@ mod tests {
         assert_eq!(hierarchical.combined_text, "Combined text");
     }
 
+    #[test]
+    fn test_attach_parents_matches_by_id() {
+        let child = |id: &str, parent: &str| SearchResult {
+            id: id.to_string(),
+            score: 0.9,
+            payload: json!({"text": format!("{id} text"), "parent_id": parent}),
+        };
+        let parents = vec![RetrievedPoint {
+            id: "parent-1".to_string(),
+            payload: json!({"text": "Parent text"}),
+        }];
+
+        let results = attach_parents(
+            vec![child("child-1", "parent-1"), child("child-2", "gone")],
+            parents,
+            "text",
+        );
+
+        assert_eq!(results[0].parent.as_ref().unwrap().id, "parent-1");
+        assert!(results[0].combined_text.contains("Parent text"));
+        assert!(results[0].combined_text.ends_with("child-1 text"));
+        assert!(results[1].parent.is_none());
+        assert_eq!(results[1].combined_text, "child-2 text");
+    }
+
+    #[test]
+    fn test_child_inside_parent_is_marked_not_repeated() {
+        let parent = "## Ownership\n\nEach value has one owner.\n\nMoving transfers it.";
+        let child = "Each value has one owner.\n";
+
+        let combined = combine_texts(parent, child);
+
+        assert_eq!(combined.matches("Each value has one owner.").count(), 1);
+        assert!(combined.contains("\n>>>Each value has one owner.<<<\n\nMoving"));
+        assert!(!combined.contains("PRECISE MATCH (Child Chunk)"));
+    }
+
     #[test]
     fn test_hierarchical_result_without_parent() {
         let child = SearchResult {
@@ -349,29 +537,11 @@ mod tests {
     }
 
     #[test]
-    fn test_embedding_request_serialization() {
-        let request = EmbeddingRequest {
-            model: "nomic-embed-text".to_string(),
-            prompt: "test query".to_string(),
-        };
-
-        let json = serde_json::to_value(&request).unwrap();
+    fn test_payload_text_reads_custom_field() {
+        let payload = json!({"content": "Stored under content", "source": "doc.md"});
 
-        assert_eq!(json["model"], "nomic-embed-text");
-        assert_eq!(json["prompt"], "test query");
-    }
-
-    #[test]
-    fn test_embedding_response_deserialization() {
-        let json_data = json!({
-            "embedding": [0.1, 0.2, 0.3, 0.4]
-        });
-
-        let response: EmbeddingResponse = serde_json::from_value(json_data).unwrap();
-
-        assert_eq!(response.embedding.len(), 4);
-        assert_eq!(response.embedding[0], 0.1);
-        assert_eq!(response.embedding[3], 0.4);
+        assert_eq!(payload_text(&payload, "content"), "Stored under content");
+        assert_eq!(payload_text(&payload, "text"), "");
     }
 
     #[test]
@@ -388,30 +558,6 @@ mod tests {
         assert_eq!(result.score, cloned.score);
     }
 
-    #[test]
-    fn test_qdrant_search_response_deserialization() {
-        let json_data = json!({
-            "result": [
-                {
-                    "id": "id-1",
-                    "score": 0.95,
-                    "payload": {"text": "Result 1"}
-                },
-                {
-                    "id": "id-2",
-                    "score": 0.90,
-                    "payload": {"text": "Result 2"}
-                }
-            ]
-        });
-
-        let response: QdrantSearchResponse = serde_json::from_value(json_data).unwrap();
-
-        assert_eq!(response.result.len(), 2);
-        assert_eq!(response.result[0].id, "id-1");
-        assert_eq!(response.result[1].score, 0.90);
-    }
-
     #[test]
     fn test_hierarchical_result_serialization() {
         let child = SearchResult {
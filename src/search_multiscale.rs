@@ -0,0 +1,405 @@
+// Copyright (c) 2025 Michael A. Wright
+// Licensed under the MIT License
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use rag_demo::config::parse_args;
+use rag_demo::rag::{
+    ApiKey, Consistency, EmbedApi, Embedder, EmbeddingCache, FusedResult, QdrantClient,
+    RetryPolicy, SearchResult, namespaced_collection, preflight, preview, rrf_fuse,
+};
+use serde::Serialize;
+use serde_json::json;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Search the _small/_medium/_large collections of ingest-markdown-multi and fuse the rankings",
+    long_about = None
+)]
+struct Args {
+    #[arg(help = "Search query")]
+    query: String,
+
+    #[arg(short, long, default_value = "5", help = "Number of results")]
+    limit: usize,
+
+    #[arg(
+        long,
+        default_value = "documents",
+        env = "RAG_COLLECTION",
+        help = "Base collection name given to ingest-markdown-multi --collection"
+    )]
+    base_collection: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_PREFIX",
+        help = "Prepended to the collection name, e.g. acme_ to keep one tenant's collections apart"
+    )]
+    collection_prefix: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_SUFFIX",
+        help = "Appended to the collection name"
+    )]
+    collection_suffix: String,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:6333",
+        env = "QDRANT_URL",
+        help = "Qdrant URL"
+    )]
+    qdrant_url: String,
+
+    #[arg(
+        long,
+        env = "QDRANT_API_KEY",
+        hide_env_values = true,
+        help = "Qdrant API key, sent as the api-key header (Qdrant Cloud)"
+    )]
+    qdrant_api_key: Option<ApiKey>,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:11434",
+        env = "OLLAMA_URL",
+        help = "Ollama URL"
+    )]
+    ollama_url: String,
+
+    #[arg(
+        short,
+        long,
+        default_value = "nomic-embed-text",
+        env = "RAG_MODEL",
+        help = "Embedding model"
+    )]
+    model: String,
+
+    #[arg(short, long, help = "Output as JSON")]
+    json: bool,
+
+    #[arg(
+        long,
+        default_value = "text",
+        help = "Payload field that stores the chunk text"
+    )]
+    text_field: String,
+
+    #[arg(
+        long,
+        help = "Like --json, but compact and errors are reported as JSON too"
+    )]
+    strict_json: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "ollama",
+        help = "Embedding API: ollama (/api/embeddings), ollama-embed (/api/embed), openai (/v1/embeddings)"
+    )]
+    embed_api: EmbedApi,
+
+    #[arg(
+        long,
+        help = "Skip checking that Qdrant, Ollama and the embedding model are available"
+    )]
+    skip_checks: bool,
+
+    #[arg(
+        long,
+        help = "Fail instead of warning when the collection was embedded with a model other than --model"
+    )]
+    strict: bool,
+
+    #[arg(
+        long,
+        default_value = "4",
+        help = "Times to retry a failed embedding request (5xx, 429, connection errors)"
+    )]
+    embed_retries: u32,
+
+    #[arg(
+        long,
+        default_value = "500",
+        help = "Delay before the first embedding retry in ms, doubled on each retry"
+    )]
+    embed_retry_delay_ms: u64,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "eventual",
+        help = "strong waits for upserts to be applied and searches exactly, so just-ingested points are always found"
+    )]
+    consistency: Consistency,
+
+    #[arg(
+        long,
+        help = "Directory for cached query embeddings (default: ~/.cache/rag-demo)"
+    )]
+    cache_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Always ask the embedding server instead of using the cache"
+    )]
+    no_cache: bool,
+
+    #[arg(
+        long,
+        default_value = "3",
+        help = "Fetch limit x this many candidates from each scale before fusing"
+    )]
+    over_fetch: usize,
+}
+
+/// Collection suffixes written by `ingest-markdown-multi --multi-scale`
+const SCALES: [&str; 3] = ["small", "medium", "large"];
+
+/// A fused hit and the scales whose rankings it appeared in
+#[derive(Debug, Serialize)]
+struct MultiScaleResult {
+    #[serde(flatten)]
+    result: SearchResult,
+    scales: Vec<String>,
+}
+
+/// Identity of a chunk across collections, whose point IDs differ: its source
+/// and line range when the payload has them, otherwise its text
+#[derive(Debug, PartialEq)]
+enum ChunkKey {
+    Lines(String, u64, u64),
+    Text(String),
+}
+
+fn chunk_key(result: &SearchResult, text_field: &str) -> ChunkKey {
+    let payload = &result.payload;
+    let source = payload.get("source").and_then(|v| v.as_str());
+    let start = payload.get("start_line").and_then(|v| v.as_u64());
+    let end = payload.get("end_line").and_then(|v| v.as_u64());
+    match (source, start, end) {
+        (Some(source), Some(start), Some(end)) => ChunkKey::Lines(source.to_string(), start, end),
+        _ => ChunkKey::Text(payload_text(payload, text_field).to_string()),
+    }
+}
+
+/// Read the chunk text from a payload using the configured field name
+fn payload_text<'a>(payload: &'a serde_json::Value, text_field: &str) -> &'a str {
+    payload
+        .get(text_field)
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+}
+
+/// Fuse one ranking per scale and keep the best `limit`, naming the scales each hit came from
+fn fuse_scales(
+    rankings: Vec<(&str, Vec<SearchResult>)>,
+    limit: usize,
+    text_field: &str,
+) -> Vec<MultiScaleResult> {
+    let (scales, rankings): (Vec<&str>, Vec<Vec<SearchResult>>) = rankings.into_iter().unzip();
+    rrf_fuse(rankings, |result| chunk_key(result, text_field))
+        .into_iter()
+        .take(limit)
+        .map(|FusedResult { result, rankings }| MultiScaleResult {
+            result,
+            scales: rankings.iter().map(|&i| scales[i].to_string()).collect(),
+        })
+        .collect()
+}
+
+fn run(args: &Args) -> Result<()> {
+    if !args.skip_checks {
+        preflight(
+            &args.qdrant_url,
+            &args.ollama_url,
+            &args.model,
+            args.embed_api,
+        )?;
+    }
+    let qdrant = QdrantClient::new(&args.qdrant_url)
+        .api_key(args.qdrant_api_key.as_ref())
+        .consistency(args.consistency);
+    let json_mode = args.json || args.strict_json;
+
+    let mut collections = Vec::new();
+    for scale in SCALES {
+        let collection = format!("{}_{scale}", args.base_collection);
+        if qdrant.collection_exists(&collection) {
+            collections.push((scale, collection));
+        } else if !json_mode {
+            eprintln!("⚠️  Collection '{collection}' not found; searching the other scales");
+        }
+    }
+    if collections.is_empty() {
+        anyhow::bail!(
+            "No {base}_small, {base}_medium or {base}_large collection found; ingest with: ingest-markdown-multi --multi-scale --collection {base}",
+            base = args.base_collection
+        );
+    }
+    for (_, collection) in &collections {
+        qdrant.check_embed_model(collection, &args.model, args.strict)?;
+    }
+
+    let embedder = Embedder::new(
+        &args.ollama_url,
+        args.embed_api,
+        &args.model,
+        RetryPolicy {
+            retries: args.embed_retries,
+            base_delay_ms: args.embed_retry_delay_ms,
+        },
+    )
+    .cache(EmbeddingCache::from_flags(
+        args.cache_dir.as_deref(),
+        args.no_cache,
+    ));
+    // One embedding serves every scale: all three use the same model
+    let query_embedding = embedder
+        .embed(&args.query)
+        .context("Failed to get query embedding")?;
+
+    let candidates = args.limit * args.over_fetch.max(1);
+    let mut rankings = Vec::with_capacity(collections.len());
+    for (scale, collection) in &collections {
+        let ranking = qdrant.search(collection, &query_embedding, candidates, None)?;
+        rankings.push((*scale, ranking));
+    }
+    let results = fuse_scales(rankings, args.limit, &args.text_field);
+
+    if json_mode {
+        let output = json!({ "query": args.query, "results": results });
+        let output = if args.strict_json {
+            serde_json::to_string(&output)?
+        } else {
+            serde_json::to_string_pretty(&output)?
+        };
+        println!("{output}");
+        return Ok(());
+    }
+
+    if results.is_empty() {
+        println!("No results found for query: {}", args.query);
+        return Ok(());
+    }
+
+    println!("🔍 Multi-Scale Search Results for: {}\n", args.query);
+    for (i, hit) in results.iter().enumerate() {
+        println!(
+            "--- Result {} (RRF: {:.4}, scales: {}) ---",
+            i + 1,
+            hit.result.score,
+            hit.scales.join(", ")
+        );
+        println!(
+            "{}",
+            preview(payload_text(&hit.result.payload, &args.text_field), 300)
+        );
+        if let Some(source) = hit.result.payload.get("source").and_then(|v| v.as_str()) {
+            println!("Source: {source}");
+        }
+        let lines = (
+            hit.result
+                .payload
+                .get("start_line")
+                .and_then(|v| v.as_u64()),
+            hit.result.payload.get("end_line").and_then(|v| v.as_u64()),
+        );
+        if let (Some(start), Some(end)) = lines {
+            println!("Lines: {start}-{end}");
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let mut args: Args = parse_args()?;
+    args.base_collection = namespaced_collection(
+        &args.collection_prefix,
+        &args.base_collection,
+        &args.collection_suffix,
+    );
+    if !args.strict_json {
+        return run(&args);
+    }
+
+    // Strict mode: even failures are reported as JSON on stdout
+    if let Err(e) = run(&args) {
+        println!("{}", json!({ "error": format!("{e:#}") }));
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(id: &str, text: &str, lines: Option<(u64, u64)>) -> SearchResult {
+        let mut payload = json!({"text": text, "source": "guide.md"});
+        if let Some((start, end)) = lines {
+            payload["start_line"] = json!(start);
+            payload["end_line"] = json!(end);
+        }
+        SearchResult {
+            id: id.to_string(),
+            score: 0.8,
+            payload,
+        }
+    }
+
+    #[test]
+    fn test_same_lines_in_two_scales_fuse_into_one_result() {
+        let rankings = vec![
+            (
+                "small",
+                vec![
+                    hit("s1", "Ownership moves values.", Some((10, 12))),
+                    hit("s2", "Borrowing lends them.", Some((13, 14))),
+                ],
+            ),
+            (
+                "medium",
+                vec![hit("m1", "Ownership moves values.", Some((10, 12)))],
+            ),
+            (
+                "large",
+                vec![hit("l1", "A whole section on ownership.", Some((1, 40)))],
+            ),
+        ];
+
+        let results = fuse_scales(rankings, 5, "text");
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].result.id, "s1");
+        assert_eq!(results[0].scales, vec!["small", "medium"]);
+        assert_eq!(results[1].scales, vec!["large"]);
+        assert_eq!(results[2].result.id, "s2");
+    }
+
+    #[test]
+    fn test_chunks_without_lines_are_matched_by_text() {
+        let a = hit("a", "Same text", None);
+        let b = hit("b", "Same text", None);
+        let c = hit("c", "Same text", Some((1, 2)));
+
+        assert_eq!(chunk_key(&a, "text"), chunk_key(&b, "text"));
+        assert_ne!(chunk_key(&a, "text"), chunk_key(&c, "text"));
+        assert_eq!(
+            fuse_scales(vec![("small", vec![a]), ("large", vec![b])], 5, "text").len(),
+            1
+        );
+    }
+}
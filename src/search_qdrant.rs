@@ -3,9 +3,18 @@
 
 use anyhow::{Context, Result};
 use clap::Parser;
+use rag_demo::config::parse_args;
+use rag_demo::rag::{
+    ApiKey, Consistency, Distance, EmbedApi, Embedder, EmbeddingCache, Highlight, OutputFormat,
+    QdrantClient, RetryPolicy, SNIPPET_RADIUS, SearchResult, http_client_builder, markdown_result,
+    namespaced_collection, ollama_has_model, preflight, query_terms, rrf_fuse, snippet,
+};
+use rag_demo::vecmath::cosine;
 use reqwest::blocking::Client;
-use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Search Qdrant for similar documents", long_about = None)]
@@ -16,154 +25,1063 @@ struct Args {
     #[arg(short, long, default_value = "5", help = "Number of results")]
     limit: usize,
 
-    #[arg(long, default_value = "documents", help = "Qdrant collection name")]
+    #[arg(
+        long,
+        default_value = "documents",
+        env = "RAG_COLLECTION",
+        help = "Qdrant collection name"
+    )]
     collection: String,
 
-    #[arg(long, default_value = "http://localhost:6333", help = "Qdrant URL")]
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_PREFIX",
+        help = "Prepended to the collection name, e.g. acme_ to keep one tenant's collections apart"
+    )]
+    collection_prefix: String,
+
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        env = "RAG_COLLECTION_SUFFIX",
+        help = "Appended to the collection name"
+    )]
+    collection_suffix: String,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:6333",
+        env = "QDRANT_URL",
+        help = "Qdrant URL"
+    )]
     qdrant_url: String,
 
-    #[arg(long, default_value = "http://localhost:11434", help = "Ollama URL")]
+    #[arg(
+        long,
+        env = "QDRANT_API_KEY",
+        hide_env_values = true,
+        help = "Qdrant API key, sent as the api-key header (Qdrant Cloud)"
+    )]
+    qdrant_api_key: Option<ApiKey>,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:11434",
+        env = "OLLAMA_URL",
+        help = "Ollama URL"
+    )]
     ollama_url: String,
 
     #[arg(
         short,
         long,
         default_value = "nomic-embed-text",
+        env = "RAG_MODEL",
         help = "Embedding model"
     )]
     model: String,
 
-    #[arg(short, long, help = "Output as JSON")]
+    #[arg(short, long, help = "Output as JSON (same as --format json)")]
     json: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "text",
+        help = "Output format: text (human-readable), json, or markdown (numbered blockquotes for pasting into docs)"
+    )]
+    format: OutputFormat,
+
+    #[arg(
+        long,
+        default_value = "text",
+        help = "Payload field that stores the chunk text"
+    )]
+    text_field: String,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["json", "strict_json", "format"],
+        help = "Print only each result's full text, separated by --separator, for piping into other tools"
+    )]
+    text_only: bool,
+
+    #[arg(
+        long,
+        default_value = "\n---\n",
+        hide_default_value = true,
+        requires = "text_only",
+        help = "String written between results with --text-only [default: a line with ---]"
+    )]
+    separator: String,
+
+    #[arg(
+        long,
+        help = "Like --json, but compact and errors are reported as JSON too"
+    )]
+    strict_json: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "ollama",
+        help = "Embedding API: ollama (/api/embeddings), ollama-embed (/api/embed), openai (/v1/embeddings)"
+    )]
+    embed_api: EmbedApi,
+
+    #[arg(
+        long,
+        help = "Skip checking that Qdrant, Ollama and the embedding model are available"
+    )]
+    skip_checks: bool,
+
+    #[arg(
+        long,
+        help = "Fail instead of warning when the collection was embedded with a model other than --model"
+    )]
+    strict: bool,
+
+    #[arg(
+        long,
+        default_value = "4",
+        help = "Times to retry a failed embedding request (5xx, 429, connection errors)"
+    )]
+    embed_retries: u32,
+
+    #[arg(
+        long,
+        default_value = "500",
+        help = "Delay before the first embedding retry in ms, doubled on each retry"
+    )]
+    embed_retry_delay_ms: u64,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "eventual",
+        help = "strong waits for upserts to be applied and searches exactly, so just-ingested points are always found"
+    )]
+    consistency: Consistency,
+
+    #[arg(
+        long,
+        help = "Directory for cached query embeddings (default: ~/.cache/rag-demo)"
+    )]
+    cache_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Always ask the embedding server instead of using the cache"
+    )]
+    no_cache: bool,
+
+    #[arg(
+        long,
+        help = "Drop results scoring below this cosine similarity (0-1 in practice, 1 = identical); for Euclid collections, results farther than this distance"
+    )]
+    min_score: Option<f32>,
+
+    #[arg(
+        long,
+        help = "Re-rank with Maximal Marginal Relevance so near-duplicate passages don't crowd out the rest"
+    )]
+    mmr: bool,
+
+    #[arg(
+        long,
+        default_value = "0.5",
+        help = "MMR trade-off: 1.0 ranks purely by relevance, 0.0 purely by diversity"
+    )]
+    mmr_lambda: f32,
+
+    #[arg(
+        long,
+        default_value = "4",
+        help = "With --mmr, --expand or --boost, fetch limit x this many candidates to choose from"
+    )]
+    over_fetch: usize,
+
+    #[arg(
+        long,
+        conflicts_with = "mmr",
+        help = "Fetch limit x 3 candidates and re-sort them by an LLM's 0-10 relevance rating"
+    )]
+    rerank: bool,
+
+    #[arg(
+        long,
+        default_value = "mistral:7b",
+        help = "Ollama model that rates relevance for --rerank"
+    )]
+    rerank_model: String,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["mmr", "rerank"],
+        help = "Also search LLM-written rewordings of the query and fuse the rankings (RRF); costs an extra LLM call"
+    )]
+    expand: bool,
+
+    #[arg(
+        long,
+        default_value = "3",
+        help = "Rewordings of the query to generate for --expand"
+    )]
+    expansions: usize,
+
+    #[arg(
+        long,
+        default_value = "mistral:7b",
+        help = "Ollama model that rewords the query for --expand"
+    )]
+    expand_model: String,
+
+    #[arg(
+        long,
+        value_name = "FIELD=WEIGHT",
+        help = "Add WEIGHT x the numeric payload FIELD (scaled 0-1 across the candidates) to each score, or WEIGHT when FIELD:VALUE matches a string, then re-sort (repeatable)"
+    )]
+    boost: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct EmbeddingRequest {
-    model: String,
-    prompt: String,
+/// With --rerank, candidates fetched per requested result
+const RERANK_CANDIDATE_FACTOR: usize = 3;
+
+const RERANK_PROMPT: &str = "Rate how relevant the passage is to the query on a scale from 0 \
+(unrelated) to 10 (directly answers it). Reply with the number only.";
+
+const EXPAND_PROMPT: &str = "Rewrite the search query in different words, spelling out \
+abbreviations and using synonyms a document might use instead. Reply with one rewording \
+per line and nothing else.";
+
+/// Ask an Ollama model for up to `count` rewordings of `query`
+fn expand_query(ollama_url: &str, model: &str, query: &str, count: usize) -> Result<Vec<String>> {
+    let client = http_client_builder()
+        .timeout(None)
+        .build()
+        .context("Failed to build HTTP client")?;
+    let response = client
+        .post(format!("{ollama_url}/api/generate"))
+        .json(&json!({
+            "model": model,
+            "system": EXPAND_PROMPT,
+            "prompt": format!("Query: {query}\n\nGive {count} rewordings."),
+            "stream": false,
+        }))
+        .send()
+        .context("Failed to reach Ollama for query expansion")?;
+    if !response.status().is_success() {
+        anyhow::bail!(
+            "Ollama returned error while expanding the query: {}",
+            response.status()
+        );
+    }
+    let reply: serde_json::Value = response
+        .json()
+        .context("Failed to parse query expansion response")?;
+    Ok(parse_expansions(
+        reply["response"].as_str().unwrap_or(""),
+        query,
+        count,
+    ))
 }
 
-#[derive(Debug, Deserialize)]
-struct EmbeddingResponse {
-    embedding: Vec<f32>,
+/// One rewording per reply line, without list markers or quotes, skipping
+/// blanks and repeats of the query
+fn parse_expansions(reply: &str, query: &str, count: usize) -> Vec<String> {
+    let mut expansions: Vec<String> = Vec::new();
+    for line in reply.lines() {
+        let line = line
+            .trim()
+            .trim_start_matches(|c: char| c.is_ascii_digit() || matches!(c, '.' | ')' | '-' | '*'))
+            .trim()
+            .trim_matches('"')
+            .trim();
+        let seen = |other: &str| other.eq_ignore_ascii_case(line);
+        if line.is_empty() || seen(query) || expansions.iter().any(|e| seen(e)) {
+            continue;
+        }
+        expansions.push(line.to_string());
+        if expansions.len() == count {
+            break;
+        }
+    }
+    expansions
 }
 
-#[derive(Debug, Deserialize)]
-struct SearchResult {
-    #[allow(dead_code)]
-    id: String,
-    score: f32,
-    payload: serde_json::Value,
+/// Rates passages against one query with an Ollama model, remembering each
+/// passage's rating so repeated candidates cost one request
+struct Reranker {
+    client: Client,
+    url: String,
+    model: String,
+    query: String,
+    ratings: HashMap<String, Option<f32>>,
 }
 
-#[derive(Debug, Deserialize)]
-struct QdrantSearchResponse {
-    result: Vec<SearchResult>,
+impl Reranker {
+    fn new(ollama_url: &str, model: &str, query: &str) -> Result<Self> {
+        // Loading the model for the first rating can take far longer than the default timeout
+        let client = http_client_builder()
+            .timeout(None)
+            .build()
+            .context("Failed to build HTTP client")?;
+        Ok(Self {
+            client,
+            url: format!("{ollama_url}/api/generate"),
+            model: model.to_string(),
+            query: query.to_string(),
+            ratings: HashMap::new(),
+        })
+    }
+
+    /// Relevance of `passage` from 0 to 1, or `None` if the model's reply had no usable rating
+    fn relevance(&mut self, passage: &str) -> Result<Option<f32>> {
+        if let Some(rating) = self.ratings.get(passage) {
+            return Ok(*rating);
+        }
+
+        let response = self
+            .client
+            .post(&self.url)
+            .json(&json!({
+                "model": self.model,
+                "system": RERANK_PROMPT,
+                "prompt": format!("Query: {}\n\nPassage: {passage}", self.query),
+                "stream": false,
+                "options": { "temperature": 0 },
+            }))
+            .send()
+            .context("Failed to reach Ollama for reranking")?;
+        if !response.status().is_success() {
+            anyhow::bail!(
+                "Ollama returned error while reranking: {}",
+                response.status()
+            );
+        }
+        let reply: serde_json::Value =
+            response.json().context("Failed to parse rerank response")?;
+
+        let rating = reply["response"].as_str().and_then(parse_rating);
+        self.ratings.insert(passage.to_string(), rating);
+        Ok(rating)
+    }
 }
 
-fn get_embedding(client: &Client, ollama_url: &str, model: &str, text: &str) -> Result<Vec<f32>> {
-    let request = EmbeddingRequest {
-        model: model.to_string(),
-        prompt: text.to_string(),
-    };
+/// First number in a model reply, scaled from 0-10 to 0-1; anything outside that range is unusable
+fn parse_rating(reply: &str) -> Option<f32> {
+    let start = reply.find(|c: char| c.is_ascii_digit())?;
+    let number: String = reply[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+    let rating: f32 = number.trim_end_matches('.').parse().ok()?;
+    (0.0..=10.0).contains(&rating).then_some(rating / 10.0)
+}
 
-    let response = client
-        .post(format!("{}/api/embeddings", ollama_url))
-        .json(&request)
-        .send()
-        .context("Failed to get embedding from Ollama")?;
+/// Re-sort `candidates` by `relevance` and keep the best `limit`. A candidate
+/// without a rating keeps its vector score.
+fn rerank(
+    candidates: Vec<SearchResult>,
+    limit: usize,
+    text_field: &str,
+    mut relevance: impl FnMut(&str) -> Result<Option<f32>>,
+) -> Result<Vec<SearchResult>> {
+    let mut reranked = Vec::with_capacity(candidates.len());
+    for mut result in candidates {
+        if let Some(rating) = relevance(payload_text(&result.payload, text_field))? {
+            result.score = rating;
+        }
+        reranked.push(result);
+    }
+    // Stable, so equal ratings keep their vector order
+    reranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+    reranked.truncate(limit);
+    Ok(reranked)
+}
 
-    if !response.status().is_success() {
-        anyhow::bail!("Ollama returned error: {}", response.status());
+/// A `--boost` rule applied to scores after retrieval
+#[derive(Debug, Clone, PartialEq)]
+enum Boost {
+    /// `weight` x the field's value, scaled so the lowest candidate gets 0 and the highest 1
+    Numeric { field: String, weight: f32 },
+    /// `weight` for results whose field equals `value`
+    Match {
+        field: String,
+        value: String,
+        weight: f32,
+    },
+}
+
+/// Parse `field=weight` (numeric field) or `field:value=weight` (string match)
+fn parse_boost(spec: &str) -> Result<Boost> {
+    let Some((target, weight)) = spec.rsplit_once('=') else {
+        anyhow::bail!(
+            "Invalid boost format: '{}'. Use field=weight or field:value=weight",
+            spec
+        );
+    };
+    let weight: f32 = weight
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid boost '{spec}': weight must be a number"))?;
+    let boost = match target.split_once(':') {
+        Some((field, value)) => Boost::Match {
+            field: field.to_string(),
+            value: value.to_string(),
+            weight,
+        },
+        None => Boost::Numeric {
+            field: target.to_string(),
+            weight,
+        },
+    };
+    let (Boost::Numeric { field, .. } | Boost::Match { field, .. }) = &boost;
+    if field.is_empty() {
+        anyhow::bail!("Invalid boost format: '{}'. Missing field", spec);
     }
+    Ok(boost)
+}
 
-    let embedding: EmbeddingResponse = response
-        .json()
-        .context("Failed to parse embedding response")?;
+/// Add each boost to the results' scores and re-sort them. Numeric fields are
+/// min-max scaled over `results`, so a boost never adds more than its weight;
+/// results missing the field get nothing. Euclid scores are distances, so
+/// there a boost is subtracted to move the result closer.
+fn apply_boosts(results: &mut [SearchResult], boosts: &[Boost], distance: Distance) {
+    let direction = if distance.higher_is_better() {
+        1.0
+    } else {
+        -1.0
+    };
+    for boost in boosts {
+        match boost {
+            Boost::Numeric { field, weight } => {
+                let value = |result: &SearchResult| result.payload.get(field)?.as_f64();
+                let (min, max) = results
+                    .iter()
+                    .filter_map(value)
+                    .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+                        (min.min(v), max.max(v))
+                    });
+                if max <= min {
+                    // Missing everywhere or the same for all: nothing to rank by
+                    continue;
+                }
+                for result in results.iter_mut() {
+                    if let Some(v) = value(result) {
+                        result.score += direction * weight * ((v - min) / (max - min)) as f32;
+                    }
+                }
+            }
+            Boost::Match {
+                field,
+                value,
+                weight,
+            } => {
+                for result in results.iter_mut() {
+                    if result.payload.get(field).and_then(|v| v.as_str()) == Some(value) {
+                        result.score += direction * weight;
+                    }
+                }
+            }
+        }
+    }
+    // Stable, so equally boosted results keep their retrieval order
+    results.sort_by(|a, b| distance.rank(a.score, b.score));
+}
 
-    Ok(embedding.embedding)
+/// Read the chunk text from a payload using the configured field name
+fn payload_text<'a>(payload: &'a serde_json::Value, text_field: &str) -> &'a str {
+    payload
+        .get(text_field)
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
-    let client = Client::new();
+fn meets_min_score(result: &SearchResult, min_score: Option<f32>, distance: Distance) -> bool {
+    min_score.is_none_or(|min| distance.meets(result.score, min))
+}
 
-    // Get embedding for query
-    let query_embedding = get_embedding(&client, &args.ollama_url, &args.model, &args.query)
-        .context("Failed to get query embedding")?;
+/// Maximal Marginal Relevance: greedily pick up to `limit` candidates, each time
+/// the one maximizing `lambda * score - (1 - lambda) * max similarity to those picked`,
+/// with Euclid distances taken as similarities first
+fn mmr_select(
+    candidates: Vec<(SearchResult, Vec<f32>)>,
+    limit: usize,
+    lambda: f32,
+    distance: Distance,
+) -> Vec<SearchResult> {
+    let mut remaining = candidates;
+    let mut selected: Vec<(SearchResult, Vec<f32>)> = Vec::new();
 
-    // Search Qdrant
-    let search_request = json!({
-        "vector": query_embedding,
-        "limit": args.limit,
-        "with_payload": true,
-    });
+    while selected.len() < limit && !remaining.is_empty() {
+        let best = remaining
+            .iter()
+            .enumerate()
+            .map(|(i, (result, vector))| {
+                let redundancy = selected
+                    .iter()
+                    .map(|(_, chosen)| cosine(vector, chosen))
+                    .reduce(f32::max)
+                    .unwrap_or(0.0);
+                let relevance = distance.similarity(result.score);
+                (i, lambda * relevance - (1.0 - lambda) * redundancy)
+            })
+            .reduce(|best, candidate| {
+                if candidate.1 > best.1 {
+                    candidate
+                } else {
+                    best
+                }
+            })
+            .map(|(i, _)| i)
+            .unwrap_or(0);
+        selected.push(remaining.remove(best));
+    }
 
-    let response = client
-        .post(format!(
-            "{}/collections/{}/points/search",
-            args.qdrant_url, args.collection
-        ))
-        .json(&search_request)
-        .send()
-        .context("Failed to search Qdrant")?;
+    selected.into_iter().map(|(result, _)| result).collect()
+}
 
-    if !response.status().is_success() {
-        let error_text = response
-            .text()
-            .unwrap_or_else(|_| "Unknown error".to_string());
-        anyhow::bail!("Qdrant search failed: {}", error_text);
+impl Args {
+    /// `--format`, which `--json` and `--strict-json` set to JSON
+    fn output_format(&self) -> OutputFormat {
+        if self.json || self.strict_json {
+            OutputFormat::Json
+        } else {
+            self.format
+        }
     }
+}
 
-    let search_response: QdrantSearchResponse =
-        response.json().context("Failed to parse search response")?;
+/// Write the search results to `out` in the chosen format. In JSON mode
+/// nothing but a single JSON document is written, so the output can be piped
+/// straight into `jq`. `highlight` marks query terms in the text output's
+/// snippets (markdown output always uses `**term**`).
+fn write_results(
+    out: &mut impl Write,
+    args: &Args,
+    results: &[SearchResult],
+    highlight: Highlight,
+) -> Result<()> {
+    if args.text_only {
+        // Nothing but the texts, so the output can be piped as is
+        for (i, result) in results.iter().enumerate() {
+            if i > 0 {
+                write!(out, "{}", args.separator)?;
+            }
+            write!(out, "{}", payload_text(&result.payload, &args.text_field))?;
+        }
+        if !results.is_empty() {
+            writeln!(out)?;
+        }
+        return Ok(());
+    }
 
-    // Output results
-    if args.json {
+    let format = args.output_format();
+    if format == OutputFormat::Json {
         // JSON output for scripting
         let output = json!({
             "query": args.query,
-            "results": search_response.result.iter().map(|r| {
+            "results": results.iter().map(|r| {
                 json!({
                     "score": r.score,
-                    "text": r.payload.get("text").and_then(|v| v.as_str()).unwrap_or(""),
+                    "text": payload_text(&r.payload, &args.text_field),
                     "source": r.payload.get("source").and_then(|v| v.as_str()).unwrap_or(""),
                     "chunk_index": r.payload.get("chunk_index").and_then(|v| v.as_i64()).unwrap_or(0),
                 })
             }).collect::<Vec<_>>()
         });
-        println!("{}", serde_json::to_string(&output)?);
-    } else {
-        // Human-readable output
-        if search_response.result.is_empty() {
-            println!("No results found for query: {}", args.query);
-        } else {
-            println!("🔍 Search Results for: {}\n", args.query);
-            for (i, result) in search_response.result.iter().enumerate() {
-                println!("--- Result {} (Score: {:.3}) ---", i + 1, result.score);
-
-                if let Some(text) = result.payload.get("text").and_then(|v| v.as_str()) {
-                    // Truncate long text for display
-                    let display_text = if text.len() > 300 {
-                        format!("{}...", &text[..300])
-                    } else {
-                        text.to_string()
-                    };
-                    println!("{}", display_text);
-                }
+        writeln!(out, "{}", serde_json::to_string(&output)?)?;
+        return Ok(());
+    }
 
-                if let Some(source) = result.payload.get("source").and_then(|v| v.as_str()) {
-                    println!("Source: {}", source);
-                }
+    // Human-readable output
+    if results.is_empty() {
+        match args.min_score {
+            Some(min) => writeln!(
+                out,
+                "No results above threshold {min} for query: {}",
+                args.query
+            )?,
+            None => writeln!(out, "No results found for query: {}", args.query)?,
+        }
+        return Ok(());
+    }
 
-                if let Some(chunk) = result.payload.get("chunk_index").and_then(|v| v.as_i64()) {
-                    println!("Chunk: {}", chunk + 1);
-                }
+    let terms = query_terms(&args.query);
+    if format == OutputFormat::Markdown {
+        writeln!(out, "### Search results for: {}\n", args.query)?;
+        for (i, result) in results.iter().enumerate() {
+            let mut source = result
+                .payload
+                .get("source")
+                .and_then(|v| v.as_str())
+                .unwrap_or("(unknown)")
+                .to_string();
+            if let Some(chunk) = result.payload.get("chunk_index").and_then(|v| v.as_i64()) {
+                source.push_str(&format!(" (chunk {})", chunk + 1));
+            }
+            let text = snippet(
+                payload_text(&result.payload, &args.text_field),
+                &terms,
+                SNIPPET_RADIUS,
+                Highlight::Markdown,
+            );
+            let score = format!("{:.3}", result.score);
+            writeln!(out, "{}", markdown_result(i + 1, &text, &source, &score))?;
+        }
+        return Ok(());
+    }
+
+    writeln!(out, "🔍 Search Results for: {}\n", args.query)?;
+    for (i, result) in results.iter().enumerate() {
+        writeln!(out, "--- Result {} (Score: {:.3}) ---", i + 1, result.score)?;
+
+        if let Some(text) = result
+            .payload
+            .get(args.text_field.as_str())
+            .and_then(|v| v.as_str())
+        {
+            // The part of long text that best matches the query
+            writeln!(out, "{}", snippet(text, &terms, SNIPPET_RADIUS, highlight))?;
+        }
+
+        if let Some(source) = result.payload.get("source").and_then(|v| v.as_str()) {
+            writeln!(out, "Source: {}", source)?;
+        }
+
+        if let Some(chunk) = result.payload.get("chunk_index").and_then(|v| v.as_i64()) {
+            writeln!(out, "Chunk: {}", chunk + 1)?;
+        }
+
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+fn run(args: &Args) -> Result<()> {
+    if !args.skip_checks {
+        preflight(
+            &args.qdrant_url,
+            &args.ollama_url,
+            &args.model,
+            args.embed_api,
+        )?;
+        if args.rerank && !ollama_has_model(&args.ollama_url, &args.rerank_model)? {
+            anyhow::bail!(
+                "Rerank model '{}' is not available in Ollama; run: ollama pull {}",
+                args.rerank_model,
+                args.rerank_model
+            );
+        }
+        if args.expand && !ollama_has_model(&args.ollama_url, &args.expand_model)? {
+            anyhow::bail!(
+                "Expansion model '{}' is not available in Ollama; run: ollama pull {}",
+                args.expand_model,
+                args.expand_model
+            );
+        }
+    }
+    let embedder = Embedder::new(
+        &args.ollama_url,
+        args.embed_api,
+        &args.model,
+        RetryPolicy {
+            retries: args.embed_retries,
+            base_delay_ms: args.embed_retry_delay_ms,
+        },
+    )
+    .cache(EmbeddingCache::from_flags(
+        args.cache_dir.as_deref(),
+        args.no_cache,
+    ));
+    let qdrant = QdrantClient::new(&args.qdrant_url)
+        .api_key(args.qdrant_api_key.as_ref())
+        .consistency(args.consistency);
+    qdrant.require_collection(&args.collection)?;
+    qdrant.check_embed_model(&args.collection, &args.model, args.strict)?;
+    let info = qdrant.collection_info(&args.collection)?;
+    let distance = info.distance_metric();
+    let qdrant = qdrant.vector_name(info.default_vector_name());
+    let boosts = args
+        .boost
+        .iter()
+        .map(|spec| parse_boost(spec))
+        .collect::<Result<Vec<_>>>()?;
+
+    // Get embedding for query
+    let query_embedding = embedder
+        .embed(&args.query)
+        .context("Failed to get query embedding")?;
 
-                println!();
+    // Search Qdrant
+    let mut results = if args.mmr {
+        if !(0.0..=1.0).contains(&args.mmr_lambda) {
+            anyhow::bail!("--mmr-lambda must be between 0.0 and 1.0");
+        }
+        let mut candidates = qdrant.search_with_vectors(
+            &args.collection,
+            &query_embedding,
+            args.limit * args.over_fetch.max(1),
+            None,
+        )?;
+        candidates.retain(|(result, _)| meets_min_score(result, args.min_score, distance));
+        mmr_select(candidates, args.limit, args.mmr_lambda, distance)
+    } else if args.rerank {
+        let mut candidates = qdrant.search(
+            &args.collection,
+            &query_embedding,
+            args.limit * RERANK_CANDIDATE_FACTOR,
+            None,
+        )?;
+        candidates.retain(|result| meets_min_score(result, args.min_score, distance));
+        let mut reranker = Reranker::new(&args.ollama_url, &args.rerank_model, &args.query)?;
+        rerank(candidates, args.limit, &args.text_field, |passage| {
+            reranker.relevance(passage)
+        })?
+    } else if args.expand {
+        if args.expansions == 0 {
+            anyhow::bail!("--expansions must be greater than zero");
+        }
+        let expansions = expand_query(
+            &args.ollama_url,
+            &args.expand_model,
+            &args.query,
+            args.expansions,
+        )?;
+        if args.output_format() != OutputFormat::Json {
+            for expansion in &expansions {
+                eprintln!("🔀 Also searching: {expansion}");
             }
         }
+        let candidates = args.limit * args.over_fetch.max(1);
+        let mut rankings = Vec::with_capacity(expansions.len() + 1);
+        let mut ranking = qdrant.search(&args.collection, &query_embedding, candidates, None)?;
+        ranking.retain(|result| meets_min_score(result, args.min_score, distance));
+        rankings.push(ranking);
+        for expansion in &expansions {
+            let embedding = embedder
+                .embed(expansion)
+                .context("Failed to get expanded query embedding")?;
+            let mut ranking = qdrant.search(&args.collection, &embedding, candidates, None)?;
+            ranking.retain(|result| meets_min_score(result, args.min_score, distance));
+            rankings.push(ranking);
+        }
+        rrf_fuse(rankings, |result| result.id.clone())
+            .into_iter()
+            .take(args.limit)
+            .map(|fused| fused.result)
+            .collect()
+    } else {
+        // Boosts can lift results from below the top `limit`, so look further down
+        let candidates = if boosts.is_empty() {
+            args.limit
+        } else {
+            args.limit * args.over_fetch.max(1)
+        };
+        let mut results = qdrant.search(&args.collection, &query_embedding, candidates, None)?;
+        results.retain(|result| meets_min_score(result, args.min_score, distance));
+        results
+    };
+    if !boosts.is_empty() {
+        apply_boosts(&mut results, &boosts, distance);
+        results.truncate(args.limit);
+    }
+
+    write_results(
+        &mut std::io::stdout().lock(),
+        args,
+        &results,
+        Highlight::for_stdout(),
+    )
+}
+
+fn main() -> Result<()> {
+    let mut args: Args = parse_args()?;
+    args.collection = namespaced_collection(
+        &args.collection_prefix,
+        &args.collection,
+        &args.collection_suffix,
+    );
+    if !args.strict_json {
+        return run(&args);
     }
 
+    // Strict mode: even failures are reported as JSON on stdout
+    if let Err(e) = run(&args) {
+        println!("{}", json!({ "error": format!("{e:#}") }));
+        std::process::exit(1);
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_results() -> Vec<SearchResult> {
+        vec![SearchResult {
+            id: "id-1".to_string(),
+            score: 0.91,
+            payload: json!({"text": "Ownership rules 🦀 — borrowing", "source": "rust.pdf", "chunk_index": 2}),
+        }]
+    }
+
+    fn render(argv: &[&str], results: &[SearchResult]) -> String {
+        let args = Args::try_parse_from(argv).unwrap();
+        let mut out = Vec::new();
+        write_results(&mut out, &args, results, Highlight::Markdown).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_json_output_is_only_json() {
+        for flag in ["--json", "--strict-json"] {
+            let stdout = render(&["search-qdrant", "ownership", flag], &sample_results());
+
+            let parsed: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+            assert_eq!(parsed["query"], "ownership");
+            assert_eq!(parsed["results"][0]["source"], "rust.pdf");
+        }
+    }
+
+    #[test]
+    fn test_json_output_with_no_results_is_valid() {
+        let stdout = render(&["search-qdrant", "nothing", "--json"], &[]);
+
+        let parsed: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+        assert!(parsed["results"].as_array().unwrap().is_empty());
+    }
+
+    fn candidate(id: &str, score: f32, vector: Vec<f32>) -> (SearchResult, Vec<f32>) {
+        let result = SearchResult {
+            id: id.to_string(),
+            score,
+            payload: json!({}),
+        };
+        (result, vector)
+    }
+
+    fn ids(results: &[SearchResult]) -> Vec<&str> {
+        results.iter().map(|r| r.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_mmr_skips_near_duplicates() {
+        let candidates = || {
+            vec![
+                candidate("page-1", 0.92, vec![1.0, 0.0, 0.0]),
+                candidate("page-1-again", 0.91, vec![0.99, 0.05, 0.0]),
+                candidate("other-chapter", 0.80, vec![0.3, 0.9, 0.1]),
+            ]
+        };
+
+        assert_eq!(
+            ids(&mmr_select(candidates(), 2, 0.5, Distance::Cosine)),
+            vec!["page-1", "other-chapter"]
+        );
+        // lambda = 1 is plain relevance order
+        assert_eq!(
+            ids(&mmr_select(candidates(), 2, 1.0, Distance::Cosine)),
+            vec!["page-1", "page-1-again"]
+        );
+        assert_eq!(mmr_select(candidates(), 5, 0.5, Distance::Cosine).len(), 3);
+    }
+
+    #[test]
+    fn test_min_score_filters_and_reports_empty_results() {
+        let results = sample_results();
+        assert!(meets_min_score(&results[0], None, Distance::Cosine));
+        assert!(meets_min_score(&results[0], Some(0.9), Distance::Cosine));
+        assert!(!meets_min_score(&results[0], Some(0.95), Distance::Cosine));
+        // Euclid scores are distances: the threshold is the farthest to keep
+        assert!(!meets_min_score(&results[0], Some(0.9), Distance::Euclid));
+        assert!(meets_min_score(&results[0], Some(0.95), Distance::Euclid));
+
+        let stdout = render(&["search-qdrant", "ownership", "--min-score", "0.95"], &[]);
+
+        assert_eq!(
+            stdout,
+            "No results above threshold 0.95 for query: ownership\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_rating_from_model_replies() {
+        assert_eq!(parse_rating("8"), Some(0.8));
+        assert_eq!(parse_rating("Relevance: 7.5/10"), Some(0.75));
+        assert_eq!(parse_rating("10."), Some(1.0));
+        assert_eq!(parse_rating("I cannot rate this."), None);
+        assert_eq!(parse_rating("42"), None);
+    }
+
+    #[test]
+    fn test_rerank_resorts_and_keeps_vector_score_when_unrated() {
+        let result = |id: &str, score: f32, text: &str| SearchResult {
+            id: id.to_string(),
+            score,
+            payload: json!({ "text": text }),
+        };
+        let candidates = vec![
+            result("close-vector", 0.90, "mentions ownership once"),
+            result("garbled", 0.85, "model replies with prose"),
+            result("best-answer", 0.70, "explains ownership in depth"),
+        ];
+        let mut asked = Vec::new();
+
+        let reranked = rerank(candidates, 2, "text", |passage| {
+            asked.push(passage.to_string());
+            Ok(match passage {
+                "mentions ownership once" => Some(0.3),
+                "explains ownership in depth" => Some(0.9),
+                _ => None,
+            })
+        })
+        .unwrap();
+
+        assert_eq!(ids(&reranked), vec!["best-answer", "garbled"]);
+        assert_eq!(reranked[1].score, 0.85);
+        assert_eq!(asked.len(), 3);
+    }
+
+    #[test]
+    fn test_parse_boost_numeric_and_match() {
+        assert_eq!(
+            parse_boost("priority=0.2").unwrap(),
+            Boost::Numeric {
+                field: "priority".to_string(),
+                weight: 0.2
+            }
+        );
+        assert_eq!(
+            parse_boost("status:current=0.1").unwrap(),
+            Boost::Match {
+                field: "status".to_string(),
+                value: "current".to_string(),
+                weight: 0.1
+            }
+        );
+        assert!(parse_boost("priority").is_err());
+        assert!(parse_boost("priority=high").is_err());
+        assert!(parse_boost("=0.2").is_err());
+    }
+
+    #[test]
+    fn test_boosts_scale_numeric_fields_and_resort() {
+        let result = |id: &str, score: f32, payload: serde_json::Value| SearchResult {
+            id: id.to_string(),
+            score,
+            payload,
+        };
+        let mut results = vec![
+            result("v1", 0.80, json!({"version": 1, "status": "archived"})),
+            result("v3", 0.78, json!({"version": 3, "status": "current"})),
+            result("v2", 0.79, json!({"version": 2})),
+            result("untagged", 0.75, json!({})),
+        ];
+        let boosts = [
+            parse_boost("version=0.1").unwrap(),
+            parse_boost("status:current=0.05").unwrap(),
+        ];
+
+        apply_boosts(&mut results, &boosts, Distance::Cosine);
+
+        assert_eq!(ids(&results), vec!["v3", "v2", "v1", "untagged"]);
+        assert!((results[0].score - 0.93).abs() < 1e-6);
+        assert!((results[1].score - 0.84).abs() < 1e-6);
+        assert_eq!(results[2].score, 0.80);
+        assert_eq!(results[3].score, 0.75);
+    }
+
+    #[test]
+    fn test_boosts_lower_euclid_distances() {
+        let result = |id: &str, score: f32, payload: serde_json::Value| SearchResult {
+            id: id.to_string(),
+            score,
+            payload,
+        };
+        let mut results = vec![
+            result("near", 0.20, json!({})),
+            result("current", 0.22, json!({"status": "current"})),
+        ];
+        let boosts = [parse_boost("status:current=0.05").unwrap()];
+
+        apply_boosts(&mut results, &boosts, Distance::Euclid);
+
+        assert_eq!(ids(&results), vec!["current", "near"]);
+        assert!((results[0].score - 0.17).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_parse_expansions_strips_markers_and_repeats() {
+        let reply = "1. garbage collection\n\n2) \"GC\"\n- automatic memory management\n* Garbage Collection\n3. tracing collector";
+        assert_eq!(
+            parse_expansions(reply, "GC", 3),
+            vec![
+                "garbage collection",
+                "automatic memory management",
+                "tracing collector"
+            ]
+        );
+        assert_eq!(parse_expansions(reply, "GC", 1), vec!["garbage collection"]);
+        assert!(parse_expansions("", "GC", 3).is_empty());
+    }
+
+    #[test]
+    fn test_markdown_output_lists_quoted_results() {
+        let stdout = render(
+            &["search-qdrant", "ownership", "--format", "markdown"],
+            &sample_results(),
+        );
+
+        assert!(stdout.starts_with(
+            "### Search results for: ownership\n\n1. > **Ownership** rules 🦀 — borrowing\n"
+        ));
+        assert!(stdout.contains("   **Source:** rust.pdf (chunk 3) · **Score:** 0.910\n"));
+        assert!(!stdout.contains("🔍"));
+        assert_eq!(
+            render(&["search-qdrant", "ownership", "--format", "json"], &[]),
+            render(&["search-qdrant", "ownership", "--json"], &[])
+        );
+    }
+
+    #[test]
+    fn test_text_only_output_is_just_the_texts() {
+        let mut results = sample_results();
+        results.push(SearchResult {
+            id: "id-2".to_string(),
+            score: 0.5,
+            payload: json!({"text": "Borrowing\nrules", "source": "rust.pdf"}),
+        });
+
+        let stdout = render(&["search-qdrant", "ownership", "--text-only"], &results);
+        let custom = render(
+            &["search-qdrant", "q", "--text-only", "--separator", "\x0c"],
+            &results,
+        );
+
+        assert_eq!(
+            stdout,
+            "Ownership rules 🦀 — borrowing\n---\nBorrowing\nrules\n"
+        );
+        assert_eq!(
+            custom,
+            "Ownership rules 🦀 — borrowing\x0cBorrowing\nrules\n"
+        );
+        assert_eq!(render(&["search-qdrant", "q", "--text-only"], &[]), "");
+        assert!(Args::try_parse_from(["search-qdrant", "q", "--text-only", "--json"]).is_err());
+    }
+
+    #[test]
+    fn test_human_output_is_not_json() {
+        let stdout = render(&["search-qdrant", "ownership"], &sample_results());
+
+        assert!(stdout.starts_with("🔍 Search Results for: ownership"));
+        assert!(stdout.contains("**Ownership** rules"));
+        assert!(serde_json::from_str::<serde_json::Value>(&stdout).is_err());
+    }
+}
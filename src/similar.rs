@@ -0,0 +1,161 @@
+// Copyright (c) 2025 Michael A. Wright
+// Licensed under the MIT License
+
+//! Find the chunks most like one already in a collection ("more like this").
+//!
+//! The query vector is the point's own stored vector, so nothing is embedded
+//! and the results are exactly its nearest neighbours.
+
+use anyhow::Result;
+use clap::Parser;
+use rag_demo::config::parse_args;
+use rag_demo::rag::{ApiKey, QdrantClient, RetrievedPoint, SearchResult, exclude_point, preview};
+use serde_json::{Value, json};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Find the chunks most similar to a point in a Qdrant collection", long_about = None)]
+struct Args {
+    #[arg(
+        help = "ID of the point to find neighbours of, as printed by hybrid-search --json or find-duplicates"
+    )]
+    point_id: String,
+
+    #[arg(short, long, default_value = "5", help = "Number of results")]
+    limit: usize,
+
+    #[arg(
+        long,
+        default_value = "documents",
+        env = "RAG_COLLECTION",
+        help = "Qdrant collection name"
+    )]
+    collection: String,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:6333",
+        env = "QDRANT_URL",
+        help = "Qdrant URL"
+    )]
+    qdrant_url: String,
+
+    #[arg(
+        long,
+        env = "QDRANT_API_KEY",
+        hide_env_values = true,
+        help = "Qdrant API key, sent as the api-key header (Qdrant Cloud)"
+    )]
+    qdrant_api_key: Option<ApiKey>,
+
+    #[arg(
+        long,
+        default_value = "text",
+        help = "Payload field that stores the chunk text"
+    )]
+    text_field: String,
+
+    #[arg(short, long, help = "Output as JSON")]
+    json: bool,
+}
+
+/// Characters of each chunk's text shown in the text output
+const PREVIEW_CHARS: usize = 300;
+
+fn payload_str<'a>(payload: &'a Value, field: &str) -> &'a str {
+    payload.get(field).and_then(|v| v.as_str()).unwrap_or("")
+}
+
+fn results_json(point: &RetrievedPoint, results: &[SearchResult], text_field: &str) -> Value {
+    json!({
+        "point": {
+            "id": point.id,
+            "source": payload_str(&point.payload, "source"),
+            "text": payload_str(&point.payload, text_field),
+        },
+        "results": results.iter().map(|r| json!({
+            "id": r.id,
+            "score": r.score,
+            "source": payload_str(&r.payload, "source"),
+            "text": payload_str(&r.payload, text_field),
+        })).collect::<Vec<_>>(),
+    })
+}
+
+fn main() -> Result<()> {
+    let args: Args = parse_args()?;
+    if args.limit == 0 {
+        anyhow::bail!("--limit must be greater than zero");
+    }
+    let qdrant = QdrantClient::new(&args.qdrant_url).api_key(args.qdrant_api_key.as_ref());
+    qdrant.require_collection(&args.collection)?;
+    let vector_name = qdrant
+        .collection_info(&args.collection)?
+        .default_vector_name();
+    let qdrant = qdrant.vector_name(vector_name);
+
+    let (point, vector) = qdrant.get_point_with_vector(&args.collection, &args.point_id)?;
+    let results = qdrant.search(
+        &args.collection,
+        &vector,
+        args.limit,
+        Some(&exclude_point(&point.id)),
+    )?;
+
+    if args.json {
+        let output = results_json(&point, &results, &args.text_field);
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        return Ok(());
+    }
+
+    println!(
+        "🔍 Chunks similar to {} ({})",
+        point.id,
+        payload_str(&point.payload, "source")
+    );
+    println!(
+        "{}\n",
+        preview(payload_str(&point.payload, &args.text_field), PREVIEW_CHARS)
+    );
+    if results.is_empty() {
+        println!("No other points in '{}'", args.collection);
+    }
+    for (i, result) in results.iter().enumerate() {
+        println!("--- Result {} (Score: {:.3}) ---", i + 1, result.score);
+        println!(
+            "{}",
+            preview(
+                payload_str(&result.payload, &args.text_field),
+                PREVIEW_CHARS
+            )
+        );
+        println!("Source: {}", payload_str(&result.payload, "source"));
+        println!("ID: {}\n", result.id);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_results_json_lists_neighbours_with_ids() {
+        let point = RetrievedPoint {
+            id: "p-1".to_string(),
+            payload: json!({"source": "book.md", "content": "Ownership"}),
+        };
+        let results = vec![SearchResult {
+            id: "p-2".to_string(),
+            score: 0.9,
+            payload: json!({"source": "book.md", "content": "Borrowing"}),
+        }];
+
+        let value = results_json(&point, &results, "content");
+
+        assert_eq!(value["point"]["text"], "Ownership");
+        assert_eq!(value["results"][0]["id"], "p-2");
+        assert_eq!(value["results"][0]["text"], "Borrowing");
+        assert_eq!(value["results"][0]["score"], 0.9_f32);
+    }
+}
@@ -0,0 +1,88 @@
+// Copyright (c) 2025 Michael A. Wright
+// Licensed under the MIT License
+
+//! Vector math for comparing embeddings client-side (MMR, deduplication,
+//! clustering), small enough not to need a linear-algebra crate.
+//!
+//! Vectors of different lengths cannot be compared: `dot` and `cosine`
+//! return NaN for them, which fails every comparison and so never wins a
+//! "most similar" contest. Check lengths first where a mismatch is a bug.
+
+/// Sum of the component-wise products; NaN if the lengths differ
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return f32::NAN;
+    }
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Euclidean (L2) length
+pub fn norm(v: &[f32]) -> f32 {
+    v.iter().map(|x| x * x).sum::<f32>().sqrt()
+}
+
+/// Cosine of the angle between two vectors, from -1 (opposite) to 1 (same
+/// direction); 0 if either is all zeros, NaN if the lengths differ
+pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return f32::NAN;
+    }
+    let (norm_a, norm_b) = (norm(a), norm(b));
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot(a, b) / (norm_a * norm_b)
+}
+
+/// `v` scaled to unit length, so `dot` of normalized vectors is their cosine;
+/// an all-zero vector is returned as is
+pub fn normalize(v: &[f32]) -> Vec<f32> {
+    let norm = norm(v);
+    if norm == 0.0 {
+        return v.to_vec();
+    }
+    v.iter().map(|x| x / norm).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn test_cosine_of_identical_orthogonal_and_opposite_vectors() {
+        let v = [1.0, 2.0, 3.0];
+
+        assert!(close(cosine(&v, &v), 1.0));
+        assert!(
+            close(cosine(&v, &[2.0, 4.0, 6.0]), 1.0),
+            "scale does not matter"
+        );
+        assert!(close(cosine(&[1.0, 0.0], &[0.0, 5.0]), 0.0));
+        assert!(close(cosine(&v, &[-1.0, -2.0, -3.0]), -1.0));
+        assert_eq!(cosine(&v, &[0.0, 0.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn test_length_mismatch_is_nan() {
+        assert!(dot(&[1.0, 2.0], &[1.0]).is_nan());
+        assert!(cosine(&[1.0, 2.0], &[1.0]).is_nan());
+        assert!(cosine(&[0.0, 0.0], &[1.0]).is_nan());
+    }
+
+    #[test]
+    fn test_normalize_gives_unit_length() {
+        let unit = normalize(&[3.0, 4.0]);
+
+        assert!(close(unit[0], 0.6) && close(unit[1], 0.8));
+        assert!(close(norm(&unit), 1.0));
+        assert!(close(
+            dot(&unit, &normalize(&[4.0, 3.0])),
+            cosine(&[3.0, 4.0], &[4.0, 3.0])
+        ));
+        assert_eq!(normalize(&[0.0, 0.0]), vec![0.0, 0.0]);
+    }
+}